@@ -1,29 +1,12 @@
-#![recursion_limit = "1024"]
-
-#[macro_use]
-extern crate error_chain;
 extern crate lemurdb;
 
 use lemurdb::{Schema, DataType};
 use lemurdb::executor::DbIterator;
+use lemurdb::executor::join::JoinType;
 use lemurdb::executor::io::CsvSource;
 use lemurdb::executor::simplesort::SortOrder;
 use lemurdb::executor::aggregate::{AggregateType};
-
-mod error {
-    use lemurdb;
-
-    error_chain!{
-        foreign_links {
-            Io(::std::io::Error);
-        }
-        links {
-            Lemur(lemurdb::error::Error, lemurdb::error::ErrorKind);
-        }
-    }
-}
-
-use error::*;
+use lemurdb::error::*;
 
 fn main() {
     if let Err(ref err) = run() {
@@ -59,7 +42,7 @@ fn run() -> Result<()> {
 //        .simplesort(1, DataType::Integer, SortOrder::Ascending)
 //        .limit(50);
 //
-//    while let Some(record) = query.next() {
+//    while let Some(record) = query.next()? {
 //        println!("{:?}", record.to_string(&schema));
 //    }
 
@@ -72,7 +55,7 @@ fn run() -> Result<()> {
 //        .simplesort(1, DataType::Integer, SortOrder::Ascending)
 //        .aggregate(AggregateType::Count, 2, DataType::Float, Some(1));
 //
-//    while let Some(record) = query.next() {
+//    while let Some(record) = query.next()? {
 //        println!("{:?}", record.to_string(&final_schema));
 //    }
 
@@ -102,13 +85,13 @@ fn run() -> Result<()> {
         column_names: vec!["title".to_owned(), "rating count".to_owned()],
         column_types: vec![Text(255), Integer],
     };
-    let movies = CsvSource::new("test_data/test_movies.csv".to_owned(), movie_schema);
-    let mut query = CsvSource::new("test_data/test_ratings.csv".to_owned(), rating_schema)
-        .nested_loops_join(movies,1, 0)
+    let movies = CsvSource::new("test_data/test_movies.csv".to_owned(), movie_schema.clone())?;
+    let mut query = CsvSource::new("test_data/test_ratings.csv".to_owned(), rating_schema.clone())?
+        .nested_loops_join(movies,1, 0, JoinType::Inner, &rating_schema.column_types, &movie_schema.column_types)?
         .simplesort(5, DataType::Text(255), SortOrder::Ascending)
-        .aggregate(AggregateType::Count, 2, DataType::Float, Some(5));
+        .aggregate(AggregateType::Count, 2, &joined_schema.column_types, Some(5))?;
 
-    while let Some(record) = query.next() {
+    while let Some(record) = query.next()? {
         println!("{:?}", record.to_string(&agg_schema));
     }
 
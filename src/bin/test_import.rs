@@ -1,30 +1,15 @@
-#![recursion_limit = "1024"]
-
-#[macro_use]
-extern crate error_chain;
 extern crate lemurdb;
 
-use lemurdb::{Schema, RelationSchema, DataType};
-use lemurdb::executor::DbIterator;
-use lemurdb::executor::simplesort::SortOrder;
-use lemurdb::executor::aggregate::{AggregateType};
-use lemurdb::storage::from_csv;
-use lemurdb::storage::disk::{DiskScan};
-
-mod error {
-    use lemurdb;
-
-    error_chain!{
-        foreign_links {
-            Io(::std::io::Error);
-        }
-        links {
-            Lemur(lemurdb::error::Error, lemurdb::error::ErrorKind);
-        }
-    }
-}
+use std::env;
+use std::time::{Duration, Instant};
 
-use error::*;
+use lemurdb::Schema;
+use lemurdb::executor::DbIterator;
+use lemurdb::plan::{Catalog, LogicalPlan, plan};
+use lemurdb::plan::binder::Binder;
+use lemurdb::sql;
+use lemurdb::storage::catalog::SystemCatalog;
+use lemurdb::error::*;
 
 fn main() {
     if let Err(ref err) = run() {
@@ -45,67 +30,72 @@ fn main() {
 fn run() -> Result<()> {
     use lemurdb::DataType::*;
 
-    let rating_schema = RelationSchema{
-        name: "ratings".to_owned(),
-        id: 1,
-        column_names: vec!["userId".to_owned(), "movieId".to_owned(), "rating".to_owned(), "timestamp".to_owned()],
-        column_types: vec![Integer, Integer, Float, Integer],
-    };
-
-    let movie_schema = RelationSchema {
-        name: "movies".to_owned(),
-        id: 2,
-        column_names: vec!["movieId".to_owned(), "title".to_owned(), "genres".to_owned()],
-        column_types: vec![Integer, Text(255), Text(255)],
-    };
+    let mut catalog = SystemCatalog::open("data")?;
 
-    // join and agg schemas
-    let joined_schema = Schema {
-        column_names: vec![
-            "userId".to_owned(),
-            "movieId".to_owned(),
-            "rating".to_owned(),
-            "timestamp".to_owned(),
-            "movieId".to_owned(),
-            "title".to_owned(),
-            "genres".to_owned()
-        ],
-        column_types: vec![
-            Integer,
-            Integer,
-            Float,
-            Integer,
-            Integer,
-            Text(255),
-            Text(255),
-        ],
-    };
+    // start import, once into the data dir
+    if catalog.relation("ratings").is_none() {
+        catalog.import_csv(
+            "ratings",
+            "test_data/test_ratings.csv",
+            vec!["userId".to_owned(), "movieId".to_owned(), "rating".to_owned(), "timestamp".to_owned()],
+            vec![Integer, Integer, Float, Integer],
+        )?;
+    }
+    if catalog.relation("movies").is_none() {
+        catalog.import_csv(
+            "movies",
+            "test_data/test_movies.csv",
+            vec!["movieId".to_owned(), "title".to_owned(), "genres".to_owned()],
+            vec![Integer, Text(255), Text(255)],
+        )?;
+    }
 
-    let agg_schema = Schema{
-        column_names: vec!["title".to_owned(), "rating count".to_owned()],
-        column_types: vec![Text(255), Integer],
+    // start query
+    let sql = "
+        SELECT m.title, count(r.rating) AS \"rating count\"
+        FROM ratings r JOIN movies m ON r.movieId = m.movieId
+        GROUP BY m.title
+        ORDER BY m.title";
+    let plan = plan(sql, &catalog)?;
+    let agg_schema = Schema {
+        column_names: plan.schema.column_names.clone(),
+        column_types: plan.schema.column_types.clone(),
     };
 
-    // start import
-    //let _ = from_csv("test_data/test_ratings.csv", rating_schema.clone());
-    //let _ = from_csv("test_data/test_movies.csv", movie_schema.clone());
+    // with --compare, time the query with and without the optimizer
+    // instead of printing it
+    if env::args().any(|arg| arg == "--compare") {
+        let unoptimized = Binder::new(&catalog).bind_select(&sql::parse(sql)?)?;
+        time_query("unoptimized", &unoptimized, &catalog)?;
+        time_query("optimized", &plan, &catalog)?;
+        return Ok(());
+    }
 
-    // start query
-    let movies = DiskScan::from_path("2", movie_schema.column_types.clone())?;
-    let mut query = DiskScan::from_path("1", rating_schema.column_types.clone())?
-        .nested_loops_join(movies,1, 0)
-        .simplesort(5, DataType::Text(255), SortOrder::Ascending)
-        .aggregate(AggregateType::Count, 2, DataType::Float, Some(5));
+    let mut query = plan.build_analyzed(&catalog)?;
 
     let mut count = 0;
-    while let Some(record) = query.next() {
+    while let Some(record) = query.next()? {
         println!("{:?}, {}", record.to_string(&agg_schema), count);
-//        println!("{:?}, {}", record.to_string(&Schema {
-//            column_names: rating_schema.column_names.clone(),
-//            column_types: rating_schema.column_types.clone(),
-//        }), count);
         count += 1;
     }
+    println!("{}", query.explain());
 
     Ok(())
 }
+
+// Best of three runs, since the first also warms the page cache.
+fn time_query<C: Catalog>(name: &str, plan: &LogicalPlan, catalog: &C) -> Result<()> {
+    let mut best = Duration::MAX;
+    let mut rows = 0;
+    for _ in 0..3 {
+        let start = Instant::now();
+        let mut query = plan.build(catalog)?;
+        rows = 0;
+        while query.next()?.is_some() {
+            rows += 1;
+        }
+        best = best.min(start.elapsed());
+    }
+    println!("{}: {} rows in {:.3}s", name, rows, best.as_secs_f64());
+    Ok(())
+}
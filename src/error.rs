@@ -8,4 +8,47 @@ error_chain! {
         ParseInt(::std::num::ParseIntError);
         ParseFloat(::std::num::ParseFloatError);
     }
+
+    errors {
+        SqlParse(msg: String, line: usize, column: usize) {
+            description("error parsing sql")
+            display("{} at line {}, column {}", msg, line, column)
+        }
+        Plan(msg: String) {
+            description("error planning query")
+            display("planning error: {}", msg)
+        }
+        ExprType(msg: String) {
+            description("expression type error")
+            display("expression type error: {}", msg)
+        }
+        ExprEval(msg: String) {
+            description("error evaluating expression")
+            display("error evaluating expression: {}", msg)
+        }
+        ParseValue(value: String, data_type: String) {
+            description("error parsing value")
+            display("can't parse {:?} as {}", value, data_type)
+        }
+        Catalog(msg: String) {
+            description("system catalog error")
+            display("catalog error: {}", msg)
+        }
+        TextTooLong(value: String, max_len: usize) {
+            description("text too long for its type")
+            display("{:?} is too long for Text({})", value, max_len)
+        }
+        CorruptBlock(reason: String) {
+            description("corrupt disk block")
+            display("corrupt disk block: {}", reason)
+        }
+        RecordTooLarge(len: usize, max: usize) {
+            description("record too large for a block")
+            display("record of {} bytes is larger than the {} bytes which fit in a block", len, max)
+        }
+        AggregateOverflow(aggregation: String, result_type: String) {
+            description("aggregate result overflowed")
+            display("{} overflowed its result type {}", aggregation, result_type)
+        }
+    }
 }
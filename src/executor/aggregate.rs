@@ -1,17 +1,131 @@
-// Make macros!
+use std::borrow::Cow;
+use std::cmp::Ordering;
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
 use DataType;
+use error::*;
 use super::DbIterator;
-use super::tuple::{Tuple, ToTupleField};
+use super::explain::{self, Explain, list};
+use super::decimal;
+use super::expr::{Expr, TypedExpr};
+use super::tuple::{Tuple, ToTupleField, cmp_with_type, field_parse};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum AggregateType {
     Count,
     Sum,
     Avg,
-    // DistinctCount,
+    Min,
+    Max,
+    CountDistinct,
+    Variance, // sample variance
+    Stddev, // sample standard deviation
+    First,
+    Last,
     // Median,
 }
 
+impl AggregateType {
+    /// Type of the aggregate's result, given the type of the column it
+    /// aggregates. Results which don't fit their type are an error.
+    /// Decimals stay exact, with the most digits before the point, and the
+    /// Avg keeps at least 6 digits after it.
+    pub fn result_type(&self, input_type: &DataType) -> DataType {
+        use self::AggregateType::*;
+        match *self {
+            Count | CountDistinct => DataType::Integer,
+            Sum => {
+                match *input_type {
+                    DataType::SmallInt => DataType::Integer,
+                    DataType::Integer | DataType::BigInt => DataType::BigInt,
+                    DataType::Decimal(_, scale) => DataType::Decimal(decimal::MAX_PRECISION, scale),
+                    _ => DataType::Double,
+                }
+            },
+            Avg => {
+                match *input_type {
+                    DataType::Decimal(_, scale) => DataType::Decimal(decimal::MAX_PRECISION, scale.max(6)),
+                    _ => DataType::Double,
+                }
+            },
+            Variance | Stddev => DataType::Double,
+            Min | Max | First | Last => input_type.clone(),
+        }
+    }
+}
+
+/// One aggregation computed by an Aggregate node: the aggregate
+/// function, and the expression over the input columns which it
+/// aggregates, e.g. a column or the product of two columns.
+pub type AggregateExpr = (AggregateType, Expr);
+
+/// An AggregateExpr type checked against the input columns. Sum, Avg,
+/// Variance and Stddev only apply to numbers.
+#[derive(Debug, Clone)]
+pub struct TypedAggregate {
+    aggregation: AggregateType,
+    arg: TypedExpr,
+    arg_type: DataType, // of the aggregated fields
+}
+
+impl TypedAggregate {
+    pub fn new(aggregate: AggregateExpr, column_types: &[DataType]) -> Result<Self> {
+        let (aggregation, arg) = aggregate;
+        let arg = TypedExpr::new(arg, column_types)?;
+        let arg_type = arg.column_type()?;
+        let numeric_only = matches!(
+            aggregation,
+            AggregateType::Sum | AggregateType::Avg | AggregateType::Variance | AggregateType::Stddev
+        );
+        if numeric_only && !is_number(&arg_type) {
+            bail!(ErrorKind::ExprType(format!(
+                "can't apply {:?} to {} of {:?}",
+                aggregation,
+                arg.expr(),
+                arg_type,
+            )));
+        }
+        Ok(TypedAggregate {
+            aggregation,
+            arg,
+            arg_type,
+        })
+    }
+
+    pub fn aggregation(&self) -> &AggregateType {
+        &self.aggregation
+    }
+
+    pub fn arg(&self) -> &Expr {
+        self.arg.expr()
+    }
+
+    pub fn arg_type(&self) -> &DataType {
+        &self.arg_type
+    }
+
+    // The field to aggregate from tuple, None for null. Column args are
+    // read straight from the tuple, without evaluating.
+    fn arg_field<'t>(&self, tuple: &'t Tuple) -> Result<Option<Cow<'t, [u8]>>> {
+        match *self.arg.expr() {
+            Expr::Column(col) if tuple.is_null(col) => Ok(None),
+            Expr::Column(col) => Ok(Some(Cow::Borrowed(&tuple[col]))),
+            _ => Ok(self.arg.eval_field(tuple)?.map(Cow::Owned)),
+        }
+    }
+}
+
+pub(crate) fn typed_aggregates(
+    aggregates: Vec<AggregateExpr>,
+    column_types: &[DataType],
+    ) -> Result<Vec<TypedAggregate>>
+{
+    aggregates.into_iter()
+        .map(|aggregate| TypedAggregate::new(aggregate, column_types))
+        .collect()
+}
+
 #[derive(Debug, Clone)]
 pub struct Aggregate<I> {
     // internal state
@@ -20,254 +134,381 @@ pub struct Aggregate<I> {
     first_time: bool,
     // intitialize
     input: I,
-    aggregation: AggregateType,
-    agg_col: usize,
-    agg_col_type: DataType,
-    group_by: Option<usize>,
+    aggregates: Vec<TypedAggregate>,
+    group_by: Vec<usize>,
 }
 
 impl<I: DbIterator> Aggregate<I> {
+    /// Output tuples hold the group_by columns in order, followed by
+    /// the result of each aggregate in order. Aggregate args are type
+    /// checked against the input's column_types.
     pub fn new(
         input: I,
-        aggregation: AggregateType,
-        aggregate_col: usize,
-        aggregate_col_type: DataType,
-        group_by: Option<usize>,
-        ) -> Self
+        aggregates: Vec<AggregateExpr>,
+        group_by: Vec<usize>,
+        column_types: &[DataType],
+        ) -> Result<Self>
     {
-        Aggregate {
+        Ok(Aggregate {
             previous_tuple: None,
             is_done: false,
             first_time: true,
-            input: input,
-            aggregation: aggregation,
-            agg_col: aggregate_col,
-            agg_col_type: aggregate_col_type,
-            group_by: group_by,
-        }
+            input,
+            aggregates: typed_aggregates(aggregates, column_types)?,
+            group_by,
+        })
     }
 }
 
 impl <I: DbIterator> DbIterator for Aggregate<I>
     where Self: Sized,
 {
-    fn next(&mut self) -> Option<Tuple> {
+    fn next(&mut self) -> Result<Option<Tuple>> {
         if self.is_done { // is_done only needed for aggregate_all
-            return None
+            Ok(None)
+        } else if self.group_by.is_empty() {
+            let res = self.aggregate_all()?;
+            self.is_done = true;
+            Ok(Some(res))
         } else {
-            if self.group_by.is_none() {
-                let res = self.aggregate_all();
-                self.is_done = true;
-                return Some(res)
+            self.aggregate_group()
+        }
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.input.reset()?;
+        self.previous_tuple = None;
+        self.is_done = false;
+        self.first_time = true;
+        Ok(())
+    }
+
+    fn explain(&self) -> Explain {
+        Explain::new("Aggregate")
+            .param("aggregates", explain::aggregates(&self.aggregates))
+            .param("group_by", list(&self.group_by))
+            .input(self.input.explain())
+    }
+}
+
+impl<I: DbIterator> Aggregate<I> {
+    fn aggregate_all(&mut self) -> Result<Tuple> {
+        let mut accs = new_accumulators(&self.aggregates);
+        while let Some(tuple) = self.input.next()? {
+            update_accumulators(&mut accs, &self.aggregates, &tuple)?;
+        }
+        Ok(Tuple::from_nullable(finish_accumulators(&accs)?))
+    }
+
+    // Null group columns are grouped together.
+    fn same_group(&self, tuple1: &Tuple, tuple2: &Tuple) -> bool {
+        self.group_by.iter().all(|&col| {
+            tuple1.is_null(col) == tuple2.is_null(col) && tuple1[col] == tuple2[col]
+        })
+    }
+
+    // Input must be sorted (or at least grouped) on the group_by columns.
+    // Each call returns the group labels and aggregations for one group.
+    fn aggregate_group(&mut self) -> Result<Option<Tuple>> {
+        // initialize the first time through
+        if self.first_time {
+            self.previous_tuple = self.input.next()?;
+            self.first_time = false;
+        }
+
+        // At this moment, previous_tuple is the first
+        // tuple of the next group, or None if input is done
+        let label = match self.previous_tuple.take() {
+            Some(label) => label,
+            None => return Ok(None),
+        };
+        let mut accs = new_accumulators(&self.aggregates);
+        update_accumulators(&mut accs, &self.aggregates, &label)?;
+
+        while let Some(tuple) = self.input.next()? {
+            if !self.same_group(&tuple, &label) {
+                // Group is done, hold on to the first tuple of the
+                // next group and send back the agg
+                self.previous_tuple = Some(tuple);
+                break;
+            }
+            update_accumulators(&mut accs, &self.aggregates, &tuple)?;
+        }
+
+        let labels = self.group_by.iter().map(|&col| {
+            if label.is_null(col) {
+                None
             } else {
-                return self.aggregate_group();
+                Some(label[col].to_vec())
             }
+        });
+        let results = finish_accumulators(&accs)?;
+        Ok(Some(Tuple::from_nullable(labels.chain(results).collect())))
+    }
+}
+
+pub(crate) fn new_accumulators(aggregates: &[TypedAggregate]) -> Vec<Accumulator> {
+    aggregates.iter()
+        .map(|aggregate| Accumulator::new(&aggregate.aggregation, &aggregate.arg_type))
+        .collect()
+}
+
+pub(crate) fn update_accumulators(
+    accs: &mut [Accumulator],
+    aggregates: &[TypedAggregate],
+    tuple: &Tuple,
+    ) -> Result<()>
+{
+    // Nulls are skipped, as in SQL, so e.g. Count counts the non null fields
+    for (acc, aggregate) in accs.iter_mut().zip(aggregates) {
+        if let Some(field) = aggregate.arg_field(tuple)? {
+            acc.update(&field)?;
         }
     }
+    Ok(())
+}
 
-    fn reset(&mut self) {
-        self.input.reset();
+pub(crate) fn finish_accumulators(accs: &[Accumulator]) -> Result<Vec<Option<Vec<u8>>>> {
+    accs.iter().map(|acc| acc.finish()).collect()
+}
+
+fn overflow(aggregation: AggregateType, result_type: DataType) -> Error {
+    ErrorKind::AggregateOverflow(
+        format!("{:?}", aggregation),
+        format!("{:?}", result_type),
+    ).into()
+}
+
+// Running sum, integers are widened to i64 and floats to f64. Decimals
+// sum their digits, at the scale of the column.
+#[derive(Debug, Clone)]
+pub(crate) enum Sum {
+    Int(i64),
+    Float(f64),
+    Decimal(i128),
+}
+
+impl Sum {
+    fn new(data_type: &DataType) -> Self {
+        match *data_type {
+            DataType::SmallInt | DataType::Integer | DataType::BigInt => Sum::Int(0),
+            DataType::Float | DataType::Double => Sum::Float(0.),
+            DataType::Decimal(..) => Sum::Decimal(0),
+            // TypedAggregate only sums numbers
+            _ => unreachable!(),
+        }
+    }
+
+    fn update(&mut self, field: &[u8], data_type: &DataType) -> Result<()> {
+        match *self {
+            Sum::Int(ref mut sum) => {
+                *sum = sum.checked_add(field_to_i64(field, data_type)?)
+                    .ok_or_else(|| overflow(AggregateType::Sum, DataType::BigInt))?;
+            },
+            Sum::Float(ref mut sum) => {
+                let x = field_to_f64(field, data_type)?;
+                if sum.is_finite() && x.is_finite() && !(*sum + x).is_finite() {
+                    return Err(overflow(AggregateType::Sum, DataType::Double));
+                }
+                *sum += x;
+            },
+            Sum::Decimal(ref mut sum) => {
+                let result_type = AggregateType::Sum.result_type(data_type);
+                *sum = sum.checked_add(field_parse::<i128>(field)?)
+                    .filter(|&sum| decimal::fits(sum, decimal::MAX_PRECISION))
+                    .ok_or_else(|| overflow(AggregateType::Sum, result_type))?;
+            },
+        }
+        Ok(())
+    }
+
+    fn to_f64(&self) -> f64 {
+        match *self {
+            Sum::Int(sum) => sum as f64,
+            Sum::Float(sum) => sum,
+            Sum::Decimal(_) => panic!("internal bug, Decimal sums are exact"),
+        }
     }
 }
 
-impl<I: DbIterator> Aggregate<I> {
-    fn aggregate_all(&mut self) -> Tuple {
+fn field_to_i64(field: &[u8], data_type: &DataType) -> Result<i64> {
+    match *data_type {
+        DataType::SmallInt => Ok(field_parse::<i16>(field)? as i64),
+        DataType::Integer => Ok(field_parse::<i32>(field)? as i64),
+        DataType::BigInt => field_parse::<i64>(field),
+        _ => panic!("internal bug, not an integer type"),
+    }
+}
+
+fn field_to_f64(field: &[u8], data_type: &DataType) -> Result<f64> {
+    match *data_type {
+        DataType::SmallInt => Ok(field_parse::<i16>(field)? as f64),
+        DataType::Integer => Ok(field_parse::<i32>(field)? as f64),
+        DataType::BigInt => Ok(field_parse::<i64>(field)? as f64),
+        DataType::Float => Ok(field_parse::<f32>(field)? as f64),
+        DataType::Double => field_parse::<f64>(field),
+        DataType::Decimal(_, scale) => Ok(decimal::to_f64(field_parse(field)?, scale)),
+        _ => unreachable!(),
+    }
+}
+
+// Encodes a widened result into the aggregate's result type
+fn to_int_result_field(value: i64, aggregation: AggregateType, result_type: DataType) -> Result<Vec<u8>> {
+    match result_type {
+        DataType::Integer => {
+            let res = i32::try_from(value).map_err(|_| overflow(aggregation, result_type))?;
+            Ok(res.to_tuple_field())
+        },
+        DataType::BigInt => Ok(value.to_tuple_field()),
+        _ => panic!("internal bug, aggregate result is not an integer"),
+    }
+}
+
+fn is_number(data_type: &DataType) -> bool {
+    use DataType::*;
+    matches!(*data_type, SmallInt | Integer | BigInt | Float | Double | Decimal(..))
+}
+
+// Running state of an aggregation over one group (or all) of the input.
+// Fields which don't parse as the aggregated type (e.g. from a corrupt
+// file) are an error.
+//
+// Min, Max, First and Last keep the field bytes, so they work on any type
+// and return the type of the aggregated column. The others only take the
+// numeric types which TypedAggregate allows them.
+#[derive(Debug, Clone)]
+pub(crate) enum Accumulator {
+    Count(u64),
+    Sum(DataType, Sum),
+    Avg(DataType, Sum, u64),
+    Min(DataType, Option<Vec<u8>>),
+    Max(DataType, Option<Vec<u8>>),
+    CountDistinct(HashSet<Vec<u8>>),
+    // Welford's online algorithm
+    Variance { data_type: DataType, count: u64, mean: f64, m2: f64, stddev: bool },
+    First(Option<Vec<u8>>),
+    Last(Option<Vec<u8>>),
+}
+
+impl Accumulator {
+    pub(crate) fn new(aggregation: &AggregateType, data_type: &DataType) -> Self {
         use self::AggregateType::*;
-        match self.aggregation {
-            Count => {
-                let mut count = 0u32;
-                while let Some(_) = self.input.next() {
-                    count += 1;
+        match *aggregation {
+            Count => Accumulator::Count(0),
+            Sum => Accumulator::Sum(data_type.clone(), self::Sum::new(data_type)),
+            Avg => Accumulator::Avg(data_type.clone(), self::Sum::new(data_type), 0),
+            Min => Accumulator::Min(data_type.clone(), None),
+            Max => Accumulator::Max(data_type.clone(), None),
+            CountDistinct => Accumulator::CountDistinct(HashSet::new()),
+            Variance | Stddev => Accumulator::Variance {
+                data_type: data_type.clone(),
+                count: 0,
+                mean: 0.,
+                m2: 0.,
+                stddev: *aggregation == Stddev,
+            },
+            First => Accumulator::First(None),
+            Last => Accumulator::Last(None),
+        }
+    }
+
+    pub(crate) fn update(&mut self, field: &[u8]) -> Result<()> {
+        match *self {
+            Accumulator::Count(ref mut count) => *count += 1,
+            Accumulator::Sum(ref data_type, ref mut sum) => sum.update(field, data_type)?,
+            Accumulator::Avg(ref data_type, ref mut sum, ref mut count) => {
+                sum.update(field, data_type)?;
+                *count += 1;
+            },
+            Accumulator::Min(ref data_type, ref mut min) => {
+                let is_min = match *min {
+                    Some(ref min) => cmp_with_type(field, min, data_type)? == Ordering::Less,
+                    None => true,
+                };
+                if is_min {
+                    *min = Some(field.to_vec());
                 }
-                Tuple::new(vec![count.to_tuple_field()])
-            }
-            Sum => {
-                use self::DataType::*;
-                match self.agg_col_type {
-                    SmallInt => {
-                        let mut sum = 0u16;
-                        while let Some(tuple) = self.input.next() {
-                            sum += tuple.get_parse::<u16>(self.agg_col)
-                                .expect("internal bug on bad parse of field");
-                        }
-                        Tuple::new(vec![sum.to_tuple_field()])
-                    },
-                    Integer => {
-                        let mut sum = 0u32;
-                        while let Some(tuple) = self.input.next() {
-                            sum += tuple.get_parse::<u32>(self.agg_col)
-                                .expect("internal bug on bad parse of field");
-                        }
-                        Tuple::new(vec![sum.to_tuple_field()])
-                    },
-                    Float => {
-                        let mut sum = 0f32;
-                        while let Some(tuple) = self.input.next() {
-                            sum += tuple.get_parse::<f32>(self.agg_col)
-                                .expect("internal bug on bad parse of field");
-                        }
-                        Tuple::new(vec![sum.to_tuple_field()])
-                    },
-                    _ => {
-                        panic!("No aggregation for Text");
-                    },
+            },
+            Accumulator::Max(ref data_type, ref mut max) => {
+                let is_max = match *max {
+                    Some(ref max) => cmp_with_type(field, max, data_type)? == Ordering::Greater,
+                    None => true,
+                };
+                if is_max {
+                    *max = Some(field.to_vec());
                 }
             },
-            Avg => {
-                use self::DataType::*;
-                match self.agg_col_type {
-                    SmallInt => {
-                        let mut sum = 0u16;
-                        let mut count = 0u32;
-                        while let Some(tuple) = self.input.next() {
-                            sum += tuple.get_parse::<u16>(self.agg_col)
-                                .expect("internal bug on bad parse of field");
-                            count += 1;
-                        }
-                        let res = sum as f32 / count as f32;
-                        Tuple::new(vec![res.to_tuple_field()])
-                    },
-                    Integer => {
-                        let mut sum = 0u32;
-                        let mut count = 0u32;
-                        while let Some(tuple) = self.input.next() {
-                            sum += tuple.get_parse::<u32>(self.agg_col)
-                                .expect("internal bug on bad parse of field");
-                            count += 1;
-                        }
-                        let res = sum as f32 / count as f32;
-                        Tuple::new(vec![res.to_tuple_field()])
-                    },
-                    Float => {
-                        let mut sum = 0f32;
-                        let mut count = 0u32;
-                        while let Some(tuple) = self.input.next() {
-                            sum += tuple.get_parse::<f32>(self.agg_col)
-                                .expect("internal bug on bad parse of field");
-                            count += 1;
-                        }
-                        let res = sum / count as f32;
-                        Tuple::new(vec![res.to_tuple_field()])
-                    },
-                    _ => {
-                        panic!("No aggregation for Text");
-                    },
+            Accumulator::CountDistinct(ref mut seen) => {
+                if !seen.contains(field) {
+                    seen.insert(field.to_vec());
+                }
+            },
+            Accumulator::Variance { ref data_type, ref mut count, ref mut mean, ref mut m2, .. } => {
+                let x = field_to_f64(field, data_type)?;
+                *count += 1;
+                let delta = x - *mean;
+                *mean += delta / *count as f64;
+                *m2 += delta * (x - *mean);
+            },
+            Accumulator::First(ref mut first) => {
+                if first.is_none() {
+                    *first = Some(field.to_vec());
                 }
             },
+            Accumulator::Last(ref mut last) => *last = Some(field.to_vec()),
         }
+        Ok(())
     }
 
-    fn aggregate_group(&mut self) -> Option<Tuple> {
+    // None is a null result, e.g. for the Min of no tuples
+    pub(crate) fn finish(&self) -> Result<Option<Vec<u8>>> {
         use self::AggregateType::*;
-        match self.aggregation {
-            Count => {
-                let mut count = 0u32;
-
-                // initialize the first time through
-                if self.first_time {
-                    self.previous_tuple = self.input.next();
-                    self.first_time = false;
+        match *self {
+            Accumulator::Count(count) => {
+                to_int_result_field(count as i64, Count, DataType::Integer).map(Some)
+            },
+            Accumulator::Sum(ref data_type, ref sum) => {
+                let result_type = Sum.result_type(data_type);
+                match *sum {
+                    self::Sum::Int(sum) => to_int_result_field(sum, Sum, result_type).map(Some),
+                    self::Sum::Float(sum) => Ok(Some(sum.to_tuple_field())),
+                    self::Sum::Decimal(sum) => Ok(Some(sum.to_tuple_field())),
                 }
-                if self.previous_tuple.is_some() {
-                    count += 1;
+            },
+            Accumulator::Avg(ref data_type, ref sum, count) => {
+                if count == 0 {
+                    return Ok(None);
                 }
-
-                while let Some(tuple) = self.input.next() {
-                    // previous tuple is Some, because of check from above,
-                    // so it's ok to unwrap.
-                    // Group is done, send back the agg
-                    // At this moment, previous_tuple is the first
-                    // tuple of the next group
-                    if tuple[self.group_by.unwrap()] != self.previous_tuple.as_ref().unwrap()[self.group_by.unwrap()] {
-                        let label = self.previous_tuple.clone().unwrap();
-                        self.previous_tuple = Some(tuple);
-                        return Some(Tuple::new(vec![
-                            label[self.group_by.unwrap()].to_vec(),
-                            count.to_tuple_field(),
-                        ]));
-                    } else {
-                        count += 1;
-                    }
+                match (sum, Avg.result_type(data_type)) {
+                    (&self::Sum::Decimal(sum), DataType::Decimal(precision, scale)) => {
+                        let input_scale = match *data_type {
+                            DataType::Decimal(_, scale) => scale,
+                            _ => unreachable!(),
+                        };
+                        let avg = decimal::rescale(sum, input_scale, scale)
+                            .and_then(|sum| decimal::div_round(sum, i128::from(count)))
+                            .filter(|&avg| decimal::fits(avg, precision))
+                            .ok_or_else(|| overflow(Avg, DataType::Decimal(precision, scale)))?;
+                        Ok(Some(avg.to_tuple_field()))
+                    },
+                    _ => Ok(Some((sum.to_f64() / count as f64).to_tuple_field())),
                 }
-                None
-            }
-            // TODO implement group by for below this line.
-            // Use macros?
-//            Sum => {
-//                use DataType::*;
-//                match self.agg_col_type {
-//                    SmallInt => {
-//                        let mut sum = 0u16;
-//                        while let Some(tuple) = self.input.next() {
-//                            sum += tuple.get_parse(self.agg_col)
-//                                .expect("internal bug on bad parse of field");
-//                        }
-//                        Tuple::new(vec![sum.to_tuple_field()])
-//                    },
-//                    Integer => {
-//                        let mut sum = 0u32;
-//                        while let Some(tuple) = self.input.next() {
-//                            sum += tuple.get_parse(self.agg_col)
-//                                .expect("internal bug on bad parse of field");
-//                        }
-//                        Tuple::new(vec![sum.to_tuple_field()])
-//                    },
-//                    Float => {
-//                        let mut sum = 0f32;
-//                        while let Some(tuple) = self.input.next() {
-//                            sum += tuple.get_parse(self.agg_col)
-//                                .expect("internal bug on bad parse of field");
-//                        }
-//                        Tuple::new(vec![sum.to_tuple_field()])
-//                    },
-//                    _ => {
-//                        panic!("No aggregation for Text");
-//                    },
-//                }
-//            },
-//            Avg => {
-//                use DataType::*;
-//                match self.agg_col_type {
-//                    SmallInt => {
-//                        let mut sum = 0u16;
-//                        let mut count = 0u32;
-//                        while let Some(tuple) = self.input.next() {
-//                            sum += tuple.get_parse(self.agg_col)
-//                                .expect("internal bug on bad parse of field");
-//                            count += 1;
-//                        }
-//                        let res = sum as f32 / count as f32;
-//                        Tuple::new(vec![res.to_tuple_field()])
-//                    },
-//                    Integer => {
-//                        let mut sum = 0u32;
-//                        let mut count = 0u32;
-//                        while let Some(tuple) = self.input.next() {
-//                            sum += tuple.get_parse(self.agg_col)
-//                                .expect("internal bug on bad parse of field");
-//                            count += 1;
-//                        }
-//                        let res = sum as f32 / count as f32;
-//                        Tuple::new(vec![res.to_tuple_field()])
-//                    },
-//                    Float => {
-//                        let mut sum = 0f32;
-//                        let mut count = 0u32;
-//                        while let Some(tuple) = self.input.next() {
-//                            sum += tuple.get_parse(self.agg_col)
-//                                .expect("internal bug on bad parse of field");
-//                            count += 1;
-//                        }
-//                        let res = sum / count as f32;
-//                        Tuple::new(vec![res.to_tuple_field()])
-//                    },
-//                    _ => {
-//                        panic!("No aggregation for Text");
-//                    },
-//                }
-//            },
-                _ => panic!("not yet implemented"),
+            },
+            Accumulator::Min(_, ref value) |
+            Accumulator::Max(_, ref value) |
+            Accumulator::First(ref value) |
+            Accumulator::Last(ref value) => Ok(value.clone()),
+            Accumulator::CountDistinct(ref seen) => {
+                to_int_result_field(seen.len() as i64, CountDistinct, DataType::Integer).map(Some)
+            },
+            Accumulator::Variance { count, m2, stddev, .. } => {
+                if count < 2 {
+                    return Ok(None);
+                }
+                let variance = m2 / (count - 1) as f64;
+                let res = if stddev { variance.sqrt() } else { variance };
+                Ok(Some(res.to_tuple_field()))
+            },
         }
     }
 }
-
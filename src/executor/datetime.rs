@@ -0,0 +1,248 @@
+// Calendar math for Date and Timestamp fields. A Date is stored as the
+// days (i32) and a Timestamp as the microseconds (i64) since the unix
+// epoch, 1970-01-01 00:00:00 UTC, in the proleptic Gregorian calendar.
+//
+// Text forms are ISO-8601: dates are YYYY-MM-DD, and timestamps are a date
+// followed by 'T' or a space and HH:MM[:SS[.fraction]], with an optional
+// 'Z' or +HH:MM offset, which is converted to UTC. A timestamp without a
+// time is midnight. Timestamps display as YYYY-MM-DD HH:MM:SS[.fraction],
+// in UTC.
+use error::*;
+
+pub const MICROS_PER_SECOND: i64 = 1_000_000;
+pub const MICROS_PER_DAY: i64 = 86_400 * MICROS_PER_SECOND;
+
+// Days from 1970-01-01 to the date, from Howard Hinnant's
+// days_from_civil. month and day must already be valid.
+pub fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let month = i64::from(month);
+    let day_of_year = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + i64::from(day) - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era * 146_097 + day_of_era - 719_468
+}
+
+/// (year, month, day) of days since 1970-01-01.
+pub fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+fn days_in_month(year: i64, month: u32) -> u32 {
+    match month {
+        2 if year % 4 == 0 && (year % 100 != 0 || year % 400 == 0) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+/// (hour, minute, second, microsecond) of a timestamp, in UTC.
+pub fn time_of_day(micros: i64) -> (u32, u32, u32, u32) {
+    let micros = micros.rem_euclid(MICROS_PER_DAY);
+    let seconds = micros / MICROS_PER_SECOND;
+    (
+        (seconds / 3600) as u32,
+        (seconds / 60 % 60) as u32,
+        (seconds % 60) as u32,
+        (micros % MICROS_PER_SECOND) as u32,
+    )
+}
+
+/// Days since the epoch of the timestamp's date, rounding down.
+pub fn timestamp_to_date(micros: i64) -> i32 {
+    micros.div_euclid(MICROS_PER_DAY) as i32
+}
+
+pub fn date_to_timestamp(days: i32) -> i64 {
+    i64::from(days) * MICROS_PER_DAY
+}
+
+pub fn parse_date(s: &str) -> Result<i32> {
+    let mut parser = DateTimeParser { s: s.trim().as_bytes(), idx: 0 };
+    let days = parser.date().ok_or_else(|| parse_error(s, "Date"))?;
+    if !parser.done() {
+        bail!(parse_error(s, "Date"));
+    }
+    Ok(days as i32)
+}
+
+pub fn parse_timestamp(s: &str) -> Result<i64> {
+    let mut parser = DateTimeParser { s: s.trim().as_bytes(), idx: 0 };
+    parser.timestamp()
+        .filter(|_| parser.done())
+        .ok_or_else(|| parse_error(s, "Timestamp"))
+}
+
+pub fn format_date(days: i32) -> String {
+    let (year, month, day) = civil_from_days(i64::from(days));
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
+
+pub fn format_timestamp(micros: i64) -> String {
+    let date = format_date(timestamp_to_date(micros));
+    let (hour, minute, second, micro) = time_of_day(micros);
+    let mut s = format!("{} {:02}:{:02}:{:02}", date, hour, minute, second);
+    if micro > 0 {
+        let fraction = format!("{:06}", micro);
+        s.push('.');
+        s.push_str(fraction.trim_end_matches('0'));
+    }
+    s
+}
+
+fn parse_error(s: &str, data_type: &str) -> Error {
+    ErrorKind::ParseValue(s.to_owned(), data_type.to_owned()).into()
+}
+
+// Each step returns None if the text doesn't match
+struct DateTimeParser<'a> {
+    s: &'a [u8],
+    idx: usize,
+}
+
+impl<'a> DateTimeParser<'a> {
+    fn done(&self) -> bool {
+        self.idx == self.s.len()
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.s.get(self.idx).cloned()
+    }
+
+    fn consume(&mut self, c: u8) -> bool {
+        if self.peek() == Some(c) {
+            self.idx += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    // Exactly len digits
+    fn digits(&mut self, len: usize) -> Option<u32> {
+        let digits = self.s.get(self.idx..self.idx + len)?;
+        if !digits.iter().all(u8::is_ascii_digit) {
+            return None;
+        }
+        self.idx += len;
+        Some(digits.iter().fold(0, |n, &d| n * 10 + u32::from(d - b'0')))
+    }
+
+    fn date(&mut self) -> Option<i64> {
+        let year = i64::from(self.digits(4)?);
+        if !self.consume(b'-') {
+            return None;
+        }
+        let month = self.digits(2)?;
+        if !self.consume(b'-') {
+            return None;
+        }
+        let day = self.digits(2)?;
+        if !(1..=12).contains(&month) || day < 1 || day > days_in_month(year, month) {
+            return None;
+        }
+        Some(days_from_civil(year, month, day))
+    }
+
+    fn timestamp(&mut self) -> Option<i64> {
+        let days = self.date()?;
+        let mut micros = days * MICROS_PER_DAY;
+        if self.done() {
+            return Some(micros);
+        }
+        if !self.consume(b'T') && !self.consume(b' ') {
+            return None;
+        }
+
+        let hour = self.digits(2)?;
+        if !self.consume(b':') {
+            return None;
+        }
+        let minute = self.digits(2)?;
+        let second = if self.consume(b':') { self.digits(2)? } else { 0 };
+        if hour > 23 || minute > 59 || second > 59 {
+            return None;
+        }
+        micros += i64::from(hour * 3600 + minute * 60 + second) * MICROS_PER_SECOND;
+
+        if self.consume(b'.') {
+            // digits past microseconds are truncated
+            let start = self.idx;
+            let mut fraction = 0;
+            while let Some(digit) = self.digits(1) {
+                if self.idx - start <= 6 {
+                    fraction = fraction * 10 + i64::from(digit);
+                }
+            }
+            let len = self.idx - start;
+            if len == 0 {
+                return None;
+            }
+            micros += fraction * 10i64.pow(6 - len.min(6) as u32);
+        }
+
+        if self.consume(b'Z') {
+            return Some(micros);
+        }
+        let sign = match self.peek() {
+            Some(b'+') => 1,
+            Some(b'-') => -1,
+            _ => return Some(micros),
+        };
+        self.idx += 1;
+        let offset_hour = self.digits(2)?;
+        self.consume(b':');
+        let offset_minute = self.digits(2)?;
+        if offset_hour > 23 || offset_minute > 59 {
+            return None;
+        }
+        let offset = i64::from(offset_hour * 3600 + offset_minute * 60) * MICROS_PER_SECOND;
+        Some(micros - sign * offset)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(parse_date("1970-01-01").unwrap(), 0);
+        assert_eq!(parse_date("1969-12-31").unwrap(), -1);
+        assert_eq!(parse_date("2000-03-01").unwrap(), 11_017);
+        assert_eq!(format_date(11_016), "2000-02-29");
+        assert!(parse_date("1999-02-29").is_err());
+        assert!(parse_date("2000-1-01").is_err());
+        assert!(parse_date("2000-01-01x").is_err());
+
+        let ts = parse_timestamp("2017-03-04T05:06:07.25Z").unwrap();
+        assert_eq!(format_timestamp(ts), "2017-03-04 05:06:07.25");
+        assert_eq!(parse_timestamp("2017-03-04 07:06:07.250+02:00").unwrap(), ts);
+        assert_eq!(parse_timestamp("2017-03-04 05:06").unwrap(), ts - 7_250_000);
+        assert_eq!(parse_timestamp("1970-01-02").unwrap(), MICROS_PER_DAY);
+        assert!(parse_timestamp("2017-03-04 24:00:00").is_err());
+        assert!(parse_timestamp("2017-03-04T05").is_err());
+
+        // before the epoch, time of day still counts up from midnight
+        let ts = parse_timestamp("1969-12-31 23:59:59.999999").unwrap();
+        assert_eq!(ts, -1);
+        assert_eq!(timestamp_to_date(ts), -1);
+        assert_eq!(format_timestamp(ts), "1969-12-31 23:59:59.999999");
+
+        for &days in &[-719_468, -1, 0, 59, 10_957, 2_932_896] {
+            let (year, month, day) = civil_from_days(days);
+            assert_eq!(days_from_civil(year, month, day), days);
+        }
+    }
+}
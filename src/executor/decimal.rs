@@ -0,0 +1,177 @@
+// Fixed point math for Decimal(precision, scale) fields. A value is stored
+// as an i128 of its digits, unscaled, so 12.50 in a Decimal(5, 2) is 1250.
+// Any precision fits in the 16 bytes of an i128, up to 38 digits, and the
+// scale (digits after the point) is at most the precision.
+//
+// Dropped digits are rounded half away from zero, and values with more
+// digits than their precision are errors instead of being truncated.
+use std::cmp::Ordering;
+
+use error::*;
+
+pub const MAX_PRECISION: u8 = 38;
+
+/// Whether 1 <= precision <= 38 and scale <= precision.
+pub fn is_valid_type(precision: u8, scale: u8) -> bool {
+    (1..=MAX_PRECISION).contains(&precision) && scale <= precision
+}
+
+/// Whether value has at most precision digits.
+pub fn fits(value: i128, precision: u8) -> bool {
+    value.unsigned_abs() < 10u128.pow(u32::from(precision))
+}
+
+/// value at from_scale moved to to_scale, rounding if digits are dropped.
+/// None if it overflows.
+pub fn rescale(value: i128, from_scale: u8, to_scale: u8) -> Option<i128> {
+    if to_scale >= from_scale {
+        value.checked_mul(10i128.checked_pow(u32::from(to_scale - from_scale))?)
+    } else {
+        div_round(value, 10i128.checked_pow(u32::from(from_scale - to_scale))?)
+    }
+}
+
+/// x / y, rounded. None if y is 0 or it overflows.
+pub fn div_round(x: i128, y: i128) -> Option<i128> {
+    let quotient = x.checked_div(y)?;
+    let remainder = x % y;
+    // round away from zero when the remainder is at least half of y
+    if remainder != 0 && remainder.unsigned_abs() >= y.unsigned_abs() - remainder.unsigned_abs() {
+        let away = if (x < 0) == (y < 0) { 1 } else { -1 };
+        quotient.checked_add(away)
+    } else {
+        Some(quotient)
+    }
+}
+
+/// Compares values at different scales.
+pub fn cmp(x: i128, x_scale: u8, y: i128, y_scale: u8) -> Ordering {
+    let scale = x_scale.max(y_scale);
+    match (rescale(x, x_scale, scale), rescale(y, y_scale, scale)) {
+        (Some(x), Some(y)) => x.cmp(&y),
+        // only scaling up can overflow, and then that side is the larger
+        // in magnitude, so its sign decides
+        (None, _) => if x < 0 { Ordering::Less } else { Ordering::Greater },
+        (_, None) => if y < 0 { Ordering::Greater } else { Ordering::Less },
+    }
+}
+
+pub fn to_f64(value: i128, scale: u8) -> f64 {
+    value as f64 / 10f64.powi(i32::from(scale))
+}
+
+/// x at scale, rounded, if it's finite and fits an i128.
+pub fn from_f64(x: f64, scale: u8) -> Option<i128> {
+    let value = (x * 10f64.powi(i32::from(scale))).round();
+    if value.is_finite() && value.abs() < i128::MAX as f64 {
+        Some(value as i128)
+    } else {
+        None
+    }
+}
+
+/// [+-]digits[.digits], with extra digits after the point rounded away.
+pub fn parse_decimal(s: &str, precision: u8, scale: u8) -> Result<i128> {
+    let parse_error = || -> Error {
+        ErrorKind::ParseValue(s.to_owned(), format!("Decimal({}, {})", precision, scale)).into()
+    };
+
+    let trimmed = s.trim();
+    let (negative, unsigned) = match trimmed.as_bytes().first() {
+        Some(b'-') => (true, &trimmed[1..]),
+        Some(b'+') => (false, &trimmed[1..]),
+        _ => (false, trimmed),
+    };
+    let (int_digits, frac_digits) = match unsigned.find('.') {
+        Some(i) => (&unsigned[..i], &unsigned[i + 1..]),
+        None => (unsigned, ""),
+    };
+    let is_digits = |digits: &str| digits.bytes().all(|b| b.is_ascii_digit());
+    if int_digits.is_empty() && frac_digits.is_empty() || !is_digits(int_digits) || !is_digits(frac_digits) {
+        return Err(parse_error());
+    }
+
+    // digits past the scale only matter for rounding, so keep one more
+    let kept = &frac_digits[..frac_digits.len().min(usize::from(scale) + 1)];
+    let mut value: i128 = 0;
+    for b in int_digits.bytes().chain(kept.bytes()) {
+        value = value.checked_mul(10)
+            .and_then(|value| value.checked_add(i128::from(b - b'0')))
+            .ok_or_else(parse_error)?;
+    }
+    let value = if negative { -value } else { value };
+    let value = rescale(value, kept.len() as u8, scale).ok_or_else(parse_error)?;
+    if !fits(value, precision) {
+        return Err(parse_error());
+    }
+    Ok(value)
+}
+
+/// With exactly scale digits after the point, e.g. 12.50 for Decimal(5, 2).
+pub fn format_decimal(value: i128, scale: u8) -> String {
+    let digits = value.unsigned_abs().to_string();
+    let scale = usize::from(scale);
+    let digits = if digits.len() <= scale {
+        format!("{}{}", "0".repeat(scale + 1 - digits.len()), digits)
+    } else {
+        digits
+    };
+    let sign = if value < 0 { "-" } else { "" };
+    let (int_part, frac_part) = digits.split_at(digits.len() - scale);
+    if frac_part.is_empty() {
+        format!("{}{}", sign, int_part)
+    } else {
+        format!("{}{}.{}", sign, int_part, frac_part)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_format() {
+        assert_eq!(parse_decimal("12.5", 5, 2).unwrap(), 1250);
+        assert_eq!(parse_decimal(" -0.125 ", 5, 2).unwrap(), -13);
+        assert_eq!(parse_decimal("+.994", 5, 2).unwrap(), 99);
+        assert_eq!(parse_decimal("7", 1, 0).unwrap(), 7);
+        assert_eq!(format_decimal(1250, 2), "12.50");
+        assert_eq!(format_decimal(-13, 2), "-0.13");
+        assert_eq!(format_decimal(5, 3), "0.005");
+        assert_eq!(format_decimal(-42, 0), "-42");
+
+        // too many digits, including after rounding
+        assert!(parse_decimal("1000", 5, 2).is_err());
+        assert!(parse_decimal("999.995", 5, 2).is_err());
+        assert!(parse_decimal("1e3", 5, 2).is_err());
+        assert!(parse_decimal("1.2.3", 5, 2).is_err());
+        assert!(parse_decimal(".", 5, 2).is_err());
+        assert!(parse_decimal("", 5, 2).is_err());
+
+        let max = "9".repeat(38);
+        assert_eq!(format_decimal(parse_decimal(&max, 38, 0).unwrap(), 0), max);
+        assert!(parse_decimal(&format!("{}9", max), 38, 0).is_err());
+        assert!(is_valid_type(38, 38));
+        assert!(!is_valid_type(39, 0));
+        assert!(!is_valid_type(5, 6));
+    }
+
+    #[test]
+    fn test_rescale_cmp() {
+        assert_eq!(rescale(1250, 2, 4), Some(125_000));
+        assert_eq!(rescale(1250, 2, 0), Some(13));
+        assert_eq!(rescale(-1249, 2, 1), Some(-125));
+        assert_eq!(rescale(i128::MAX, 0, 1), None);
+        assert_eq!(div_round(7, 2), Some(4));
+        assert_eq!(div_round(-7, 2), Some(-4));
+        assert_eq!(div_round(5, -3), Some(-2));
+        assert_eq!(div_round(1, 0), None);
+
+        assert_eq!(cmp(1250, 2, 125, 1), Ordering::Equal);
+        assert_eq!(cmp(-1, 0, 5, 3), Ordering::Less);
+        assert_eq!(cmp(i128::MAX, 0, 1, 10), Ordering::Greater);
+        assert_eq!(cmp(1, 10, i128::MIN, 0), Ordering::Greater);
+        assert!((to_f64(1250, 2) - 12.5).abs() < 1e-12);
+        assert_eq!(from_f64(0.125, 2), Some(13));
+    }
+}
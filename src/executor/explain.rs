@@ -0,0 +1,178 @@
+// EXPLAIN for executor pipelines. Each node describes itself, its
+// parameters and its inputs as an Explain tree, which displays as one
+// node per line, with the inputs of a node indented below it:
+//
+//   HashJoin (join_type: Inner, col_l: 1, col_r: 0)
+//     Selection (predicate: #0 = 3)
+//       DiskScan (columns: 3 of 3)
+//     DiskScan (columns: 2 of 3)
+//
+// For EXPLAIN ANALYZE, nodes are wrapped in Analyze, which counts the
+// tuples a node returns and the calls to it while the pipeline runs, and
+// adds them to the node's line. Elapsed time includes the time spent in
+// the node's inputs, so the time of a node by itself is its elapsed time
+// minus its inputs'.
+use std::any::type_name;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use error::*;
+use super::DbIterator;
+use super::aggregate::TypedAggregate;
+use super::simplesort::{SortKey, SortOrder};
+use super::tuple::Tuple;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Explain {
+    pub name: String,
+    pub params: Vec<(String, String)>,
+    pub inputs: Vec<Explain>,
+    pub stats: Option<NodeStats>, // only from Analyze
+}
+
+impl Explain {
+    pub fn new<S: Into<String>>(name: S) -> Self {
+        Explain {
+            name: name.into(),
+            params: Vec::new(),
+            inputs: Vec::new(),
+            stats: None,
+        }
+    }
+
+    /// Node named after the type of T, without its path or generics.
+    pub fn from_type<T: ?Sized>() -> Self {
+        let name = type_name::<T>();
+        let name = name.split('<').next().unwrap_or(name);
+        Explain::new(name.rsplit("::").next().unwrap_or(name))
+    }
+
+    pub fn param<V: fmt::Display>(mut self, name: &str, value: V) -> Self {
+        self.params.push((name.to_owned(), value.to_string()));
+        self
+    }
+
+    pub fn input(mut self, input: Explain) -> Self {
+        self.inputs.push(input);
+        self
+    }
+
+    fn fmt_indented(&self, f: &mut fmt::Formatter, depth: usize) -> fmt::Result {
+        write!(f, "{:width$}{}", "", self.name, width = depth * 2)?;
+        if !self.params.is_empty() {
+            let params: Vec<String> = self.params.iter()
+                .map(|(name, value)| format!("{}: {}", name, value))
+                .collect();
+            write!(f, " ({})", params.join(", "))?;
+        }
+        if let Some(ref stats) = self.stats {
+            write!(f, " [{}]", stats)?;
+        }
+        writeln!(f)?;
+        for input in &self.inputs {
+            input.fmt_indented(f, depth + 1)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Explain {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_indented(f, 0)
+    }
+}
+
+/// What one node did while its pipeline ran.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct NodeStats {
+    pub tuples: u64, // returned by next
+    pub next_calls: u64,
+    pub reset_calls: u64,
+    pub elapsed: Duration, // in next and reset, including inputs
+}
+
+impl fmt::Display for NodeStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "tuples: {}, next: {}, reset: {}, time: {:.3}ms",
+            self.tuples,
+            self.next_calls,
+            self.reset_calls,
+            self.elapsed.as_secs_f64() * 1000.,
+        )
+    }
+}
+
+/// Passes through the tuples of its input, recording NodeStats for it.
+#[derive(Debug, Clone)]
+pub struct Analyze<I> {
+    input: I,
+    stats: NodeStats,
+}
+
+impl<I> Analyze<I> {
+    pub fn new(input: I) -> Self {
+        Analyze {
+            input,
+            stats: NodeStats::default(),
+        }
+    }
+
+    pub fn stats(&self) -> &NodeStats {
+        &self.stats
+    }
+}
+
+impl<I: DbIterator> DbIterator for Analyze<I> {
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        let start = Instant::now();
+        let res = self.input.next();
+        self.stats.elapsed += start.elapsed();
+        self.stats.next_calls += 1;
+        if let Ok(Some(_)) = res {
+            self.stats.tuples += 1;
+        }
+        res
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        let start = Instant::now();
+        let res = self.input.reset();
+        self.stats.elapsed += start.elapsed();
+        self.stats.reset_calls += 1;
+        res
+    }
+
+    /// The input's node, with these stats.
+    fn explain(&self) -> Explain {
+        Explain {
+            stats: Some(self.stats.clone()),
+            ..self.input.explain()
+        }
+    }
+}
+
+// Formatting of node parameters
+
+pub(crate) fn list<T: fmt::Debug>(items: &[T]) -> String {
+    let items: Vec<String> = items.iter().map(|item| format!("{:?}", item)).collect();
+    format!("[{}]", items.join(", "))
+}
+
+pub(crate) fn sort_keys(sort_keys: &[SortKey]) -> String {
+    let keys: Vec<String> = sort_keys.iter().map(|&(col, _, ref order)| {
+        let order = if *order == SortOrder::Ascending { "ASC" } else { "DESC" };
+        format!("#{} {}", col, order)
+    }).collect();
+    keys.join(", ")
+}
+
+pub(crate) fn aggregates(aggregates: &[TypedAggregate]) -> String {
+    let aggregates: Vec<String> = aggregates.iter()
+        .map(|aggregate| {
+            format!("{}({})", format!("{:?}", aggregate.aggregation()).to_lowercase(), aggregate.arg())
+        })
+        .collect();
+    aggregates.join(", ")
+}
@@ -0,0 +1,1295 @@
+// Typed expressions over the fields of a tuple, e.g. for Selection
+// predicates, so that callers don't have to decode big-endian bytes.
+//
+// An Expr is type checked against the ColumnTypes of its input, giving a
+// TypedExpr which can then be evaluated against each tuple. Nulls follow
+// SQL's three-valued logic: most operators on a null give null, AND and OR
+// only give null when the other side can't decide the result, and a
+// predicate only passes a tuple when it is true.
+//
+// Integer arithmetic is checked, in i64 and then against the range of
+// the result type, so overflow is an error instead of wrapping. Decimal
+// arithmetic is exact, with ints as Decimals of scale 0, and its result
+// type has the digits needed for the result, up to 38.
+use std::cmp::Ordering;
+use std::convert::TryFrom;
+use std::fmt;
+
+use DataType;
+use error::*;
+use super::{datetime, decimal};
+use super::tuple::{Tuple, ToTupleField, field_parse, parse_bool, string_to_binary};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    And,
+    Or,
+}
+
+impl BinaryOp {
+    fn is_arithmetic(&self) -> bool {
+        use self::BinaryOp::*;
+        matches!(*self, Add | Subtract | Multiply | Divide)
+    }
+
+    fn is_comparison(&self) -> bool {
+        use self::BinaryOp::*;
+        matches!(*self, Eq | NotEq | Lt | LtEq | Gt | GtEq)
+    }
+
+    fn symbol(&self) -> &'static str {
+        use self::BinaryOp::*;
+        match *self {
+            Add => "+",
+            Subtract => "-",
+            Multiply => "*",
+            Divide => "/",
+            Eq => "=",
+            NotEq => "<>",
+            Lt => "<",
+            LtEq => "<=",
+            Gt => ">",
+            GtEq => ">=",
+            And => "AND",
+            Or => "OR",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Column(usize),
+    Literal(Value),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    IsNull(Box<Expr>),
+    Like(Box<Expr>, String), // % matches any run of chars, _ any one char
+    In(Box<Expr>, Vec<Expr>),
+    Case(Vec<(Expr, Expr)>, Option<Box<Expr>>), // (when, then), else
+    Cast(Box<Expr>, DataType),
+    Function(Function, Vec<Expr>),
+}
+
+impl Expr {
+    pub fn col(col: usize) -> Self {
+        Expr::Column(col)
+    }
+
+    pub fn lit<V: Into<Value>>(value: V) -> Self {
+        Expr::Literal(value.into())
+    }
+
+    pub fn binary(op: BinaryOp, left: Expr, right: Expr) -> Self {
+        Expr::Binary(op, Box::new(left), Box::new(right))
+    }
+
+    pub fn and(left: Expr, right: Expr) -> Self {
+        Expr::binary(BinaryOp::And, left, right)
+    }
+
+    pub fn or(left: Expr, right: Expr) -> Self {
+        Expr::binary(BinaryOp::Or, left, right)
+    }
+
+    pub fn negate(expr: Expr) -> Self {
+        Expr::Not(Box::new(expr))
+    }
+
+    pub fn is_null(expr: Expr) -> Self {
+        Expr::IsNull(Box::new(expr))
+    }
+
+    pub fn like(expr: Expr, pattern: &str) -> Self {
+        Expr::Like(Box::new(expr), pattern.to_owned())
+    }
+
+    pub fn in_list(expr: Expr, list: Vec<Expr>) -> Self {
+        Expr::In(Box::new(expr), list)
+    }
+
+    pub fn case(whens: Vec<(Expr, Expr)>, otherwise: Option<Expr>) -> Self {
+        Expr::Case(whens, otherwise.map(Box::new))
+    }
+
+    pub fn cast(expr: Expr, data_type: DataType) -> Self {
+        Expr::Cast(Box::new(expr), data_type)
+    }
+
+    pub fn call(function: Function, args: Vec<Expr>) -> Self {
+        Expr::Function(function, args)
+    }
+
+    pub fn children(&self) -> Vec<&Expr> {
+        match *self {
+            Expr::Column(_) | Expr::Literal(_) => vec![],
+            Expr::Binary(_, ref left, ref right) => vec![left, right],
+            Expr::Not(ref expr) |
+            Expr::IsNull(ref expr) |
+            Expr::Like(ref expr, _) |
+            Expr::Cast(ref expr, _) => vec![expr],
+            Expr::In(ref expr, ref list) => {
+                let mut children = vec![&**expr];
+                children.extend(list);
+                children
+            },
+            Expr::Case(ref whens, ref otherwise) => {
+                let mut children = Vec::new();
+                for (when, then) in whens {
+                    children.push(when);
+                    children.push(then);
+                }
+                children.extend(otherwise.as_deref());
+                children
+            },
+            Expr::Function(_, ref args) => args.iter().collect(),
+        }
+    }
+
+    /// Rebuilds the expression with f applied to each of its children.
+    pub fn map_children<F: FnMut(Expr) -> Expr>(self, mut f: F) -> Self {
+        match self {
+            Expr::Column(_) | Expr::Literal(_) => self,
+            Expr::Binary(op, left, right) => Expr::binary(op, f(*left), f(*right)),
+            Expr::Not(expr) => Expr::negate(f(*expr)),
+            Expr::IsNull(expr) => Expr::is_null(f(*expr)),
+            Expr::Like(expr, pattern) => Expr::Like(Box::new(f(*expr)), pattern),
+            Expr::Cast(expr, data_type) => Expr::cast(f(*expr), data_type),
+            Expr::In(expr, list) => {
+                let expr = f(*expr);
+                Expr::in_list(expr, list.into_iter().map(&mut f).collect())
+            },
+            Expr::Case(whens, otherwise) => {
+                let whens = whens.into_iter()
+                    .map(|(when, then)| (f(when), f(then)))
+                    .collect();
+                Expr::case(whens, otherwise.map(|otherwise| f(*otherwise)))
+            },
+            Expr::Function(function, args) => {
+                Expr::call(function, args.into_iter().map(f).collect())
+            },
+        }
+    }
+
+    /// The columns the expression reads, sorted and without duplicates.
+    pub fn columns(&self) -> Vec<usize> {
+        let mut columns = match *self {
+            Expr::Column(col) => vec![col],
+            _ => self.children().into_iter().flat_map(|child| child.columns()).collect(),
+        };
+        columns.sort();
+        columns.dedup();
+        columns
+    }
+
+    /// Replaces each column with map(column), e.g. when the input of the
+    /// expression's node changes.
+    pub fn map_columns(self, map: &dyn Fn(usize) -> usize) -> Self {
+        match self {
+            Expr::Column(col) => Expr::Column(map(col)),
+            expr => expr.map_children(|child| child.map_columns(map)),
+        }
+    }
+
+    /// Type of the expression's result for input of column_types.
+    /// Decimal types which decimal::is_valid_type rejects are an error.
+    pub fn check(&self, column_types: &[DataType]) -> Result<ExprType> {
+        match *self {
+            Expr::Column(col) => {
+                let data_type = column_types.get(col)
+                    .ok_or_else(|| type_error(format!(
+                        "column {} out of range for {} columns",
+                        col,
+                        column_types.len(),
+                    )))?;
+                check_type(data_type)?;
+                Ok(ExprType::Column(data_type.clone()))
+            },
+            Expr::Literal(ref value) => Ok(value.expr_type()),
+            Expr::Binary(op, ref left, ref right) => {
+                let left = left.check(column_types)?;
+                let right = right.check(column_types)?;
+                if op.is_arithmetic() {
+                    arithmetic_type(op, &left, &right)
+                        .ok_or_else(|| type_error(format!("can't apply {:?} to {:?} and {:?}", op, left, right)))
+                } else if op.is_comparison() {
+                    if left.unify(&right).is_none() {
+                        bail!(type_error(format!("can't compare {:?} with {:?}", left, right)));
+                    }
+                    Ok(ExprType::Column(DataType::Boolean))
+                } else {
+                    if !left.is_boolean() || !right.is_boolean() {
+                        bail!(type_error(format!("can't apply {:?} to {:?} and {:?}", op, left, right)));
+                    }
+                    Ok(ExprType::Column(DataType::Boolean))
+                }
+            },
+            Expr::Not(ref expr) => {
+                let expr_type = expr.check(column_types)?;
+                if !expr_type.is_boolean() {
+                    bail!(type_error(format!("can't apply NOT to {:?}", expr_type)));
+                }
+                Ok(ExprType::Column(DataType::Boolean))
+            },
+            Expr::IsNull(ref expr) => {
+                expr.check(column_types)?;
+                Ok(ExprType::Column(DataType::Boolean))
+            },
+            Expr::Like(ref expr, _) => {
+                match expr.check(column_types)? {
+                    ExprType::Column(DataType::Text(_)) | ExprType::Null => Ok(ExprType::Column(DataType::Boolean)),
+                    expr_type => bail!(type_error(format!("can't apply LIKE to {:?}", expr_type))),
+                }
+            },
+            Expr::In(ref expr, ref list) => {
+                let expr_type = expr.check(column_types)?;
+                for item in list {
+                    let item_type = item.check(column_types)?;
+                    if expr_type.unify(&item_type).is_none() {
+                        bail!(type_error(format!("can't compare {:?} with {:?}", expr_type, item_type)));
+                    }
+                }
+                Ok(ExprType::Column(DataType::Boolean))
+            },
+            Expr::Case(ref whens, ref otherwise) => {
+                let mut result_type = ExprType::Null;
+                for (when, then) in whens {
+                    let when_type = when.check(column_types)?;
+                    if !when_type.is_boolean() {
+                        bail!(type_error(format!("CASE condition is {:?}, not Boolean", when_type)));
+                    }
+                    let then_type = then.check(column_types)?;
+                    result_type = result_type.unify(&then_type)
+                        .ok_or_else(|| type_error(format!(
+                            "CASE results {:?} and {:?} differ",
+                            result_type,
+                            then_type,
+                        )))?;
+                }
+                if let Some(ref otherwise) = *otherwise {
+                    let else_type = otherwise.check(column_types)?;
+                    result_type = result_type.unify(&else_type)
+                        .ok_or_else(|| type_error(format!(
+                            "CASE results {:?} and {:?} differ",
+                            result_type,
+                            else_type,
+                        )))?;
+                }
+                Ok(result_type)
+            },
+            Expr::Cast(ref expr, ref data_type) => {
+                check_type(data_type)?;
+                match expr.check(column_types)? {
+                    ExprType::Column(ref from) if !can_cast(from, data_type) => {
+                        bail!(type_error(format!("can't cast {:?} to {:?}", from, data_type)));
+                    },
+                    _ => Ok(ExprType::Column(data_type.clone())),
+                }
+            },
+            Expr::Function(function, ref args) => {
+                let arg_types = args.iter()
+                    .map(|arg| arg.check(column_types))
+                    .collect::<Result<Vec<_>>>()?;
+                function.check(&arg_types)
+            },
+        }
+    }
+
+    // Only call on an expression which was type checked for the tuple's
+    // column types, see TypedExpr.
+    fn eval(&self, tuple: &Tuple, column_types: &[DataType]) -> Result<Value> {
+        match *self {
+            Expr::Column(col) => {
+                if tuple.is_null(col) {
+                    Ok(Value::Null)
+                } else {
+                    Value::from_field(&tuple[col], &column_types[col])
+                }
+            },
+            Expr::Literal(ref value) => Ok(value.clone()),
+            Expr::Binary(BinaryOp::And, ref left, ref right) => {
+                let left = left.eval(tuple, column_types)?;
+                if left == Value::Boolean(false) {
+                    return Ok(left);
+                }
+                match right.eval(tuple, column_types)? {
+                    Value::Boolean(true) => Ok(left),
+                    right => Ok(right),
+                }
+            },
+            Expr::Binary(BinaryOp::Or, ref left, ref right) => {
+                let left = left.eval(tuple, column_types)?;
+                if left == Value::Boolean(true) {
+                    return Ok(left);
+                }
+                match right.eval(tuple, column_types)? {
+                    Value::Boolean(false) => Ok(left),
+                    right => Ok(right),
+                }
+            },
+            Expr::Binary(op, ref left, ref right) => {
+                let left = left.eval(tuple, column_types)?;
+                let right = right.eval(tuple, column_types)?;
+                if op.is_arithmetic() {
+                    arithmetic(op, &left, &right)
+                } else {
+                    Ok(compare(op, &left, &right))
+                }
+            },
+            Expr::Not(ref expr) => {
+                match expr.eval(tuple, column_types)? {
+                    Value::Boolean(b) => Ok(Value::Boolean(!b)),
+                    _ => Ok(Value::Null),
+                }
+            },
+            Expr::IsNull(ref expr) => {
+                Ok(Value::Boolean(expr.eval(tuple, column_types)? == Value::Null))
+            },
+            Expr::Like(ref expr, ref pattern) => {
+                match expr.eval(tuple, column_types)? {
+                    Value::Text(ref s) => Ok(Value::Boolean(like(s, pattern))),
+                    _ => Ok(Value::Null),
+                }
+            },
+            Expr::In(ref expr, ref list) => {
+                let value = expr.eval(tuple, column_types)?;
+                let mut res = Value::Boolean(false);
+                for item in list {
+                    match compare(BinaryOp::Eq, &value, &item.eval(tuple, column_types)?) {
+                        Value::Boolean(true) => return Ok(Value::Boolean(true)),
+                        Value::Boolean(false) => (),
+                        _ => res = Value::Null,
+                    }
+                }
+                Ok(res)
+            },
+            Expr::Case(ref whens, ref otherwise) => {
+                for (when, then) in whens {
+                    if when.eval(tuple, column_types)? == Value::Boolean(true) {
+                        return then.eval(tuple, column_types);
+                    }
+                }
+                match *otherwise {
+                    Some(ref otherwise) => otherwise.eval(tuple, column_types),
+                    None => Ok(Value::Null),
+                }
+            },
+            Expr::Cast(ref expr, ref data_type) => {
+                expr.eval(tuple, column_types)?.cast(data_type)
+            },
+            Expr::Function(function, ref args) => {
+                let args = args.iter()
+                    .map(|arg| arg.eval(tuple, column_types))
+                    .collect::<Result<Vec<_>>>()?;
+                // all functions give null on null input
+                if args.contains(&Value::Null) {
+                    return Ok(Value::Null);
+                }
+                function.eval(args)
+            },
+        }
+    }
+}
+
+/// SQL-like, with columns as #index, e.g. for EXPLAIN. Operands which are
+/// operators themselves are parenthesized, so precedence is never needed
+/// to read it.
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Expr::Column(col) => write!(f, "#{}", col),
+            Expr::Literal(ref value) => write!(f, "{}", value),
+            Expr::Binary(op, ref left, ref right) => {
+                write!(f, "{} {} {}", Operand(left), op.symbol(), Operand(right))
+            },
+            Expr::Not(ref expr) => write!(f, "NOT {}", Operand(expr)),
+            Expr::IsNull(ref expr) => write!(f, "{} IS NULL", Operand(expr)),
+            Expr::Like(ref expr, ref pattern) => {
+                write!(f, "{} LIKE {}", Operand(expr), Value::Text(pattern.clone()))
+            },
+            Expr::In(ref expr, ref list) => {
+                write!(f, "{} IN (", Operand(expr))?;
+                write_list(f, list)?;
+                write!(f, ")")
+            },
+            Expr::Case(ref branches, ref default) => {
+                write!(f, "CASE")?;
+                for (when, then) in branches {
+                    write!(f, " WHEN {} THEN {}", when, then)?;
+                }
+                if let Some(ref default) = *default {
+                    write!(f, " ELSE {}", default)?;
+                }
+                write!(f, " END")
+            },
+            Expr::Cast(ref expr, ref data_type) => write!(f, "CAST({} AS {:?})", expr, data_type),
+            Expr::Function(function, ref args) => {
+                write!(f, "{}(", format!("{:?}", function).to_uppercase())?;
+                write_list(f, args)?;
+                write!(f, ")")
+            },
+        }
+    }
+}
+
+struct Operand<'a>(&'a Expr);
+
+impl<'a> fmt::Display for Operand<'a> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self.0 {
+            Expr::Binary(..) | Expr::Not(_) | Expr::IsNull(_) | Expr::Like(..) | Expr::In(..) => {
+                write!(f, "({})", self.0)
+            },
+            ref expr => write!(f, "{}", expr),
+        }
+    }
+}
+
+fn write_list(f: &mut fmt::Formatter, exprs: &[Expr]) -> fmt::Result {
+    for (i, expr) in exprs.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        write!(f, "{}", expr)?;
+    }
+    Ok(())
+}
+
+/// Scalar functions on the values of one tuple.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Function {
+    Upper,
+    Lower,
+    Trim,
+    Length, // in chars
+    Substring, // (text, start, length), start counts from 1
+    Concat,
+    Round, // to the nearest integer
+    // Parts of a Date or Timestamp, as Integers. The time of a Date is
+    // midnight, and Second drops any fraction.
+    Year,
+    Month,
+    Day,
+    Hour,
+    Minute,
+    Second,
+}
+
+impl Function {
+    fn check(&self, arg_types: &[ExprType]) -> Result<ExprType> {
+        use self::Function::*;
+        let arity = match *self {
+            Upper | Lower | Trim | Length | Round => 1,
+            Year | Month | Day | Hour | Minute | Second => 1,
+            Concat => 2,
+            Substring => 3,
+        };
+        if arg_types.len() != arity {
+            bail!(type_error(format!("{:?} takes {} args, not {}", self, arity, arg_types.len())));
+        }
+
+        let is_text = |arg_type: &ExprType| {
+            matches!(*arg_type, ExprType::Column(DataType::Text(_)) | ExprType::Null)
+        };
+        let text_len = |arg_type: &ExprType| {
+            match *arg_type {
+                ExprType::Column(DataType::Text(x)) => x,
+                _ => 0,
+            }
+        };
+
+        let well_typed = match *self {
+            Upper | Lower | Trim | Length | Concat => arg_types.iter().all(is_text),
+            Substring => is_text(&arg_types[0]) && arg_types[1].is_int() && arg_types[2].is_int(),
+            Round => arg_types[0].is_numeric(),
+            Year | Month | Day | Hour | Minute | Second => arg_types[0].is_datetime(),
+        };
+        if !well_typed {
+            bail!(type_error(format!("can't apply {:?} to {:?}", self, arg_types)));
+        }
+
+        Ok(match *self {
+            Upper | Lower | Trim | Substring => ExprType::Column(DataType::Text(text_len(&arg_types[0]))),
+            Length => ExprType::Column(DataType::Integer),
+            Concat => ExprType::Column(DataType::Text(text_len(&arg_types[0]) + text_len(&arg_types[1]))),
+            Round => arg_types[0].clone(),
+            Year | Month | Day | Hour | Minute | Second => ExprType::Column(DataType::Integer),
+        })
+    }
+
+    // args were type checked, and none are null
+    fn eval(&self, args: Vec<Value>) -> Result<Value> {
+        use self::Function::*;
+        let text = |i: usize| {
+            match args[i] {
+                Value::Text(ref s) => s.as_str(),
+                _ => panic!("internal bug, {:?} arg {} is not Text", self, i),
+            }
+        };
+        let timestamp = || {
+            args[0].to_timestamp()
+                .unwrap_or_else(|| panic!("internal bug, {:?} arg is not a Date or Timestamp", self))
+        };
+        let res = match *self {
+            Upper => Value::Text(text(0).to_uppercase()),
+            Lower => Value::Text(text(0).to_lowercase()),
+            Trim => Value::Text(text(0).trim().to_owned()),
+            Length => Value::Integer(text(0).chars().count() as i32),
+            Substring => {
+                let start = args[1].to_i64().expect("internal bug, start is not an int");
+                let len = args[2].to_i64().expect("internal bug, length is not an int");
+                // positions before 1 use up some of the length
+                let before_text = 1i64.saturating_sub(start).max(0);
+                let s = text(0).chars()
+                    .skip((start.max(1) - 1) as usize)
+                    .take(len.saturating_sub(before_text).max(0) as usize)
+                    .collect();
+                Value::Text(s)
+            },
+            Concat => Value::Text(format!("{}{}", text(0), text(1))),
+            Round => {
+                match args[0] {
+                    Value::Float(x) => Value::Float(x.round()),
+                    Value::Double(x) => Value::Double(x.round()),
+                    Value::Decimal(x, precision, scale) => {
+                        decimal::rescale(x, scale, 0)
+                            .and_then(|x| decimal_value(x, 0, precision, scale))
+                            .ok_or_else(|| eval_error(format!("{} is out of range when rounded", args[0])))?
+                    },
+                    ref x => x.clone(),
+                }
+            },
+            Year | Month | Day => {
+                let days = datetime::timestamp_to_date(timestamp());
+                let (year, month, day) = datetime::civil_from_days(i64::from(days));
+                match *self {
+                    Year => Value::Integer(year as i32),
+                    Month => Value::Integer(month as i32),
+                    _ => Value::Integer(day as i32),
+                }
+            },
+            Hour | Minute | Second => {
+                let (hour, minute, second, _) = datetime::time_of_day(timestamp());
+                match *self {
+                    Hour => Value::Integer(hour as i32),
+                    Minute => Value::Integer(minute as i32),
+                    _ => Value::Integer(second as i32),
+                }
+            },
+        };
+        Ok(res)
+    }
+}
+
+/// Type of an expression's result. Predicates are Boolean.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ExprType {
+    Column(DataType),
+    Null, // a null literal, which fits any type
+}
+
+impl ExprType {
+    pub(crate) fn is_numeric(&self) -> bool {
+        use DataType::*;
+        match *self {
+            ExprType::Column(SmallInt) |
+            ExprType::Column(Integer) |
+            ExprType::Column(BigInt) |
+            ExprType::Column(Float) |
+            ExprType::Column(Double) |
+            ExprType::Column(Decimal(..)) |
+            ExprType::Null => true,
+            ExprType::Column(_) => false,
+        }
+    }
+
+    fn is_int(&self) -> bool {
+        use DataType::*;
+        matches!(
+            *self,
+            ExprType::Column(SmallInt) | ExprType::Column(Integer) | ExprType::Column(BigInt) | ExprType::Null
+        )
+    }
+
+    pub(crate) fn is_boolean(&self) -> bool {
+        matches!(*self, ExprType::Column(DataType::Boolean) | ExprType::Null)
+    }
+
+    fn is_datetime(&self) -> bool {
+        matches!(
+            *self,
+            ExprType::Column(DataType::Date) | ExprType::Column(DataType::Timestamp) | ExprType::Null
+        )
+    }
+
+    // The type which both can be converted to, if any. Numbers widen to
+    // the larger type, Text to the longer length, and Dates to Timestamps.
+    // Decimals keep the most digits on each side of the point, and only
+    // floats are larger.
+    pub(crate) fn unify(&self, other: &ExprType) -> Option<ExprType> {
+        use DataType::*;
+        if let Some(((p1, s1), (p2, s2))) = decimal_operands(self, other) {
+            let scale = s1.max(s2);
+            let int_digits = (p1 - s1).max(p2 - s2);
+            return Some(ExprType::Column(decimal_type(u32::from(int_digits + scale), u32::from(scale))));
+        }
+        match (self, other) {
+            (&ExprType::Null, _) => Some(other.clone()),
+            (_, &ExprType::Null) => Some(self.clone()),
+            (&ExprType::Column(Text(x)), &ExprType::Column(Text(y))) => {
+                Some(ExprType::Column(Text(x.max(y))))
+            },
+            (&ExprType::Column(Double), _) | (_, &ExprType::Column(Double))
+                if self.is_numeric() && other.is_numeric() => Some(ExprType::Column(Double)),
+            (&ExprType::Column(Float), _) | (_, &ExprType::Column(Float))
+                if self.is_numeric() && other.is_numeric() => Some(ExprType::Column(Float)),
+            (&ExprType::Column(BigInt), _) | (_, &ExprType::Column(BigInt))
+                if self.is_numeric() && other.is_numeric() => Some(ExprType::Column(BigInt)),
+            (&ExprType::Column(Integer), _) | (_, &ExprType::Column(Integer))
+                if self.is_numeric() && other.is_numeric() => Some(ExprType::Column(Integer)),
+            (&ExprType::Column(Timestamp), _) | (_, &ExprType::Column(Timestamp))
+                if self.is_datetime() && other.is_datetime() => Some(ExprType::Column(Timestamp)),
+            _ if self == other => Some(self.clone()),
+            _ => None,
+        }
+    }
+}
+
+// Dates and Timestamps can be moved by a number of days and seconds,
+// and subtracted to give the days or seconds between them.
+fn arithmetic_type(op: BinaryOp, left: &ExprType, right: &ExprType) -> Option<ExprType> {
+    use DataType::*;
+    let (is_add, is_subtract) = (op == BinaryOp::Add, op == BinaryOp::Subtract);
+    match (left, right) {
+        _ if left.is_numeric() && right.is_numeric() => {
+            match decimal_operands(left, right) {
+                Some(operands) => Some(ExprType::Column(decimal_arithmetic_type(op, operands))),
+                None => left.unify(right),
+            }
+        },
+        (&ExprType::Column(Date), _) if right.is_int() && (is_add || is_subtract) => Some(left.clone()),
+        (_, &ExprType::Column(Date)) if left.is_int() && is_add => Some(right.clone()),
+        (&ExprType::Column(Date), &ExprType::Column(Date)) if is_subtract => Some(ExprType::Column(Integer)),
+        (&ExprType::Column(Timestamp), _) if right.is_numeric() && (is_add || is_subtract) => Some(left.clone()),
+        (_, &ExprType::Column(Timestamp)) if left.is_numeric() && is_add => Some(right.clone()),
+        (&ExprType::Column(Timestamp), &ExprType::Column(Timestamp)) if is_subtract => Some(ExprType::Column(Double)),
+        _ => None,
+    }
+}
+
+// Precision and scale of each side, if one is a Decimal and the other is
+// a Decimal or an int
+fn decimal_operands(left: &ExprType, right: &ExprType) -> Option<((u8, u8), (u8, u8))> {
+    match (left, right) {
+        (ExprType::Column(x), ExprType::Column(y)) => {
+            let is_decimal = |data_type: &DataType| matches!(*data_type, DataType::Decimal(..));
+            if is_decimal(x) || is_decimal(y) {
+                Some((decimal_parts(x)?, decimal_parts(y)?))
+            } else {
+                None
+            }
+        },
+        _ => None,
+    }
+}
+
+// Checked before any type arithmetic, which assumes scale <= precision.
+fn check_type(data_type: &DataType) -> Result<()> {
+    match *data_type {
+        DataType::Decimal(precision, scale) if !decimal::is_valid_type(precision, scale) => {
+            bail!(type_error(format!("bad type {:?}", data_type)))
+        },
+        _ => Ok(()),
+    }
+}
+
+// Precision and scale of a Decimal, or of the smallest Decimal which holds
+// every value of an int type
+fn decimal_parts(data_type: &DataType) -> Option<(u8, u8)> {
+    match *data_type {
+        DataType::SmallInt => Some((5, 0)),
+        DataType::Integer => Some((10, 0)),
+        DataType::BigInt => Some((19, 0)),
+        DataType::Decimal(precision, scale) => Some((precision, scale)),
+        _ => None,
+    }
+}
+
+// Past 38 digits, digits after the point are dropped first, but at
+// least 6 are kept (or all of them, if fewer).
+fn decimal_type(precision: u32, scale: u32) -> DataType {
+    let max = u32::from(decimal::MAX_PRECISION);
+    if precision <= max {
+        return DataType::Decimal(precision as u8, scale as u8);
+    }
+    let scale = scale.saturating_sub(precision - max).max(scale.min(6));
+    DataType::Decimal(decimal::MAX_PRECISION, scale as u8)
+}
+
+// Enough digits for any result, e.g. a sum has one more digit than the
+// larger side, and a quotient keeps at least 6 digits after the point.
+fn decimal_arithmetic_type(op: BinaryOp, operands: ((u8, u8), (u8, u8))) -> DataType {
+    let ((p1, s1), (p2, s2)) = operands;
+    let (p1, s1, p2, s2) = (u32::from(p1), u32::from(s1), u32::from(p2), u32::from(s2));
+    match op {
+        BinaryOp::Add | BinaryOp::Subtract => {
+            let scale = s1.max(s2);
+            decimal_type((p1 - s1).max(p2 - s2) + scale + 1, scale)
+        },
+        BinaryOp::Multiply => decimal_type(p1 + p2 + 1, s1 + s2),
+        BinaryOp::Divide => {
+            let scale = (s1 + p2 + 1).max(6);
+            decimal_type(p1 - s1 + s2 + scale, scale)
+        },
+        _ => panic!("internal bug, {:?} is not arithmetic", op),
+    }
+}
+
+// Whether values of one type can be cast to another, though the cast of a
+// particular value may still fail, e.g. Text which doesn't parse.
+fn can_cast(from: &DataType, to: &DataType) -> bool {
+    use DataType::*;
+    let is_number = |data_type: &DataType| ExprType::Column(data_type.clone()).is_numeric();
+    match (from, to) {
+        (&Text(_), _) | (_, &Text(_)) => true,
+        _ if from == to => true,
+        (&Date, &Timestamp) | (&Timestamp, &Date) => true,
+        // Timestamps are unix seconds as numbers
+        (&Timestamp, _) => is_number(to),
+        (_, &Timestamp) => is_number(from),
+        (&Date, _) | (_, &Date) => false,
+        // Booleans are 0 or 1, and numbers are true unless 0
+        (&Boolean, _) | (_, &Boolean) => is_number(from) || is_number(to),
+        _ => is_number(from) && is_number(to),
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Null,
+    Boolean(bool),
+    SmallInt(i16),
+    Integer(i32),
+    BigInt(i64),
+    Float(f32),
+    Double(f64),
+    Date(i32), // days since 1970-01-01
+    Timestamp(i64), // microseconds since 1970-01-01 00:00:00 UTC
+    Decimal(i128, u8, u8), // digits, precision, scale
+    Text(String),
+}
+
+impl Value {
+    /// Decodes a (non-null) tuple field.
+    pub fn from_field(field: &[u8], data_type: &DataType) -> Result<Self> {
+        match *data_type {
+            DataType::SmallInt => Ok(Value::SmallInt(field_parse(field)?)),
+            DataType::Integer => Ok(Value::Integer(field_parse(field)?)),
+            DataType::BigInt => Ok(Value::BigInt(field_parse(field)?)),
+            DataType::Float => Ok(Value::Float(field_parse(field)?)),
+            DataType::Double => Ok(Value::Double(field_parse(field)?)),
+            DataType::Boolean => Ok(Value::Boolean(field_parse(field)?)),
+            DataType::Date => Ok(Value::Date(field_parse(field)?)),
+            DataType::Timestamp => Ok(Value::Timestamp(field_parse(field)?)),
+            DataType::Decimal(precision, scale) => Ok(Value::Decimal(field_parse(field)?, precision, scale)),
+            DataType::Text(_) => Ok(Value::Text(field_parse(field)?)),
+        }
+    }
+
+    /// Encodes the value as a field of data_type, which it must cast to.
+    /// None is a null field.
+    pub fn to_field(&self, data_type: &DataType) -> Result<Option<Vec<u8>>> {
+        let res = match self.cast(data_type)? {
+            Value::Null => None,
+            Value::Boolean(b) => Some(b.to_tuple_field()),
+            Value::SmallInt(x) => Some(x.to_tuple_field()),
+            Value::Integer(x) => Some(x.to_tuple_field()),
+            Value::BigInt(x) => Some(x.to_tuple_field()),
+            Value::Float(x) => Some(x.to_tuple_field()),
+            Value::Double(x) => Some(x.to_tuple_field()),
+            Value::Date(x) => Some(x.to_tuple_field()),
+            Value::Timestamp(x) => Some(x.to_tuple_field()),
+            Value::Decimal(x, _, _) => Some(x.to_tuple_field()),
+            Value::Text(s) => Some(string_to_binary(&s, data_type)?),
+        };
+        Ok(res)
+    }
+
+    fn expr_type(&self) -> ExprType {
+        match *self {
+            Value::Null => ExprType::Null,
+            Value::Boolean(_) => ExprType::Column(DataType::Boolean),
+            Value::SmallInt(_) => ExprType::Column(DataType::SmallInt),
+            Value::Integer(_) => ExprType::Column(DataType::Integer),
+            Value::BigInt(_) => ExprType::Column(DataType::BigInt),
+            Value::Float(_) => ExprType::Column(DataType::Float),
+            Value::Double(_) => ExprType::Column(DataType::Double),
+            Value::Date(_) => ExprType::Column(DataType::Date),
+            Value::Timestamp(_) => ExprType::Column(DataType::Timestamp),
+            Value::Decimal(_, precision, scale) => ExprType::Column(DataType::Decimal(precision, scale)),
+            Value::Text(ref s) => ExprType::Column(DataType::Text(s.len())),
+        }
+    }
+
+    fn to_i64(&self) -> Option<i64> {
+        match *self {
+            Value::SmallInt(x) => Some(x as i64),
+            Value::Integer(x) => Some(x as i64),
+            Value::BigInt(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    fn to_f64(&self) -> Option<f64> {
+        match *self {
+            Value::Float(x) => Some(x as f64),
+            Value::Double(x) => Some(x),
+            Value::Decimal(x, _, scale) => Some(decimal::to_f64(x, scale)),
+            _ => self.to_i64().map(|x| x as f64),
+        }
+    }
+
+    // Digits and scale of a Decimal, with ints at scale 0
+    fn to_decimal(&self) -> Option<(i128, u8)> {
+        match *self {
+            Value::Decimal(x, _, scale) => Some((x, scale)),
+            _ => self.to_i64().map(|x| (i128::from(x), 0)),
+        }
+    }
+
+    // Microseconds since the epoch, Dates at midnight
+    fn to_timestamp(&self) -> Option<i64> {
+        match *self {
+            Value::Date(x) => Some(datetime::date_to_timestamp(x)),
+            Value::Timestamp(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    /// Null casts to null of any type. Numbers which don't fit the target
+    /// type, and Text which doesn't parse or is too long, are errors.
+    /// Timestamps cast to and from numbers as unix seconds.
+    pub fn cast(&self, data_type: &DataType) -> Result<Value> {
+        let res = match (self, data_type) {
+            (Value::Null, _) => Some(Value::Null),
+            (Value::Text(s), &DataType::Text(x)) => {
+                if s.len() > x {
+                    bail!(eval_error(format!("{:?} is too long for Text({})", s, x)));
+                }
+                Some(self.clone())
+            },
+            (Value::Text(s), _) => {
+                let s = s.trim();
+                match *data_type {
+                    DataType::SmallInt => s.parse().ok().map(Value::SmallInt),
+                    DataType::Integer => s.parse().ok().map(Value::Integer),
+                    DataType::BigInt => s.parse().ok().map(Value::BigInt),
+                    DataType::Float => s.parse().ok().map(Value::Float),
+                    DataType::Double => s.parse().ok().map(Value::Double),
+                    DataType::Boolean => parse_bool(s).ok().map(Value::Boolean),
+                    DataType::Date => datetime::parse_date(s).ok().map(Value::Date),
+                    DataType::Timestamp => datetime::parse_timestamp(s).ok().map(Value::Timestamp),
+                    DataType::Decimal(precision, scale) => {
+                        decimal::parse_decimal(s, precision, scale).ok()
+                            .map(|x| Value::Decimal(x, precision, scale))
+                    },
+                    DataType::Text(_) => unreachable!(),
+                }
+            },
+            (_, DataType::Text(_)) => {
+                let s = match *self {
+                    Value::Boolean(b) => b.to_string(),
+                    Value::Float(x) => x.to_string(),
+                    Value::Double(x) => x.to_string(),
+                    Value::Date(x) => datetime::format_date(x),
+                    Value::Timestamp(x) => datetime::format_timestamp(x),
+                    Value::Decimal(x, _, scale) => decimal::format_decimal(x, scale),
+                    _ => self.to_i64().expect("only ints left").to_string(),
+                };
+                return Value::Text(s).cast(data_type);
+            },
+            (&Value::Boolean(_), &DataType::Boolean) => Some(self.clone()),
+            (&Value::Boolean(b), _) => int_value(b as i64, data_type),
+            (_, DataType::Boolean) => self.to_f64().map(|x| Value::Boolean(x != 0.)),
+            (&Value::Date(x), &DataType::Date) => Some(Value::Date(x)),
+            (&Value::Date(x), &DataType::Timestamp) => Some(Value::Timestamp(datetime::date_to_timestamp(x))),
+            (&Value::Date(_), _) => None,
+            (&Value::Timestamp(x), &DataType::Date) => Some(Value::Date(datetime::timestamp_to_date(x))),
+            (&Value::Timestamp(x), &DataType::Timestamp) => Some(Value::Timestamp(x)),
+            (&Value::Timestamp(x), _) => {
+                int_value(x.div_euclid(datetime::MICROS_PER_SECOND), data_type)
+            },
+            (_, DataType::Date) => None,
+            (_, DataType::Timestamp) => seconds_to_micros(self).map(Value::Timestamp),
+            (_, DataType::Float) => self.to_f64().map(|x| Value::Float(x as f32)),
+            (_, DataType::Double) => self.to_f64().map(Value::Double),
+            (_, &DataType::Decimal(precision, scale)) => {
+                match self.to_decimal() {
+                    Some((x, x_scale)) => decimal_value(x, x_scale, precision, scale),
+                    None => {
+                        let x = self.to_f64().expect("only floats");
+                        decimal::from_f64(x, scale).and_then(|x| decimal_value(x, scale, precision, scale))
+                    },
+                }
+            },
+            (&Value::Float(_), _) | (&Value::Double(_), _) => {
+                let x = self.to_f64().expect("only floats");
+                if x.is_finite() {
+                    int_value(x.round() as i64, data_type)
+                } else {
+                    None
+                }
+            },
+            (&Value::Decimal(x, _, scale), _) => {
+                decimal::rescale(x, scale, 0)
+                    .and_then(|x| i64::try_from(x).ok())
+                    .and_then(|x| int_value(x, data_type))
+            },
+            _ => self.to_i64().and_then(|x| int_value(x, data_type)),
+        };
+        res.ok_or_else(|| eval_error(format!("can't cast {:?} to {:?}", self, data_type)))
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match *self {
+            Value::Null => write!(f, "NULL"),
+            Value::Boolean(b) => write!(f, "{}", if b { "TRUE" } else { "FALSE" }),
+            Value::SmallInt(x) => write!(f, "{}", x),
+            Value::Integer(x) => write!(f, "{}", x),
+            Value::BigInt(x) => write!(f, "{}", x),
+            Value::Float(x) => write!(f, "{:?}", x),
+            Value::Double(x) => write!(f, "{:?}", x),
+            Value::Date(x) => write!(f, "DATE '{}'", datetime::format_date(x)),
+            Value::Timestamp(x) => write!(f, "TIMESTAMP '{}'", datetime::format_timestamp(x)),
+            Value::Decimal(x, _, scale) => write!(f, "{}", decimal::format_decimal(x, scale)),
+            Value::Text(ref s) => write!(f, "'{}'", s.replace('\'', "''")),
+        }
+    }
+}
+
+// Value of an int type, if x fits
+fn int_value(x: i64, data_type: &DataType) -> Option<Value> {
+    match *data_type {
+        DataType::SmallInt => i16::try_from(x).ok().map(Value::SmallInt),
+        DataType::Integer => i32::try_from(x).ok().map(Value::Integer),
+        DataType::BigInt => Some(Value::BigInt(x)),
+        DataType::Float => Some(Value::Float(x as f32)),
+        DataType::Double => Some(Value::Double(x as f64)),
+        DataType::Decimal(precision, scale) => decimal_value(i128::from(x), 0, precision, scale),
+        _ => None,
+    }
+}
+
+// Value of Decimal(precision, scale), if x at x_scale fits once rounded
+fn decimal_value(x: i128, x_scale: u8, precision: u8, scale: u8) -> Option<Value> {
+    decimal::rescale(x, x_scale, scale)
+        .filter(|&x| decimal::fits(x, precision))
+        .map(|x| Value::Decimal(x, precision, scale))
+}
+
+// A number of seconds as microseconds, if it fits
+fn seconds_to_micros(seconds: &Value) -> Option<i64> {
+    match seconds.to_i64() {
+        Some(x) => x.checked_mul(datetime::MICROS_PER_SECOND),
+        None => {
+            let micros = (seconds.to_f64()? * datetime::MICROS_PER_SECOND as f64).round();
+            if micros.is_finite() && micros.abs() < i64::MAX as f64 {
+                Some(micros as i64)
+            } else {
+                None
+            }
+        },
+    }
+}
+
+impl From<i16> for Value {
+    fn from(x: i16) -> Self {
+        Value::SmallInt(x)
+    }
+}
+
+impl From<i32> for Value {
+    fn from(x: i32) -> Self {
+        Value::Integer(x)
+    }
+}
+
+impl From<i64> for Value {
+    fn from(x: i64) -> Self {
+        Value::BigInt(x)
+    }
+}
+
+impl From<f32> for Value {
+    fn from(x: f32) -> Self {
+        Value::Float(x)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(x: f64) -> Self {
+        Value::Double(x)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Value::Boolean(b)
+    }
+}
+
+impl<'a> From<&'a str> for Value {
+    fn from(s: &'a str) -> Self {
+        Value::Text(s.to_owned())
+    }
+}
+
+fn arithmetic(op: BinaryOp, left: &Value, right: &Value) -> Result<Value> {
+    if *left == Value::Null || *right == Value::Null {
+        return Ok(Value::Null);
+    }
+    let out_of_range = || eval_error(format!("{:?} of {:?} and {:?} is out of range", op, left, right));
+
+    // Dates move by days, and Timestamps by seconds
+    match (left, right) {
+        (&Value::Date(x), &Value::Date(y)) => {
+            return Ok(Value::Integer(x.checked_sub(y).ok_or_else(out_of_range)?));
+        },
+        (&Value::Timestamp(_), &Value::Timestamp(_)) |
+        (&Value::Date(_), &Value::Timestamp(_)) |
+        (&Value::Timestamp(_), &Value::Date(_)) => {
+            let (x, y) = (left.to_timestamp().unwrap(), right.to_timestamp().unwrap());
+            let micros = x.checked_sub(y).ok_or_else(out_of_range)?;
+            return Ok(Value::Double(micros as f64 / datetime::MICROS_PER_SECOND as f64));
+        },
+        (&Value::Date(date), days) | (days, &Value::Date(date)) => {
+            let days = days.to_i64().expect("internal bug, days is not an int");
+            let days = if op == BinaryOp::Subtract { days.checked_neg() } else { Some(days) };
+            return days.and_then(|days| i64::from(date).checked_add(days))
+                .and_then(|res| i32::try_from(res).ok())
+                .map(Value::Date)
+                .ok_or_else(out_of_range);
+        },
+        (&Value::Timestamp(ts), seconds) | (seconds, &Value::Timestamp(ts)) => {
+            let micros = seconds_to_micros(seconds).ok_or_else(out_of_range)?;
+            let micros = if op == BinaryOp::Subtract { micros.checked_neg() } else { Some(micros) };
+            return micros.and_then(|micros| ts.checked_add(micros))
+                .map(Value::Timestamp)
+                .ok_or_else(out_of_range);
+        },
+        _ => (),
+    }
+
+    if let Some(operands) = decimal_operands(&left.expr_type(), &right.expr_type()) {
+        let (x, x_scale) = left.to_decimal().expect("internal bug, not a Decimal");
+        let (y, y_scale) = right.to_decimal().expect("internal bug, not a Decimal");
+        let (precision, scale) = match decimal_arithmetic_type(op, operands) {
+            DataType::Decimal(precision, scale) => (precision, scale),
+            _ => unreachable!(),
+        };
+        let res = match op {
+            BinaryOp::Add | BinaryOp::Subtract => {
+                let x = decimal::rescale(x, x_scale, scale).ok_or_else(out_of_range)?;
+                let y = decimal::rescale(y, y_scale, scale).ok_or_else(out_of_range)?;
+                if op == BinaryOp::Add { x.checked_add(y) } else { x.checked_sub(y) }
+            },
+            BinaryOp::Multiply => {
+                x.checked_mul(y).and_then(|product| decimal::rescale(product, x_scale + y_scale, scale))
+            },
+            BinaryOp::Divide if y == 0 => bail!(eval_error("division by zero".to_owned())),
+            BinaryOp::Divide => {
+                // x / y at scale is x * 10^(scale + y_scale - x_scale) / y
+                let shift = i32::from(scale) + i32::from(y_scale) - i32::from(x_scale);
+                let factor = 10i128.checked_pow(shift.unsigned_abs());
+                if shift >= 0 {
+                    factor.and_then(|factor| x.checked_mul(factor)).and_then(|x| decimal::div_round(x, y))
+                } else {
+                    factor.and_then(|factor| y.checked_mul(factor)).and_then(|y| decimal::div_round(x, y))
+                }
+            },
+            _ => unreachable!(),
+        };
+        return res.and_then(|res| decimal_value(res, scale, precision, scale))
+            .ok_or_else(out_of_range);
+    }
+
+    let result_type = match left.expr_type().unify(&right.expr_type()) {
+        Some(ExprType::Column(data_type)) => data_type,
+        _ => panic!("internal bug, arithmetic on {:?} and {:?}", left, right),
+    };
+
+    if result_type == DataType::Float || result_type == DataType::Double {
+        // both are numbers, so to_f64 won't fail
+        let (x, y) = (left.to_f64().unwrap(), right.to_f64().unwrap());
+        let res = match op {
+            BinaryOp::Add => x + y,
+            BinaryOp::Subtract => x - y,
+            BinaryOp::Multiply => x * y,
+            BinaryOp::Divide if y == 0. => bail!(eval_error("division by zero".to_owned())),
+            BinaryOp::Divide => x / y,
+            _ => unreachable!(),
+        };
+        if result_type == DataType::Float {
+            return Ok(Value::Float(res as f32));
+        }
+        return Ok(Value::Double(res));
+    }
+
+    // integer types, to_i64 won't fail
+    let (x, y) = (left.to_i64().unwrap(), right.to_i64().unwrap());
+    let res = match op {
+        BinaryOp::Add => x.checked_add(y),
+        BinaryOp::Subtract => x.checked_sub(y),
+        BinaryOp::Multiply => x.checked_mul(y),
+        BinaryOp::Divide if y == 0 => bail!(eval_error("division by zero".to_owned())),
+        BinaryOp::Divide => x.checked_div(y), // i64::MIN / -1 overflows
+        _ => unreachable!(),
+    };
+    res.and_then(|res| int_value(res, &result_type))
+        .ok_or_else(out_of_range)
+}
+
+// Null if either side is null. Values were type checked, so numbers are
+// only compared with numbers, etc. Floats compare as IEEE 754, so -0.0
+// equals 0.0, and NaN is unordered: it equals nothing, not even itself,
+// and is only not equal to everything.
+fn compare(op: BinaryOp, left: &Value, right: &Value) -> Value {
+    let ordering = match (left, right) {
+        (Value::Null, _) | (_, Value::Null) => return Value::Null,
+        (Value::Text(x), Value::Text(y)) => Some(x.cmp(y)),
+        (Value::Boolean(x), Value::Boolean(y)) => Some(x.cmp(y)),
+        _ => {
+            match (left.to_i64(), right.to_i64()) {
+                (Some(x), Some(y)) => Some(x.cmp(&y)),
+                _ => {
+                    if let (Some(x), Some(y)) = (left.to_timestamp(), right.to_timestamp()) {
+                        Some(x.cmp(&y))
+                    } else if let (Some((x, x_scale)), Some((y, y_scale))) = (left.to_decimal(), right.to_decimal()) {
+                        Some(decimal::cmp(x, x_scale, y, y_scale))
+                    } else {
+                        let x = left.to_f64().expect("internal bug, compare of mismatched types");
+                        let y = right.to_f64().expect("internal bug, compare of mismatched types");
+                        x.partial_cmp(&y)
+                    }
+                },
+            }
+        },
+    };
+    let res = match (op, ordering) {
+        (BinaryOp::NotEq, None) => true,
+        (_, None) => false,
+        (BinaryOp::Eq, Some(ordering)) => ordering == Ordering::Equal,
+        (BinaryOp::NotEq, Some(ordering)) => ordering != Ordering::Equal,
+        (BinaryOp::Lt, Some(ordering)) => ordering == Ordering::Less,
+        (BinaryOp::LtEq, Some(ordering)) => ordering != Ordering::Greater,
+        (BinaryOp::Gt, Some(ordering)) => ordering == Ordering::Greater,
+        (BinaryOp::GtEq, Some(ordering)) => ordering != Ordering::Less,
+        _ => panic!("internal bug, {:?} is not a comparison", op),
+    };
+    Value::Boolean(res)
+}
+
+// SQL LIKE, without escapes
+fn like(s: &str, pattern: &str) -> bool {
+    let s: Vec<char> = s.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+
+    // on a mismatch, backtrack to the last % and let it match one more char
+    let (mut i, mut j) = (0, 0);
+    let mut backtrack: Option<(usize, usize)> = None;
+    while i < s.len() {
+        if j < pattern.len() && pattern[j] == '%' {
+            backtrack = Some((i, j));
+            j += 1;
+        } else if j < pattern.len() && (pattern[j] == '_' || pattern[j] == s[i]) {
+            i += 1;
+            j += 1;
+        } else if let Some((bi, bj)) = backtrack {
+            backtrack = Some((bi + 1, bj));
+            i = bi + 1;
+            j = bj + 1;
+        } else {
+            return false;
+        }
+    }
+    pattern[j..].iter().all(|&c| c == '%')
+}
+
+fn type_error(msg: String) -> Error {
+    ErrorKind::ExprType(msg).into()
+}
+
+fn eval_error(msg: String) -> Error {
+    ErrorKind::ExprEval(msg).into()
+}
+
+/// An Expr which was type checked against the types of its input.
+#[derive(Debug, Clone)]
+pub struct TypedExpr {
+    expr: Expr,
+    column_types: Vec<DataType>,
+    result_type: ExprType,
+}
+
+impl TypedExpr {
+    pub fn new(expr: Expr, column_types: &[DataType]) -> Result<Self> {
+        let result_type = expr.check(column_types)?;
+        Ok(TypedExpr {
+            expr,
+            column_types: column_types.to_vec(),
+            result_type,
+        })
+    }
+
+    /// Like new, but the expression must be a predicate.
+    pub fn predicate(expr: Expr, column_types: &[DataType]) -> Result<Self> {
+        let typed = Self::new(expr, column_types)?;
+        if !typed.result_type.is_boolean() {
+            bail!(type_error(format!("predicate is {:?}, not Boolean", typed.result_type)));
+        }
+        Ok(typed)
+    }
+
+    pub fn expr(&self) -> &Expr {
+        &self.expr
+    }
+
+    pub fn result_type(&self) -> &ExprType {
+        &self.result_type
+    }
+
+    pub fn eval(&self, tuple: &Tuple) -> Result<Value> {
+        self.expr.eval(tuple, &self.column_types)
+    }
+
+    /// Only true passes, not false or null.
+    pub fn eval_predicate(&self, tuple: &Tuple) -> Result<bool> {
+        Ok(self.eval(tuple)? == Value::Boolean(true))
+    }
+
+    /// Type of the field from eval_field, only for expressions whose
+    /// result is a column type.
+    pub fn column_type(&self) -> Result<DataType> {
+        match self.result_type {
+            ExprType::Column(ref data_type) => Ok(data_type.clone()),
+            ref result_type => bail!(type_error(format!("{:?} is not a column type", result_type))),
+        }
+    }
+
+    /// Evaluates to an encoded field of column_type, None is null.
+    pub fn eval_field(&self, tuple: &Tuple) -> Result<Option<Vec<u8>>> {
+        let data_type = self.column_type()?;
+        self.eval(tuple)?.to_field(&data_type)
+    }
+}
@@ -0,0 +1,220 @@
+// Sort for input which may not fit in memory.
+//
+// Input is buffered until it reaches the memory budget, then the buffer
+// is sorted and written out as a run to a temp file in the spill stream
+// format. Once the input is done, the runs are read back with SpillScan
+// and k-way merged, with the head tuple of each run in a binary heap. If
+// all the input fits in the budget, nothing is written to disk and this
+// behaves like SimpleSort.
+//
+// Runs are only merged in one pass, and each run holds one read buffer in
+// memory while merging. Runs are written in input order and ties in the
+// merge go to the earlier run, so a stable sort stays stable across runs.
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+use std::fs::File;
+use std::mem;
+use std::rc::Rc;
+
+use error::*;
+use storage::TempFile;
+use storage::spill::{SpillScan, SpillWriter};
+use super::DbIterator;
+use super::explain::{self, Explain};
+use super::simplesort::{SortKey, cmp_tuples, sort_tuples};
+use super::tuple::Tuple;
+
+struct SortRun {
+    scan: SpillScan<File>,
+    _file: TempFile, // removes the run from disk when dropped
+}
+
+// The next tuple to be merged from a run. BinaryHeap pops the greatest
+// entry, so entries order as the reverse of their tuples, then of their
+// runs.
+struct MergeHead {
+    tuple: Tuple,
+    run: usize,
+    sort_keys: Rc<[SortKey]>,
+}
+
+impl MergeHead {
+    // Comparing the tuple with itself parses each of its key fields, so
+    // that comparisons inside the heap can't fail.
+    fn new(tuple: Tuple, run: usize, sort_keys: &Rc<[SortKey]>) -> Result<Self> {
+        cmp_tuples(&tuple, &tuple, sort_keys)?;
+        Ok(MergeHead {
+            tuple,
+            run,
+            sort_keys: sort_keys.clone(),
+        })
+    }
+}
+
+impl Ord for MergeHead {
+    fn cmp(&self, other: &Self) -> Ordering {
+        cmp_tuples(&other.tuple, &self.tuple, &self.sort_keys)
+            .expect("internal bug, merge head key didn't parse")
+            .then(other.run.cmp(&self.run))
+    }
+}
+
+impl PartialOrd for MergeHead {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl PartialEq for MergeHead {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for MergeHead {}
+
+pub struct ExternalSort<I> {
+    // internal state
+    first_time: bool,
+    sorted: Vec<Tuple>, // used when all input fit in memory
+    output_idx: usize, // index into sorted
+    runs: Vec<SortRun>,
+    heads: BinaryHeap<MergeHead>, // at most one from each run
+    // intitialize
+    input: I,
+    sort_keys: Rc<[SortKey]>,
+    stable: bool,
+    memory_budget: usize, // bytes of tuples to buffer before spilling
+}
+
+impl<I: DbIterator> ExternalSort<I> {
+    pub fn new(
+        input: I,
+        sort_keys: Vec<SortKey>,
+        stable: bool,
+        memory_budget: usize,
+        ) -> Self
+    {
+        ExternalSort {
+            first_time: true,
+            sorted: Vec::new(),
+            output_idx: 0,
+            runs: Vec::new(),
+            heads: BinaryHeap::new(),
+            input,
+            sort_keys: sort_keys.into(),
+            stable,
+            memory_budget,
+        }
+    }
+
+    /// Number of sorted runs written to disk, zero if the input fit in
+    /// the memory budget.
+    pub fn spilled_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    fn sort_input(&mut self) -> Result<()> {
+        let mut buffer = Vec::new();
+        let mut buffer_size = 0;
+        while let Some(tuple) = self.input.next()? {
+            buffer_size += tuple.data.len() + tuple.indexes.len() * mem::size_of::<usize>();
+            buffer.push(tuple);
+            if buffer_size > self.memory_budget {
+                let run = mem::take(&mut buffer);
+                self.write_run(run)?;
+                buffer_size = 0;
+            }
+        }
+
+        if self.runs.is_empty() {
+            sort_tuples(&mut buffer, &self.sort_keys, self.stable)?;
+            self.sorted = buffer;
+        } else {
+            if !buffer.is_empty() {
+                self.write_run(buffer)?;
+            }
+            self.start_merge()?;
+        }
+        Ok(())
+    }
+
+    fn write_run(&mut self, mut buffer: Vec<Tuple>) -> Result<()> {
+        sort_tuples(&mut buffer, &self.sort_keys, self.stable)?;
+
+        let file = TempFile::new("sort");
+        let mut wtr = SpillWriter::new(file.create()?);
+        for tuple in &buffer {
+            wtr.add_tuple(tuple)?;
+        }
+        wtr.flush()?;
+
+        self.runs.push(SortRun {
+            scan: SpillScan::new(file.open()?),
+            _file: file,
+        });
+        Ok(())
+    }
+
+    // Fills the heap with the first tuple of each run.
+    fn start_merge(&mut self) -> Result<()> {
+        self.heads.clear();
+        for run in 0..self.runs.len() {
+            self.push_next(run)?;
+        }
+        Ok(())
+    }
+
+    fn push_next(&mut self, run: usize) -> Result<()> {
+        if let Some(tuple) = self.runs[run].scan.next()? {
+            self.heads.push(MergeHead::new(tuple, run, &self.sort_keys)?);
+        }
+        Ok(())
+    }
+
+    fn next_merged(&mut self) -> Result<Option<Tuple>> {
+        let head = match self.heads.pop() {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+        self.push_next(head.run)?;
+        Ok(Some(head.tuple))
+    }
+}
+
+impl<I: DbIterator> DbIterator for ExternalSort<I> {
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        if self.first_time {
+            self.sort_input()?;
+            self.first_time = false;
+        }
+
+        if !self.runs.is_empty() {
+            return self.next_merged();
+        }
+        let res = self.sorted.get(self.output_idx).cloned();
+        self.output_idx += 1;
+        Ok(res)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        // an error while sorting leaves partial runs, so start over
+        if self.first_time {
+            self.runs.clear();
+            return self.input.reset();
+        }
+        self.output_idx = 0;
+        for run in &mut self.runs {
+            run.scan.reset()?;
+        }
+        self.start_merge()
+    }
+
+    fn explain(&self) -> Explain {
+        Explain::new("ExternalSort")
+            .param("sort_keys", explain::sort_keys(&self.sort_keys))
+            .param("stable", self.stable)
+            .param("memory_budget", self.memory_budget)
+            .input(self.input.explain())
+    }
+}
@@ -0,0 +1,268 @@
+// Grouped aggregation which does not need sorted input.
+//
+// Groups are kept in a hash table keyed by the bytes of the group_by
+// columns. Once the table holds max_groups groups, tuples for any new
+// group are instead written out to partition temp files by the hash of
+// their group key, in the spill stream format. After the input is done,
+// the in-memory groups are emitted, then each partition is aggregated in
+// turn by a nested HashAggregate (which may spill again, hashing with a
+// different seed).
+//
+// Output tuples hold the group_by columns followed by the aggregates,
+// the same as Aggregate. Groups are emitted in order of first appearance
+// within the in-memory table and within each partition.
+use std::collections::HashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::{Hash, Hasher};
+
+use byteorder::{WriteBytesExt, BigEndian};
+
+use error::*;
+use storage::TempFile;
+use storage::spill::{SpillScan, SpillWriter};
+use super::DbIterator;
+use super::explain::{self, Explain, list};
+use DataType;
+use super::aggregate::{
+    Accumulator,
+    AggregateExpr,
+    TypedAggregate,
+    finish_accumulators,
+    new_accumulators,
+    typed_aggregates,
+    update_accumulators,
+};
+use super::tuple::Tuple;
+
+const PARTITION_COUNT: usize = 8;
+
+// Past this many levels of spilling, groups are kept in memory regardless
+// of max_groups.
+const MAX_SPILL_LEVEL: usize = 4;
+
+#[derive(Debug)]
+struct Group {
+    labels: Vec<Option<Vec<u8>>>,
+    accs: Vec<Accumulator>,
+}
+
+struct Partition {
+    file: TempFile,
+    writer: Option<SpillWriter<File>>,
+    spilled: bool, // whether any tuple was written
+}
+
+pub struct HashAggregate<I> {
+    // internal state
+    first_time: bool,
+    groups: Vec<Group>,
+    output_idx: usize, // index into groups
+    partitions: Vec<Partition>, // spilled, and not yet aggregated
+    spilled_partitions: usize,
+    current: Option<(TempFile, Box<dyn DbIterator>)>, // aggregating a partition
+    level: usize, // levels of spilling above this node
+    // intitialize
+    input: I,
+    aggregates: Vec<TypedAggregate>,
+    group_by: Vec<usize>,
+    max_groups: usize,
+}
+
+impl<I: DbIterator> HashAggregate<I> {
+    /// Aggregate args are type checked against the input's column_types.
+    pub fn new(
+        input: I,
+        aggregates: Vec<AggregateExpr>,
+        group_by: Vec<usize>,
+        max_groups: usize,
+        column_types: &[DataType],
+        ) -> Result<Self>
+    {
+        let aggregates = typed_aggregates(aggregates, column_types)?;
+        Ok(Self::with_level(input, aggregates, group_by, max_groups, 0))
+    }
+
+    fn with_level(
+        input: I,
+        aggregates: Vec<TypedAggregate>,
+        group_by: Vec<usize>,
+        max_groups: usize,
+        level: usize,
+        ) -> Self
+    {
+        HashAggregate {
+            first_time: true,
+            groups: Vec::new(),
+            output_idx: 0,
+            partitions: Vec::new(),
+            spilled_partitions: 0,
+            current: None,
+            level,
+            input,
+            aggregates,
+            group_by,
+            max_groups,
+        }
+    }
+
+    /// Number of partitions written to disk by this node (not counting
+    /// nested spills), zero if all groups fit in memory.
+    pub fn spilled_partitions(&self) -> usize {
+        self.spilled_partitions
+    }
+
+    // Length prefixed fields, so that keys of different columns
+    // can't run together. Null is a single 0 byte.
+    fn group_key(&self, tuple: &Tuple) -> Vec<u8> {
+        let mut key = Vec::new();
+        for &col in &self.group_by {
+            if tuple.is_null(col) {
+                key.push(0);
+            } else {
+                key.push(1);
+                key.write_u32::<BigEndian>(tuple[col].len() as u32)
+                    .expect("Bad Convert");
+                key.extend_from_slice(&tuple[col]);
+            }
+        }
+        key
+    }
+
+    fn aggregate_input(&mut self) -> Result<()> {
+        let mut table: HashMap<Vec<u8>, usize> = HashMap::new(); // index into groups
+        while let Some(tuple) = self.input.next()? {
+            let key = self.group_key(&tuple);
+            let group_idx = match table.get(&key) {
+                Some(&group_idx) => group_idx,
+                None => {
+                    if self.groups.len() >= self.max_groups && self.level < MAX_SPILL_LEVEL {
+                        self.spill(&key, tuple)?;
+                        continue;
+                    }
+                    let labels = self.group_by.iter().map(|&col| {
+                        if tuple.is_null(col) {
+                            None
+                        } else {
+                            Some(tuple[col].to_vec())
+                        }
+                    }).collect();
+                    self.groups.push(Group {
+                        labels,
+                        accs: new_accumulators(&self.aggregates),
+                    });
+                    table.insert(key, self.groups.len() - 1);
+                    self.groups.len() - 1
+                },
+            };
+            update_accumulators(&mut self.groups[group_idx].accs, &self.aggregates, &tuple)?;
+        }
+
+        // Without group_by there is always one group, even for no input
+        if self.group_by.is_empty() && self.groups.is_empty() {
+            self.groups.push(Group {
+                labels: vec![],
+                accs: new_accumulators(&self.aggregates),
+            });
+        }
+
+        for partition in &mut self.partitions {
+            if let Some(mut writer) = partition.writer.take() {
+                writer.flush()?;
+            }
+        }
+        self.partitions.retain(|partition| partition.spilled);
+        self.spilled_partitions = self.partitions.len();
+        Ok(())
+    }
+
+    fn spill(&mut self, key: &[u8], tuple: Tuple) -> Result<()> {
+        if self.partitions.is_empty() {
+            for _ in 0..PARTITION_COUNT {
+                let file = TempFile::new("agg");
+                let writer = SpillWriter::new(file.create()?);
+                self.partitions.push(Partition {
+                    file,
+                    writer: Some(writer),
+                    spilled: false,
+                });
+            }
+        }
+
+        // seed with level, so that a partition spilled again
+        // is split up differently
+        let mut hasher = DefaultHasher::new();
+        self.level.hash(&mut hasher);
+        key.hash(&mut hasher);
+        let partition = &mut self.partitions[hasher.finish() as usize % PARTITION_COUNT];
+
+        partition.spilled = true;
+        // writers are only taken after input is done
+        partition.writer.as_mut().unwrap().add_tuple(&tuple)
+    }
+
+    fn next_partition(&mut self) -> Result<bool> {
+        if self.partitions.is_empty() {
+            return Ok(false);
+        }
+        let partition = self.partitions.remove(0);
+        // partitions which weren't spilled to were removed after spilling
+        let scan = SpillScan::new(partition.file.open()?);
+        let aggregate = HashAggregate::with_level(
+            scan,
+            self.aggregates.clone(),
+            self.group_by.clone(),
+            self.max_groups,
+            self.level + 1,
+        );
+        self.current = Some((partition.file, aggregate.boxed()));
+        Ok(true)
+    }
+}
+
+impl<I: DbIterator> DbIterator for HashAggregate<I> {
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        if self.first_time {
+            self.aggregate_input()?;
+            self.first_time = false;
+        }
+
+        if let Some(group) = self.groups.get(self.output_idx) {
+            self.output_idx += 1;
+            let results = finish_accumulators(&group.accs)?;
+            return Ok(Some(Tuple::from_nullable(group.labels.iter().cloned().chain(results).collect())));
+        }
+
+        loop {
+            if let Some((_, ref mut aggregate)) = self.current {
+                if let Some(tuple) = aggregate.next()? {
+                    return Ok(Some(tuple));
+                }
+            }
+            self.current = None;
+            if !self.next_partition()? {
+                return Ok(None);
+            }
+        }
+    }
+
+    // Partitions are consumed while aggregating them, so start over
+    fn reset(&mut self) -> Result<()> {
+        self.input.reset()?;
+        self.first_time = true;
+        self.groups.clear();
+        self.output_idx = 0;
+        self.partitions.clear();
+        self.spilled_partitions = 0;
+        self.current = None;
+        Ok(())
+    }
+
+    fn explain(&self) -> Explain {
+        Explain::new("HashAggregate")
+            .param("aggregates", explain::aggregates(&self.aggregates))
+            .param("group_by", list(&self.group_by))
+            .param("max_groups", self.max_groups)
+            .input(self.input.explain())
+    }
+}
@@ -0,0 +1,171 @@
+// The right input is drained into an in-memory hash table keyed by the
+// bytes of its join key (see join::JoinKey), then the left input is
+// streamed and probed against the table. The table is built lazily on
+// the first call to next, and is kept across resets since the right
+// input is not rescanned.
+//
+// For right and full outer joins, the right tuples which were never
+// matched are emitted from the table after the left input is done.
+use std::collections::HashMap;
+
+use DataType;
+use error::*;
+use super::DbIterator;
+use super::explain::Explain;
+use super::join::{JoinKey, JoinType, pad_left, pad_right};
+use super::tuple::{Tuple};
+
+#[derive(Debug, Clone)]
+pub struct HashJoin<L, R> {
+    // internal state
+    table: Option<HashMap<Vec<u8>, Vec<usize>>>, // indexes into build
+    build: Vec<Tuple>, // all right tuples, including those with null keys
+    matched_r: Vec<bool>, // by index into build
+    current_l: Option<Tuple>,
+    matches: Vec<usize>, // indexes into build which match current_l
+    match_idx: usize, // index into matches
+    unmatched_idx: usize, // index into build, after left input is done
+    // intitialize
+    input_l: L,
+    input_r: R,
+    key: JoinKey,
+    join_type: JoinType,
+    width_l: usize, // columns of input_l, for padding
+    width_r: usize,
+}
+
+impl<L: DbIterator, R: DbIterator> HashJoin<L, R> {
+    pub fn new(
+        input_l: L,
+        input_r: R,
+        col_l: usize,
+        col_r: usize,
+        join_type: JoinType,
+        types_l: &[DataType],
+        types_r: &[DataType],
+        ) -> Result<Self>
+    {
+        Ok(HashJoin {
+            table: None,
+            build: Vec::new(),
+            matched_r: Vec::new(),
+            current_l: None,
+            matches: Vec::new(),
+            match_idx: 0,
+            unmatched_idx: 0,
+            input_l,
+            input_r,
+            key: JoinKey::new(col_l, col_r, types_l, types_r)?,
+            join_type,
+            width_l: types_l.len(),
+            width_r: types_r.len(),
+        })
+    }
+
+    fn build(&mut self) -> Result<()> {
+        let mut table: HashMap<Vec<u8>, Vec<usize>> = HashMap::new();
+        self.build.clear(); // may be left over from a failed build
+        while let Some(tuple_r) = self.input_r.next()? {
+            // keys which never match are only kept in build
+            if let Some(key) = self.key.right(&tuple_r)? {
+                table.entry(key.into_owned())
+                    .or_default()
+                    .push(self.build.len());
+            }
+            self.build.push(tuple_r);
+        }
+        self.matched_r = vec![false; self.build.len()];
+        self.table = Some(table);
+        Ok(())
+    }
+
+    fn next_unmatched_right(&mut self) -> Option<Tuple> {
+        if !self.join_type.emits_unmatched_right() {
+            return None;
+        }
+        while self.unmatched_idx < self.build.len() {
+            let idx = self.unmatched_idx;
+            self.unmatched_idx += 1;
+            if !self.matched_r[idx] {
+                return Some(pad_left(self.width_l, self.build[idx].clone()));
+            }
+        }
+        None
+    }
+}
+
+impl <L: DbIterator, R: DbIterator> DbIterator for HashJoin<L, R>
+    where Self: Sized,
+{
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        if self.table.is_none() {
+            self.build()?;
+        }
+
+        loop {
+            if let Some(ref current_l) = self.current_l {
+                if self.match_idx < self.matches.len() {
+                    let idx = self.matches[self.match_idx];
+                    self.match_idx += 1;
+                    self.matched_r[idx] = true;
+                    let mut tuple_r = self.build[idx].clone();
+                    return Ok(Some(current_l.clone().append(&mut tuple_r)));
+                }
+            }
+
+            self.current_l = None;
+            self.match_idx = 0;
+            let tuple_l = match self.input_l.next()? {
+                Some(tuple_l) => tuple_l,
+                None => return Ok(self.next_unmatched_right()),
+            };
+
+            // table was built above, so it's ok to unwrap
+            self.matches = match self.key.left(&tuple_l)? {
+                Some(key) => {
+                    self.table.as_ref().unwrap()
+                        .get(&*key)
+                        .cloned()
+                        .unwrap_or_default()
+                },
+                None => Vec::new(),
+            };
+
+            let is_matched = !self.matches.is_empty();
+            match self.join_type {
+                JoinType::Semi if is_matched => return Ok(Some(tuple_l)),
+                JoinType::Anti if !is_matched => return Ok(Some(tuple_l)),
+                JoinType::Semi | JoinType::Anti => (),
+                _ if !is_matched && self.join_type.emits_unmatched_left() => {
+                    return Ok(Some(pad_right(tuple_l, self.width_r)));
+                },
+                _ => self.current_l = Some(tuple_l),
+            }
+        }
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.input_l.reset()?;
+        self.current_l = None;
+        self.matches.clear();
+        self.match_idx = 0;
+        self.unmatched_idx = 0;
+        for matched in &mut self.matched_r {
+            *matched = false;
+        }
+        // a failed build is started over
+        if self.table.is_none() {
+            self.input_r.reset()?;
+        }
+        Ok(())
+    }
+
+    fn explain(&self) -> Explain {
+        Explain::new("HashJoin")
+            .param("join_type", format!("{:?}", self.join_type))
+            .param("col_l", self.key.col_l)
+            .param("col_r", self.key.col_r)
+            .input(self.input_l.explain())
+            .input(self.input_r.explain())
+    }
+}
@@ -7,7 +7,9 @@ use std::fs::File;
 
 // TODO change Schema to ColumnTypes?
 use Schema;
+use error::*;
 use super::DbIterator;
+use super::explain::Explain;
 use super::tuple::Tuple;
 
 pub struct CsvSource {
@@ -17,41 +19,48 @@ pub struct CsvSource {
 }
 
 impl CsvSource {
-    pub fn new (location: String, schema: Schema) -> Self {
+    pub fn new (location: String, schema: Schema) -> Result<Self> {
+        let rdr = Self::open(&location)?;
 
-        let f = File::open(&location).expect("fix this panic");
+        Ok(CsvSource {
+            location,
+            output: rdr,
+            schema,
+        })
+    }
+
+    fn open(location: &str) -> Result<StringRecordsIntoIter<File>> {
+        let f = File::open(location)
+            .chain_err(|| format!("error opening csv file {}", location))?;
 
-        let rdr = ReaderBuilder::new()
+        Ok(ReaderBuilder::new()
             .has_headers(true)
             .from_reader(f)
-            .into_records();
-
-        CsvSource {
-            location: location,
-            output: rdr,
-            schema: schema,
-        }
+            .into_records())
     }
 }
 
 impl DbIterator for CsvSource {
-    fn next(&mut self) -> Option<Tuple> {
-        self.output.next().map(|record| {
-            Tuple::from_stringrecord(
-                record.expect("could not read csv record"),
-                &self.schema
-            ).expect("convert to tuple failed")
-        })
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        match self.output.next() {
+            Some(record) => {
+                let record = record
+                    .chain_err(|| format!("could not read csv record from {}", self.location))?;
+                Tuple::from_stringrecord(record, &self.schema)
+                    .chain_err(|| "convert to tuple failed")
+                    .map(Some)
+            },
+            None => Ok(None),
+        }
     }
 
-    fn reset(&mut self) {
-        // hacky, let's fix this to be consistent with original
-        // initialization
-        let f = File::open(&self.location).expect("fix this panic to rtn err");
-        self.output = ReaderBuilder::new()
-            .has_headers(true)
-            .from_reader(f)
-            .into_records();
+    fn reset(&mut self) -> Result<()> {
+        self.output = Self::open(&self.location)?;
+        Ok(())
+    }
+
+    fn explain(&self) -> Explain {
+        Explain::new("CsvSource").param("location", &self.location)
     }
 }
 
@@ -0,0 +1,182 @@
+// Common pieces for the join nodes.
+//
+// Joins are equijoins on one column from each side, except that a nested
+// loops join can instead take any predicate over the columns of both
+// sides. Key columns of different types are converted to the type which
+// they both convert to exactly (see key_type), so e.g. an Integer column
+// can be joined on a BigInt one. Null keys never match anything, and
+// neither do float NaNs, so they only show up in the output as unmatched
+// tuples.
+//
+// Semi and anti joins emit only the left tuple. Outer joins pad the
+// missing side with a null field for each of its columns. Joins are given
+// the column types of both inputs when they're made, so the padding has
+// the right width even when the other input produced no tuples at all.
+use std::borrow::Cow;
+use std::cmp::Ordering;
+
+use DataType;
+use error::*;
+use super::expr::{ExprType, Value};
+use super::tuple::{Tuple, ToTupleField, cmp_with_type, field_parse};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinType {
+    Inner,
+    LeftOuter,
+    RightOuter,
+    FullOuter,
+    Semi,
+    Anti,
+}
+
+impl JoinType {
+    pub fn emits_unmatched_left(&self) -> bool {
+        *self == JoinType::LeftOuter || *self == JoinType::FullOuter
+    }
+
+    pub fn emits_unmatched_right(&self) -> bool {
+        *self == JoinType::RightOuter || *self == JoinType::FullOuter
+    }
+}
+
+/// Whether keys of these types are equal exactly when their bytes are,
+/// so that they can be compared without converting. Decimals only need
+/// the same scale, and Text any length.
+pub fn same_key_encoding(type_l: &DataType, type_r: &DataType) -> bool {
+    match (type_l, type_r) {
+        (&DataType::Decimal(_, scale_l), &DataType::Decimal(_, scale_r)) => scale_l == scale_r,
+        (&DataType::Text(_), &DataType::Text(_)) => true,
+        _ => type_l == type_r,
+    }
+}
+
+/// Type which keys of these types are compared as, if they can be joined.
+/// As in expressions, numbers widen and Dates become Timestamps, except
+/// that keys are only compared as Float if both are, since ints and
+/// Decimals don't all fit one.
+pub fn key_type(type_l: &DataType, type_r: &DataType) -> Option<DataType> {
+    if same_key_encoding(type_l, type_r) {
+        return Some(type_l.clone());
+    }
+    match ExprType::Column(type_l.clone()).unify(&ExprType::Column(type_r.clone()))? {
+        ExprType::Column(DataType::Float) => Some(DataType::Double),
+        ExprType::Column(data_type) => Some(data_type),
+        ExprType::Null => None,
+    }
+}
+
+/// The key column of each side of a join, and the types they're read and
+/// compared as.
+#[derive(Debug, Clone)]
+pub struct JoinKey {
+    pub col_l: usize,
+    pub col_r: usize,
+    type_l: DataType,
+    type_r: DataType,
+    key_type: DataType,
+}
+
+impl JoinKey {
+    /// Key columns whose types don't convert to a common one (see
+    /// key_type) are an error.
+    pub fn new(col_l: usize, col_r: usize, types_l: &[DataType], types_r: &[DataType]) -> Result<Self> {
+        let type_l = types_l[col_l].clone();
+        let type_r = types_r[col_r].clone();
+        let key_type = match key_type(&type_l, &type_r) {
+            Some(key_type) => key_type,
+            None => bail!(ErrorKind::ExprType(format!(
+                "can't join keys of {:?} and {:?}",
+                type_l,
+                type_r,
+            ))),
+        };
+        Ok(JoinKey {
+            col_l,
+            col_r,
+            type_l,
+            type_r,
+            key_type,
+        })
+    }
+
+    /// The key of a left tuple, as bytes which are equal exactly when
+    /// keys are. None for keys which never match: nulls and NaNs.
+    pub fn left<'t>(&self, tuple_l: &'t Tuple) -> Result<Option<Cow<'t, [u8]>>> {
+        join_key(tuple_l, self.col_l, &self.type_l, &self.key_type)
+    }
+
+    pub fn right<'t>(&self, tuple_r: &'t Tuple) -> Result<Option<Cow<'t, [u8]>>> {
+        join_key(tuple_r, self.col_r, &self.type_r, &self.key_type)
+    }
+
+    pub fn matches(&self, tuple_l: &Tuple, tuple_r: &Tuple) -> Result<bool> {
+        match (self.left(tuple_l)?, self.right(tuple_r)?) {
+            (Some(key_l), Some(key_r)) => Ok(key_l == key_r),
+            _ => Ok(false),
+        }
+    }
+
+    /// Order of the keys of tuples which both have one, i.e. aren't null.
+    pub fn cmp(&self, tuple_l: &Tuple, tuple_r: &Tuple) -> Result<Ordering> {
+        let key_l = convert(&tuple_l[self.col_l], &self.type_l, &self.key_type)?;
+        let key_r = convert(&tuple_r[self.col_r], &self.type_r, &self.key_type)?;
+        cmp_with_type(&key_l, &key_r, &self.key_type)
+    }
+}
+
+// Float zeros are all made 0.0, since -0.0 is equal to it.
+fn join_key<'t>(
+    tuple: &'t Tuple,
+    col: usize,
+    col_type: &DataType,
+    key_type: &DataType,
+) -> Result<Option<Cow<'t, [u8]>>>
+{
+    if tuple.is_null(col) {
+        return Ok(None);
+    }
+    let field = convert(&tuple[col], col_type, key_type)?;
+    let key = match *key_type {
+        DataType::Float => {
+            let x: f32 = field_parse(&field)?;
+            if x.is_nan() {
+                None
+            } else if x == 0. {
+                Some(Cow::Owned(0f32.to_tuple_field()))
+            } else {
+                Some(field)
+            }
+        },
+        DataType::Double => {
+            let x: f64 = field_parse(&field)?;
+            if x.is_nan() {
+                None
+            } else if x == 0. {
+                Some(Cow::Owned(0f64.to_tuple_field()))
+            } else {
+                Some(field)
+            }
+        },
+        _ => Some(field),
+    };
+    Ok(key)
+}
+
+fn convert<'f>(field: &'f [u8], from: &DataType, to: &DataType) -> Result<Cow<'f, [u8]>> {
+    if same_key_encoding(from, to) {
+        return Ok(Cow::Borrowed(field));
+    }
+    match Value::from_field(field, from)?.to_field(to)? {
+        Some(converted) => Ok(Cow::Owned(converted)),
+        None => bail!("internal bug, {:?} key converted to null", from),
+    }
+}
+
+pub fn pad_right(tuple_l: Tuple, width_r: usize) -> Tuple {
+    tuple_l.append(&mut Tuple::null(width_r))
+}
+
+pub fn pad_left(width_l: usize, mut tuple_r: Tuple) -> Tuple {
+    Tuple::null(width_l).append(&mut tuple_r)
+}
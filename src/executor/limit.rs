@@ -1,27 +1,45 @@
+use error::*;
 use super::DbIterator;
+use super::explain::Explain;
 use super::tuple::Tuple;
 
 #[derive(Debug, Clone)]
 pub struct Limit<I> {
     pub count: usize,
-    pub limit: usize,
+    pub limit: Option<usize>, // None is no limit, e.g. for only an offset
+    pub offset: usize,
     pub input: I,
 }
 
 impl <I: DbIterator> DbIterator for Limit<I>
     where Self: Sized,
 {
-    fn next(&mut self) -> Option<Tuple> {
-        if self.count < self.limit {
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        while self.count < self.offset {
+            if self.input.next()?.is_none() {
+                return Ok(None);
+            }
+            self.count += 1;
+        }
+        if self.limit.is_none_or(|limit| self.count - self.offset < limit) {
             self.count += 1;
             self.input.next()
         } else {
-            None
+            Ok(None)
         }
     }
 
-    fn reset(&mut self) {
-        self.input.reset();
+    fn reset(&mut self) -> Result<()> {
+        self.count = 0;
+        self.input.reset()
     }
-}
 
+    fn explain(&self) -> Explain {
+        let explain = Explain::new("Limit");
+        let explain = match self.limit {
+            Some(limit) => explain.param("limit", limit),
+            None => explain,
+        };
+        explain.param("offset", self.offset).input(self.input.explain())
+    }
+}
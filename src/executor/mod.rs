@@ -1,50 +1,122 @@
 pub mod aggregate;
+pub mod datetime;
+pub mod decimal;
+pub mod explain;
+pub mod expr;
+pub mod external_sort;
+pub mod hash_aggregate;
+pub mod hash_join;
 pub mod io;
+pub mod join;
 pub mod limit;
 pub mod nested_loops_join;
 pub mod projection;
 pub mod scan;
 pub mod selection;
 pub mod simplesort;
+pub mod sort_merge_join;
 pub mod tuple;
 
 use DataType;
-use self::aggregate::{Aggregate, AggregateType};
+use error::*;
+use self::aggregate::{Aggregate, AggregateExpr, AggregateType};
+use self::explain::{Analyze, Explain};
+use self::expr::{Expr, TypedExpr};
+use self::external_sort::ExternalSort;
+use self::hash_aggregate::HashAggregate;
+use self::hash_join::HashJoin;
+use self::join::JoinType;
 use self::limit::Limit;
 use self::nested_loops_join::NestedLoopsJoin;
-use self::projection::Projection;
+use self::projection::{ComputedProjection, Projection};
 use self::scan::Scan;
 use self::selection::Selection;
-use self::simplesort::{SimpleSort, SortOrder};
+use self::simplesort::{SimpleSort, SortKey, SortOrder};
+use self::sort_merge_join::SortMergeJoin;
 use self::tuple::Tuple;
 
 // The Executor
 
+/// Nodes return errors (e.g. I/O, corrupt blocks, bad parses) instead of
+/// panicking, and Ok(None) once the input is done. After an error, the
+/// node's state is unspecified until it is reset.
 pub trait DbIterator {
-    fn next(&mut self) -> Option<Tuple>;
+    fn next(&mut self) -> Result<Option<Tuple>>;
 
-    fn reset(&mut self);
+    fn reset(&mut self) -> Result<()>;
+
+    /// Describes this node and its inputs, for EXPLAIN. Nodes which don't
+    /// implement it are shown by their type's name, with no inputs.
+    fn explain(&self) -> Explain {
+        Explain::from_type::<Self>()
+    }
+
+    /// Records what this node does as it runs, for EXPLAIN ANALYZE.
+    fn analyze(self) -> Analyze<Self> where Self: Sized {
+        Analyze::new(self)
+    }
 
     fn scan(self) -> Scan<Self> where Self: Sized {
         Scan {input: self}
     }
 
+    /// Erases the type of a pipeline, e.g. so that inputs of different
+    /// types can be chosen at runtime.
+    fn boxed<'a>(self) -> Box<dyn DbIterator + 'a>
+        where Self: Sized + 'a,
+    {
+        Box::new(self)
+    }
+
     fn limit(self, limit: usize) -> Limit<Self>
         where Self: Sized,
     {
-        Limit {input: self, limit: limit, count: 0,}
+        Limit {input: self, limit: Some(limit), offset: 0, count: 0,}
+    }
+
+    /// Skips the first offset tuples, then returns at most limit (or all
+    /// the rest, if None), like SQL's LIMIT ... OFFSET.
+    fn limit_offset(self, limit: Option<usize>, offset: usize) -> Limit<Self>
+        where Self: Sized,
+    {
+        Limit {input: self, limit, offset, count: 0,}
     }
 
     fn selection<P>(self, predicate: P) -> Selection<Self, P>
         where Self: Sized, P: FnMut(&Tuple) -> bool,
     {
-        Selection {input: self, predicate: predicate}
+        Selection {input: self, predicate}
+    }
+
+    /// Selection on a typed expression, which is checked against the
+    /// types of this node's columns.
+    fn filter(
+        self,
+        predicate: Expr,
+        column_types: &[DataType],
+    ) -> Result<Selection<Self, TypedExpr>>
+        where Self: Sized,
+    {
+        let predicate = TypedExpr::predicate(predicate, column_types)?;
+        Ok(Selection {input: self, predicate})
     }
 
     fn projection(self, columns: Vec<usize>) -> Projection<Self>
         where Self: Sized,
     {
-        Projection {input: self, columns: columns}
+        Projection {input: self, columns}
+    }
+
+    /// Projection of expressions over this node's columns, e.g. for
+    /// derived columns. Output column types come from the expressions.
+    fn computed_projection(
+        self,
+        exprs: Vec<Expr>,
+        column_types: &[DataType],
+    ) -> Result<ComputedProjection<Self>>
+        where Self: Sized,
+    {
+        ComputedProjection::new(self, exprs, column_types)
     }
 
     fn simplesort(
@@ -57,9 +129,34 @@ pub trait DbIterator {
     {
         SimpleSort::new(
             self,
-            sort_on_col,
-            sort_on_type,
-            sort_order,
+            vec![(sort_on_col, sort_on_type, sort_order)],
+            false,
+        )
+    }
+
+    fn sort_by_keys(
+        self,
+        sort_keys: Vec<SortKey>,
+        stable: bool,
+    ) -> SimpleSort<Self>
+        where Self: Sized,
+    {
+        SimpleSort::new(self, sort_keys, stable)
+    }
+
+    fn external_sort(
+        self,
+        sort_keys: Vec<SortKey>,
+        stable: bool,
+        memory_budget: usize,
+    ) -> ExternalSort<Self>
+        where Self: Sized,
+    {
+        ExternalSort::new(
+            self,
+            sort_keys,
+            stable,
+            memory_budget,
         )
     }
 
@@ -67,56 +164,176 @@ pub trait DbIterator {
         self,
         aggregation: AggregateType,
         aggregate_col: usize,
-        aggregate_col_type: DataType,
+        column_types: &[DataType],
         group_by: Option<usize>,
-    ) -> Aggregate<Self>
+    ) -> Result<Aggregate<Self>>
         where Self: Sized
     {
         Aggregate::new(
             self,
-            aggregation,
-            aggregate_col,
-            aggregate_col_type,
-            group_by,
+            vec![(aggregation, Expr::col(aggregate_col))],
+            group_by.into_iter().collect(),
+            column_types,
         )
     }
 
-    fn nested_loops_join(
+    /// Aggregates are type checked against this node's column types.
+    fn aggregates(
+        self,
+        aggregates: Vec<AggregateExpr>,
+        group_by: Vec<usize>,
+        column_types: &[DataType],
+    ) -> Result<Aggregate<Self>>
+        where Self: Sized
+    {
+        Aggregate::new(self, aggregates, group_by, column_types)
+    }
+
+    /// Aggregates without needing input sorted by group. Past max_groups
+    /// groups in memory, input is partitioned to disk.
+    fn hash_aggregate(
+        self,
+        aggregates: Vec<AggregateExpr>,
+        group_by: Vec<usize>,
+        max_groups: usize,
+        column_types: &[DataType],
+    ) -> Result<HashAggregate<Self>>
+        where Self: Sized
+    {
+        HashAggregate::new(self, aggregates, group_by, max_groups, column_types)
+    }
+
+    /// Joins take the column types of both inputs, for padding outer
+    /// joins with null fields even when an input is empty. Key columns
+    /// whose types can't be compared are an error.
+    fn nested_loops_join<J>(
         self,
-        other: Self,
+        other: J,
         col_l: usize,
         col_r: usize,
-    ) -> NestedLoopsJoin<Self>
-        where Self: Sized
+        join_type: JoinType,
+        types_l: &[DataType],
+        types_r: &[DataType],
+    ) -> Result<NestedLoopsJoin<Self, J>>
+        where Self: Sized, J: DbIterator,
     {
         NestedLoopsJoin::new(
             self,
             other,
             col_l,
             col_r,
+            join_type,
+            types_l,
+            types_r,
+        )
+    }
+
+    /// A nested loops join on a predicate over the columns of self
+    /// followed by those of other.
+    fn nested_loops_join_on<J>(
+        self,
+        other: J,
+        predicate: Expr,
+        join_type: JoinType,
+        types_l: &[DataType],
+        types_r: &[DataType],
+    ) -> Result<NestedLoopsJoin<Self, J>>
+        where Self: Sized, J: DbIterator,
+    {
+        NestedLoopsJoin::on(self, other, predicate, join_type, types_l, types_r)
+    }
+
+    fn hash_join<J>(
+        self,
+        other: J,
+        col_l: usize,
+        col_r: usize,
+        join_type: JoinType,
+        types_l: &[DataType],
+        types_r: &[DataType],
+    ) -> Result<HashJoin<Self, J>>
+        where Self: Sized, J: DbIterator,
+    {
+        HashJoin::new(
+            self,
+            other,
+            col_l,
+            col_r,
+            join_type,
+            types_l,
+            types_r,
+        )
+    }
+
+    /// Both key columns are converted to the common type they're joined
+    /// as (see join::key_type), and are compared as that type.
+    fn sort_merge_join<J>(
+        self,
+        other: J,
+        col_l: usize,
+        col_r: usize,
+        join_type: JoinType,
+        types_l: &[DataType],
+        types_r: &[DataType],
+    ) -> Result<SortMergeJoin<Self, J>>
+        where Self: Sized, J: DbIterator,
+    {
+        SortMergeJoin::new(
+            self,
+            other,
+            col_l,
+            col_r,
+            join_type,
+            types_l,
+            types_r,
         )
     }
 }
 
-#[derive(Debug)]
+impl<I: DbIterator + ?Sized> DbIterator for Box<I> {
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        (**self).next()
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        (**self).reset()
+    }
+
+    fn explain(&self) -> Explain {
+        (**self).explain()
+    }
+}
+
+#[derive(Debug, Clone)]
 pub struct TestSource {
     source: Vec<Tuple>,
     i: usize,
 }
 
+impl TestSource {
+    pub fn new(source: Vec<Tuple>) -> Self {
+        TestSource { source, i: 0 }
+    }
+}
+
 impl DbIterator for TestSource {
-    fn next(&mut self) -> Option<Tuple> {
+    fn next(&mut self) -> Result<Option<Tuple>> {
         if self.i < self.source.len() {
             let res = self.source[self.i].clone();
             self.i += 1;
-            Some(res)
+            Ok(Some(res))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    fn reset(&mut self) {
+    fn reset(&mut self) -> Result<()> {
         self.i = 0;
+        Ok(())
+    }
+
+    fn explain(&self) -> Explain {
+        Explain::new("TestSource").param("tuples", self.source.len())
     }
 }
 
@@ -152,6 +369,10 @@ mod tests {
         vec![t0, t1, t2]
     }
 
+    fn make_tuple_types() -> Vec<DataType> {
+        vec![DataType::Text(6), DataType::SmallInt, DataType::Text(6)]
+    }
+
     #[test]
     fn test_tuples() {
         let tuples = make_tuples();
@@ -168,10 +389,10 @@ mod tests {
             i: 0,
         };
         let mut scan_iter = test_source.scan();
-        assert_eq!(scan_iter.next(), Some(tuples[0].clone()));
-        assert_eq!(scan_iter.next(), Some(tuples[1].clone()));
-        assert_eq!(scan_iter.next(), Some(tuples[2].clone()));
-        assert_eq!(scan_iter.next(), None);
+        assert_eq!(scan_iter.next().unwrap(), Some(tuples[0].clone()));
+        assert_eq!(scan_iter.next().unwrap(), Some(tuples[1].clone()));
+        assert_eq!(scan_iter.next().unwrap(), Some(tuples[2].clone()));
+        assert_eq!(scan_iter.next().unwrap(), None);
     }
 
     #[test]
@@ -186,8 +407,437 @@ mod tests {
                 t.data.len() < 10 // fake, without using api for tuple
             }
         );
-        assert_eq!(selection_iter.next(), Some(tuples[1].clone()));
-        assert_eq!(selection_iter.next(), None);
+        assert_eq!(selection_iter.next().unwrap(), Some(tuples[1].clone()));
+        assert_eq!(selection_iter.next().unwrap(), None);
+    }
+
+    #[test]
+    fn test_filter() {
+        use self::expr::BinaryOp::*;
+        use DataType::*;
+
+        let column_types = vec![Text(6), SmallInt, Text(6)];
+        let tuples = make_tuples();
+        let test_source = TestSource {
+            source: tuples.clone(),
+            i: 0,
+        };
+
+        // number_test > 50 and the name doesn't start with "t"
+        let predicate = Expr::and(
+            Expr::binary(Gt, Expr::col(1), Expr::lit(50i16)),
+            Expr::negate(Expr::like(Expr::col(0), "t%")),
+        );
+        let mut query = test_source.clone().filter(predicate, &column_types).unwrap();
+        assert_eq!(query.next().unwrap(), Some(tuples[1].clone()));
+        assert_eq!(query.next().unwrap(), None);
+
+        let predicate = Expr::in_list(Expr::col(2), vec![Expr::lit("three"), Expr::lit("twelve")]);
+        let mut query = test_source.clone().filter(predicate, &column_types).unwrap();
+        assert_eq!(query.next().unwrap(), Some(tuples[0].clone()));
+        assert_eq!(query.next().unwrap(), Some(tuples[2].clone()));
+        assert_eq!(query.next().unwrap(), None);
+
+        // not a predicate, or mismatched types
+        assert!(test_source.clone().filter(Expr::col(1), &column_types).is_err());
+        let predicate = Expr::binary(Eq, Expr::col(0), Expr::lit(2i16));
+        assert!(test_source.clone().filter(predicate, &column_types).is_err());
+        let predicate = Expr::binary(Eq, Expr::col(3), Expr::lit(2i16));
+        assert!(test_source.clone().filter(predicate, &column_types).is_err());
+
+        // errors while evaluating come up through next
+        let predicate = Expr::binary(
+            Lt,
+            Expr::binary(Multiply, Expr::col(1), Expr::lit(200i16)),
+            Expr::lit(10i16),
+        );
+        let mut query = test_source.filter(predicate, &column_types).unwrap();
+        assert!(query.next().is_err());
+    }
+
+    #[test]
+    fn test_computed_projection() {
+        use self::expr::BinaryOp::*;
+        use self::expr::Function::*;
+        use self::tuple::{ToTupleField, string_to_binary};
+        use DataType::*;
+
+        let column_types = vec![Text(8), Float, SmallInt];
+        let movie = |title: &str, rating: Option<f32>, year: i16| {
+            Tuple::from_nullable(vec![
+                Some(string_to_binary(title, &Text(8)).unwrap()),
+                rating.map(|rating| rating.to_tuple_field()),
+                Some(year.to_tuple_field()),
+            ])
+        };
+        let source = TestSource {
+            source: vec![
+                movie("heat", Some(4.5), 1995),
+                movie("ran", None, 1985),
+            ],
+            i: 0,
+        };
+
+        let exprs = vec![
+            Expr::call(Upper, vec![Expr::col(0)]),
+            Expr::binary(Multiply, Expr::col(1), Expr::lit(2f32)),
+            Expr::call(Concat, vec![
+                Expr::call(Substring, vec![Expr::col(0), Expr::lit(1i16), Expr::lit(2i16)]),
+                Expr::cast(Expr::col(2), Text(4)),
+            ]),
+            Expr::case(
+                vec![(Expr::binary(Lt, Expr::col(2), Expr::lit(1990i16)), Expr::lit(0i16))],
+                Some(Expr::binary(Subtract, Expr::col(2), Expr::lit(1990i32))),
+            ),
+        ];
+        let mut query = source.clone().computed_projection(exprs, &column_types).unwrap();
+        assert_eq!(query.column_types(), &[Text(8), Float, Text(12), Integer][..]);
+
+        assert_eq!(query.next().unwrap(), Some(Tuple::from_nullable(vec![
+            Some(string_to_binary("HEAT", &Text(8)).unwrap()),
+            Some(9f32.to_tuple_field()),
+            Some(string_to_binary("he1995", &Text(12)).unwrap()),
+            Some(5i32.to_tuple_field()),
+        ])));
+        assert_eq!(query.next().unwrap(), Some(Tuple::from_nullable(vec![
+            Some(string_to_binary("RAN", &Text(8)).unwrap()),
+            None,
+            Some(string_to_binary("ra1985", &Text(12)).unwrap()),
+            Some(0i32.to_tuple_field()),
+        ])));
+        assert_eq!(query.next().unwrap(), None);
+        query.reset().unwrap();
+        assert!(query.next().unwrap().is_some());
+
+        // computed columns can be aggregated, or expressions directly
+        let expr = Expr::binary(Add, Expr::col(2), Expr::lit(100_000i32));
+        let mut query = source.clone()
+            .computed_projection(vec![expr.clone()], &column_types).unwrap()
+            .aggregate(AggregateType::Sum, 0, &[Integer], None).unwrap();
+        assert_eq!(query.next().unwrap(), Some(Tuple::new(vec![203_980i64.to_tuple_field()])));
+        let mut query = source.clone()
+            .aggregates(vec![(AggregateType::Sum, expr)], vec![], &column_types).unwrap();
+        assert_eq!(query.next().unwrap(), Some(Tuple::new(vec![203_980i64.to_tuple_field()])));
+
+        // predicates are Boolean columns
+        let exprs = vec![Expr::binary(Lt, Expr::col(2), Expr::lit(1990i16))];
+        let mut query = source.clone().computed_projection(exprs, &column_types).unwrap();
+        assert_eq!(query.column_types(), &[Boolean][..]);
+        assert_eq!(query.next().unwrap(), Some(Tuple::new(vec![false.to_tuple_field()])));
+        assert_eq!(query.next().unwrap(), Some(Tuple::new(vec![true.to_tuple_field()])));
+        let exprs = vec![Expr::call(Length, vec![Expr::col(1)])];
+        assert!(source.computed_projection(exprs, &column_types).is_err());
+    }
+
+    #[test]
+    fn test_expr_eval() {
+        use self::expr::BinaryOp::*;
+        use self::expr::{ExprType, Value};
+        use self::tuple::ToTupleField;
+        use DataType::*;
+
+        let column_types = vec![SmallInt, Integer, Float, Text(8)];
+        let tuple = Tuple::from_nullable(vec![
+            Some(300i16.to_tuple_field()),
+            None,
+            Some(2.5f32.to_tuple_field()),
+            Some(b"lemur".to_vec()),
+        ]);
+        let eval = |expr: Expr| {
+            TypedExpr::new(expr, &column_types).unwrap().eval(&tuple)
+        };
+
+        // arithmetic widens to the larger type
+        let expr = Expr::binary(Add, Expr::col(0), Expr::lit(70_000i32));
+        assert_eq!(expr.check(&column_types).unwrap(), ExprType::Column(Integer));
+        assert_eq!(eval(expr).unwrap(), Value::Integer(70_300));
+        let expr = Expr::binary(Multiply, Expr::col(0), Expr::col(2));
+        assert_eq!(eval(expr).unwrap(), Value::Float(750.));
+        assert!(eval(Expr::binary(Multiply, Expr::col(0), Expr::col(0))).is_err());
+        assert!(eval(Expr::binary(Divide, Expr::col(0), Expr::lit(0i16))).is_err());
+
+        // three valued logic on the null column
+        let is_big = Expr::binary(Gt, Expr::col(1), Expr::lit(5i32));
+        assert_eq!(eval(is_big.clone()).unwrap(), Value::Null);
+        assert_eq!(eval(Expr::negate(is_big.clone())).unwrap(), Value::Null);
+        assert_eq!(eval(Expr::and(is_big.clone(), Expr::lit(false))).unwrap(), Value::Boolean(false));
+        assert_eq!(eval(Expr::and(is_big.clone(), Expr::lit(true))).unwrap(), Value::Null);
+        assert_eq!(eval(Expr::or(is_big.clone(), Expr::lit(true))).unwrap(), Value::Boolean(true));
+        assert_eq!(eval(Expr::binary(Add, Expr::col(1), Expr::lit(1i32))).unwrap(), Value::Null);
+        let expr = Expr::in_list(Expr::col(0), vec![Expr::col(1), Expr::lit(1i16)]);
+        assert_eq!(eval(expr).unwrap(), Value::Null);
+        let expr = Expr::in_list(Expr::col(0), vec![Expr::col(1), Expr::lit(300i16)]);
+        assert_eq!(eval(expr).unwrap(), Value::Boolean(true));
+        assert_eq!(eval(Expr::is_null(is_big.clone())).unwrap(), Value::Boolean(true));
+        assert_eq!(eval(Expr::is_null(Expr::col(0))).unwrap(), Value::Boolean(false));
+
+        // text
+        assert_eq!(eval(Expr::binary(Eq, Expr::col(3), Expr::lit("lemur"))).unwrap(), Value::Boolean(true));
+        assert_eq!(eval(Expr::like(Expr::col(3), "l_m%")).unwrap(), Value::Boolean(true));
+        assert_eq!(eval(Expr::like(Expr::col(3), "%r%u")).unwrap(), Value::Boolean(false));
+        assert_eq!(eval(Expr::like(Expr::col(3), "%%ur")).unwrap(), Value::Boolean(true));
+        let substring = |start: i64, len: i64| {
+            eval(Expr::call(self::expr::Function::Substring, vec![Expr::col(3), Expr::lit(start), Expr::lit(len)])).unwrap()
+        };
+        assert_eq!(substring(2, 3), Value::Text("emu".to_owned()));
+        assert_eq!(substring(-1, 4), Value::Text("le".to_owned()));
+        assert_eq!(substring(i64::MIN, i64::MAX), Value::Text("".to_owned()));
+        assert_eq!(substring(i64::MAX, i64::MAX), Value::Text("".to_owned()));
+        assert_eq!(substring(3, i64::MIN), Value::Text("".to_owned()));
+
+        // floats compare as IEEE 754: -0.0 equals 0.0, and NaN is unordered
+        let cmp = |op, x: f32, y: f32| eval(Expr::binary(op, Expr::lit(x), Expr::lit(y))).unwrap();
+        assert_eq!(cmp(Eq, -0.0, 0.0), Value::Boolean(true));
+        assert_eq!(cmp(Lt, -0.0, 0.0), Value::Boolean(false));
+        assert_eq!(cmp(Eq, f32::NAN, f32::NAN), Value::Boolean(false));
+        assert_eq!(cmp(NotEq, f32::NAN, f32::NAN), Value::Boolean(true));
+        for op in [Lt, LtEq, Gt, GtEq] {
+            assert_eq!(cmp(op, f32::NAN, 1.0), Value::Boolean(false));
+            assert_eq!(cmp(op, 1.0, f32::NAN), Value::Boolean(false));
+        }
+
+        let case = Expr::case(
+            vec![
+                (is_big, Expr::lit("big")),
+                (Expr::binary(Lt, Expr::col(2), Expr::lit(3f32)), Expr::lit("small")),
+            ],
+            Some(Expr::lit("other")),
+        );
+        assert_eq!(case.check(&column_types).unwrap(), ExprType::Column(Text(5)));
+        assert_eq!(eval(case).unwrap(), Value::Text("small".to_owned()));
+        let case = Expr::case(vec![(Expr::lit(true), Expr::lit(1i16))], Some(Expr::lit("one")));
+        assert!(case.check(&column_types).is_err());
+
+        assert_eq!(eval(Expr::cast(Expr::col(2), SmallInt)).unwrap(), Value::SmallInt(3));
+        assert_eq!(eval(Expr::cast(Expr::col(0), Text(3))).unwrap(), Value::Text("300".to_owned()));
+        assert_eq!(eval(Expr::cast(Expr::lit(" 42"), Integer)).unwrap(), Value::Integer(42));
+        assert_eq!(eval(Expr::cast(Expr::col(1), Float)).unwrap(), Value::Null);
+        assert!(eval(Expr::cast(Expr::col(3), Integer)).is_err());
+        assert!(eval(Expr::cast(Expr::col(0), Text(2))).is_err());
+    }
+
+    #[test]
+    fn test_datetime_exprs() {
+        use self::datetime::{parse_date, parse_timestamp};
+        use self::expr::BinaryOp::*;
+        use self::expr::Function::*;
+        use self::expr::{ExprType, Value};
+        use self::tuple::ToTupleField;
+        use DataType::*;
+
+        let column_types = vec![Date, Timestamp, Boolean, Double];
+        let date = parse_date("2000-02-28").unwrap();
+        let ts = parse_timestamp("2000-03-01T12:30:15.5Z").unwrap();
+        let tuple = Tuple::new(vec![
+            date.to_tuple_field(),
+            ts.to_tuple_field(),
+            true.to_tuple_field(),
+            0.1f64.to_tuple_field(),
+        ]);
+        let eval = |expr: Expr| {
+            TypedExpr::new(expr, &column_types).unwrap().eval(&tuple)
+        };
+        let date_lit = |s: &str| Expr::lit(Value::Date(parse_date(s).unwrap()));
+
+        // days are added to Dates, and seconds to Timestamps
+        let expr = Expr::binary(Add, Expr::col(0), Expr::lit(2i16));
+        assert_eq!(expr.check(&column_types).unwrap(), ExprType::Column(Date));
+        assert_eq!(eval(expr).unwrap(), Value::Date(parse_date("2000-03-01").unwrap()));
+        assert_eq!(
+            eval(Expr::binary(Subtract, Expr::col(1), Expr::lit(0.5f64))).unwrap(),
+            Value::Timestamp(parse_timestamp("2000-03-01 12:30:15").unwrap())
+        );
+        assert_eq!(
+            eval(Expr::binary(Subtract, date_lit("2000-03-01"), Expr::col(0))).unwrap(),
+            Value::Integer(2)
+        );
+        assert_eq!(
+            eval(Expr::binary(Subtract, Expr::col(1), Expr::cast(Expr::col(0), Timestamp))).unwrap(),
+            Value::Double(2. * 86_400. + 45_015.5)
+        );
+        assert!(Expr::binary(Multiply, Expr::col(0), Expr::lit(2i16)).check(&column_types).is_err());
+        assert!(Expr::binary(Add, Expr::col(0), Expr::col(0)).check(&column_types).is_err());
+
+        // Dates compare with Timestamps at midnight
+        assert_eq!(eval(Expr::binary(Lt, Expr::col(0), Expr::col(1))).unwrap(), Value::Boolean(true));
+        let expr = Expr::binary(Eq, date_lit("2000-03-01"), Expr::cast(Expr::col(1), Date));
+        assert_eq!(eval(expr).unwrap(), Value::Boolean(true));
+
+        assert_eq!(eval(Expr::call(Year, vec![Expr::col(0)])).unwrap(), Value::Integer(2000));
+        assert_eq!(eval(Expr::call(Month, vec![Expr::col(1)])).unwrap(), Value::Integer(3));
+        assert_eq!(eval(Expr::call(Day, vec![Expr::col(0)])).unwrap(), Value::Integer(28));
+        assert_eq!(eval(Expr::call(Hour, vec![Expr::col(1)])).unwrap(), Value::Integer(12));
+        assert_eq!(eval(Expr::call(Second, vec![Expr::col(1)])).unwrap(), Value::Integer(15));
+        assert_eq!(eval(Expr::call(Minute, vec![Expr::col(0)])).unwrap(), Value::Integer(0));
+        assert!(Expr::call(Year, vec![Expr::col(3)]).check(&column_types).is_err());
+
+        // casts, with Timestamps as unix seconds
+        assert_eq!(
+            eval(Expr::cast(Expr::lit("2000-02-28"), Date)).unwrap(),
+            Value::Date(date)
+        );
+        assert_eq!(
+            eval(Expr::cast(Expr::col(1), Text(30))).unwrap(),
+            Value::Text("2000-03-01 12:30:15.5".to_owned())
+        );
+        assert_eq!(
+            eval(Expr::cast(Expr::lit(86_400i32), Timestamp)).unwrap(),
+            Value::Timestamp(parse_timestamp("1970-01-02").unwrap())
+        );
+        assert_eq!(eval(Expr::cast(Expr::col(2), Integer)).unwrap(), Value::Integer(1));
+        assert_eq!(eval(Expr::cast(Expr::lit("no"), Boolean)).unwrap(), Value::Boolean(false));
+        assert!(eval(Expr::cast(Expr::lit("2000-02-30"), Date)).is_err());
+        assert!(Expr::cast(Expr::col(0), Integer).check(&column_types).is_err());
+
+        // Booleans are predicates, and Doubles keep their precision
+        assert_eq!(eval(Expr::and(Expr::col(2), Expr::lit(true))).unwrap(), Value::Boolean(true));
+        assert_eq!(
+            eval(Expr::binary(Add, Expr::col(3), Expr::lit(0.2f64))).unwrap(),
+            Value::Double(0.1 + 0.2)
+        );
+    }
+
+    #[test]
+    fn test_decimal_exprs() {
+        use self::expr::BinaryOp::*;
+        use self::expr::Function::*;
+        use self::expr::{ExprType, Value};
+        use self::tuple::{ToTupleField, string_to_binary};
+        use DataType::*;
+
+        let column_types = vec![Decimal(5, 2), Decimal(10, 4), Integer, Double];
+        let tuple = Tuple::new(vec![
+            string_to_binary("12.5", &column_types[0]).unwrap(),
+            string_to_binary("-0.0125", &column_types[1]).unwrap(),
+            3i32.to_tuple_field(),
+            0.5f64.to_tuple_field(),
+        ]);
+        let eval = |expr: Expr| {
+            TypedExpr::new(expr, &column_types).unwrap().eval(&tuple)
+        };
+
+        // exact, with enough digits on each side of the point
+        let expr = Expr::binary(Add, Expr::col(0), Expr::col(1));
+        assert_eq!(expr.check(&column_types).unwrap(), ExprType::Column(Decimal(11, 4)));
+        assert_eq!(eval(expr).unwrap(), Value::Decimal(124_875, 11, 4));
+        assert_eq!(
+            eval(Expr::binary(Multiply, Expr::col(0), Expr::col(2))).unwrap(),
+            Value::Decimal(3750, 16, 2)
+        );
+        // quotients round, and keep at least 6 digits after the point
+        let expr = Expr::binary(Divide, Expr::col(0), Expr::col(2));
+        assert_eq!(
+            eval(Expr::cast(expr, Text(20))).unwrap(),
+            Value::Text("4.1666666666667".to_owned())
+        );
+        assert!(eval(Expr::binary(Divide, Expr::col(0), Expr::lit(0i16))).is_err());
+        // floats are approximate, so they win
+        assert_eq!(eval(Expr::binary(Add, Expr::col(0), Expr::col(3))).unwrap(), Value::Double(13.));
+
+        // past 38 digits is an error
+        let max = Expr::cast(Expr::lit("9".repeat(38).as_str()), Decimal(38, 0));
+        let expr = Expr::binary(Add, max.clone(), max);
+        assert_eq!(expr.check(&column_types).unwrap(), ExprType::Column(Decimal(38, 0)));
+        assert!(eval(expr).is_err());
+
+        // compares across scales and with ints
+        let expr = Expr::binary(Eq, Expr::col(0), Expr::cast(Expr::lit("12.5"), Decimal(3, 1)));
+        assert_eq!(eval(expr).unwrap(), Value::Boolean(true));
+        assert_eq!(eval(Expr::binary(Gt, Expr::col(0), Expr::col(2))).unwrap(), Value::Boolean(true));
+        assert_eq!(eval(Expr::binary(Lt, Expr::col(1), Expr::lit(0i16))).unwrap(), Value::Boolean(true));
+        let expr = Expr::case(vec![(Expr::lit(false), Expr::col(0))], Some(Expr::col(2)));
+        assert_eq!(expr.check(&column_types).unwrap(), ExprType::Column(Decimal(12, 2)));
+
+        // casts round half away from zero, and must fit the precision
+        assert_eq!(eval(Expr::cast(Expr::col(0), Integer)).unwrap(), Value::Integer(13));
+        assert_eq!(eval(Expr::cast(Expr::col(1), Decimal(3, 2))).unwrap(), Value::Decimal(-1, 3, 2));
+        assert_eq!(eval(Expr::cast(Expr::col(0), Decimal(2, 0))).unwrap(), Value::Decimal(13, 2, 0));
+        assert!(eval(Expr::cast(Expr::col(0), Decimal(3, 2))).is_err());
+        assert_eq!(eval(Expr::cast(Expr::lit(0.1f64), Decimal(5, 2))).unwrap(), Value::Decimal(10, 5, 2));
+        assert_eq!(eval(Expr::cast(Expr::col(0), Text(8))).unwrap(), Value::Text("12.50".to_owned()));
+        assert!(eval(Expr::cast(Expr::lit("12.5x"), Decimal(5, 2))).is_err());
+
+        assert_eq!(eval(Expr::call(Round, vec![Expr::col(0)])).unwrap(), Value::Decimal(1300, 5, 2));
+        let expr = Expr::call(Round, vec![Expr::cast(Expr::lit("9.9"), Decimal(2, 1))]);
+        assert!(eval(expr).is_err());
+
+        // with more digits after the point than in all, or none at all
+        let bad_types = [Decimal(5, 6), Decimal(0, 0)];
+        let expr = Expr::binary(Add, Expr::col(0), Expr::col(1));
+        assert!(expr.check(&bad_types).is_err());
+        let expr = Expr::binary(Add, Expr::cast(Expr::lit("1"), Decimal(5, 6)), Expr::col(0));
+        assert!(expr.check(&column_types).is_err());
+        assert!(Expr::cast(Expr::col(2), Decimal(0, 0)).check(&column_types).is_err());
+    }
+
+    #[test]
+    fn test_decimal_sort_join_aggregate() {
+        use self::aggregate::Accumulator;
+        use self::tuple::{ToTupleField, display_with_type, string_to_binary};
+        use self::AggregateType::*;
+        use DataType::*;
+
+        let price_type = Decimal(6, 2);
+        let price = |s: &str| string_to_binary(s, &price_type).unwrap();
+        let source = TestSource::new(
+            vec![(1i16, "-1.5"), (2, "10"), (1, "2.25"), (2, "-1.50"), (1, "0.1"), (1, "0.2")]
+                .into_iter()
+                .map(|(group, s)| Tuple::new(vec![group.to_tuple_field(), price(s)]))
+                .collect()
+        );
+        let collect = |mut query: Box<dyn DbIterator>, col: usize, data_type: &DataType| {
+            let mut res = Vec::new();
+            while let Some(tuple) = query.next().unwrap() {
+                res.push(display_with_type(&tuple[col], data_type).unwrap());
+            }
+            res
+        };
+
+        // negative digits do not sort correctly as raw bytes
+        let sorted = source.clone().simplesort(1, price_type.clone(), SortOrder::Ascending);
+        assert_eq!(
+            collect(sorted.boxed(), 1, &price_type),
+            vec!["-1.50", "-1.50", "0.10", "0.20", "2.25", "10.00"]
+        );
+
+        // keys of the same scale join, whatever their precision
+        let key_type = Decimal(10, 2);
+        let keys = TestSource::new(
+            vec!["-1.5", "0.2"].into_iter()
+                .map(|s| Tuple::new(vec![string_to_binary(s, &key_type).unwrap()]))
+                .collect()
+        );
+        let joined = source.clone()
+            .simplesort(1, price_type.clone(), SortOrder::Ascending)
+            .sort_merge_join(keys, 1, 0, JoinType::Inner, &[SmallInt, price_type.clone()], std::slice::from_ref(&key_type)).unwrap();
+        assert_eq!(collect(joined.boxed(), 2, &key_type), vec!["-1.50", "-1.50", "0.20"]);
+
+        // sums are exact, and averages keep at least 6 digits
+        assert_eq!(Sum.result_type(&price_type), Decimal(38, 2));
+        assert_eq!(Avg.result_type(&price_type), Decimal(38, 6));
+        let column_types = [SmallInt, price_type.clone()];
+        let aggregates = vec![
+            (Sum, Expr::col(1)),
+            (Avg, Expr::col(1)),
+            (Min, Expr::col(1)),
+        ];
+        let query = source.clone().hash_aggregate(aggregates.clone(), vec![0], 10, &column_types).unwrap();
+        let sorted = query.simplesort(0, SmallInt, SortOrder::Ascending);
+        let res = collect(sorted.boxed(), 1, &Decimal(38, 2));
+        assert_eq!(res, vec!["1.05", "8.50"]);
+
+        let mut query = source.aggregates(aggregates, vec![], &column_types).unwrap();
+        let tuple = query.next().unwrap().unwrap();
+        let results = [(0, Decimal(38, 2), "9.55"), (1, Decimal(38, 6), "1.591667"), (2, price_type, "-1.50")];
+        for &(col, ref data_type, expected) in &results {
+            assert_eq!(display_with_type(&tuple[col], data_type).unwrap(), expected);
+        }
+
+        // a sum past 38 digits is an error
+        let max_type = Decimal(38, 0);
+        let mut acc = Accumulator::new(&Sum, &max_type);
+        acc.update(&string_to_binary(&"9".repeat(38), &max_type).unwrap()).unwrap();
+        assert!(acc.update(&1i128.to_tuple_field()).is_err());
     }
 
     #[test]
@@ -213,11 +863,11 @@ mod tests {
                 vec![0u8, 222],
             ]
         );
-        let res_tuples = vec![r0, r1, r2];
-        assert_eq!(projection_iter.next(), Some(res_tuples[0].clone()));
-        assert_eq!(projection_iter.next(), Some(res_tuples[1].clone()));
-        assert_eq!(projection_iter.next(), Some(res_tuples[2].clone()));
-        assert_eq!(projection_iter.next(), None);
+        let res_tuples = [r0, r1, r2];
+        assert_eq!(projection_iter.next().unwrap(), Some(res_tuples[0].clone()));
+        assert_eq!(projection_iter.next().unwrap(), Some(res_tuples[1].clone()));
+        assert_eq!(projection_iter.next().unwrap(), Some(res_tuples[2].clone()));
+        assert_eq!(projection_iter.next().unwrap(), None);
     }
 
     #[test]
@@ -241,10 +891,994 @@ mod tests {
                 vec![0u8, 66],
             ]
         );
-        let res_tuples = vec![r0, r1];
-        assert_eq!(query.next(), Some(res_tuples[0].clone()));
-        assert_eq!(query.next(), Some(res_tuples[1].clone()));
-        assert_eq!(query.next(), None);
+        let res_tuples = [r0, r1];
+        assert_eq!(query.next().unwrap(), Some(res_tuples[0].clone()));
+        assert_eq!(query.next().unwrap(), Some(res_tuples[1].clone()));
+        assert_eq!(query.next().unwrap(), None);
+    }
+
+    #[test]
+    fn test_hash_join() {
+        let tuples = make_tuples();
+        let left = TestSource {
+            source: tuples.clone(),
+            i: 0,
+        };
+        let right = TestSource {
+            source: vec![
+                Tuple::new(vec![vec![0u8, 66], b"x".to_vec()]),
+                Tuple::new(vec![vec![0u8, 2], b"y".to_vec()]),
+                Tuple::new(vec![vec![0u8, 66], b"z".to_vec()]),
+            ],
+            i: 0,
+        };
+        let (types_l, types_r) = (make_tuple_types(), vec![DataType::SmallInt, DataType::Text(1)]);
+        let mut query = left.clone().hash_join(right.clone(), 1, 0, JoinType::Inner, &types_l, &types_r).unwrap();
+        let mut expected = left.nested_loops_join(right, 1, 0, JoinType::Inner, &types_l, &types_r).unwrap();
+
+        let mut count = 0;
+        while let Some(tuple) = query.next().unwrap() {
+            assert_eq!(Some(tuple), expected.next().unwrap());
+            count += 1;
+        }
+        assert_eq!(expected.next().unwrap(), None);
+        assert_eq!(count, 3);
+
+        query.reset().unwrap();
+        assert_eq!(
+            query.next().unwrap(),
+            Some(tuples[0].clone().append(&mut Tuple::new(vec![vec![0u8, 2], b"y".to_vec()])))
+        );
+    }
+
+    #[test]
+    fn test_sort_merge_join() {
+        use self::tuple::ToTupleField;
+
+        let make_source = |keys: Vec<f32>| {
+            TestSource {
+                source: keys.into_iter().enumerate().map(|(i, key)| {
+                    Tuple::new(vec![key.to_tuple_field(), (i as i16).to_tuple_field()])
+                }).collect(),
+                i: 0,
+            }
+        };
+        let joined = |key: f32, i_l: i16, i_r: i16| {
+            Tuple::new(vec![
+                key.to_tuple_field(),
+                i_l.to_tuple_field(),
+                key.to_tuple_field(),
+                i_r.to_tuple_field(),
+            ])
+        };
+
+        // negative floats do not sort correctly as raw bytes
+        let left = make_source(vec![-2.0, 1.5, 1.5, 3.0, 4.0]);
+        let right = make_source(vec![-3.0, -2.0, -2.0, 1.5, 2.0, 3.0]);
+        let types = vec![DataType::Float, DataType::SmallInt];
+        let mut query = left.sort_merge_join(right, 0, 0, JoinType::Inner, &types, &types).unwrap();
+
+        let expected = vec![
+            joined(-2.0, 0, 1),
+            joined(-2.0, 0, 2),
+            joined(1.5, 1, 3),
+            joined(1.5, 2, 3),
+            joined(3.0, 3, 5),
+        ];
+        for tuple in &expected {
+            assert_eq!(query.next().unwrap().as_ref(), Some(tuple));
+        }
+        assert_eq!(query.next().unwrap(), None);
+
+        query.reset().unwrap();
+        assert_eq!(query.next().unwrap(), Some(expected[0].clone()));
+    }
+
+    #[test]
+    fn test_float_join_keys() {
+        use self::tuple::ToTupleField;
+
+        // -0.0 joins 0.0, and NaN joins nothing, whatever the algorithm
+        let make_source = |keys: Vec<f64>| {
+            let mut tuples: Vec<Tuple> = keys.into_iter()
+                .map(|key| Tuple::new(vec![key.to_tuple_field()]))
+                .collect();
+            simplesort::sort_tuples(&mut tuples, &[(0, DataType::Double, SortOrder::Ascending)], true).unwrap();
+            TestSource::new(tuples)
+        };
+        let left = make_source(vec![f64::NAN, -0.0, 1.5]);
+        let right = make_source(vec![0.0, f64::NAN, 1.5]);
+        let types = vec![DataType::Double];
+        let queries = vec![
+            left.clone().nested_loops_join(right.clone(), 0, 0, JoinType::Inner, &types, &types).unwrap().boxed(),
+            left.clone().hash_join(right.clone(), 0, 0, JoinType::Inner, &types, &types).unwrap().boxed(),
+            left.clone().sort_merge_join(right.clone(), 0, 0, JoinType::Inner, &types, &types).unwrap().boxed(),
+        ];
+        for mut query in queries {
+            let mut keys = Vec::new();
+            while let Some(tuple) = query.next().unwrap() {
+                keys.push((tuple.get_parse::<f64>(0).unwrap(), tuple.get_parse::<f64>(1).unwrap()));
+            }
+            assert_eq!(keys.len(), 2);
+            assert_eq!(keys[0], (0.0, 0.0));
+            assert!(keys[0].0.is_sign_negative());
+            assert_eq!(keys[1], (1.5, 1.5));
+        }
+
+        // a field which isn't its key's type is an error, not a panic
+        let bad = TestSource::new(vec![Tuple::new(vec![vec![0u8, 1, 2]]), Tuple::new(vec![vec![0u8, 2]])]);
+        assert!(bad.clone().simplesort(0, DataType::SmallInt, SortOrder::Ascending).next().is_err());
+        let mut query = bad.clone().sort_merge_join(bad, 0, 0, JoinType::Inner, &[DataType::SmallInt], &[DataType::SmallInt]).unwrap();
+        assert!(query.next().is_err());
+    }
+
+    #[test]
+    fn test_heterogeneous_join() {
+        let tuples = make_tuples();
+        let left = TestSource {
+            source: tuples.clone(),
+            i: 0,
+        };
+        let right = TestSource {
+            source: tuples.clone(),
+            i: 0,
+        };
+        let expected = tuples[1].clone().append(&mut tuples[1].clone());
+        let types = make_tuple_types();
+
+        // join against a different pipeline type
+        let mut query = left.clone()
+            .nested_loops_join(right.clone().selection(|t| t[1][1] == 66), 1, 1, JoinType::Inner, &types, &types).unwrap();
+        assert_eq!(query.next().unwrap(), Some(expected.clone()));
+        assert_eq!(query.next().unwrap(), None);
+
+        // join against boxed inputs
+        let inputs: Vec<Box<dyn DbIterator>> = vec![
+            right.clone().boxed(),
+            right.selection(|t| t[1][1] == 66).boxed(),
+        ];
+        for input in inputs {
+            let mut query = left.clone()
+                .selection(|t| t[1][1] == 66)
+                .boxed()
+                .hash_join(input, 1, 1, JoinType::Inner, &types, &types).unwrap();
+            assert_eq!(query.next().unwrap(), Some(expected.clone()));
+            assert_eq!(query.next().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_join_types() {
+        use self::tuple::ToTupleField;
+
+        // key, tag. Null keys sort last.
+        let make_tuple = |key: Option<i16>, tag: i16| {
+            let key = match key {
+                Some(key) => Tuple::new(vec![key.to_tuple_field()]),
+                None => Tuple::null(1),
+            };
+            key.append(&mut Tuple::new(vec![tag.to_tuple_field()]))
+        };
+        let left = TestSource {
+            source: vec![
+                make_tuple(Some(1), 10),
+                make_tuple(Some(2), 11),
+                make_tuple(Some(2), 12),
+                make_tuple(Some(4), 13),
+                make_tuple(None, 14),
+            ],
+            i: 0,
+        };
+        let right = TestSource {
+            source: vec![
+                make_tuple(Some(2), 20),
+                make_tuple(Some(3), 21),
+                make_tuple(Some(4), 22),
+                make_tuple(Some(4), 23),
+                make_tuple(None, 24),
+            ],
+            i: 0,
+        };
+        let types = vec![DataType::SmallInt, DataType::SmallInt];
+        let l = |i: usize| left.source[i].clone();
+        let r = |i: usize| right.source[i].clone();
+        let inner = vec![
+            l(1).append(&mut r(0)),
+            l(2).append(&mut r(0)),
+            l(3).append(&mut r(2)),
+            l(3).append(&mut r(3)),
+        ];
+        let unmatched_l = [
+            l(0).append(&mut Tuple::null(2)),
+            l(4).append(&mut Tuple::null(2)),
+        ];
+        let unmatched_r = [
+            Tuple::null(2).append(&mut r(1)),
+            Tuple::null(2).append(&mut r(4)),
+        ];
+
+        let cases = vec![
+            (JoinType::Inner, inner.clone()),
+            (JoinType::LeftOuter, [&inner[..], &unmatched_l[..]].concat()),
+            (JoinType::RightOuter, [&inner[..], &unmatched_r[..]].concat()),
+            (JoinType::FullOuter, [&inner[..], &unmatched_l[..], &unmatched_r[..]].concat()),
+            (JoinType::Semi, vec![l(1), l(2), l(3)]),
+            (JoinType::Anti, vec![l(0), l(4)]),
+        ];
+
+        // Operators may emit unmatched tuples in different orders
+        let sorted = |mut tuples: Vec<Tuple>| {
+            tuples.sort_by_key(|t| format!("{:?}", t));
+            tuples
+        };
+        let collect = |mut query: Box<dyn DbIterator>| {
+            let mut res = Vec::new();
+            while let Some(tuple) = query.next().unwrap() {
+                res.push(tuple);
+            }
+            // and again after reset
+            query.reset().unwrap();
+            let mut res_after_reset = Vec::new();
+            while let Some(tuple) = query.next().unwrap() {
+                res_after_reset.push(tuple);
+            }
+            assert_eq!(res, res_after_reset);
+            sorted(res)
+        };
+
+        for (join_type, expected) in cases {
+            let expected = sorted(expected);
+            let predicate = Expr::binary(expr::BinaryOp::Eq, Expr::col(0), Expr::col(2));
+            let queries = vec![
+                left.clone().nested_loops_join(right.clone(), 0, 0, join_type, &types, &types).unwrap().boxed(),
+                left.clone().nested_loops_join_on(right.clone(), predicate, join_type, &types, &types).unwrap().boxed(),
+                left.clone().hash_join(right.clone(), 0, 0, join_type, &types, &types).unwrap().boxed(),
+                left.clone().sort_merge_join(right.clone(), 0, 0, join_type, &types, &types).unwrap().boxed(),
+            ];
+            for query in queries {
+                assert_eq!(collect(query), expected, "{:?}", join_type);
+            }
+        }
+
+        // an empty input is still padded to its width
+        let empty = TestSource::new(vec![]);
+        let padded_l = sorted(left.source.iter().map(|t| t.clone().append(&mut Tuple::null(2))).collect());
+        let padded_r = sorted(right.source.iter().map(|t| Tuple::null(2).append(&mut t.clone())).collect());
+        let queries = vec![
+            (left.clone().nested_loops_join(empty.clone(), 0, 0, JoinType::LeftOuter, &types, &types).unwrap().boxed(), &padded_l),
+            (left.clone().hash_join(empty.clone(), 0, 0, JoinType::FullOuter, &types, &types).unwrap().boxed(), &padded_l),
+            (left.clone().sort_merge_join(empty.clone(), 0, 0, JoinType::LeftOuter, &types, &types).unwrap().boxed(), &padded_l),
+            (empty.clone().nested_loops_join(right.clone(), 0, 0, JoinType::FullOuter, &types, &types).unwrap().boxed(), &padded_r),
+            (empty.clone().hash_join(right.clone(), 0, 0, JoinType::RightOuter, &types, &types).unwrap().boxed(), &padded_r),
+            (empty.sort_merge_join(right.clone(), 0, 0, JoinType::RightOuter, &types, &types).unwrap().boxed(), &padded_r),
+        ];
+        for (query, expected) in queries {
+            let res = collect(query);
+            assert_eq!(&res, expected);
+            for tuple in res {
+                assert_eq!(tuple.indexes.len(), 4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_join_key_types() {
+        use self::tuple::ToTupleField;
+
+        // keys are compared as the type both sides convert to
+        let left = TestSource::new(vec![
+            Tuple::new(vec![1i16.to_tuple_field()]),
+            Tuple::new(vec![2i16.to_tuple_field()]),
+            Tuple::new(vec![3i16.to_tuple_field()]),
+        ]);
+        let right = TestSource::new(vec![
+            Tuple::new(vec![2.0f64.to_tuple_field()]),
+            Tuple::new(vec![2.5f64.to_tuple_field()]),
+            Tuple::new(vec![3.0f64.to_tuple_field()]),
+        ]);
+        let big = TestSource::new(vec![
+            Tuple::new(vec![(-1i64).to_tuple_field()]),
+            Tuple::new(vec![3i64.to_tuple_field()]),
+            Tuple::new(vec![(1i64 << 40).to_tuple_field()]),
+        ]);
+        let cases = vec![
+            (right, DataType::Double, vec![(2, 2.0), (3, 3.0)]),
+            (big, DataType::BigInt, vec![(3, 3.0)]),
+        ];
+        for (right, type_r, expected) in cases {
+            let (types_l, types_r) = (vec![DataType::SmallInt], vec![type_r.clone()]);
+            let queries = vec![
+                left.clone().nested_loops_join(right.clone(), 0, 0, JoinType::Inner, &types_l, &types_r).unwrap().boxed(),
+                left.clone().hash_join(right.clone(), 0, 0, JoinType::Inner, &types_l, &types_r).unwrap().boxed(),
+                left.clone().sort_merge_join(right.clone(), 0, 0, JoinType::Inner, &types_l, &types_r).unwrap().boxed(),
+            ];
+            for mut query in queries {
+                let mut keys = Vec::new();
+                while let Some(tuple) = query.next().unwrap() {
+                    let key_r = expr::Value::from_field(&tuple[1], &type_r).unwrap().cast(&DataType::Double).unwrap();
+                    keys.push((tuple.get_parse::<i16>(0).unwrap(), key_r));
+                }
+                let expected: Vec<_> = expected.iter().map(|&(l, r)| (l, expr::Value::Double(r))).collect();
+                assert_eq!(keys, expected, "{:?}", type_r);
+            }
+        }
+
+        // keys which don't convert to a common type can't be joined
+        let (types_l, types_r) = (vec![DataType::SmallInt], vec![DataType::Text(4)]);
+        assert!(left.clone().nested_loops_join(left.clone(), 0, 0, JoinType::Inner, &types_l, &types_r).is_err());
+        assert!(left.clone().hash_join(left.clone(), 0, 0, JoinType::Inner, &types_l, &types_r).is_err());
+        assert!(left.clone().sort_merge_join(left, 0, 0, JoinType::Inner, &types_l, &types_r).is_err());
+    }
+
+    #[test]
+    fn test_nested_loops_join_on() {
+        use self::expr::BinaryOp::*;
+        use self::tuple::ToTupleField;
+
+        let make_source = |keys: Vec<i32>| {
+            TestSource::new(keys.into_iter().map(|key| Tuple::new(vec![key.to_tuple_field()])).collect())
+        };
+        let left = make_source(vec![1, 2, 3]);
+        let right = make_source(vec![2, 3]);
+        let types = vec![DataType::Integer];
+        let pairs = |join_type: JoinType, predicate: Expr| {
+            let mut query = left.clone()
+                .nested_loops_join_on(right.clone(), predicate, join_type, &types, &types)
+                .unwrap();
+            let mut pairs = Vec::new();
+            while let Some(tuple) = query.next().unwrap() {
+                let key = |col: usize| if tuple.is_null(col) { None } else { tuple.get_parse::<i32>(col).ok() };
+                pairs.push((key(0), key(1)));
+            }
+            pairs
+        };
+
+        let less = Expr::binary(Lt, Expr::col(0), Expr::col(1));
+        assert_eq!(pairs(JoinType::Inner, less.clone()), vec![
+            (Some(1), Some(2)), (Some(1), Some(3)), (Some(2), Some(3)),
+        ]);
+        assert_eq!(pairs(JoinType::LeftOuter, less.clone()), vec![
+            (Some(1), Some(2)), (Some(1), Some(3)), (Some(2), Some(3)), (Some(3), None),
+        ]);
+        assert_eq!(pairs(JoinType::FullOuter, Expr::binary(Gt, Expr::col(0), Expr::col(1))), vec![
+            (Some(1), None), (Some(2), None), (Some(3), Some(2)), (None, Some(3)),
+        ]);
+        // a cross join
+        assert_eq!(pairs(JoinType::Inner, Expr::lit(true)).len(), 6);
+
+        // the predicate is over both inputs, and must be boolean
+        let query = left.clone().nested_loops_join_on(right.clone(), Expr::col(2), JoinType::Inner, &types, &types);
+        assert!(query.is_err());
+        let query = left.clone().nested_loops_join_on(right.clone(), Expr::col(0), JoinType::Inner, &types, &types);
+        assert!(query.is_err());
+
+        let query = left.nested_loops_join_on(right, less, JoinType::Semi, &types, &types).unwrap();
+        assert_eq!(
+            query.explain().to_string().lines().next().unwrap(),
+            "NestedLoopsJoin (join_type: Semi, predicate: #0 < #1)"
+        );
+    }
+
+    #[test]
+    fn test_external_sort() {
+        use self::tuple::ToTupleField;
+
+        // 2 byte key, 4 byte payload
+        let source = TestSource {
+            source: (0..5000i32).map(|i| {
+                let key = ((i * 7919) % 5000) as i16;
+                Tuple::new(vec![key.to_tuple_field(), i.to_tuple_field()])
+            }).collect(),
+            i: 0,
+        };
+
+        for &(memory_budget, spills) in &[(1_000_000, false), (10_000, true)] {
+            for sort_order in [SortOrder::Ascending, SortOrder::Descending] {
+                let mut expected = source.clone()
+                    .simplesort(0, DataType::SmallInt, sort_order.clone());
+                let mut query = source.clone()
+                    .external_sort(vec![(0, DataType::SmallInt, sort_order)], false, memory_budget);
+
+                let mut count = 0;
+                while let Some(tuple) = query.next().unwrap() {
+                    assert_eq!(Some(tuple), expected.next().unwrap());
+                    count += 1;
+                }
+                assert_eq!(count, 5000);
+                assert_eq!(query.spilled_runs() > 1, spills);
+
+                query.reset().unwrap();
+                expected.reset().unwrap();
+                assert_eq!(query.next().unwrap(), expected.next().unwrap());
+            }
+        }
+
+        // spilled tuples can be larger than a block, and keep their nulls
+        let source = TestSource {
+            source: (0..20i16).map(|i| {
+                let text = vec![b'a' + i as u8; 10000];
+                Tuple::from_nullable(vec![
+                    Some((i % 7).to_tuple_field()),
+                    if i % 3 == 0 { None } else { Some(text) },
+                ])
+            }).collect(),
+            i: 0,
+        };
+        let sort_keys = vec![(0, DataType::SmallInt, SortOrder::Ascending)];
+        let mut expected = source.clone().sort_by_keys(sort_keys.clone(), true);
+        let mut query = source.external_sort(sort_keys, true, 30000);
+        for _ in 0..20 {
+            assert_eq!(query.next().unwrap(), expected.next().unwrap());
+        }
+        assert_eq!(query.next().unwrap(), None);
+        assert!(query.spilled_runs() > 1);
+    }
+
+    #[test]
+    fn test_sort_by_keys() {
+        use self::tuple::{ToTupleField, string_to_binary};
+
+        // title, rating, timestamp
+        let make_tuple = |title: &str, rating: f32, timestamp: i32| {
+            Tuple::new(vec![
+                string_to_binary(title, &DataType::Text(2)).unwrap(),
+                rating.to_tuple_field(),
+                timestamp.to_tuple_field(),
+            ])
+        };
+        let source = TestSource {
+            source: (0..400i32).map(|i| {
+                let title = ["b", "a", "c", "ab"][(i % 4) as usize];
+                let rating = (i % 3) as f32 * 1.5;
+                make_tuple(title, rating, 1000 - i / 24)
+            }).collect(),
+            i: 0,
+        };
+        let sort_keys = vec![
+            (0, DataType::Text(2), SortOrder::Ascending),
+            (1, DataType::Float, SortOrder::Descending),
+        ];
+        let is_sorted = |tuples: &[Tuple]| {
+            tuples.windows(2).all(|pair| {
+                let (t0, t1) = (&pair[0], &pair[1]);
+                let rating0: f32 = t0.get_parse(1).unwrap();
+                let rating1: f32 = t1.get_parse(1).unwrap();
+                t0[0] < t1[0] || (t0[0] == t1[0] && rating0 >= rating1)
+            })
+        };
+        // timestamps decrease in input order, so a stable sort
+        // leaves them in decreasing order within ties
+        let is_stable = |tuples: &[Tuple]| {
+            tuples.windows(2).all(|pair| {
+                let (t0, t1) = (&pair[0], &pair[1]);
+                t0[0] != t1[0] || t0[1] != t1[1] ||
+                    t0.get_parse::<i32>(2).unwrap() >= t1.get_parse::<i32>(2).unwrap()
+            })
+        };
+
+        let queries = vec![
+            source.clone().sort_by_keys(sort_keys.clone(), true).boxed(),
+            source.clone().external_sort(sort_keys.clone(), true, 1000).boxed(),
+        ];
+        for mut query in queries {
+            let mut res = Vec::new();
+            while let Some(tuple) = query.next().unwrap() {
+                res.push(tuple);
+            }
+            assert_eq!(res.len(), 400);
+            assert!(is_sorted(&res));
+            assert!(is_stable(&res));
+            assert_eq!(&res[0], &make_tuple("a", 3.0, 1000));
+            assert_eq!(&res[399], &make_tuple("c", 0.0, 984));
+        }
+
+        let mut query = source.sort_by_keys(sort_keys, false);
+        let mut res = Vec::new();
+        while let Some(tuple) = query.next().unwrap() {
+            res.push(tuple);
+        }
+        assert!(is_sorted(&res));
+    }
+
+    #[test]
+    fn test_aggregate_group() {
+        use self::tuple::ToTupleField;
+
+        // group label, smallint, integer, float; grouped on label
+        let rows: Vec<(&[u8], i16, i32, f32)> = vec![
+            (b"a", 1, 10, 0.5),
+            (b"a", 2, 20, 1.0),
+            (b"b", 3, 30, 1.5),
+            (b"c", 4, 40, 2.0),
+            (b"c", 5, 50, 2.5),
+            (b"c", 6, 60, 3.0),
+        ];
+        let source = TestSource {
+            source: rows.iter().map(|&(label, small, int, float)| {
+                Tuple::new(vec![
+                    label.to_vec(),
+                    small.to_tuple_field(),
+                    int.to_tuple_field(),
+                    float.to_tuple_field(),
+                ])
+            }).collect(),
+            i: 0,
+        };
+        let collect = |mut query: Aggregate<TestSource>| {
+            let mut res = Vec::new();
+            while let Some(tuple) = query.next().unwrap() {
+                res.push(tuple);
+            }
+            query.reset().unwrap();
+            assert_eq!(query.next().unwrap().as_ref(), res.first());
+            res
+        };
+        let group = |label: &[u8], agg: Vec<u8>| {
+            Tuple::new(vec![label.to_vec(), agg])
+        };
+        let column_types = [DataType::Text(1), DataType::SmallInt, DataType::Integer, DataType::Float];
+
+        let query = source.clone()
+            .aggregate(AggregateType::Count, 1, &column_types, Some(0)).unwrap();
+        assert_eq!(collect(query), vec![
+            group(b"a", 2i32.to_tuple_field()),
+            group(b"b", 1i32.to_tuple_field()),
+            group(b"c", 3i32.to_tuple_field()),
+        ]);
+
+        let query = source.clone()
+            .aggregate(AggregateType::Sum, 1, &column_types, Some(0)).unwrap();
+        assert_eq!(collect(query), vec![
+            group(b"a", 3i32.to_tuple_field()),
+            group(b"b", 3i32.to_tuple_field()),
+            group(b"c", 15i32.to_tuple_field()),
+        ]);
+
+        let query = source.clone()
+            .aggregate(AggregateType::Sum, 2, &column_types, Some(0)).unwrap();
+        assert_eq!(collect(query), vec![
+            group(b"a", 30i64.to_tuple_field()),
+            group(b"b", 30i64.to_tuple_field()),
+            group(b"c", 150i64.to_tuple_field()),
+        ]);
+
+        let query = source.clone()
+            .aggregate(AggregateType::Sum, 3, &column_types, Some(0)).unwrap();
+        assert_eq!(collect(query), vec![
+            group(b"a", 1.5f64.to_tuple_field()),
+            group(b"b", 1.5f64.to_tuple_field()),
+            group(b"c", 7.5f64.to_tuple_field()),
+        ]);
+
+        let query = source.clone()
+            .aggregate(AggregateType::Avg, 1, &column_types, Some(0)).unwrap();
+        assert_eq!(collect(query), vec![
+            group(b"a", 1.5f64.to_tuple_field()),
+            group(b"b", 3f64.to_tuple_field()),
+            group(b"c", 5f64.to_tuple_field()),
+        ]);
+
+        let query = source.clone()
+            .aggregate(AggregateType::Avg, 2, &column_types, Some(0)).unwrap();
+        assert_eq!(collect(query), vec![
+            group(b"a", 15f64.to_tuple_field()),
+            group(b"b", 30f64.to_tuple_field()),
+            group(b"c", 50f64.to_tuple_field()),
+        ]);
+
+        let query = source.clone()
+            .aggregate(AggregateType::Avg, 3, &column_types, Some(0)).unwrap();
+        assert_eq!(collect(query), vec![
+            group(b"a", 0.75f64.to_tuple_field()),
+            group(b"b", 1.5f64.to_tuple_field()),
+            group(b"c", 2.5f64.to_tuple_field()),
+        ]);
+
+        // without group by
+        let query = source
+            .aggregate(AggregateType::Avg, 2, &column_types, None).unwrap();
+        assert_eq!(collect(query), vec![
+            Tuple::new(vec![35f64.to_tuple_field()]),
+        ]);
+    }
+
+    #[test]
+    fn test_aggregate_functions() {
+        use self::tuple::{ToTupleField, string_to_binary};
+
+        // group label, title, rating; grouped on label
+        let rows = [
+            ("a", "mango", 4.0f32),
+            ("a", "apple", 2.0),
+            ("a", "pear", 4.0),
+            ("a", "kiwi", 2.0),
+            ("b", "plum", 3.5),
+        ];
+        let source = TestSource {
+            source: rows.iter().map(|&(label, title, rating)| {
+                Tuple::new(vec![
+                    label.as_bytes().to_vec(),
+                    string_to_binary(title, &DataType::Text(8)).unwrap(),
+                    rating.to_tuple_field(),
+                ])
+            }).collect(),
+            i: 0,
+        };
+        let text = |s: &str| string_to_binary(s, &DataType::Text(8)).unwrap();
+        let column_types = [DataType::Text(1), DataType::Text(8), DataType::Float];
+        let run = |aggregation: AggregateType, col: usize, group_by: Option<usize>| {
+            let mut query = source.clone()
+                .aggregate(aggregation, col, &column_types, group_by).unwrap();
+            let mut res = Vec::new();
+            while let Some(tuple) = query.next().unwrap() {
+                // only look at the aggregate, not the group label
+                let last = tuple.indexes.len() - 1;
+                if tuple.is_null(last) {
+                    res.push(None);
+                } else {
+                    res.push(Some(tuple[last].to_vec()));
+                }
+            }
+            res
+        };
+        use self::AggregateType::*;
+
+        assert_eq!(run(Min, 1, Some(0)), vec![Some(text("apple")), Some(text("plum"))]);
+        assert_eq!(run(Max, 1, Some(0)), vec![Some(text("pear")), Some(text("plum"))]);
+        assert_eq!(run(Min, 1, None), vec![Some(text("apple"))]);
+        assert_eq!(run(Max, 1, None), vec![Some(text("plum"))]);
+        assert_eq!(run(Min, 2, None), vec![Some(2f32.to_tuple_field())]);
+        assert_eq!(run(Max, 2, Some(0)), vec![
+            Some(4f32.to_tuple_field()),
+            Some(3.5f32.to_tuple_field()),
+        ]);
+
+        assert_eq!(run(CountDistinct, 2, Some(0)), vec![
+            Some(2i32.to_tuple_field()),
+            Some(1i32.to_tuple_field()),
+        ]);
+        assert_eq!(run(CountDistinct, 1, None), vec![Some(5i32.to_tuple_field())]);
+
+        // sample variance, which is null for a single value
+        assert_eq!(run(Variance, 2, Some(0)), vec![
+            Some((4f64 / 3.).to_tuple_field()),
+            None,
+        ]);
+        assert_eq!(run(Stddev, 2, Some(0)), vec![
+            Some((4f64 / 3.).sqrt().to_tuple_field()),
+            None,
+        ]);
+
+        assert_eq!(run(First, 1, Some(0)), vec![Some(text("mango")), Some(text("plum"))]);
+        assert_eq!(run(Last, 1, Some(0)), vec![Some(text("kiwi")), Some(text("plum"))]);
+        assert_eq!(run(Last, 2, None), vec![Some(3.5f32.to_tuple_field())]);
+
+        // only numbers can be summed
+        assert!(source.clone().aggregate(Sum, 1, &column_types, None).is_err());
+        assert!(source.clone().aggregate(Stddev, 1, &column_types, Some(0)).is_err());
+
+        // no input
+        let mut query = TestSource { source: vec![], i: 0 }
+            .aggregate(Min, 0, &[DataType::Float], None).unwrap();
+        assert_eq!(query.next().unwrap(), Some(Tuple::from_nullable(vec![None])));
+    }
+
+    #[test]
+    fn test_nulls() {
+        use self::tuple::ToTupleField;
+        use self::AggregateType::*;
+
+        // group, rating; either can be null
+        let rows = [
+            (Some(1i16), Some(2.5f32)),
+            (None, Some(1.0)),
+            (Some(1), None),
+            (Some(2), None),
+            (None, Some(3.0)),
+            (Some(1), Some(0.5)),
+        ];
+        let source = TestSource {
+            source: rows.iter().map(|&(group, rating)| {
+                Tuple::from_nullable(vec![
+                    group.map(|group| group.to_tuple_field()),
+                    rating.map(|rating| rating.to_tuple_field()),
+                ])
+            }).collect(),
+            i: 0,
+        };
+        let collect = |mut query: Box<dyn DbIterator>| {
+            let mut res = Vec::new();
+            while let Some(tuple) = query.next().unwrap() {
+                res.push(tuple);
+            }
+            res
+        };
+        let ratings = |tuples: Vec<Tuple>| -> Vec<Option<f32>> {
+            tuples.iter().map(|tuple| {
+                if tuple.is_null(1) { None } else { Some(tuple.get_parse(1).unwrap()) }
+            }).collect()
+        };
+
+        // nulls sort last, or first when descending
+        let sort_keys = vec![(1, DataType::Float, SortOrder::Ascending)];
+        let sorted = collect(source.clone().sort_by_keys(sort_keys, true).boxed());
+        assert_eq!(ratings(sorted), vec![Some(0.5), Some(1.0), Some(2.5), Some(3.0), None, None]);
+        let sort_keys = vec![(1, DataType::Float, SortOrder::Descending)];
+        let sorted = collect(source.clone().external_sort(sort_keys, true, 100).boxed());
+        assert_eq!(ratings(sorted), vec![None, None, Some(3.0), Some(2.5), Some(1.0), Some(0.5)]);
+
+        // null ratings are skipped by aggregates, and null groups
+        // are grouped together
+        let column_types = [DataType::SmallInt, DataType::Float];
+        let aggregates = vec![
+            (Count, Expr::col(1)),
+            (Avg, Expr::col(1)),
+            (Max, Expr::col(1)),
+        ];
+        let expected = vec![
+            Tuple::from_nullable(vec![
+                Some(1i16.to_tuple_field()),
+                Some(2i32.to_tuple_field()),
+                Some(1.5f64.to_tuple_field()),
+                Some(2.5f32.to_tuple_field()),
+            ]),
+            Tuple::from_nullable(vec![
+                Some(2i16.to_tuple_field()),
+                Some(0i32.to_tuple_field()),
+                None,
+                None,
+            ]),
+            Tuple::from_nullable(vec![
+                None,
+                Some(2i32.to_tuple_field()),
+                Some(2f64.to_tuple_field()),
+                Some(3f32.to_tuple_field()),
+            ]),
+        ];
+        let group_keys = vec![(0, DataType::SmallInt, SortOrder::Ascending)];
+        let query = source.clone()
+            .sort_by_keys(group_keys, true)
+            .aggregates(aggregates.clone(), vec![0], &column_types).unwrap();
+        assert_eq!(collect(query.boxed()), expected);
+
+        let query = source.hash_aggregate(aggregates, vec![0], 100, &column_types).unwrap();
+        let mut res = collect(query.boxed());
+        res.sort_by(|t0, t1| {
+            simplesort::cmp_tuples(t0, t1, &[(0, DataType::SmallInt, SortOrder::Ascending)]).unwrap()
+        });
+        assert_eq!(res, expected);
+    }
+
+    #[test]
+    fn test_aggregate_overflow() {
+        use self::aggregate::Accumulator;
+        use self::tuple::ToTupleField;
+        use self::AggregateType::*;
+
+        // SmallInt sums are widened to Integer
+        let mut acc = Accumulator::new(&Sum, &DataType::SmallInt);
+        for _ in 0..3 {
+            acc.update(&i16::MAX.to_tuple_field()).unwrap();
+        }
+        assert_eq!(acc.finish().unwrap(), Some((3 * i16::MAX as i32).to_tuple_field()));
+        assert_eq!(Sum.result_type(&DataType::SmallInt), DataType::Integer);
+
+        // and Integer sums to BigInt
+        let mut acc = Accumulator::new(&Sum, &DataType::Integer);
+        acc.update(&i32::MAX.to_tuple_field()).unwrap();
+        acc.update(&(-1i32).to_tuple_field()).unwrap();
+        acc.update(&i32::MAX.to_tuple_field()).unwrap();
+        assert_eq!(acc.finish().unwrap(), Some((2 * i32::MAX as i64 - 1).to_tuple_field()));
+        assert_eq!(Sum.result_type(&DataType::Integer), DataType::BigInt);
+
+        // a BigInt sum past i64::MAX is an error, not a wrapped result
+        let mut acc = Accumulator::new(&Sum, &DataType::BigInt);
+        acc.update(&i64::MAX.to_tuple_field()).unwrap();
+        assert!(acc.update(&1i64.to_tuple_field()).is_err());
+
+        // but Avg only needs the wider running sum
+        let mut acc = Accumulator::new(&Avg, &DataType::Integer);
+        acc.update(&i32::MAX.to_tuple_field()).unwrap();
+        acc.update(&i32::MAX.to_tuple_field()).unwrap();
+        assert_eq!(acc.finish().unwrap(), Some((i32::MAX as f64).to_tuple_field()));
+
+        // Float sums are Doubles, so they don't lose precision
+        let mut acc = Accumulator::new(&Sum, &DataType::Float);
+        acc.update(&f32::MAX.to_tuple_field()).unwrap();
+        acc.update(&0.5f32.to_tuple_field()).unwrap();
+        acc.update(&f32::MAX.to_tuple_field()).unwrap();
+        assert_eq!(acc.finish().unwrap(), Some((2. * f32::MAX as f64 + 0.5).to_tuple_field()));
+        assert_eq!(Sum.result_type(&DataType::Float), DataType::Double);
+
+        // but Double sums past f64::MAX are an error
+        let mut acc = Accumulator::new(&Sum, &DataType::Double);
+        acc.update(&f64::MAX.to_tuple_field()).unwrap();
+        assert!(acc.update(&f64::MAX.to_tuple_field()).is_err());
+    }
+
+    #[test]
+    fn test_aggregates_multi_group_by() {
+        use self::tuple::ToTupleField;
+
+        // genre, userId, rating, timestamp; sorted by genre, userId
+        let rows: Vec<(&[u8], i32, f32, i32)> = vec![
+            (b"comedy", 1, 4.0, 100),
+            (b"comedy", 1, 3.0, 300),
+            (b"comedy", 2, 5.0, 200),
+            (b"drama", 1, 2.0, 400),
+            (b"drama", 1, 1.0, 500),
+            (b"drama", 1, 3.0, 50),
+        ];
+        let source = TestSource {
+            source: rows.iter().map(|&(genre, user, rating, timestamp)| {
+                Tuple::new(vec![
+                    genre.to_vec(),
+                    user.to_tuple_field(),
+                    rating.to_tuple_field(),
+                    timestamp.to_tuple_field(),
+                ])
+            }).collect(),
+            i: 0,
+        };
+        let column_types = [DataType::Text(6), DataType::Integer, DataType::Float, DataType::Integer];
+        let aggregates = vec![
+            (AggregateType::Count, Expr::col(0)),
+            (AggregateType::Avg, Expr::col(2)),
+            (AggregateType::Max, Expr::col(3)),
+        ];
+        let group = |genre: &[u8], user: i32, count: i32, avg: f64, max: i32| {
+            Tuple::new(vec![
+                genre.to_vec(),
+                user.to_tuple_field(),
+                count.to_tuple_field(),
+                avg.to_tuple_field(),
+                max.to_tuple_field(),
+            ])
+        };
+
+        let mut query = source.clone().aggregates(aggregates.clone(), vec![0, 1], &column_types).unwrap();
+        assert_eq!(query.next().unwrap(), Some(group(b"comedy", 1, 2, 3.5, 300)));
+        assert_eq!(query.next().unwrap(), Some(group(b"comedy", 2, 1, 5.0, 200)));
+        assert_eq!(query.next().unwrap(), Some(group(b"drama", 1, 3, 2.0, 500)));
+        assert_eq!(query.next().unwrap(), None);
+
+        // group columns are output in group_by order
+        let mut query = source.clone().aggregates(aggregates.clone(), vec![1, 0], &column_types).unwrap();
+        assert_eq!(
+            query.next().unwrap(),
+            Some(Tuple::new(vec![
+                1i32.to_tuple_field(),
+                b"comedy".to_vec(),
+                2i32.to_tuple_field(),
+                3.5f64.to_tuple_field(),
+                300i32.to_tuple_field(),
+            ]))
+        );
+
+        let mut query = source.clone().aggregates(aggregates, vec![], &column_types).unwrap();
+        assert_eq!(
+            query.next().unwrap(),
+            Some(Tuple::new(vec![
+                6i32.to_tuple_field(),
+                3f64.to_tuple_field(),
+                500i32.to_tuple_field(),
+            ]))
+        );
+        assert_eq!(query.next().unwrap(), None);
+
+        // aggregate args are expressions, type checked against the input
+        let user_time = Expr::binary(expr::BinaryOp::Multiply, Expr::col(1), Expr::col(3));
+        let aggregates = vec![(AggregateType::Sum, user_time), (AggregateType::Max, Expr::col(1))];
+        let mut query = source.clone().aggregates(aggregates.clone(), vec![0], &column_types).unwrap();
+        assert_eq!(query.next().unwrap(), Some(Tuple::new(vec![
+            b"comedy".to_vec(),
+            800i64.to_tuple_field(),
+            2i32.to_tuple_field(),
+        ])));
+        assert_eq!(query.next().unwrap(), Some(Tuple::new(vec![
+            b"drama".to_vec(),
+            950i64.to_tuple_field(),
+            1i32.to_tuple_field(),
+        ])));
+        let query = source.clone().hash_aggregate(aggregates, vec![], 1, &column_types).unwrap();
+        assert_eq!(
+            query.explain().to_string().lines().next(),
+            Some("HashAggregate (aggregates: sum(#1 * #3), max(#1), group_by: [], max_groups: 1)"),
+        );
+        let aggregates = vec![(AggregateType::Sum, Expr::binary(expr::BinaryOp::Add, Expr::col(0), Expr::col(1)))];
+        assert!(source.clone().aggregates(aggregates, vec![], &column_types).is_err());
+        assert!(source.aggregates(vec![(AggregateType::Sum, Expr::col(4))], vec![], &column_types).is_err());
+    }
+
+    #[test]
+    fn test_hash_aggregate() {
+        use self::tuple::ToTupleField;
+
+        // group a, group b, value; not sorted by group
+        let source = TestSource {
+            source: (0..2000i32).map(|i| {
+                Tuple::new(vec![
+                    ((i * 7) % 20).to_tuple_field(),
+                    ((i % 3) as i16).to_tuple_field(),
+                    i.to_tuple_field(),
+                ])
+            }).collect(),
+            i: 0,
+        };
+        let column_types = [DataType::Integer, DataType::SmallInt, DataType::Integer];
+        let aggregates = vec![
+            (AggregateType::Count, Expr::col(2)),
+            (AggregateType::Sum, Expr::col(2)),
+            (AggregateType::Min, Expr::col(2)),
+        ];
+        let collect = |mut query: Box<dyn DbIterator>| {
+            let mut res = Vec::new();
+            while let Some(tuple) = query.next().unwrap() {
+                res.push(tuple);
+            }
+            res.sort_by_key(|t| format!("{:?}", t));
+            res
+        };
+
+        let expected = collect(
+            source.clone()
+                .sort_by_keys(vec![
+                    (0, DataType::Integer, SortOrder::Ascending),
+                    (1, DataType::SmallInt, SortOrder::Ascending),
+                ], false)
+                .aggregates(aggregates.clone(), vec![0, 1], &column_types).unwrap()
+                .boxed()
+        );
+        assert_eq!(expected.len(), 60);
+
+        for &(max_groups, spills) in &[(100, false), (5, true)] {
+            let mut query = source.clone()
+                .hash_aggregate(aggregates.clone(), vec![0, 1], max_groups, &column_types).unwrap();
+            let first = query.next().unwrap();
+            assert_eq!(query.spilled_partitions() > 0, spills);
+            query.reset().unwrap();
+            assert_eq!(query.next().unwrap(), first);
+            query.reset().unwrap();
+            assert_eq!(collect(query.boxed()), expected);
+        }
+
+        // Without group by, one group even without input
+        let mut query = TestSource { source: vec![], i: 0 }
+            .hash_aggregate(aggregates, vec![], 1, &column_types).unwrap();
+        assert_eq!(
+            query.next().unwrap(),
+            Some(Tuple::from_nullable(vec![
+                Some(0i32.to_tuple_field()),
+                Some(0i64.to_tuple_field()),
+                None,
+            ]))
+        );
+        assert_eq!(query.next().unwrap(), None);
+
+        // spilled tuples can be larger than a block, and keep their nulls
+        let source = TestSource {
+            source: (0..40i32).map(|i| {
+                Tuple::from_nullable(vec![
+                    if i % 10 == 9 { None } else { Some((i % 10).to_tuple_field()) },
+                    if i % 4 == 0 { None } else { Some(vec![b'a'; 10000]) },
+                ])
+            }).collect(),
+            i: 0,
+        };
+        let column_types = [DataType::Integer, DataType::Text(10000)];
+        let aggregates = vec![(AggregateType::Count, Expr::col(1))];
+        let expected = collect(
+            source.clone()
+                .sort_by_keys(vec![(0, DataType::Integer, SortOrder::Ascending)], false)
+                .aggregates(aggregates.clone(), vec![0], &column_types).unwrap()
+                .boxed()
+        );
+        assert_eq!(expected.len(), 10);
+        let mut query = source.hash_aggregate(aggregates, vec![0], 2, &column_types).unwrap();
+        query.next().unwrap();
+        assert!(query.spilled_partitions() > 0);
+        query.reset().unwrap();
+        assert_eq!(collect(query.boxed()), expected);
     }
 
     #[test]
@@ -265,6 +1899,96 @@ mod tests {
             "one, 2, three"
         );
     }
+
+    #[test]
+    fn test_errors_propagate() {
+        use std::io::Write;
+        use DataType::*;
+        use storage::TempFile;
+
+        let schema = Schema {
+            column_names: vec!["id".to_owned(), "name".to_owned()],
+            column_types: vec![SmallInt, Text(5)],
+        };
+
+        let missing = TempFile::new("missing");
+        assert!(io::CsvSource::new(
+            missing.path().to_str().unwrap().to_owned(),
+            schema.clone(),
+        ).is_err());
+
+        // the second record doesn't parse as a SmallInt
+        let file = TempFile::new("csv");
+        file.create().unwrap().write_all(b"id,name\n1,a\nb,b\n3,c\n").unwrap();
+        let location = file.path().to_str().unwrap().to_owned();
+
+        let mut query = io::CsvSource::new(location.clone(), schema.clone()).unwrap();
+        assert!(query.next().unwrap().is_some());
+        assert!(query.next().is_err());
+
+        // errors come up through nodes which buffer their input
+        let mut query = io::CsvSource::new(location, schema.clone()).unwrap()
+            .sort_by_keys(vec![(0, SmallInt, SortOrder::Ascending)], false)
+            .aggregate(AggregateType::Count, 0, &schema.column_types, None).unwrap();
+        assert!(query.next().is_err());
+    }
+
+    #[test]
+    fn test_explain() {
+        use self::expr::BinaryOp::*;
+        use DataType::*;
+
+        let column_types = vec![Text(6), SmallInt, Text(6)];
+        let right_tuples = vec![
+            Tuple::new(vec![vec![0u8, 66], b"x".to_vec()]),
+            Tuple::new(vec![vec![0u8, 2], b"y".to_vec()]),
+            Tuple::new(vec![vec![0u8, 66], b"z".to_vec()]),
+        ];
+        let types_r = vec![SmallInt, Text(1)];
+        let predicate = Expr::and(
+            Expr::binary(Gt, Expr::col(1), Expr::lit(50i16)),
+            Expr::negate(Expr::like(Expr::col(0), "t%")),
+        );
+        let sort_keys = vec![(4, Text(1), SortOrder::Descending)];
+
+        let query = TestSource::new(make_tuples())
+            .filter(predicate.clone(), &column_types).unwrap()
+            .nested_loops_join(TestSource::new(right_tuples.clone()), 1, 0, JoinType::Inner, &column_types, &types_r).unwrap()
+            .sort_by_keys(sort_keys.clone(), false);
+        assert_eq!(query.explain().to_string(), "\
+SimpleSort (sort_keys: #4 DESC, stable: false)
+  NestedLoopsJoin (join_type: Inner, col_l: 1, col_r: 0)
+    Selection (predicate: (#1 > 50) AND (NOT (#0 LIKE 't%')))
+      TestSource (tuples: 3)
+    TestSource (tuples: 3)
+");
+
+        // the same pipeline, with each node analyzed
+        let mut query = TestSource::new(make_tuples()).analyze()
+            .filter(predicate, &column_types).unwrap().analyze()
+            .nested_loops_join(TestSource::new(right_tuples).analyze(), 1, 0, JoinType::Inner, &column_types, &types_r).unwrap().analyze()
+            .sort_by_keys(sort_keys, false).analyze();
+        let mut output = Vec::new();
+        while let Some(tuple) = query.next().unwrap() {
+            output.push(tuple[4].to_vec());
+        }
+        assert_eq!(output, vec![b"z".to_vec(), b"x".to_vec()]);
+
+        let explain = query.explain();
+        let counts = |explain: &Explain| {
+            let stats = explain.stats.as_ref().unwrap();
+            (stats.tuples, stats.next_calls, stats.reset_calls)
+        };
+        assert_eq!(explain.name, "SimpleSort");
+        assert_eq!(counts(&explain), (2, 3, 0));
+        let join = &explain.inputs[0];
+        assert_eq!(counts(join), (2, 3, 0));
+        assert_eq!(counts(&join.inputs[0]), (1, 2, 0));
+        assert_eq!(counts(&join.inputs[0].inputs[0]), (3, 4, 0));
+        assert_eq!(counts(&join.inputs[1]), (3, 4, 2));
+        assert!(explain.to_string().starts_with("SimpleSort (sort_keys: #4 DESC, stable: false) [tuples: 2, next: 3, reset: 0, time: "));
+    }
+
 //    #[test]
 //    #[ignore] // TODO figure out a better way to test csv if not from file
 //    fn test_csv_to_tuple() {
@@ -290,12 +2014,12 @@ mod tests {
 //        let mut query = io::CsvSource::new(rdr, schema.clone());
 //
 //        assert_eq!(
-//            format!("{}", query.next().unwrap().clone().to_string(&schema).unwrap()),
+//            format!("{}", query.next().unwrap().unwrap().clone().to_string(&schema).unwrap()),
 //            "robin, 1, student, 25.4"
 //        );
 //
 //        assert_eq!(
-//            format!("{}", query.next().unwrap().clone().to_string(&schema).unwrap()),
+//            format!("{}", query.next().unwrap().unwrap().clone().to_string(&schema).unwrap()),
 //            "sam, 22, employee, 12.12"
 //        );
 //    }
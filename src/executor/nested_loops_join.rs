@@ -1,59 +1,227 @@
-// TODO use predicate instead of equijoin
-// This is an inner equijoin only for now.
-use super::{DbIterator};
+// Joins either on a key, like the other joins, or on any predicate over
+// the columns of the left input followed by those of the right, e.g. for
+// cross joins or inequalities.
+//
+// The right input is rescanned (reset) for every left tuple. For right and
+// full outer joins, matched right tuples are tracked by their position in
+// the scan, and the unmatched ones are emitted after the left input is done.
+use DataType;
+use error::*;
+use super::DbIterator;
+use super::explain::Explain;
+use super::expr::{Expr, TypedExpr};
+use super::join::{JoinKey, JoinType, pad_left, pad_right};
 use super::tuple::{Tuple};
 
 #[derive(Debug, Clone)]
-pub struct NestedLoopsJoin<I> {
-    // intitialize
-    input_l: I,
-    input_r: I,
+enum Condition {
+    Key(JoinKey),
+    Predicate(TypedExpr),
+}
+
+#[derive(Debug, Clone)]
+pub struct NestedLoopsJoin<L, R> {
+    // internal state
+    first_time: bool,
     current_l: Option<Tuple>,
-    col_l: usize,
-    col_r: usize,
+    matched_l: bool, // whether current_l has matched yet
+    matched_r: Vec<bool>, // by position in input_r
+    pos_r: usize, // position in the current scan of input_r
+    // intitialize
+    input_l: L,
+    input_r: R,
+    condition: Condition,
+    join_type: JoinType,
+    width_l: usize, // columns of input_l, for padding
+    width_r: usize,
 }
 
-impl<I: DbIterator> NestedLoopsJoin<I> {
+impl<L: DbIterator, R: DbIterator> NestedLoopsJoin<L, R> {
     pub fn new(
-        mut input_l: I,
-        input_r: I,
+        input_l: L,
+        input_r: R,
         col_l: usize,
         col_r: usize,
-        ) -> Self
+        join_type: JoinType,
+        types_l: &[DataType],
+        types_r: &[DataType],
+        ) -> Result<Self>
+    {
+        let key = JoinKey::new(col_l, col_r, types_l, types_r)?;
+        Ok(Self::with_condition(input_l, input_r, Condition::Key(key), join_type, types_l, types_r))
+    }
+
+    /// Joins the tuples for which predicate is true, the predicate's
+    /// columns being those of input_l followed by those of input_r.
+    pub fn on(
+        input_l: L,
+        input_r: R,
+        predicate: Expr,
+        join_type: JoinType,
+        types_l: &[DataType],
+        types_r: &[DataType],
+        ) -> Result<Self>
     {
-        let current_l = input_l.next();
+        let column_types: Vec<_> = types_l.iter().chain(types_r).cloned().collect();
+        let predicate = TypedExpr::predicate(predicate, &column_types)?;
+        Ok(Self::with_condition(input_l, input_r, Condition::Predicate(predicate), join_type, types_l, types_r))
+    }
 
+    fn with_condition(
+        input_l: L,
+        input_r: R,
+        condition: Condition,
+        join_type: JoinType,
+        types_l: &[DataType],
+        types_r: &[DataType],
+        ) -> Self
+    {
         NestedLoopsJoin {
-            input_l: input_l,
-            input_r: input_r,
-            current_l: current_l,
-            col_l: col_l,
-            col_r: col_r,
+            first_time: true,
+            current_l: None,
+            matched_l: false,
+            matched_r: Vec::new(),
+            pos_r: 0,
+            input_l,
+            input_r,
+            condition,
+            join_type,
+            width_l: types_l.len(),
+            width_r: types_r.len(),
+        }
+    }
+
+    fn next_left(&mut self) -> Result<()> {
+        self.input_r.reset()?;
+        self.pos_r = 0;
+        self.matched_l = false;
+        self.current_l = self.input_l.next()?;
+        Ok(())
+    }
+
+    fn next_right(&mut self) -> Result<Option<(usize, Tuple)>> {
+        let tuple_r = match self.input_r.next()? {
+            Some(tuple_r) => tuple_r,
+            None => return Ok(None),
+        };
+        let pos_r = self.pos_r;
+        self.pos_r += 1;
+        if self.matched_r.len() <= pos_r {
+            self.matched_r.push(false);
+        }
+        Ok(Some((pos_r, tuple_r)))
+    }
+
+    // The joined tuple, if the pair matches.
+    fn join_pair(&self, tuple_l: &Tuple, tuple_r: &mut Tuple) -> Result<Option<Tuple>> {
+        match self.condition {
+            Condition::Key(ref key) => {
+                if key.matches(tuple_l, tuple_r)? {
+                    Ok(Some(tuple_l.clone().append(tuple_r)))
+                } else {
+                    Ok(None)
+                }
+            },
+            Condition::Predicate(ref predicate) => {
+                let joined = tuple_l.clone().append(tuple_r);
+                if predicate.eval_predicate(&joined)? {
+                    Ok(Some(joined))
+                } else {
+                    Ok(None)
+                }
+            },
         }
     }
+
+    // After the left input is done, rescan the right input for
+    // tuples which never matched.
+    fn next_unmatched_right(&mut self) -> Result<Option<Tuple>> {
+        if !self.join_type.emits_unmatched_right() {
+            return Ok(None);
+        }
+        while let Some((pos_r, tuple_r)) = self.next_right()? {
+            if !self.matched_r[pos_r] {
+                return Ok(Some(pad_left(self.width_l, tuple_r)));
+            }
+        }
+        Ok(None)
+    }
 }
 
-impl <I: DbIterator> DbIterator for NestedLoopsJoin<I>
+impl <L: DbIterator, R: DbIterator> DbIterator for NestedLoopsJoin<L, R>
     where Self: Sized,
 {
-    fn next(&mut self) -> Option<Tuple> {
-        while let Some(mut tuple_r) = self.input_r.next() {
-            let current_l = self.current_l.clone().unwrap();
-            if current_l[self.col_l] == tuple_r[self.col_r] {
-                return Some(current_l.append(&mut tuple_r));
-            }
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        if self.first_time {
+            self.next_left()?;
+            self.first_time = false;
         }
-        self.input_r.reset();
-        self.current_l = self.input_l.next();
-        if self.current_l.is_none() {
-            return None
-        } else {
-            self.next()
+
+        loop {
+            if self.current_l.is_none() {
+                return self.next_unmatched_right();
+            }
+
+            while let Some((pos_r, mut tuple_r)) = self.next_right()? {
+                // checked for none above, and current_l only changes
+                // right before leaving this loop.
+                let joined = match self.join_pair(self.current_l.as_ref().unwrap(), &mut tuple_r)? {
+                    Some(joined) => joined,
+                    None => continue,
+                };
+                self.matched_l = true;
+                self.matched_r[pos_r] = true;
+                match self.join_type {
+                    JoinType::Semi => {
+                        let current_l = self.current_l.take().unwrap();
+                        self.next_left()?;
+                        return Ok(Some(current_l));
+                    },
+                    JoinType::Anti => break,
+                    _ => return Ok(Some(joined)),
+                }
+            }
+
+            if self.matched_l {
+                self.next_left()?;
+                continue;
+            }
+
+            // current_l is done and never matched
+            let current_l = self.current_l.take().unwrap();
+            self.next_left()?;
+            if self.join_type.emits_unmatched_left() {
+                return Ok(Some(pad_right(current_l, self.width_r)));
+            } else if self.join_type == JoinType::Anti {
+                return Ok(Some(current_l));
+            }
         }
     }
 
-    fn reset(&mut self) {
-        self.input_l.reset();
-        self.input_r.reset();
+    fn reset(&mut self) -> Result<()> {
+        self.input_l.reset()?;
+        self.input_r.reset()?;
+        self.first_time = true;
+        self.current_l = None;
+        self.matched_l = false;
+        self.matched_r.clear();
+        self.pos_r = 0;
+        Ok(())
+    }
+
+    fn explain(&self) -> Explain {
+        let explain = Explain::new("NestedLoopsJoin")
+            .param("join_type", format!("{:?}", self.join_type));
+        let explain = match self.condition {
+            Condition::Key(ref key) => {
+                explain.param("col_l", key.col_l).param("col_r", key.col_r)
+            },
+            Condition::Predicate(ref predicate) => {
+                explain.param("predicate", predicate.expr())
+            },
+        };
+        explain
+            .input(self.input_l.explain())
+            .input(self.input_r.explain())
     }
 }
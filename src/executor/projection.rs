@@ -1,4 +1,8 @@
+use DataType;
+use error::*;
 use super::DbIterator;
+use super::explain::{Explain, list};
+use super::expr::{Expr, TypedExpr};
 use super::tuple::Tuple;
 
 #[derive(Debug, Clone)]
@@ -10,22 +14,82 @@ pub struct Projection<I> {
 impl <I: DbIterator> DbIterator for Projection<I>
     where Self: Sized,
 {
-    fn next(&mut self) -> Option<Tuple> {
+    fn next(&mut self) -> Result<Option<Tuple>> {
         // TODO assert that all cols exist
 
-        if let Some(tuple) = self.input.next() {
+        if let Some(tuple) = self.input.next()? {
             let new_data: Vec<Vec<_>> = self.columns.iter().map(|i| {
                 tuple[*i].to_vec() // try not to allocate?
             }).collect();
-            Some(Tuple::new(new_data))
+            Ok(Some(Tuple::new(new_data)))
         } else {
-            None
+            Ok(None)
         }
     }
 
-    fn reset(&mut self) {
-        self.input.reset();
+    fn reset(&mut self) -> Result<()> {
+        self.input.reset()
     }
+
+    fn explain(&self) -> Explain {
+        Explain::new("Projection")
+            .param("columns", list(&self.columns))
+            .input(self.input.explain())
+    }
+}
+
+/// Projection where each output column is computed by an expression over
+/// the input columns. The output types are known once the expressions
+/// are type checked, so nodes downstream don't need to work them out.
+#[derive(Debug, Clone)]
+pub struct ComputedProjection<I> {
+    input: I,
+    exprs: Vec<TypedExpr>,
+    column_types: Vec<DataType>, // of the output
 }
 
+impl<I: DbIterator> ComputedProjection<I> {
+    pub fn new(input: I, exprs: Vec<Expr>, column_types: &[DataType]) -> Result<Self> {
+        let exprs = exprs.into_iter()
+            .map(|expr| TypedExpr::new(expr, column_types))
+            .collect::<Result<Vec<_>>>()?;
+        let column_types = exprs.iter()
+            .map(|expr| expr.column_type())
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(ComputedProjection {
+            input,
+            exprs,
+            column_types,
+        })
+    }
+
+    pub fn column_types(&self) -> &[DataType] {
+        &self.column_types
+    }
+}
+
+impl<I: DbIterator> DbIterator for ComputedProjection<I> {
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        let tuple = match self.input.next()? {
+            Some(tuple) => tuple,
+            None => return Ok(None),
+        };
+        let fields = self.exprs.iter()
+            .map(|expr| expr.eval_field(&tuple))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Some(Tuple::from_nullable(fields)))
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.input.reset()
+    }
+
+    fn explain(&self) -> Explain {
+        let exprs: Vec<String> = self.exprs.iter().map(|expr| expr.expr().to_string()).collect();
+        Explain::new("ComputedProjection")
+            .param("exprs", exprs.join(", "))
+            .input(self.input.explain())
+    }
+}
 
@@ -1,4 +1,6 @@
+use error::*;
 use super::DbIterator;
+use super::explain::Explain;
 use super::tuple::Tuple;
 
 #[derive(Debug, Clone)]
@@ -7,12 +9,16 @@ pub struct Scan<I> {
 }
 
 impl <I: DbIterator> DbIterator for Scan<I> {
-    fn next(&mut self) -> Option<Tuple> {
+    fn next(&mut self) -> Result<Option<Tuple>> {
         self.input.next()
     }
 
-    fn reset(&mut self) {
-        self.input.reset();
+    fn reset(&mut self) -> Result<()> {
+        self.input.reset()
+    }
+
+    fn explain(&self) -> Explain {
+        Explain::new("Scan").input(self.input.explain())
     }
 }
 
@@ -1,6 +1,38 @@
+use error::*;
 use super::DbIterator;
+use super::explain::Explain;
+use super::expr::TypedExpr;
 use super::tuple::Tuple;
 
+/// Decides which tuples pass a Selection, either a closure or a
+/// type checked expression.
+pub trait Predicate {
+    fn test(&mut self, tuple: &Tuple) -> Result<bool>;
+
+    /// For EXPLAIN, which can't see into closures.
+    fn describe(&self) -> String {
+        "closure".to_owned()
+    }
+}
+
+impl<F> Predicate for F
+    where F: FnMut(&Tuple) -> bool,
+{
+    fn test(&mut self, tuple: &Tuple) -> Result<bool> {
+        Ok(self(tuple))
+    }
+}
+
+impl Predicate for TypedExpr {
+    fn test(&mut self, tuple: &Tuple) -> Result<bool> {
+        self.eval_predicate(tuple)
+    }
+
+    fn describe(&self) -> String {
+        self.expr().to_string()
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Selection<I,P> {
     pub input: I,
@@ -8,19 +40,25 @@ pub struct Selection<I,P> {
 }
 
 impl <I: DbIterator, P> DbIterator for Selection<I,P>
-    where P: FnMut(&Tuple) -> bool,
+    where P: Predicate,
 {
-    fn next(&mut self) -> Option<Tuple> {
-        while let Some(x) = self.input.next() {
-            if (self.predicate)(&x) {
-                return Some(x)
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        while let Some(x) = self.input.next()? {
+            if self.predicate.test(&x)? {
+                return Ok(Some(x))
             }
         }
-        None
+        Ok(None)
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.input.reset()
     }
 
-    fn reset(&mut self) {
-        self.input.reset();
+    fn explain(&self) -> Explain {
+        Explain::new("Selection")
+            .param("predicate", self.predicate.describe())
+            .input(self.input.explain())
     }
 }
 
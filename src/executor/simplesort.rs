@@ -1,12 +1,13 @@
-//TODO fix the cloning of buffer
-// tricky because I want to have reference to another field
-// in the struct?
+// Input is buffered and sorted on the first call to next, and the
+// buffer is kept across resets.
 
 use std::cmp::Ordering;
-use std::marker::PhantomData;
 
-use super::{DbIterator, DataType};
-use super::tuple::{Tuple, FromTupleField};
+use DataType;
+use error::*;
+use super::DbIterator;
+use super::explain::{self, Explain};
+use super::tuple::{Tuple, cmp_with_type};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum SortOrder {
@@ -14,86 +15,83 @@ pub enum SortOrder {
     Descending,
 }
 
-// Sort by only one column first
+/// Column to sort on, with its type and direction. When sorting on
+/// several keys, later keys only break ties of earlier keys.
+pub type SortKey = (usize, DataType, SortOrder);
+
+/// Nulls sort after all values, so they come last in ascending order
+/// and first in descending order.
+pub fn cmp_tuples(tuple1: &Tuple, tuple2: &Tuple, sort_keys: &[SortKey]) -> Result<Ordering> {
+    for &(sort_on_col, ref sort_on_type, ref sort_order) in sort_keys {
+        let ordering = match (tuple1.is_null(sort_on_col), tuple2.is_null(sort_on_col)) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Greater,
+            (false, true) => Ordering::Less,
+            (false, false) => cmp_with_type(
+                &tuple1[sort_on_col],
+                &tuple2[sort_on_col],
+                sort_on_type,
+            )?,
+        };
+        let ordering = if *sort_order == SortOrder::Ascending {
+            ordering
+        } else {
+            ordering.reverse()
+        };
+        if ordering != Ordering::Equal {
+            return Ok(ordering);
+        }
+    }
+    Ok(Ordering::Equal)
+}
+
+/// Stable sorts keep tuples with equal keys in input order. A field which
+/// can't be compared is an error, and leaves tuples in some order.
+pub fn sort_tuples(tuples: &mut [Tuple], sort_keys: &[SortKey], stable: bool) -> Result<()> {
+    let mut error = None;
+    let compare = |tuple1: &Tuple, tuple2: &Tuple| {
+        cmp_tuples(tuple1, tuple2, sort_keys).unwrap_or_else(|err| {
+            error.get_or_insert(err);
+            Ordering::Equal
+        })
+    };
+    if stable {
+        tuples.sort_by(compare);
+    } else {
+        tuples.sort_unstable_by(compare);
+    }
+    match error {
+        Some(err) => Err(err),
+        None => Ok(()),
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct SimpleSort<I> {
+    // internal state
+    first_time: bool,
     buffer: Vec<Tuple>,
-    //sort_on_col: usize, // currently only sort on one column
-    //sort_on_type: DataType, // currently only sort on one column
-    output: ::std::vec::IntoIter<Tuple>,
-    phantom: PhantomData<I>, //Takes an I in input but doesn't need to saveto struct
+    output_idx: usize, // index into buffer
+    // intitialize
+    input: I,
+    sort_keys: Vec<SortKey>,
+    stable: bool,
 }
 
 impl<I: DbIterator> SimpleSort<I> {
     pub fn new(
-        mut input: I, // input iterator
-        sort_on_col: usize, // currently only one column at a time
-        sort_on_type: DataType,
-        sort_order: SortOrder,
+        input: I, // input iterator
+        sort_keys: Vec<SortKey>,
+        stable: bool,
         ) -> Self
     {
-        let mut buf = Vec::new(); // implement iterator to make this simpler?
-        while let Some(tuple) = input.next() {
-            buf.push(tuple);
-        }
-        //sort TODO abstract out in-memory sort for use in out-of-core sort
-        // Conversion must not fail, since lib controls deserialization
-        buf.sort_unstable_by(|tuple1, tuple2| {
-            let field1 = &tuple1[sort_on_col];
-            let field2 = &tuple2[sort_on_col];
-            match sort_on_type {
-                DataType::SmallInt => {
-                    // read it into u16
-                    let int1: u16 = FromTupleField::from_tuple_field(field1)
-                        .expect("incorrect convert");
-                    let int2: u16 = FromTupleField::from_tuple_field(field2)
-                        .expect("incorrect convert");
-                    if sort_order == SortOrder::Ascending {
-                        int1.cmp(&int2)
-                    } else {
-                        int2.cmp(&int1)
-                    }
-                },
-                DataType::Integer => {
-                    // read it into u32
-                    let int1: u32 = FromTupleField::from_tuple_field(field1)
-                        .expect("incorrect convert");
-                    let int2: u32 = FromTupleField::from_tuple_field(field2)
-                        .expect("incorrect convert");
-                    if sort_order == SortOrder::Ascending {
-                        int1.cmp(&int2)
-                    } else {
-                        int2.cmp(&int1)
-                    }
-                },
-                DataType::Float => {
-                    // read it into f32
-                    let flt1: f32 = FromTupleField::from_tuple_field(field1)
-                        .expect("incorrect convert");
-                    let flt2: f32 = FromTupleField::from_tuple_field(field2)
-                        .expect("incorrect convert");
-                    if sort_order == SortOrder::Ascending {
-                        flt1.partial_cmp(&flt2).unwrap_or(Ordering::Less)
-                    } else {
-                        flt2.partial_cmp(&flt1).unwrap_or(Ordering::Less)
-                    }
-                },
-                DataType::Text(_) => {
-                    let s1 = String::from_utf8(field1.to_vec()).expect("bad convert");
-                    let s2 = String::from_utf8(field2.to_vec()).expect("bad convert");
-                    if sort_order == SortOrder::Ascending {
-                        s1.cmp(&s2)
-                    } else {
-                        s2.cmp(&s1)
-                    }
-                },
-            }
-        });
         SimpleSort {
-            buffer: buf.clone(),
-            output: buf.into_iter(),
-            phantom: PhantomData,
+            first_time: true,
+            buffer: Vec::new(),
+            output_idx: 0,
+            input,
+            sort_keys,
+            stable,
         }
     }
 }
@@ -101,12 +99,34 @@ impl<I: DbIterator> SimpleSort<I> {
 impl<I: DbIterator> DbIterator for SimpleSort<I>
     where Self: Sized,
 {
-    fn next(&mut self) -> Option<Tuple> {
-        self.output.next()
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        if self.first_time {
+            while let Some(tuple) = self.input.next()? {
+                self.buffer.push(tuple);
+            }
+            sort_tuples(&mut self.buffer, &self.sort_keys, self.stable)?;
+            self.first_time = false;
+        }
+
+        let res = self.buffer.get(self.output_idx).cloned();
+        self.output_idx += 1;
+        Ok(res)
     }
 
-    fn reset(& mut self) {
-        self.output = self.buffer.clone().into_iter();
+    fn reset(&mut self) -> Result<()> {
+        // an error while buffering leaves a partial buffer
+        if self.first_time {
+            self.buffer.clear();
+            self.input.reset()?;
+        }
+        self.output_idx = 0;
+        Ok(())
     }
-}
 
+    fn explain(&self) -> Explain {
+        Explain::new("SimpleSort")
+            .param("sort_keys", explain::sort_keys(&self.sort_keys))
+            .param("stable", self.stable)
+            .input(self.input.explain())
+    }
+}
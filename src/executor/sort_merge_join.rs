@@ -0,0 +1,201 @@
+// Both inputs must already be sorted ascending on their join columns
+// (e.g. by SimpleSort). Keys are compared as the type they're joined as
+// (see join::key_type), not by raw bytes, so the sort order of the
+// inputs and of the merge agree.
+//
+// For duplicate keys, the run of right tuples sharing a key is buffered,
+// and every left tuple with that key is paired with the whole run.
+//
+// Right tuples which are skipped over while looking for a key (and any
+// right tuples with null or NaN keys) never match, so for right and full outer
+// joins they are queued up as padded output.
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+
+use DataType;
+use error::*;
+use super::DbIterator;
+use super::explain::Explain;
+use super::join::{JoinKey, JoinType, pad_left, pad_right};
+use super::tuple::{Tuple};
+
+#[derive(Debug, Clone)]
+pub struct SortMergeJoin<L, R> {
+    // internal state
+    first_time: bool,
+    current_l: Option<Tuple>,
+    next_r: Option<Tuple>, // first right tuple not yet added to a group
+    group_r: Vec<Tuple>, // right tuples which share the current key
+    match_idx: usize, // index into group_r for current_l
+    unmatched: VecDeque<Tuple>, // padded tuples waiting to be emitted
+    // intitialize
+    input_l: L,
+    input_r: R,
+    key: JoinKey,
+    join_type: JoinType,
+    width_l: usize, // columns of input_l, for padding
+    width_r: usize,
+}
+
+impl<L: DbIterator, R: DbIterator> SortMergeJoin<L, R> {
+    pub fn new(
+        input_l: L,
+        input_r: R,
+        col_l: usize,
+        col_r: usize,
+        join_type: JoinType,
+        types_l: &[DataType],
+        types_r: &[DataType],
+        ) -> Result<Self>
+    {
+        Ok(SortMergeJoin {
+            first_time: true,
+            current_l: None,
+            next_r: None,
+            group_r: Vec::new(),
+            match_idx: 0,
+            unmatched: VecDeque::new(),
+            input_l,
+            input_r,
+            key: JoinKey::new(col_l, col_r, types_l, types_r)?,
+            join_type,
+            width_l: types_l.len(),
+            width_r: types_r.len(),
+        })
+    }
+
+    // Compare a left key against a right key
+    fn cmp_keys(&self, tuple_l: &Tuple, tuple_r: &Tuple) -> Result<Ordering> {
+        self.key.cmp(tuple_l, tuple_r)
+    }
+
+    fn advance_right(&mut self) -> Result<()> {
+        self.next_r = self.input_r.next()?;
+        Ok(())
+    }
+
+    fn skip_right(&mut self, tuple_r: Tuple) -> Result<()> {
+        if self.join_type.emits_unmatched_right() {
+            self.unmatched.push_back(pad_left(self.width_l, tuple_r));
+        }
+        self.advance_right()
+    }
+
+    // Advance the right input past all keys smaller than tuple_l's,
+    // then gather the run of right tuples equal to tuple_l's key.
+    fn fill_group(&mut self, tuple_l: &Tuple) -> Result<()> {
+        self.group_r.clear();
+        while let Some(tuple_r) = self.next_r.take() {
+            if self.key.right(&tuple_r)?.is_none() {
+                self.skip_right(tuple_r)?;
+                continue;
+            }
+            match self.cmp_keys(tuple_l, &tuple_r)? {
+                Ordering::Greater => {
+                    self.skip_right(tuple_r)?;
+                },
+                Ordering::Equal => {
+                    self.group_r.push(tuple_r);
+                    self.advance_right()?;
+                },
+                Ordering::Less => {
+                    self.next_r = Some(tuple_r);
+                    break;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    // Find the group of right tuples for tuple_l, returns whether
+    // there was a match.
+    fn match_left(&mut self, tuple_l: &Tuple) -> Result<bool> {
+        if self.key.left(tuple_l)?.is_none() {
+            return Ok(false);
+        }
+        // A left duplicate reuses the group from the previous left tuple
+        let same_group = match self.group_r.first() {
+            Some(first_r) => self.cmp_keys(tuple_l, first_r)? == Ordering::Equal,
+            None => false,
+        };
+        if !same_group {
+            self.fill_group(tuple_l)?;
+        }
+        Ok(!self.group_r.is_empty())
+    }
+}
+
+impl <L: DbIterator, R: DbIterator> DbIterator for SortMergeJoin<L, R>
+    where Self: Sized,
+{
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        if self.first_time {
+            self.advance_right()?;
+            self.first_time = false;
+        }
+
+        loop {
+            if let Some(tuple) = self.unmatched.pop_front() {
+                return Ok(Some(tuple));
+            }
+
+            if let Some(ref current_l) = self.current_l {
+                if self.match_idx < self.group_r.len() {
+                    let mut tuple_r = self.group_r[self.match_idx].clone();
+                    self.match_idx += 1;
+                    return Ok(Some(current_l.clone().append(&mut tuple_r)));
+                }
+            }
+
+            self.current_l = None;
+            self.match_idx = 0;
+            let tuple_l = match self.input_l.next()? {
+                Some(tuple_l) => tuple_l,
+                None => {
+                    // the rest of the right input never matched
+                    if !self.join_type.emits_unmatched_right() {
+                        return Ok(None);
+                    }
+                    match self.next_r.take() {
+                        Some(tuple_r) => self.skip_right(tuple_r)?,
+                        None => return Ok(None),
+                    }
+                    continue;
+                },
+            };
+
+            let is_matched = self.match_left(&tuple_l)?;
+            match self.join_type {
+                JoinType::Semi if is_matched => return Ok(Some(tuple_l)),
+                JoinType::Anti if !is_matched => return Ok(Some(tuple_l)),
+                JoinType::Semi | JoinType::Anti => (),
+                _ if !is_matched && self.join_type.emits_unmatched_left() => {
+                    self.unmatched.push_back(pad_right(tuple_l, self.width_r));
+                },
+                _ if !is_matched => (),
+                _ => self.current_l = Some(tuple_l),
+            }
+        }
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.input_l.reset()?;
+        self.input_r.reset()?;
+        self.first_time = true;
+        self.current_l = None;
+        self.next_r = None;
+        self.group_r.clear();
+        self.match_idx = 0;
+        self.unmatched.clear();
+        Ok(())
+    }
+
+    fn explain(&self) -> Explain {
+        Explain::new("SortMergeJoin")
+            .param("join_type", format!("{:?}", self.join_type))
+            .param("col_l", self.key.col_l)
+            .param("col_r", self.key.col_r)
+            .input(self.input_l.explain())
+            .input(self.input_r.explain())
+    }
+}
@@ -1,18 +1,21 @@
 use byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
 use csv::StringRecord;
+use std::cmp::Ordering;
 use std::io::Cursor;
 use std::ops::Index;
 
 // TODO change Schema to ColumnTypes?
-// TODO remove indexes, all info can be inferred from schema
-//   once Text is allocated as fixed length
+// Text fields are as long as their strings, and nulls hold no data, so
+// indexes are needed to find the fields.
 use {DataType, Schema};
 use error::*;
+use super::{datetime, decimal};
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct Tuple{
     pub data: Vec<u8>,
     pub indexes: Vec<usize>, // pointers to start of data for a field
+    pub nulls: Vec<usize>, // sorted indexes of null fields, which hold no data
 }
 
 impl Tuple {
@@ -32,14 +35,48 @@ impl Tuple {
         let _ = indexes.pop(); // 
         Tuple {
             data: buf,
-            indexes: indexes,
+            indexes,
+            nulls: vec![],
         }
     }
+
+    /// A tuple where every one of the `width` fields is null,
+    /// e.g. for padding the unmatched side of an outer join.
+    pub fn null(width: usize) -> Self {
+        Tuple {
+            data: vec![],
+            indexes: vec![0; width],
+            nulls: (0..width).collect(),
+        }
+    }
+
+    /// None fields are null
+    pub fn from_nullable(fields: Vec<Option<Vec<u8>>>) -> Self {
+        let nulls = fields.iter()
+            .enumerate()
+            .filter(|&(_, field)| field.is_none())
+            .map(|(i, _)| i)
+            .collect();
+        let mut tuple = Tuple::new(
+            fields.into_iter().map(|field| field.unwrap_or_default()).collect()
+        );
+        tuple.nulls = nulls;
+        tuple
+    }
+
+    pub fn is_null(&self, col: usize) -> bool {
+        self.nulls.binary_search(&col).is_ok()
+    }
+
     pub fn to_string(self, schema: &Schema) -> Result<String> {
         assert_eq!(schema.column_types.len(), self.indexes.len());
 
         let fields = (0..self.indexes.len()).map(|i| {
-            display_with_type(&self[i], &schema.column_types[i])
+            if self.is_null(i) {
+                Ok("NULL".to_owned())
+            } else {
+                display_with_type(&self[i], &schema.column_types[i])
+            }
             }).collect::<Result<Vec<_>>>()?;
 
         Ok(fields.join(", "))
@@ -49,18 +86,26 @@ impl Tuple {
 pub fn display_with_type(data: &[u8], data_type: &DataType) -> Result<String> {
     match *data_type {
         DataType::SmallInt => {
-            // read it into u16
+            // read it into i16
             let mut s = String::new();
             let mut rdr = Cursor::new(data);
-            let int = rdr.read_u16::<BigEndian>()?;
+            let int = rdr.read_i16::<BigEndian>()?;
             s.push_str(&int.to_string()[..]);
             Ok(s)
         },
         DataType::Integer => {
-            // read it into u32
+            // read it into i32
+            let mut s = String::new();
+            let mut rdr = Cursor::new(data);
+            let int = rdr.read_i32::<BigEndian>()?;
+            s.push_str(&int.to_string()[..]);
+            Ok(s)
+        },
+        DataType::BigInt => {
+            // read it into i64
             let mut s = String::new();
             let mut rdr = Cursor::new(data);
-            let int = rdr.read_u32::<BigEndian>()?;
+            let int = rdr.read_i64::<BigEndian>()?;
             s.push_str(&int.to_string()[..]);
             Ok(s)
         },
@@ -72,28 +117,88 @@ pub fn display_with_type(data: &[u8], data_type: &DataType) -> Result<String> {
             s.push_str(&float.to_string()[..]);
             Ok(s)
         },
+        DataType::Double => {
+            let double: f64 = field_parse(data)?;
+            Ok(double.to_string())
+        },
+        DataType::Boolean => {
+            let b: bool = field_parse(data)?;
+            Ok(b.to_string())
+        },
+        DataType::Date => Ok(datetime::format_date(field_parse(data)?)),
+        DataType::Timestamp => Ok(datetime::format_timestamp(field_parse(data)?)),
+        DataType::Decimal(_, scale) => Ok(decimal::format_decimal(field_parse(data)?, scale)),
         DataType::Text(_) => {
-            let s = String::from_utf8(data.to_vec())
-                .chain_err(|| "Error converting back to Utf8 for display")?;
-            Ok(s.trim_right_matches('\0').to_owned())
+            String::from_utf8(data.to_vec())
+                .chain_err(|| "Error converting back to Utf8 for display")
         },
     }
 }
 
+/// Compares two fields in ascending order, according to their type.
+/// Fields which don't decode as data_type are an error, instead of being
+/// ordered by their bytes.
+pub fn cmp_with_type(field1: &[u8], field2: &[u8], data_type: &DataType) -> Result<Ordering> {
+    let ordering = match *data_type {
+        DataType::SmallInt => field_parse::<i16>(field1)?.cmp(&field_parse(field2)?),
+        DataType::Integer => field_parse::<i32>(field1)?.cmp(&field_parse(field2)?),
+        DataType::BigInt => field_parse::<i64>(field1)?.cmp(&field_parse(field2)?),
+        // every f32 is exactly an f64
+        DataType::Float => {
+            let flt1: f32 = field_parse(field1)?;
+            let flt2: f32 = field_parse(field2)?;
+            cmp_floats(f64::from(flt1), f64::from(flt2))
+        },
+        DataType::Double => cmp_floats(field_parse(field1)?, field_parse(field2)?),
+        // false < true
+        DataType::Boolean => field_parse::<bool>(field1)?.cmp(&field_parse(field2)?),
+        DataType::Date => field_parse::<i32>(field1)?.cmp(&field_parse(field2)?),
+        DataType::Timestamp => field_parse::<i64>(field1)?.cmp(&field_parse(field2)?),
+        // both are at the column's scale
+        DataType::Decimal(..) => field_parse::<i128>(field1)?.cmp(&field_parse(field2)?),
+        // utf8 bytes order the same as their chars
+        DataType::Text(_) => field1.cmp(field2),
+    };
+    Ok(ordering)
+}
+
+/// A total order for sorting floats: -0.0 is equal to 0.0, and NaNs are
+/// equal to each other and after every number. Joins don't match NaN keys
+/// at all, see join::JoinKey.
+pub fn cmp_floats(x: f64, y: f64) -> Ordering {
+    let canonical = |x: f64| {
+        if x == 0. {
+            0.
+        } else if x.is_nan() {
+            f64::NAN
+        } else {
+            x
+        }
+    };
+    canonical(x).total_cmp(&canonical(y))
+}
+
 pub fn string_to_binary(s: &str, data_type: &DataType) -> Result<Vec<u8>> {
     match *data_type {
         DataType::SmallInt => {
             //TODO support other radix
-            let integer = s.parse::<u16>()?;
+            let integer = s.parse::<i16>()?;
             let mut buf = Vec::new();
-            buf.write_u16::<BigEndian>(integer)?;
+            buf.write_i16::<BigEndian>(integer)?;
             Ok(buf)
         },
         DataType::Integer => {
             //TODO support other radix
-            let integer = s.parse::<u32>()?;
+            let integer = s.parse::<i32>()?;
+            let mut buf = Vec::new();
+            buf.write_i32::<BigEndian>(integer)?;
+            Ok(buf)
+        },
+        DataType::BigInt => {
+            //TODO support other radix
+            let integer = s.parse::<i64>()?;
             let mut buf = Vec::new();
-            buf.write_u32::<BigEndian>(integer)?;
+            buf.write_i64::<BigEndian>(integer)?;
             Ok(buf)
         },
         DataType::Float => {
@@ -102,16 +207,36 @@ pub fn string_to_binary(s: &str, data_type: &DataType) -> Result<Vec<u8>> {
             buf.write_f32::<BigEndian>(float)?;
             Ok(buf)
         },
+        DataType::Double => Ok(s.parse::<f64>()?.to_tuple_field()),
+        DataType::Boolean => Ok(parse_bool(s)?.to_tuple_field()),
+        DataType::Date => Ok(datetime::parse_date(s)?.to_tuple_field()),
+        DataType::Timestamp => Ok(datetime::parse_timestamp(s)?.to_tuple_field()),
+        DataType::Decimal(precision, scale) => Ok(decimal::parse_decimal(s, precision, scale)?.to_tuple_field()),
         DataType::Text(x) => {
-            // Requires padding 0 (null) bytes out to text alloc
-            let mut bytes = s.as_bytes().to_vec();
-            let padding_len = x - bytes.len(); // for now panic on underflow
-            let padding = (0..padding_len).map(|_| 0);
+            // stored as is, without padding out to x
+            if s.len() > x {
+                bail!(ErrorKind::TextTooLong(s.to_owned(), x));
+            }
+            Ok(s.as_bytes().to_vec())
+        },
+    }
+}
 
-            bytes.extend(padding);
+/// true/false, t/f, yes/no or 1/0, in any case.
+pub fn parse_bool(s: &str) -> Result<bool> {
+    match s.trim().to_lowercase().as_str() {
+        "true" | "t" | "yes" | "1" => Ok(true),
+        "false" | "f" | "no" | "0" => Ok(false),
+        _ => Err(ErrorKind::ParseValue(s.to_owned(), "Boolean".to_owned()).into()),
+    }
+}
 
-            Ok(bytes)
-        },
+/// Like string_to_binary, but an empty string is null (None).
+pub fn string_to_nullable(s: &str, data_type: &DataType) -> Result<Option<Vec<u8>>> {
+    if s.is_empty() {
+        Ok(None)
+    } else {
+        string_to_binary(s, data_type).map(Some)
     }
 }
 
@@ -128,26 +253,20 @@ impl Index<usize> for Tuple {
 }
 
 impl Tuple {
+    /// Empty fields are null, e.g. blanks in a csv file.
     pub fn from_stringrecord(record: StringRecord, schema: &Schema) -> Result<Self> {
-        let mut indexes = Vec::new();
-        let mut data = Vec::new();
-        for col_idx in 0..record.len() {
-            // Get the pointer to the start of next field
-            indexes.push(data.len());
-
-            // Now convert based on Schema
-            let mut field_data = string_to_binary(
-                &record[col_idx],
-                &schema.column_types[col_idx]
-            )?;
-            data.append(&mut field_data);
-
+        if record.len() != schema.column_types.len() {
+            bail!(format!(
+                "record has {} fields, but schema has {} columns",
+                record.len(),
+                schema.column_types.len(),
+            ));
         }
-
-        Ok(Tuple {
-            data: data,
-            indexes: indexes,
-        })
+        let fields = record.iter()
+            .zip(&schema.column_types)
+            .map(|(field, data_type)| string_to_nullable(field, data_type))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(Tuple::from_nullable(fields))
     }
 }
 
@@ -158,28 +277,50 @@ pub trait FromTupleField {
         where Self: Sized;
 }
 
-impl FromTupleField for u16 {
-    fn from_tuple_field(field: &[u8]) -> Result<u16> {
+impl FromTupleField for i16 {
+    fn from_tuple_field(field: &[u8]) -> Result<i16> {
         if field.len() != 2 {
             return Err("data has wrong number of bytes".into());
         }
         let mut rdr = Cursor::new(field);
-        rdr.read_u16::<BigEndian>()
+        rdr.read_i16::<BigEndian>()
             .chain_err(|| "Error converting field")
     }
 }
 
-impl FromTupleField for u32 {
-    fn from_tuple_field(field: &[u8]) -> Result<u32> {
+impl FromTupleField for i32 {
+    fn from_tuple_field(field: &[u8]) -> Result<i32> {
         if field.len() != 4 {
             return Err("data has wrong number of bytes".into());
         }
         let mut rdr = Cursor::new(field);
-        rdr.read_u32::<BigEndian>()
+        rdr.read_i32::<BigEndian>()
+            .chain_err(|| "Error converting field")
+    }
+}
+
+impl FromTupleField for i64 {
+    fn from_tuple_field(field: &[u8]) -> Result<i64> {
+        if field.len() != 8 {
+            return Err("data has wrong number of bytes".into());
+        }
+        let mut rdr = Cursor::new(field);
+        rdr.read_i64::<BigEndian>()
             .chain_err(|| "Error converting field")
     }
 }
 
+impl FromTupleField for i128 {
+    fn from_tuple_field(field: &[u8]) -> Result<i128> {
+        let mut bytes = [0; 16];
+        if field.len() != bytes.len() {
+            return Err("data has wrong number of bytes".into());
+        }
+        bytes.copy_from_slice(field);
+        Ok(i128::from_be_bytes(bytes))
+    }
+}
+
 impl FromTupleField for f32 {
     fn from_tuple_field(field: &[u8]) -> Result<f32> {
         if field.len() != 4 {
@@ -191,6 +332,27 @@ impl FromTupleField for f32 {
     }
 }
 
+impl FromTupleField for f64 {
+    fn from_tuple_field(field: &[u8]) -> Result<f64> {
+        if field.len() != 8 {
+            return Err("data has wrong number of bytes".into());
+        }
+        let mut rdr = Cursor::new(field);
+        rdr.read_f64::<BigEndian>()
+            .chain_err(|| "Error converting field")
+    }
+}
+
+impl FromTupleField for bool {
+    fn from_tuple_field(field: &[u8]) -> Result<bool> {
+        match *field {
+            [0] => Ok(false),
+            [1] => Ok(true),
+            _ => Err("data is not a boolean".into()),
+        }
+    }
+}
+
 impl FromTupleField for String {
     fn from_tuple_field(field: &[u8]) -> Result<String> {
         String::from_utf8(field.to_vec())
@@ -217,22 +379,36 @@ pub trait ToTupleField {
     fn to_tuple_field(self) -> Vec<u8>;
 }
 
-impl ToTupleField for u16 {
+impl ToTupleField for i16 {
     fn to_tuple_field(self) -> Vec<u8> {
         let mut buf = Vec::new();
-        buf.write_u16::<BigEndian>(self).expect("Bad Convert");
+        buf.write_i16::<BigEndian>(self).expect("Bad Convert");
         buf
     }
 }
 
-impl ToTupleField for u32 {
+impl ToTupleField for i32 {
     fn to_tuple_field(self) -> Vec<u8> {
         let mut buf = Vec::new();
-        buf.write_u32::<BigEndian>(self).expect("Bad Convert");
+        buf.write_i32::<BigEndian>(self).expect("Bad Convert");
         buf
     }
 }
 
+impl ToTupleField for i64 {
+    fn to_tuple_field(self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.write_i64::<BigEndian>(self).expect("Bad Convert");
+        buf
+    }
+}
+
+impl ToTupleField for i128 {
+    fn to_tuple_field(self) -> Vec<u8> {
+        self.to_be_bytes().to_vec()
+    }
+}
+
 impl ToTupleField for f32 {
     fn to_tuple_field(self) -> Vec<u8> {
         let mut buf = Vec::new();
@@ -241,6 +417,20 @@ impl ToTupleField for f32 {
     }
 }
 
+impl ToTupleField for f64 {
+    fn to_tuple_field(self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        buf.write_f64::<BigEndian>(self).expect("Bad Convert");
+        buf
+    }
+}
+
+impl ToTupleField for bool {
+    fn to_tuple_field(self) -> Vec<u8> {
+        vec![self as u8]
+    }
+}
+
 impl ToTupleField for String {
     fn to_tuple_field(self) -> Vec<u8> {
         self.into_bytes()
@@ -253,6 +443,14 @@ impl Tuple {
     pub fn append(mut self, other: &mut Tuple) -> Tuple {
         let shift = self.data.len();
         self.data.append(&mut other.data);
+        // shift null column numbers on second tuple
+        let col_shift = self.indexes.len();
+        let other_nulls = other.nulls
+            .iter()
+            .map(|col| {
+                *col + col_shift
+            });
+        self.nulls.extend(other_nulls);
         // shift indexes on second tuple
         let other_indexes = other.indexes
             .iter()
@@ -287,6 +485,56 @@ mod tests {
         vec![t0, t1]
     }
 
+    #[test]
+    fn test_cmp_with_type() {
+        use DataType::*;
+
+        // 256 > 2 as an integer, but not as raw bytes
+        assert_eq!(
+            cmp_with_type(&[1, 0], &[0, 2], &SmallInt).unwrap(),
+            Ordering::Greater
+        );
+        // ints are signed, so -1 < 1 even though 0xFF.. > 0x00..
+        assert_eq!(
+            cmp_with_type(&(-1i16).to_tuple_field(), &1i16.to_tuple_field(), &SmallInt).unwrap(),
+            Ordering::Less
+        );
+        assert_eq!(
+            cmp_with_type(&i64::MIN.to_tuple_field(), &0i64.to_tuple_field(), &BigInt).unwrap(),
+            Ordering::Less
+        );
+        assert_eq!(
+            cmp_with_type(&(-1.5f32).to_tuple_field(), &0.5f32.to_tuple_field(), &Float).unwrap(),
+            Ordering::Less
+        );
+        assert_eq!(
+            cmp_with_type(b"abc", b"abd", &Text(4)).unwrap(),
+            Ordering::Less
+        );
+        assert_eq!(
+            cmp_with_type(b"ab", b"abc", &Text(5)).unwrap(),
+            Ordering::Less
+        );
+
+        // -0.0 is 0.0, and NaNs are equal and after every number
+        assert_eq!(
+            cmp_with_type(&(-0.0f64).to_tuple_field(), &0.0f64.to_tuple_field(), &Double).unwrap(),
+            Ordering::Equal
+        );
+        assert_eq!(
+            cmp_with_type(&f32::NAN.to_tuple_field(), &f32::INFINITY.to_tuple_field(), &Float).unwrap(),
+            Ordering::Greater
+        );
+        assert_eq!(
+            cmp_with_type(&(-f64::NAN).to_tuple_field(), &f64::NAN.to_tuple_field(), &Double).unwrap(),
+            Ordering::Equal
+        );
+
+        // fields which aren't of the type are errors
+        assert!(cmp_with_type(&[0, 1, 2], &[0, 2], &SmallInt).is_err());
+        assert!(cmp_with_type(&[2], &[1], &Boolean).is_err());
+    }
+
     #[test]
     fn test_append() {
         let tuples = make_tuples();
@@ -300,10 +548,115 @@ mod tests {
             indexes: vec![
                 0,3,5,10,14,16,
             ],
+            nulls: vec![],
         };
         let t0 = tuples[0].clone();
         let mut t1 = tuples[1].clone();
         assert_eq!(expected, t0.append(&mut t1));
 
     }
+
+    #[test]
+    fn test_append_null() {
+        let tuples = make_tuples();
+        let joined = Tuple::null(2).append(&mut tuples[0].clone());
+
+        assert_eq!(joined.nulls, vec![0, 1]);
+        assert!(joined.is_null(1));
+        assert!(!joined.is_null(2));
+        assert_eq!(&joined[0], &b""[..]);
+        assert_eq!(&joined[2], &b"one"[..]);
+        assert_eq!(&joined[4], &b"three"[..]);
+
+        let joined = tuples[0].clone().append(&mut Tuple::null(2));
+        assert_eq!(joined.nulls, vec![3, 4]);
+        assert_eq!(&joined[2], &b"three"[..]);
+        assert_eq!(&joined[4], &b""[..]);
+    }
+
+    #[test]
+    fn test_signed_ints() {
+        use DataType::*;
+
+        for &(s, ref data_type) in &[("-32768", SmallInt), ("-7", Integer), ("1700000000000", BigInt)] {
+            let field = string_to_binary(s, data_type).unwrap();
+            assert_eq!(Some(field.len()), data_type.bytes_length());
+            assert_eq!(display_with_type(&field, data_type).unwrap(), s);
+        }
+        assert_eq!(string_to_binary("-2", &BigInt).unwrap(), (-2i64).to_tuple_field());
+        assert_eq!(field_parse::<i32>(&(-2i32).to_tuple_field()).unwrap(), -2);
+
+        // out of range for the type
+        assert!(string_to_binary("32768", &SmallInt).is_err());
+        assert!(string_to_binary("1700000000000", &Integer).is_err());
+    }
+
+    #[test]
+    fn test_datetime_bool_fields() {
+        use DataType::*;
+
+        for &(s, ref data_type) in &[
+            ("true", Boolean),
+            ("0.1", Double),
+            ("1969-07-20", Date),
+            ("1969-07-20 20:17:40.5", Timestamp),
+        ] {
+            let field = string_to_binary(s, data_type).unwrap();
+            assert_eq!(Some(field.len()), data_type.bytes_length());
+            assert_eq!(display_with_type(&field, data_type).unwrap(), s);
+        }
+        assert_eq!(string_to_binary("F", &Boolean).unwrap(), false.to_tuple_field());
+        assert_eq!(
+            string_to_binary("2000-01-01T01:00:00+01:00", &Timestamp).unwrap(),
+            string_to_binary("2000-01-01", &Timestamp).unwrap()
+        );
+
+        let earlier = string_to_binary("1969-12-31", &Date).unwrap();
+        let later = string_to_binary("1970-01-01", &Date).unwrap();
+        assert_eq!(cmp_with_type(&earlier, &later, &Date).unwrap(), Ordering::Less);
+
+        assert!(string_to_binary("maybe", &Boolean).is_err());
+        assert!(string_to_binary("2000-13-01", &Date).is_err());
+    }
+
+    #[test]
+    fn test_from_stringrecord_blanks() {
+        use DataType::*;
+
+        let schema = Schema {
+            column_names: vec![],
+            column_types: vec![SmallInt, Text(3), Float],
+        };
+        let record = StringRecord::from(vec!["", "ab", ""]);
+        let tuple = Tuple::from_stringrecord(record, &schema).unwrap();
+        assert_eq!(tuple.nulls, vec![0, 2]);
+        assert_eq!(&tuple[1], &b"ab"[..]);
+
+        // a blank is null for every type, but a bad field is still an error
+        assert_eq!(string_to_nullable("", &Integer).unwrap(), None);
+        assert!(string_to_nullable("x", &Integer).is_err());
+
+        let record = StringRecord::from(vec!["1", "ab"]);
+        assert!(Tuple::from_stringrecord(record, &schema).is_err());
+    }
+
+    #[test]
+    fn test_text_fields() {
+        use DataType::*;
+
+        // as long as the string, up to the type's length
+        assert_eq!(string_to_binary("", &Text(3)).unwrap(), b"");
+        assert_eq!(string_to_binary("été", &Text(5)).unwrap(), "été".as_bytes());
+        assert_eq!(display_with_type(b"ab", &Text(3)).unwrap(), "ab");
+        match string_to_binary("été", &Text(4)) {
+            Err(Error(ErrorKind::TextTooLong(ref s, 4), _)) if s == "été" => (),
+            res => panic!("expected text too long, got {:?}", res),
+        }
+
+        let schema = Schema {
+            column_names: vec![],
+            column_types: vec![Text(3)],
+        };
+        assert!(Tuple::from_stringrecord(StringRecord::from(vec!["abcd"]), &schema).is_err());
+    }
 }
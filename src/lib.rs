@@ -1,13 +1,17 @@
 #![recursion_limit = "1024"]
-// TODO change unsigned ints to signed
 
 extern crate byteorder;
 extern crate csv;
 #[macro_use]
 extern crate error_chain;
 
+// error_chain 0.11 implements Error::description and cause, which are
+// deprecated
+#[allow(deprecated)]
 pub mod error;
 pub mod executor;
+pub mod plan;
+pub mod sql;
 pub mod storage;
 
 
@@ -31,20 +35,34 @@ type ColumnTypes = Vec<DataType>;
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum DataType {
-    SmallInt, //u16
-    Integer, //u32
+    SmallInt, //i16
+    Integer, //i32
+    BigInt, //i64
     Float, //f32
-    Text(usize), //String
+    Double, //f64
+    Boolean, //u8, 0 or 1
+    Date, //i32 days since 1970-01-01
+    Timestamp, //i64 microseconds since 1970-01-01 00:00:00 UTC
+    Decimal(u8, u8), //i128 of digits, (precision, scale) e.g. 12.50 is 1250 in (5, 2)
+    Text(usize), //String of at most n bytes, stored without padding
 }
 
 impl DataType {
-    pub fn bytes_length(&self) -> usize {
+    /// Length of every field of this type, None for Text, whose
+    /// fields are only as long as their strings.
+    pub fn bytes_length(&self) -> Option<usize> {
         use DataType::*;
         match *self {
-            SmallInt => 2,
-            Integer => 4,
-            Float => 4,
-            Text(x) => x,
+            SmallInt => Some(2),
+            Integer => Some(4),
+            BigInt => Some(8),
+            Float => Some(4),
+            Double => Some(8),
+            Boolean => Some(1),
+            Date => Some(4),
+            Timestamp => Some(8),
+            Decimal(..) => Some(16),
+            Text(_) => None,
         }
     }
 }
@@ -0,0 +1,651 @@
+// Binds a parsed SELECT to a LogicalPlan: table names are looked up in
+// the catalog, column names are resolved to indexes, and SQL expressions
+// become executor Exprs.
+//
+// The plan has the usual shape:
+//
+//   scans and joins (FROM)
+//   -> filter (WHERE)
+//   -> project group keys and aggregate args -> aggregate -> filter (HAVING)
+//   -> project (SELECT, plus any ORDER BY exprs which aren't output)
+//   -> distinct -> sort -> project away the extra ORDER BY columns
+//   -> limit
+//
+// Where it can, each join takes one equality between a column of each
+// side as its key, so that it can be run as a hash or sort merge join.
+// For comma (or cross) joins the key is taken from the WHERE clause. Any
+// other ON conditions are a filter on top of an inner join. Joins with no
+// such equality, and outer joins with other ON conditions, are instead
+// joins on their whole ON condition (or on true, for a cross join), which
+// only a nested loops join can run.
+use std::convert::TryFrom;
+
+use error::*;
+use executor::aggregate::AggregateType;
+use executor::decimal;
+use executor::expr::{BinaryOp, Expr, Function, Value};
+use executor::join::{JoinType, key_type};
+use executor::simplesort::SortKey;
+use sql::Position;
+use sql::ast::{self, Literal, Select, SelectItem, TableExpr};
+use super::{Catalog, JoinCondition, LogicalPlan, plan_error};
+
+pub struct Binder<'a, C: 'a + ?Sized> {
+    catalog: &'a C,
+}
+
+impl<'a, C: Catalog + ?Sized> Binder<'a, C> {
+    pub fn new(catalog: &'a C) -> Self {
+        Binder { catalog }
+    }
+
+    pub fn bind_select(&self, select: &Select) -> Result<LogicalPlan> {
+        let mut conjuncts = Vec::new();
+        if let Some(ref selection) = select.selection {
+            split_conjuncts(selection, &mut conjuncts);
+        }
+
+        let mut plan = match select.from {
+            Some(ref from) => self.bind_table_expr(from, &mut conjuncts)?,
+            None => bail!(plan_error("queries without FROM aren't supported".to_owned())),
+        };
+        if !conjuncts.is_empty() {
+            let predicate = ExprBinder::new(&plan, "WHERE").bind_all(&conjuncts)?;
+            plan = plan.filter(predicate)?;
+        }
+
+        let aggregating = !select.group_by.is_empty() ||
+            select.having.is_some() ||
+            select.projection.iter().any(|item| match *item {
+                SelectItem::Expr { ref expr, .. } => contains_aggregate(expr),
+                _ => false,
+            }) ||
+            select.order_by.iter().any(|item| contains_aggregate(&item.expr));
+
+        // Select items (and HAVING) are bound over the output of the
+        // aggregate, which only knows the aggregates once they're all
+        // bound. So the aggregate is added to the plan afterwards.
+        let mut binder = ExprBinder::new(&plan, "GROUP BY");
+        if aggregating {
+            let group_by = select.group_by.iter()
+                .map(|expr| binder.bind(expr))
+                .collect::<Result<Vec<_>>>()?;
+            binder.grouping = Some(Grouping { group_by, aggregates: Vec::new() });
+        }
+
+        binder.clause = "SELECT";
+        let mut exprs = Vec::new();
+        let mut column_names = Vec::new();
+        for item in &select.projection {
+            match *item {
+                SelectItem::Wildcard | SelectItem::QualifiedWildcard(_) if aggregating => {
+                    bail!(plan_error("* can't be used with aggregates or GROUP BY".to_owned()));
+                },
+                SelectItem::Wildcard => {
+                    for col in 0..plan.schema.column_names.len() {
+                        exprs.push(Expr::col(col));
+                        column_names.push(plan.schema.column_names[col].clone());
+                    }
+                },
+                SelectItem::QualifiedWildcard(ref table) => {
+                    let cols: Vec<usize> = (0..plan.tables.len())
+                        .filter(|&col| matches_name(&plan.tables[col], table))
+                        .collect();
+                    if cols.is_empty() {
+                        bail!(plan_error(format!("unknown table {} in {}.*", table, table)));
+                    }
+                    for col in cols {
+                        exprs.push(Expr::col(col));
+                        column_names.push(plan.schema.column_names[col].clone());
+                    }
+                },
+                SelectItem::Expr { ref expr, ref alias } => {
+                    exprs.push(binder.bind(expr)?);
+                    column_names.push(output_name(expr, alias));
+                },
+            }
+        }
+        let output_width = exprs.len();
+
+        let having = match select.having {
+            Some(ref having) => {
+                binder.clause = "HAVING";
+                Some(binder.bind(having)?)
+            },
+            None => None,
+        };
+
+        // ORDER BY can name an output column, give its position, or be any
+        // expression over the input, which is then computed as an extra
+        // column.
+        binder.clause = "ORDER BY";
+        let mut order_by = Vec::new();
+        for item in &select.order_by {
+            let col = match item.expr {
+                ast::Expr::Literal(Literal::Integer(position)) => {
+                    if position < 1 || position as usize > output_width {
+                        bail!(plan_error(format!("ORDER BY position {} is out of range", position)));
+                    }
+                    position as usize - 1
+                },
+                ast::Expr::Column { table: None, ref name, .. } if column_names[..output_width]
+                    .iter().filter(|column_name| column_name.eq_ignore_ascii_case(name)).count() == 1 =>
+                {
+                    column_names.iter()
+                        .position(|column_name| column_name.eq_ignore_ascii_case(name))
+                        .unwrap()
+                },
+                ref expr => {
+                    let bound = binder.bind(expr)?;
+                    match exprs.iter().position(|e| *e == bound) {
+                        Some(col) => col,
+                        None => {
+                            exprs.push(bound);
+                            column_names.push(output_name(expr, &None));
+                            exprs.len() - 1
+                        },
+                    }
+                },
+            };
+            order_by.push((col, item.order.clone()));
+        }
+        if select.distinct && exprs.len() > output_width {
+            bail!(plan_error("with SELECT DISTINCT, ORDER BY expressions must appear in the select list".to_owned()));
+        }
+
+        if let Some(grouping) = binder.grouping.take() {
+            plan = aggregate(plan, grouping)?;
+            if let Some(having) = having {
+                plan = plan.filter(having)?;
+            }
+        }
+
+        plan = plan.project(exprs, column_names)?;
+        if select.distinct {
+            plan = plan.aggregate(vec![], (0..output_width).collect())?;
+        }
+        if !order_by.is_empty() {
+            let sort_keys: Vec<SortKey> = order_by.into_iter()
+                .map(|(col, order)| (col, plan.schema.column_types[col].clone(), order))
+                .collect();
+            plan = plan.sort(sort_keys);
+        }
+        if plan.schema.column_names.len() > output_width {
+            let column_names = plan.schema.column_names[..output_width].to_vec();
+            plan = plan.project((0..output_width).map(Expr::col).collect(), column_names)?;
+        }
+        if select.limit.is_some() || select.offset.is_some() {
+            let limit = select.limit.map(|limit| limit as usize);
+            plan = plan.limit(limit, select.offset.unwrap_or(0) as usize);
+        }
+        Ok(plan)
+    }
+
+    // Join keys are removed from conjuncts (the WHERE clause) when used.
+    fn bind_table_expr(
+        &self,
+        table_expr: &TableExpr,
+        conjuncts: &mut Vec<ast::Expr>,
+    ) -> Result<LogicalPlan>
+    {
+        match *table_expr {
+            TableExpr::Table { ref name, ref alias, position } => {
+                let relation = self.catalog.relation(name)
+                    .ok_or_else(|| plan_error(format!("unknown table {}{}", name, at(position))))?;
+                Ok(LogicalPlan::scan(relation, alias.clone()))
+            },
+            TableExpr::Join { ref left, ref right, join_type, ref constraint } => {
+                let left = self.bind_table_expr(left, conjuncts)?;
+                let right = self.bind_table_expr(right, conjuncts)?;
+
+                let mut on = Vec::new();
+                let key = match *constraint {
+                    Some(ref constraint) => {
+                        split_conjuncts(constraint, &mut on);
+                        take_join_key(&mut on, &left, &right)?
+                    },
+                    None => take_join_key(conjuncts, &left, &right)?,
+                };
+
+                match key {
+                    Some((col_l, col_r)) if on.is_empty() || join_type == JoinType::Inner => {
+                        let plan = LogicalPlan::join(left, right, col_l, col_r, join_type)?;
+                        if on.is_empty() {
+                            return Ok(plan);
+                        }
+                        let predicate = ExprBinder::new(&plan, "ON").bind_all(&on)?;
+                        plan.filter(predicate)
+                    },
+                    _ => {
+                        let predicate = match *constraint {
+                            Some(ref constraint) => {
+                                // bound over the columns of both sides
+                                let joined = LogicalPlan::join_with(
+                                    left.clone(),
+                                    right.clone(),
+                                    JoinCondition::Predicate(Expr::lit(true)),
+                                    JoinType::Inner,
+                                );
+                                ExprBinder::new(&joined, "ON").bind(constraint)?
+                            },
+                            None => Expr::lit(true),
+                        };
+                        LogicalPlan::join_on(left, right, predicate, join_type)
+                    },
+                }
+            },
+        }
+    }
+}
+
+// Group keys and aggregates for the aggregate node, as exprs over its input
+struct Grouping {
+    group_by: Vec<Expr>,
+    aggregates: Vec<(AggregateType, Expr)>,
+}
+
+// Resolves expressions against the columns of input. Once grouping is
+// set, expressions are bound over the output of the aggregate instead:
+// group keys and aggregates become its columns.
+struct ExprBinder<'p> {
+    input: &'p LogicalPlan,
+    clause: &'static str, // for errors
+    grouping: Option<Grouping>,
+}
+
+impl<'p> ExprBinder<'p> {
+    fn new(input: &'p LogicalPlan, clause: &'static str) -> Self {
+        ExprBinder { input, clause, grouping: None }
+    }
+
+    // ANDs together
+    fn bind_all(&mut self, exprs: &[ast::Expr]) -> Result<Expr> {
+        let mut bound = exprs.iter().map(|expr| self.bind(expr));
+        let first = match bound.next() {
+            Some(first) => first?,
+            None => bail!(plan_error(format!("nothing to bind in {}", self.clause))),
+        };
+        bound.try_fold(first, |acc, expr| Ok(Expr::and(acc, expr?)))
+    }
+
+    fn bind(&mut self, expr: &ast::Expr) -> Result<Expr> {
+        if let Some(grouping) = self.grouping.as_ref().filter(|_| !contains_aggregate(expr)) {
+            let bound = ExprBinder::new(self.input, self.clause).bind(expr)?;
+            if let Some(col) = grouping.group_by.iter().position(|e| *e == bound) {
+                return Ok(Expr::col(col));
+            }
+            if bound.columns().is_empty() {
+                return Ok(bound);
+            }
+            if let ast::Expr::Column { ref name, position, .. } = *expr {
+                bail!(plan_error(format!(
+                    "column {} must appear in GROUP BY or be used in an aggregate{}",
+                    name,
+                    at(position),
+                )));
+            }
+            // otherwise the parts of expr may be grouped
+        }
+
+        let bound = match *expr {
+            ast::Expr::Column { ref table, ref name, position } => {
+                Expr::col(resolve_column(self.input, table, name, position)?)
+            },
+            ast::Expr::Literal(ref literal) => Expr::Literal(literal_value(literal)?),
+            ast::Expr::Binary { op, ref left, ref right } => {
+                Expr::binary(op, self.bind(left)?, self.bind(right)?)
+            },
+            ast::Expr::Negative(ref expr) => {
+                match **expr {
+                    ast::Expr::Literal(Literal::Float(x)) => Expr::lit(-x),
+                    ast::Expr::Literal(Literal::Decimal(ref s)) => Expr::Literal(decimal_value(&format!("-{}", s))?),
+                    ast::Expr::Literal(Literal::Integer(x)) => Expr::Literal(int_value(-i128::from(x))?),
+                    _ => Expr::binary(BinaryOp::Subtract, Expr::lit(0i16), self.bind(expr)?),
+                }
+            },
+            ast::Expr::Not(ref expr) => Expr::negate(self.bind(expr)?),
+            ast::Expr::IsNull { ref expr, negated } => {
+                negate_if(negated, Expr::is_null(self.bind(expr)?))
+            },
+            ast::Expr::Like { ref expr, ref pattern, negated } => {
+                negate_if(negated, Expr::like(self.bind(expr)?, pattern))
+            },
+            ast::Expr::In { ref expr, ref list, negated } => {
+                let list = list.iter()
+                    .map(|item| self.bind(item))
+                    .collect::<Result<Vec<_>>>()?;
+                negate_if(negated, Expr::in_list(self.bind(expr)?, list))
+            },
+            ast::Expr::Between { ref expr, ref low, ref high, negated } => {
+                let expr = self.bind(expr)?;
+                let between = Expr::and(
+                    Expr::binary(BinaryOp::GtEq, expr.clone(), self.bind(low)?),
+                    Expr::binary(BinaryOp::LtEq, expr, self.bind(high)?),
+                );
+                negate_if(negated, between)
+            },
+            ast::Expr::Case { ref operand, ref whens, ref otherwise } => {
+                let operand = match *operand {
+                    Some(ref operand) => Some(self.bind(operand)?),
+                    None => None,
+                };
+                let mut bound_whens = Vec::new();
+                for (when, then) in whens {
+                    let mut when = self.bind(when)?;
+                    // CASE x WHEN 1 is CASE WHEN x = 1
+                    if let Some(ref operand) = operand {
+                        when = Expr::binary(BinaryOp::Eq, operand.clone(), when);
+                    }
+                    bound_whens.push((when, self.bind(then)?));
+                }
+                let otherwise = match *otherwise {
+                    Some(ref otherwise) => Some(self.bind(otherwise)?),
+                    None => None,
+                };
+                Expr::case(bound_whens, otherwise)
+            },
+            ast::Expr::Cast { ref expr, ref data_type } => {
+                Expr::cast(self.bind(expr)?, data_type.clone())
+            },
+            ast::Expr::Function { ref name, ref args, distinct, position } => {
+                if let Some(aggregation) = aggregate_type(name, distinct) {
+                    if distinct && aggregation != AggregateType::CountDistinct {
+                        bail!(plan_error(format!("DISTINCT is only supported in count{}", at(position))));
+                    }
+                    return self.bind_aggregate(aggregation, name, args, position);
+                }
+                let function = scalar_function(name)
+                    .ok_or_else(|| plan_error(format!("unknown function {}{}", name, at(position))))?;
+                if distinct {
+                    bail!(plan_error(format!("DISTINCT isn't allowed in {}{}", name, at(position))));
+                }
+                let args = args.iter()
+                    .map(|arg| self.bind(arg))
+                    .collect::<Result<Vec<_>>>()?;
+                Expr::call(function, args)
+            },
+            ast::Expr::Wildcard => {
+                bail!(plan_error("* is only allowed in count(*)".to_owned()));
+            },
+        };
+        Ok(bound)
+    }
+
+    // Aggregates with the same function and arg share a column.
+    fn bind_aggregate(
+        &mut self,
+        aggregation: AggregateType,
+        name: &str,
+        args: &[ast::Expr],
+        position: Position,
+    ) -> Result<Expr>
+    {
+        if self.grouping.is_none() {
+            bail!(plan_error(format!(
+                "aggregate {} isn't allowed in {}{}",
+                name,
+                self.clause,
+                at(position),
+            )));
+        }
+        if args.len() != 1 {
+            bail!(plan_error(format!("aggregate {} takes one argument{}", name, at(position))));
+        }
+        let arg = match args[0] {
+            // count(*) counts rows, so any non-null column will do
+            ast::Expr::Wildcard if aggregation == AggregateType::Count => Expr::lit(1i16),
+            ref arg => ExprBinder::new(self.input, "aggregate arguments").bind(arg)?,
+        };
+
+        let grouping = self.grouping.as_mut().unwrap();
+        let aggregate = (aggregation, arg);
+        let idx = match grouping.aggregates.iter().position(|a| *a == aggregate) {
+            Some(idx) => idx,
+            None => {
+                grouping.aggregates.push(aggregate);
+                grouping.aggregates.len() - 1
+            },
+        };
+        Ok(Expr::col(grouping.group_by.len() + idx))
+    }
+}
+
+// Projects the group keys and aggregate args, then aggregates on them.
+fn aggregate(input: LogicalPlan, grouping: Grouping) -> Result<LogicalPlan> {
+    let Grouping { group_by, aggregates } = grouping;
+    let group_width = group_by.len();
+
+    let mut column_names: Vec<String> = group_by.iter()
+        .map(|expr| match *expr {
+            Expr::Column(col) => input.schema.column_names[col].clone(),
+            _ => "?column?".to_owned(),
+        })
+        .collect();
+    let mut exprs = group_by;
+    let mut aggregations = Vec::new();
+    for (aggregation, arg) in aggregates {
+        column_names.push(format!("{:?}", aggregation).to_lowercase());
+        exprs.push(arg);
+        aggregations.push(aggregation);
+    }
+    let input = input.project(exprs, column_names)?;
+
+    let aggregates = aggregations.into_iter().enumerate()
+        .map(|(i, aggregation)| (aggregation, Expr::col(group_width + i)))
+        .collect();
+    input.aggregate(aggregates, (0..group_width).collect())
+}
+
+// Finds an equality between a column of left and a column of right in
+// exprs, whose types can be compared as join keys, removing it from
+// exprs. Returns the key columns of each side.
+fn take_join_key(
+    exprs: &mut Vec<ast::Expr>,
+    left: &LogicalPlan,
+    right: &LogicalPlan,
+) -> Result<Option<(usize, usize)>>
+{
+    for i in 0..exprs.len() {
+        let key = match exprs[i] {
+            ast::Expr::Binary { op: BinaryOp::Eq, left: ref expr_l, right: ref expr_r } => {
+                match (&**expr_l, &**expr_r) {
+                    (ast::Expr::Column { table: table_l, name: name_l, .. },
+                     ast::Expr::Column { table: table_r, name: name_r, .. }) => {
+                        let l = (find_column(left, table_l, name_l), find_column(right, table_l, name_l));
+                        let r = (find_column(left, table_r, name_r), find_column(right, table_r, name_r));
+                        match (l, r) {
+                            ((Some(col_l), None), (None, Some(col_r))) => Some((col_l, col_r)),
+                            ((None, Some(col_r)), (Some(col_l), None)) => Some((col_l, col_r)),
+                            _ => None,
+                        }
+                    },
+                    _ => None,
+                }
+            },
+            _ => None,
+        };
+        let comparable = key.is_some_and(|(col_l, col_r)| {
+            key_type(&left.schema.column_types[col_l], &right.schema.column_types[col_r]).is_some()
+        });
+        if comparable {
+            exprs.remove(i);
+            return Ok(key);
+        }
+    }
+    Ok(None)
+}
+
+// Splits ANDs into a list of exprs.
+fn split_conjuncts(expr: &ast::Expr, conjuncts: &mut Vec<ast::Expr>) {
+    match *expr {
+        ast::Expr::Binary { op: BinaryOp::And, ref left, ref right } => {
+            split_conjuncts(left, conjuncts);
+            split_conjuncts(right, conjuncts);
+        },
+        _ => conjuncts.push(expr.clone()),
+    }
+}
+
+fn resolve_column(
+    plan: &LogicalPlan,
+    table: &Option<String>,
+    name: &str,
+    position: Position,
+) -> Result<usize>
+{
+    let cols: Vec<usize> = matching_columns(plan, table, name).collect();
+    match cols.len() {
+        0 => bail!(plan_error(format!("unknown column {}{}", qualified(table, name), at(position)))),
+        1 => Ok(cols[0]),
+        _ => bail!(plan_error(format!("column {} is ambiguous{}", qualified(table, name), at(position)))),
+    }
+}
+
+// Only if there is exactly one match
+fn find_column(plan: &LogicalPlan, table: &Option<String>, name: &str) -> Option<usize> {
+    let cols: Vec<usize> = matching_columns(plan, table, name).collect();
+    if cols.len() == 1 { Some(cols[0]) } else { None }
+}
+
+// Names aren't case sensitive.
+fn matching_columns<'p>(
+    plan: &'p LogicalPlan,
+    table: &'p Option<String>,
+    name: &'p str,
+) -> impl Iterator<Item = usize> + 'p
+{
+    (0..plan.schema.column_names.len()).filter(move |&col| {
+        plan.schema.column_names[col].eq_ignore_ascii_case(name) &&
+            table.as_ref().is_none_or(|table| matches_name(&plan.tables[col], table))
+    })
+}
+
+fn matches_name(name: &Option<String>, other: &str) -> bool {
+    name.as_ref().is_some_and(|name| name.eq_ignore_ascii_case(other))
+}
+
+fn qualified(table: &Option<String>, name: &str) -> String {
+    match *table {
+        Some(ref table) => format!("{}.{}", table, name),
+        None => name.to_owned(),
+    }
+}
+
+fn at(position: Position) -> String {
+    format!(" at line {}, column {}", position.line, position.column)
+}
+
+fn output_name(expr: &ast::Expr, alias: &Option<String>) -> String {
+    match (alias, expr) {
+        (Some(alias), _) => alias.clone(),
+        (None, ast::Expr::Column { name, .. }) => name.clone(),
+        (None, ast::Expr::Function { name, .. }) => name.to_lowercase(),
+        _ => "?column?".to_owned(),
+    }
+}
+
+fn negate_if(negated: bool, expr: Expr) -> Expr {
+    if negated { Expr::negate(expr) } else { expr }
+}
+
+fn literal_value(literal: &Literal) -> Result<Value> {
+    let value = match *literal {
+        Literal::Integer(x) => int_value(i128::from(x))?,
+        Literal::Float(x) => Value::Double(x),
+        Literal::Decimal(ref s) => decimal_value(s)?,
+        Literal::Str(ref s) => Value::Text(s.clone()),
+        Literal::Boolean(b) => Value::Boolean(b),
+        Literal::Null => Value::Null,
+    };
+    Ok(value)
+}
+
+// Integers take the smallest type they fit in.
+fn int_value(x: i128) -> Result<Value> {
+    if let Ok(x) = i16::try_from(x) {
+        Ok(Value::SmallInt(x))
+    } else if let Ok(x) = i32::try_from(x) {
+        Ok(Value::Integer(x))
+    } else if let Ok(x) = i64::try_from(x) {
+        Ok(Value::BigInt(x))
+    } else {
+        bail!(plan_error(format!("integer {} is out of range", x)))
+    }
+}
+
+// Exact, with the digits as written, e.g. 0.10 is a Decimal(2, 2). Past 38
+// digits it's a Double instead.
+fn decimal_value(s: &str) -> Result<Value> {
+    let unsigned = s.trim_start_matches('-');
+    let (int_digits, frac_digits) = unsigned.split_once('.').unwrap_or((unsigned, ""));
+    let scale = frac_digits.len();
+    let precision = (int_digits.trim_start_matches('0').len() + scale).max(1);
+    if precision > usize::from(decimal::MAX_PRECISION) {
+        return Ok(Value::Double(s.parse()?));
+    }
+    let (precision, scale) = (precision as u8, scale as u8);
+    Ok(Value::Decimal(decimal::parse_decimal(s, precision, scale)?, precision, scale))
+}
+
+fn contains_aggregate(expr: &ast::Expr) -> bool {
+    use sql::ast::Expr::*;
+    match *expr {
+        Column { .. } | Literal(_) | Wildcard => false,
+        Binary { ref left, ref right, .. } => contains_aggregate(left) || contains_aggregate(right),
+        Negative(ref expr) |
+        Not(ref expr) |
+        IsNull { ref expr, .. } |
+        Like { ref expr, .. } |
+        Cast { ref expr, .. } => contains_aggregate(expr),
+        In { ref expr, ref list, .. } => contains_aggregate(expr) || list.iter().any(contains_aggregate),
+        Between { ref expr, ref low, ref high, .. } => {
+            contains_aggregate(expr) || contains_aggregate(low) || contains_aggregate(high)
+        },
+        Case { ref operand, ref whens, ref otherwise } => {
+            operand.as_ref().is_some_and(|operand| contains_aggregate(operand)) ||
+                whens.iter().any(|(when, then)| contains_aggregate(when) || contains_aggregate(then)) ||
+                otherwise.as_ref().is_some_and(|otherwise| contains_aggregate(otherwise))
+        },
+        Function { ref name, ref args, distinct, .. } => {
+            aggregate_type(name, distinct).is_some() || args.iter().any(contains_aggregate)
+        },
+    }
+}
+
+fn aggregate_type(name: &str, distinct: bool) -> Option<AggregateType> {
+    let aggregation = match name.to_lowercase().as_str() {
+        "count" if distinct => AggregateType::CountDistinct,
+        "count" => AggregateType::Count,
+        "sum" => AggregateType::Sum,
+        "avg" => AggregateType::Avg,
+        "min" => AggregateType::Min,
+        "max" => AggregateType::Max,
+        "variance" | "var_samp" => AggregateType::Variance,
+        "stddev" | "stddev_samp" => AggregateType::Stddev,
+        "first" => AggregateType::First,
+        "last" => AggregateType::Last,
+        _ => return None,
+    };
+    Some(aggregation)
+}
+
+fn scalar_function(name: &str) -> Option<Function> {
+    let function = match name.to_lowercase().as_str() {
+        "upper" => Function::Upper,
+        "lower" => Function::Lower,
+        "trim" => Function::Trim,
+        "length" | "char_length" => Function::Length,
+        "substring" | "substr" => Function::Substring,
+        "concat" => Function::Concat,
+        "round" => Function::Round,
+        "year" => Function::Year,
+        "month" => Function::Month,
+        "day" => Function::Day,
+        "hour" => Function::Hour,
+        "minute" => Function::Minute,
+        "second" => Function::Second,
+        _ => return None,
+    };
+    Some(function)
+}
+
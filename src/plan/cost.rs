@@ -0,0 +1,300 @@
+// Estimates of how many tuples each node of a plan gives, from the stats
+// of the relations it scans. A relation without stats is taken to have
+// DEFAULT_ROWS rows, all distinct.
+//
+// Conditions are assumed to be independent, and values to be spread
+// evenly between a column's min and max.
+use DataType;
+use executor::decimal;
+use executor::expr::{BinaryOp, Expr, Value};
+use executor::join::JoinType;
+use executor::tuple::field_parse;
+use storage::stats::TableStats;
+use super::{Catalog, JoinAlgorithm, JoinCondition, LogicalPlan, PlanNode};
+
+const DEFAULT_ROWS: f64 = 1000.;
+
+// For conditions which the stats don't help with
+const DEFAULT_SELECTIVITY: f64 = 1. / 3.;
+
+// Resetting the right input of a nested loops join rescans it, e.g. from
+// its first disk block, which costs about this many tuples.
+const RESCAN_COST: f64 = 10.;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Estimate {
+    pub rows: f64,
+    pub columns: Vec<ColumnEstimate>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnEstimate {
+    pub distinct: f64,
+    pub min: Option<f64>, // only for numeric columns
+    pub max: Option<f64>,
+}
+
+impl Estimate {
+    /// After keeping a fraction of the tuples. A column can't have more
+    /// distinct values than there are tuples.
+    pub fn filtered(&self, selectivity: f64) -> Estimate {
+        let rows = self.rows * selectivity;
+        Estimate {
+            rows,
+            columns: self.columns.iter().map(|column| column.capped(rows)).collect(),
+        }
+    }
+}
+
+impl ColumnEstimate {
+    fn unknown(rows: f64) -> Self {
+        ColumnEstimate { distinct: rows, min: None, max: None }
+    }
+
+    fn capped(&self, rows: f64) -> Self {
+        ColumnEstimate {
+            distinct: self.distinct.min(rows),
+            min: self.min,
+            max: self.max,
+        }
+    }
+}
+
+pub fn estimate<C: Catalog + ?Sized>(plan: &LogicalPlan, catalog: &C) -> Estimate {
+    match plan.node {
+        PlanNode::Scan { ref relation, ref columns } => {
+            let stats = catalog.stats(relation);
+            let column_types: Vec<DataType> = columns.iter()
+                .map(|&col| relation.column_types[col].clone())
+                .collect();
+            scan_estimate(stats.as_ref(), columns, &column_types)
+        },
+        PlanNode::Filter { ref input, ref predicate } => {
+            let input = estimate(input, catalog);
+            let selectivity = selectivity(predicate, &input);
+            input.filtered(selectivity)
+        },
+        PlanNode::Project { ref input, ref exprs } => {
+            let input = estimate(input, catalog);
+            let columns = exprs.iter().map(|expr| match *expr {
+                Expr::Column(col) => input.columns[col].clone(),
+                _ => ColumnEstimate::unknown(input.rows),
+            }).collect();
+            Estimate { rows: input.rows, columns }
+        },
+        PlanNode::Join { ref left, ref right, ref condition, join_type, .. } => {
+            let left = estimate(left, catalog);
+            let right = estimate(right, catalog);
+            join_estimate(&left, &right, condition, join_type)
+        },
+        PlanNode::Aggregate { ref input, ref aggregates, ref group_by } => {
+            let input = estimate(input, catalog);
+            let rows = if group_by.is_empty() {
+                1.
+            } else {
+                group_by.iter()
+                    .map(|&col| input.columns[col].distinct)
+                    .product::<f64>()
+                    .min(input.rows)
+            };
+            let mut columns: Vec<ColumnEstimate> = group_by.iter()
+                .map(|&col| input.columns[col].capped(rows))
+                .collect();
+            columns.extend(aggregates.iter().map(|_| ColumnEstimate::unknown(rows)));
+            Estimate { rows, columns }
+        },
+        PlanNode::Sort { ref input, .. } => estimate(input, catalog),
+        PlanNode::Limit { ref input, limit, offset } => {
+            let input = estimate(input, catalog);
+            let rows = (input.rows - offset as f64).max(0.);
+            let rows = limit.map_or(rows, |limit| rows.min(limit as f64));
+            input.filtered(if input.rows > 0. { rows / input.rows } else { 0. })
+        },
+    }
+}
+
+fn scan_estimate(
+    stats: Option<&TableStats>,
+    columns: &[usize],
+    column_types: &[DataType],
+) -> Estimate
+{
+    let stats = match stats {
+        Some(stats) => stats,
+        None => {
+            return Estimate {
+                rows: DEFAULT_ROWS,
+                columns: columns.iter().map(|_| ColumnEstimate::unknown(DEFAULT_ROWS)).collect(),
+            };
+        },
+    };
+    let rows = stats.row_count as f64;
+    let columns = columns.iter().zip(column_types).map(|(&col, data_type)| {
+        match stats.columns.get(col) {
+            Some(column) => ColumnEstimate {
+                distinct: column.distinct_count as f64,
+                min: column.min.as_ref().and_then(|field| numeric(field, data_type)),
+                max: column.max.as_ref().and_then(|field| numeric(field, data_type)),
+            },
+            None => ColumnEstimate::unknown(rows),
+        }
+    }).collect();
+    Estimate { rows, columns }
+}
+
+fn numeric(field: &[u8], data_type: &DataType) -> Option<f64> {
+    match *data_type {
+        DataType::SmallInt => field_parse::<i16>(field).ok().map(f64::from),
+        DataType::Integer => field_parse::<i32>(field).ok().map(f64::from),
+        DataType::BigInt => field_parse::<i64>(field).ok().map(|x| x as f64),
+        DataType::Float => field_parse::<f32>(field).ok().map(f64::from),
+        DataType::Double => field_parse::<f64>(field).ok(),
+        DataType::Date => field_parse::<i32>(field).ok().map(f64::from),
+        DataType::Timestamp => field_parse::<i64>(field).ok().map(|x| x as f64),
+        DataType::Decimal(_, scale) => field_parse::<i128>(field).ok().map(|x| decimal::to_f64(x, scale)),
+        DataType::Boolean | DataType::Text(_) => None,
+    }
+}
+
+/// Each tuple of the smaller side matches tuples of the other side with
+/// the same key, of which there are rows / distinct keys. A join on a
+/// predicate keeps its selectivity of all the pairs of tuples.
+pub fn join_estimate(
+    left: &Estimate,
+    right: &Estimate,
+    condition: &JoinCondition,
+    join_type: JoinType,
+) -> Estimate
+{
+    let (matches, semi) = match *condition {
+        JoinCondition::Key { col_l, col_r } => {
+            let distinct_l = left.columns[col_l].distinct.max(1.);
+            let distinct_r = right.columns[col_r].distinct.max(1.);
+            let matches = left.rows * right.rows / distinct_l.max(distinct_r);
+            (matches, left.rows * (distinct_r / distinct_l).min(1.))
+        },
+        JoinCondition::Predicate(ref predicate) => {
+            let pairs = Estimate {
+                rows: left.rows * right.rows,
+                columns: left.columns.iter().chain(&right.columns).cloned().collect(),
+            };
+            let selectivity = selectivity(predicate, &pairs);
+            (pairs.rows * selectivity, left.rows * (right.rows * selectivity).min(1.))
+        },
+    };
+    let rows = match join_type {
+        JoinType::Inner => matches,
+        JoinType::LeftOuter => matches.max(left.rows),
+        JoinType::RightOuter => matches.max(right.rows),
+        JoinType::FullOuter => matches.max(left.rows).max(right.rows),
+        JoinType::Semi => semi,
+        JoinType::Anti => left.rows - semi,
+    };
+
+    let mut columns: Vec<ColumnEstimate> = left.columns.iter().map(|column| column.capped(rows)).collect();
+    if join_type != JoinType::Semi && join_type != JoinType::Anti {
+        columns.extend(right.columns.iter().map(|column| column.capped(rows)));
+    }
+    Estimate { rows, columns }
+}
+
+/// Algorithm with the lowest cost for joining inputs of these sizes,
+/// and its cost, in tuples read or built.
+pub fn join_algorithm(rows_l: f64, rows_r: f64) -> (JoinAlgorithm, f64) {
+    // the hash table is built from the right input
+    let hash = rows_l + 2. * rows_r;
+    let nested_loops = nested_loops_cost(rows_l, rows_r);
+    if nested_loops < hash {
+        (JoinAlgorithm::NestedLoops, nested_loops)
+    } else {
+        (JoinAlgorithm::Hash, hash)
+    }
+}
+
+/// Cost of a nested loops join, the only algorithm for joins on a
+/// predicate.
+pub fn nested_loops_cost(rows_l: f64, rows_r: f64) -> f64 {
+    rows_l * (rows_r + RESCAN_COST)
+}
+
+/// Fraction of input tuples for which predicate is true.
+pub fn selectivity(predicate: &Expr, input: &Estimate) -> f64 {
+    use self::BinaryOp::*;
+    match *predicate {
+        Expr::Literal(Value::Boolean(true)) => 1.,
+        Expr::Literal(_) => 0., // false or null
+        Expr::Binary(And, ref left, ref right) => {
+            selectivity(left, input) * selectivity(right, input)
+        },
+        Expr::Binary(Or, ref left, ref right) => {
+            let (left, right) = (selectivity(left, input), selectivity(right, input));
+            left + right - left * right
+        },
+        Expr::Not(ref expr) => 1. - selectivity(expr, input),
+        Expr::Binary(op, ref left, ref right) => {
+            match (&**left, &**right) {
+                (Expr::Column(col_l), Expr::Column(col_r)) if op == Eq => {
+                    let distinct = input.columns[*col_l].distinct.max(input.columns[*col_r].distinct);
+                    1. / distinct.max(1.)
+                },
+                (Expr::Column(col), Expr::Literal(value)) => {
+                    compare_selectivity(op, &input.columns[*col], value)
+                },
+                (Expr::Literal(value), Expr::Column(col)) => {
+                    compare_selectivity(flip(op), &input.columns[*col], value)
+                },
+                _ => DEFAULT_SELECTIVITY,
+            }
+        },
+        Expr::In(ref expr, ref list) => {
+            match **expr {
+                Expr::Column(col) => (list.len() as f64 / input.columns[col].distinct.max(1.)).min(1.),
+                _ => DEFAULT_SELECTIVITY,
+            }
+        },
+        _ => DEFAULT_SELECTIVITY,
+    }
+}
+
+// col op value
+fn compare_selectivity(op: BinaryOp, column: &ColumnEstimate, value: &Value) -> f64 {
+    use self::BinaryOp::*;
+    let equal = 1. / column.distinct.max(1.);
+    let value = match *value {
+        Value::SmallInt(x) => Some(f64::from(x)),
+        Value::Integer(x) => Some(f64::from(x)),
+        Value::BigInt(x) => Some(x as f64),
+        Value::Float(x) => Some(f64::from(x)),
+        Value::Double(x) => Some(x),
+        Value::Date(x) => Some(f64::from(x)),
+        Value::Timestamp(x) => Some(x as f64),
+        Value::Decimal(x, _, scale) => Some(decimal::to_f64(x, scale)),
+        _ => None,
+    };
+    match op {
+        Eq => equal,
+        NotEq => 1. - equal,
+        Lt | LtEq | Gt | GtEq => {
+            match (column.min, column.max, value) {
+                (Some(min), Some(max), Some(value)) if max > min => {
+                    let below = ((value - min) / (max - min)).clamp(0., 1.);
+                    if op == Lt || op == LtEq { below } else { 1. - below }
+                },
+                _ => DEFAULT_SELECTIVITY,
+            }
+        },
+        _ => DEFAULT_SELECTIVITY,
+    }
+}
+
+// x op y is y flip(op) x
+fn flip(op: BinaryOp) -> BinaryOp {
+    use self::BinaryOp::*;
+    match op {
+        Lt => Gt,
+        LtEq => GtEq,
+        Gt => Lt,
+        GtEq => LtEq,
+        op => op,
+    }
+}
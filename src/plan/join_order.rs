@@ -0,0 +1,351 @@
+// Cost-based choice of join order and join algorithms.
+//
+// A tree of inner joins (and the filters between them) is flattened into
+// its inputs, the leaves, and the conditions on them: each join's key,
+// and the conjuncts of the filters. Then, by dynamic programming over
+// sets of leaves, the cheapest plan for each set is the cheapest split
+// into two smaller sets with a key between them, costing each join by
+// the tuples it reads and outputs. Only a set which can't be split that
+// way is split without a key, as a nested loops join on the conditions
+// between its two sides, or a cross join if there are none.
+//
+// Conditions other than the key are filters right above the first join
+// which has all of their columns. The output columns are put back in
+// their original order at the end.
+//
+// Other joins (e.g. outer joins) keep their order, but still get the
+// algorithm with the lowest cost.
+use std::collections::HashMap;
+
+use DataType;
+use error::*;
+use executor::expr::{BinaryOp, Expr};
+use executor::join::{JoinType, key_type};
+use super::{Catalog, JoinAlgorithm, JoinCondition, LogicalPlan, PlanNode};
+use super::cost::{Estimate, estimate, join_algorithm, join_estimate, nested_loops_cost, selectivity};
+use super::optimizer::{map_inputs, split_conjuncts, with_algorithm};
+
+// Past this many leaves, there are too many sets to enumerate, and joins
+// keep their order.
+const MAX_LEAVES: usize = 10;
+
+pub fn reorder_joins<C: Catalog + ?Sized>(plan: LogicalPlan, catalog: &C) -> Result<LogicalPlan> {
+    if is_inner_join(&plan) && count_leaves(&plan) <= MAX_LEAVES {
+        return JoinOrder::new(plan, catalog)?.best_plan();
+    }
+
+    let mut plan = map_inputs(plan, |input| reorder_joins(input, catalog))?;
+    // joins on a predicate are always nested loops
+    if let PlanNode::Join { ref left, ref right, condition: JoinCondition::Key { .. }, ref mut algorithm, .. } = plan.node {
+        let rows_l = estimate(left, catalog).rows;
+        let rows_r = estimate(right, catalog).rows;
+        *algorithm = join_algorithm(rows_l, rows_r).0;
+    }
+    Ok(plan)
+}
+
+// A join condition, over the columns of all the leaves side by side
+struct Condition {
+    expr: Expr,
+    leaves: u32, // set of leaves whose columns it reads
+    key: Option<(usize, usize)>, // for an equality of columns of two leaves
+}
+
+// Cheapest plan found for a set of leaves
+struct Candidate {
+    cost: f64,
+    estimate: Estimate,
+    columns: Vec<usize>, // output, as columns of all the leaves
+    split: Option<Split>, // None for a leaf
+}
+
+struct Split {
+    left: u32,
+    right: u32,
+    key: Option<usize>, // index into conditions, None for a join on a predicate
+    algorithm: JoinAlgorithm,
+}
+
+struct JoinOrder<'a, C: 'a + ?Sized> {
+    catalog: &'a C,
+    column_names: Vec<String>, // of the original plan
+    leaves: Vec<Option<LogicalPlan>>, // taken when built
+    conditions: Vec<Condition>,
+    best: HashMap<u32, Candidate>,
+}
+
+impl<'a, C: Catalog + ?Sized> JoinOrder<'a, C> {
+    fn new(plan: LogicalPlan, catalog: &'a C) -> Result<Self> {
+        let column_names = plan.schema.column_names.clone();
+        let mut leaves = Vec::new();
+        let mut exprs = Vec::new();
+        flatten(plan, &mut leaves, &mut exprs);
+        let leaves = leaves.into_iter()
+            .map(|leaf| reorder_joins(leaf, catalog))
+            .collect::<Result<Vec<_>>>()?;
+
+        // which leaf each column is from, and its type
+        let mut leaf_of = Vec::new();
+        let mut column_types: Vec<DataType> = Vec::new();
+        for (i, leaf) in leaves.iter().enumerate() {
+            leaf_of.extend(leaf.column_types().iter().map(|_| i));
+            column_types.extend(leaf.column_types().iter().cloned());
+        }
+
+        let conditions = exprs.into_iter().map(|expr| {
+            let mut leaves = expr.columns().iter().fold(0, |set, &col| set | 1 << leaf_of[col]);
+            if leaves == 0 {
+                leaves = 1; // constant, so filter the first leaf
+            }
+            let key = match expr {
+                Expr::Binary(BinaryOp::Eq, ref left, ref right) => match (&**left, &**right) {
+                    (&Expr::Column(col_l), &Expr::Column(col_r))
+                        if leaf_of[col_l] != leaf_of[col_r] &&
+                        key_type(&column_types[col_l], &column_types[col_r]).is_some() => Some((col_l, col_r)),
+                    _ => None,
+                },
+                _ => None,
+            };
+            Condition { expr, leaves, key }
+        }).collect();
+
+        Ok(JoinOrder {
+            catalog,
+            column_names,
+            leaves: leaves.into_iter().map(Some).collect(),
+            conditions,
+            best: HashMap::new(),
+        })
+    }
+
+    fn best_plan(mut self) -> Result<LogicalPlan> {
+        let leaf_count = self.leaves.len();
+        let mut offset = 0;
+        for i in 0..leaf_count {
+            let leaf = self.leaves[i].as_ref().unwrap();
+            let width = leaf.column_types().len();
+            let set = 1 << i;
+            let columns: Vec<usize> = (offset..offset + width).collect();
+            offset += width;
+
+            let mut estimate = estimate(leaf, self.catalog);
+            for condition in self.conditions.iter().filter(|condition| condition.leaves == set) {
+                estimate = estimate.filtered(selectivity(&localize(&condition.expr, &columns), &estimate));
+            }
+            self.best.insert(set, Candidate { cost: 0., estimate, columns, split: None });
+        }
+
+        // subsets of a set are smaller numbers, so are done before it
+        let all = (1u32 << leaf_count) - 1;
+        for set in 1..=all {
+            if set.count_ones() < 2 {
+                continue;
+            }
+            for keyed in [true, false] {
+                let mut left = (set - 1) & set;
+                while left > 0 {
+                    let right = set ^ left;
+                    if let Some(candidate) = self.join(left, right, keyed) {
+                        if self.best.get(&set).is_none_or(|best| candidate.cost < best.cost) {
+                            self.best.insert(set, candidate);
+                        }
+                    }
+                    left = (left - 1) & set;
+                }
+                if self.best.contains_key(&set) {
+                    break;
+                }
+            }
+        }
+
+        let plan = self.build(all)?;
+
+        // back to the original order of columns
+        let columns = &self.best[&all].columns;
+        if columns.iter().enumerate().all(|(i, &col)| i == col) {
+            return Ok(plan);
+        }
+        let exprs = (0..columns.len())
+            .map(|col| Expr::col(columns.iter().position(|&c| c == col).unwrap()))
+            .collect();
+        plan.project(exprs, self.column_names.clone())
+    }
+
+    // Join of the best plans for left and right, on a key between them if
+    // keyed, otherwise on a predicate.
+    fn join(&self, left: u32, right: u32, keyed: bool) -> Option<Candidate> {
+        let (candidate_l, candidate_r) = (self.best.get(&left)?, self.best.get(&right)?);
+        let key = if keyed {
+            Some(self.conditions.iter().position(|condition| {
+                condition.key.is_some() &&
+                    condition.leaves & left != 0 &&
+                    condition.leaves & right != 0 &&
+                    condition.leaves & !(left | right) == 0
+            })?)
+        } else {
+            None
+        };
+        let condition = match key {
+            Some(key) => {
+                let (col_l, col_r) = self.key_columns(key, left);
+                JoinCondition::Key {
+                    col_l: position(&candidate_l.columns, col_l),
+                    col_r: position(&candidate_r.columns, col_r),
+                }
+            },
+            // the conditions between left and right are estimated below
+            None => JoinCondition::Predicate(Expr::lit(true)),
+        };
+
+        let mut estimate = join_estimate(
+            &candidate_l.estimate,
+            &candidate_r.estimate,
+            &condition,
+            JoinType::Inner,
+        );
+        let mut columns = candidate_l.columns.clone();
+        columns.extend(candidate_r.columns.iter().cloned());
+        for i in self.filters_at(left, right, key) {
+            let predicate = localize(&self.conditions[i].expr, &columns);
+            estimate = estimate.filtered(selectivity(&predicate, &estimate));
+        }
+
+        let (rows_l, rows_r) = (candidate_l.estimate.rows, candidate_r.estimate.rows);
+        let (algorithm, join_cost) = match key {
+            Some(_) => join_algorithm(rows_l, rows_r),
+            None => (JoinAlgorithm::NestedLoops, nested_loops_cost(rows_l, rows_r)),
+        };
+        Some(Candidate {
+            cost: candidate_l.cost + candidate_r.cost + join_cost + estimate.rows,
+            estimate,
+            columns,
+            split: Some(Split { left, right, key, algorithm }),
+        })
+    }
+
+    fn build(&mut self, set: u32) -> Result<LogicalPlan> {
+        let (left, right, key, algorithm) = match self.best[&set].split {
+            Some(Split { left, right, key, algorithm }) => (left, right, key, algorithm),
+            None => {
+                let mut plan = self.leaves[set.trailing_zeros() as usize].take().unwrap();
+                let columns = &self.best[&set].columns;
+                for condition in self.conditions.iter().filter(|condition| condition.leaves == set) {
+                    plan = plan.filter(localize(&condition.expr, columns))?;
+                }
+                return Ok(plan);
+            },
+        };
+
+        let plan_l = self.build(left)?;
+        let plan_r = self.build(right)?;
+        let filters = self.filters_at(left, right, key);
+        let key = match key {
+            Some(key) => key,
+            None => {
+                let predicate = filters.iter()
+                    .map(|&i| localize(&self.conditions[i].expr, &self.best[&set].columns))
+                    .reduce(Expr::and)
+                    .unwrap_or_else(|| Expr::lit(true));
+                return LogicalPlan::join_on(plan_l, plan_r, predicate, JoinType::Inner);
+            },
+        };
+        let (col_l, col_r) = self.key_columns(key, left);
+        let join = LogicalPlan::join(
+            plan_l,
+            plan_r,
+            position(&self.best[&left].columns, col_l),
+            position(&self.best[&right].columns, col_r),
+            JoinType::Inner,
+        )?;
+        let mut plan = with_algorithm(join, algorithm);
+        for i in filters {
+            let predicate = localize(&self.conditions[i].expr, &self.best[&set].columns);
+            plan = plan.filter(predicate)?;
+        }
+        Ok(plan)
+    }
+
+    // Key columns of the condition, with the column of left first
+    fn key_columns(&self, key: usize, left: u32) -> (usize, usize) {
+        let (col_a, col_b) = self.conditions[key].key.unwrap();
+        if self.best[&left].columns.contains(&col_a) {
+            (col_a, col_b)
+        } else {
+            (col_b, col_a)
+        }
+    }
+
+    // Conditions, other than the key, which can be checked once left and
+    // right are joined but not before.
+    fn filters_at(&self, left: u32, right: u32, key: Option<usize>) -> Vec<usize> {
+        let set = left | right;
+        (0..self.conditions.len()).filter(|&i| {
+            let leaves = self.conditions[i].leaves;
+            Some(i) != key &&
+                leaves & !set == 0 &&
+                leaves & !left != 0 &&
+                leaves & !right != 0
+        }).collect()
+    }
+}
+
+// A tree of inner joins, possibly under a filter
+fn is_inner_join(plan: &LogicalPlan) -> bool {
+    match plan.node {
+        PlanNode::Join { join_type: JoinType::Inner, .. } => true,
+        PlanNode::Filter { ref input, .. } => is_inner_join(input),
+        _ => false,
+    }
+}
+
+fn count_leaves(plan: &LogicalPlan) -> usize {
+    match plan.node {
+        PlanNode::Join { join_type: JoinType::Inner, ref left, ref right, .. } => {
+            count_leaves(left) + count_leaves(right)
+        },
+        PlanNode::Filter { ref input, .. } if is_inner_join(input) => count_leaves(input),
+        _ => 1,
+    }
+}
+
+// Conditions are over the columns of all leaves so far, side by side,
+// which is also the output of the joins.
+fn flatten(plan: LogicalPlan, leaves: &mut Vec<LogicalPlan>, conditions: &mut Vec<Expr>) {
+    let offset: usize = leaves.iter().map(|leaf| leaf.column_types().len()).sum();
+    let LogicalPlan { node, schema, tables } = plan;
+    match node {
+        PlanNode::Join { left, right, condition, join_type: JoinType::Inner, .. } => {
+            flatten(*left, leaves, conditions);
+            let offset_r: usize = leaves.iter().map(|leaf| leaf.column_types().len()).sum();
+            flatten(*right, leaves, conditions);
+            match condition {
+                JoinCondition::Key { col_l, col_r } => {
+                    conditions.push(Expr::binary(
+                        BinaryOp::Eq,
+                        Expr::col(offset + col_l),
+                        Expr::col(offset_r + col_r),
+                    ));
+                },
+                // over the left columns then the right, which are the
+                // leaves' columns from offset on
+                JoinCondition::Predicate(predicate) => {
+                    conditions.extend(split_conjuncts(predicate.map_columns(&|col| col + offset)));
+                },
+            }
+        },
+        PlanNode::Filter { input, predicate } if is_inner_join(&input) => {
+            flatten(*input, leaves, conditions);
+            conditions.extend(split_conjuncts(predicate.map_columns(&|col| col + offset)));
+        },
+        node => leaves.push(LogicalPlan { node, schema, tables }),
+    }
+}
+
+// From columns of all leaves to the output of a plan with columns
+fn localize(expr: &Expr, columns: &[usize]) -> Expr {
+    expr.clone().map_columns(&|col| position(columns, col))
+}
+
+fn position(columns: &[usize], col: usize) -> usize {
+    columns.iter().position(|&c| c == col).expect("column not in plan")
+}
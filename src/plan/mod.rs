@@ -0,0 +1,1005 @@
+//! Plan module
+//!
+//! - logical plan, a tree of relational operators which each carry the
+//!   schema of their output
+//! - binder, resolving the names in a parsed SELECT against a catalog,
+//!   giving a logical plan
+//! - optimizer, rewriting a logical plan with rules, e.g. pushing
+//!   filters below joins
+//! - cost estimates from the stats of relations, which the optimizer
+//!   uses to order joins and choose their algorithms
+//! - building a logical plan into a pipeline of executor nodes
+//!
+//! Expressions in a plan are executor Exprs, so columns are already
+//! resolved to indexes into the input of their node.
+
+pub mod binder;
+pub mod cost;
+mod join_order;
+pub mod optimizer;
+
+use std::path::PathBuf;
+
+use {DataType, RelationSchema};
+use error::*;
+use executor::DbIterator;
+use executor::aggregate::{AggregateExpr, TypedAggregate};
+use executor::expr::{Expr, ExprType, TypedExpr};
+use executor::join::{JoinType, key_type};
+use executor::simplesort::SortKey;
+use sql;
+use storage::catalog::SystemCatalog;
+use storage::disk::DiskScan;
+use storage::stats::TableStats;
+use self::binder::Binder;
+pub use self::optimizer::optimize;
+
+// Hash aggregates partition to disk past this many groups.
+const MAX_GROUPS_IN_MEMORY: usize = 100_000;
+
+/// Where the binder looks up relations by name, and how a plan scans
+/// them.
+pub trait Catalog {
+    fn relation(&self, name: &str) -> Option<RelationSchema>;
+
+    /// Scan of only the given columns of relation, in that order.
+    fn scan(&self, relation: &RelationSchema, columns: &[usize]) -> Result<Box<dyn DbIterator>>;
+
+    /// Stats for estimates, if the relation has them.
+    fn stats(&self, _relation: &RelationSchema) -> Option<TableStats> {
+        None
+    }
+}
+
+/// Relations imported with storage::from_csv, which names each file by
+/// relation id.
+#[derive(Debug, Clone)]
+pub struct FileCatalog {
+    dir: PathBuf,
+    relations: Vec<RelationSchema>,
+}
+
+impl FileCatalog {
+    pub fn new<P: Into<PathBuf>>(dir: P, relations: Vec<RelationSchema>) -> Self {
+        FileCatalog {
+            dir: dir.into(),
+            relations,
+        }
+    }
+}
+
+impl Catalog for FileCatalog {
+    fn relation(&self, name: &str) -> Option<RelationSchema> {
+        self.relations.iter()
+            .find(|relation| relation.name.eq_ignore_ascii_case(name))
+            .cloned()
+    }
+
+    fn scan(&self, relation: &RelationSchema, columns: &[usize]) -> Result<Box<dyn DbIterator>> {
+        let path = self.dir.join(relation.id.to_string());
+        let scan = DiskScan::from_path(&path, relation.column_types.clone())
+            .chain_err(|| format!("could not open {:?} for relation {}", path, relation.name))?;
+        Ok(scan.with_columns(columns).boxed())
+    }
+
+    // saved next to the relation by from_csv. Stats are only hints, so
+    // stats which can't be read are the same as none.
+    fn stats(&self, relation: &RelationSchema) -> Option<TableStats> {
+        TableStats::load(self.dir.join(format!("{}.stats", relation.id))).ok()
+    }
+}
+
+/// Relations recorded in the system catalog of a data directory.
+impl Catalog for SystemCatalog {
+    fn relation(&self, name: &str) -> Option<RelationSchema> {
+        SystemCatalog::relation(self, name).cloned()
+    }
+
+    fn scan(&self, relation: &RelationSchema, columns: &[usize]) -> Result<Box<dyn DbIterator>> {
+        Ok(SystemCatalog::scan(self, &relation.name)?.with_columns(columns).boxed())
+    }
+
+    fn stats(&self, relation: &RelationSchema) -> Option<TableStats> {
+        SystemCatalog::stats(self, &relation.name).ok()
+    }
+}
+
+/// Parses, binds and optimizes one SELECT query.
+pub fn plan<C: Catalog + ?Sized>(sql: &str, catalog: &C) -> Result<LogicalPlan> {
+    let select = sql::parse(sql)?;
+    let plan = Binder::new(catalog).bind_select(&select)?;
+    optimize(plan, catalog)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum JoinAlgorithm {
+    NestedLoops,
+    Hash,
+    SortMerge,
+}
+
+/// Equijoin on col_l of left and col_r of right, or a predicate over the
+/// left columns followed by the right.
+#[derive(Debug, Clone)]
+pub enum JoinCondition {
+    Key {
+        col_l: usize,
+        col_r: usize,
+    },
+    Predicate(Expr),
+}
+
+#[derive(Debug, Clone)]
+pub enum PlanNode {
+    Scan {
+        relation: RelationSchema,
+        columns: Vec<usize>, // which are read, in output order
+    },
+    Filter {
+        input: Box<LogicalPlan>,
+        predicate: Expr,
+    },
+    Project {
+        input: Box<LogicalPlan>,
+        exprs: Vec<Expr>,
+    },
+    Join {
+        left: Box<LogicalPlan>,
+        right: Box<LogicalPlan>,
+        condition: JoinCondition,
+        join_type: JoinType,
+        algorithm: JoinAlgorithm,
+    },
+    Aggregate {
+        input: Box<LogicalPlan>,
+        aggregates: Vec<AggregateExpr>,
+        group_by: Vec<usize>,
+    },
+    Sort {
+        input: Box<LogicalPlan>,
+        sort_keys: Vec<SortKey>,
+    },
+    Limit {
+        input: Box<LogicalPlan>,
+        limit: Option<usize>,
+        offset: usize,
+    },
+}
+
+/// A node of the plan, with the schema of its output. Only scans have a
+/// relation name and id; for other nodes they're empty.
+///
+/// Each output column also remembers the table (or alias) it came from,
+/// so that qualified names like m.title can be resolved against it.
+#[derive(Debug, Clone)]
+pub struct LogicalPlan {
+    pub node: PlanNode,
+    pub schema: RelationSchema,
+    pub tables: Vec<Option<String>>,
+}
+
+impl LogicalPlan {
+    pub fn scan(relation: RelationSchema, alias: Option<String>) -> Self {
+        let table = alias.unwrap_or_else(|| relation.name.clone());
+        let columns = (0..relation.column_types.len()).collect();
+        Self::scan_columns(relation, table, columns)
+    }
+
+    /// Scan of only some columns, known by table.
+    pub fn scan_columns(relation: RelationSchema, table: String, columns: Vec<usize>) -> Self {
+        let width = columns.len();
+        let schema = RelationSchema {
+            name: relation.name.clone(),
+            id: relation.id,
+            column_names: columns.iter().map(|&col| relation.column_names[col].clone()).collect(),
+            column_types: columns.iter().map(|&col| relation.column_types[col].clone()).collect(),
+        };
+        LogicalPlan {
+            node: PlanNode::Scan { relation, columns },
+            schema,
+            tables: vec![Some(table); width],
+        }
+    }
+
+    pub fn filter(self, predicate: Expr) -> Result<Self> {
+        let predicate_type = predicate.check(self.column_types())?;
+        if !predicate_type.is_boolean() {
+            bail!(plan_error(format!("predicate is {:?}, not Boolean", predicate_type)));
+        }
+        let schema = derived_schema(
+            self.schema.column_names.clone(),
+            self.schema.column_types.clone(),
+        );
+        let tables = self.tables.clone();
+        Ok(LogicalPlan {
+            node: PlanNode::Filter { input: Box::new(self), predicate },
+            schema,
+            tables,
+        })
+    }
+
+    /// Output column names are given, since they usually come from the
+    /// query rather than from the expressions.
+    pub fn project(self, exprs: Vec<Expr>, column_names: Vec<String>) -> Result<Self> {
+        assert_eq!(exprs.len(), column_names.len());
+
+        let mut column_types = Vec::new();
+        let mut tables = Vec::new();
+        for expr in &exprs {
+            match expr.check(self.column_types())? {
+                ExprType::Column(data_type) => column_types.push(data_type),
+                expr_type => bail!(plan_error(format!("can't output a column of {:?}", expr_type))),
+            }
+            // plain columns can still be qualified by their table
+            tables.push(match *expr {
+                Expr::Column(col) => self.tables[col].clone(),
+                _ => None,
+            });
+        }
+        Ok(LogicalPlan {
+            node: PlanNode::Project { input: Box::new(self), exprs },
+            schema: derived_schema(column_names, column_types),
+            tables,
+        })
+    }
+
+    /// Equijoin on col_l of left and col_r of right, whose types must
+    /// both convert to one which keys are compared as, e.g. an Integer
+    /// and a BigInt. Output is the left columns followed by the right, or
+    /// only the left for semi and anti joins.
+    pub fn join(
+        left: LogicalPlan,
+        right: LogicalPlan,
+        col_l: usize,
+        col_r: usize,
+        join_type: JoinType,
+    ) -> Result<Self>
+    {
+        let type_l = &left.schema.column_types[col_l];
+        let type_r = &right.schema.column_types[col_r];
+        if key_type(type_l, type_r).is_none() {
+            bail!(plan_error(format!(
+                "can't join {} ({:?}) with {} ({:?}), join keys must have comparable types",
+                left.schema.column_names[col_l],
+                type_l,
+                right.schema.column_names[col_r],
+                type_r,
+            )));
+        }
+        Ok(Self::join_with(left, right, JoinCondition::Key { col_l, col_r }, join_type))
+    }
+
+    /// Join on a predicate over the left columns followed by the right,
+    /// which can only be run as a nested loops join. Output is as for join.
+    pub fn join_on(
+        left: LogicalPlan,
+        right: LogicalPlan,
+        predicate: Expr,
+        join_type: JoinType,
+    ) -> Result<Self>
+    {
+        let column_types: Vec<_> = left.column_types().iter()
+            .chain(right.column_types())
+            .cloned()
+            .collect();
+        TypedExpr::predicate(predicate.clone(), &column_types)?;
+        Ok(Self::join_with(left, right, JoinCondition::Predicate(predicate), join_type))
+    }
+
+    fn join_with(
+        left: LogicalPlan,
+        right: LogicalPlan,
+        condition: JoinCondition,
+        join_type: JoinType,
+    ) -> Self
+    {
+        let mut column_names = left.schema.column_names.clone();
+        let mut column_types = left.schema.column_types.clone();
+        let mut tables = left.tables.clone();
+        if join_type != JoinType::Semi && join_type != JoinType::Anti {
+            column_names.extend(right.schema.column_names.iter().cloned());
+            column_types.extend(right.schema.column_types.iter().cloned());
+            tables.extend(right.tables.iter().cloned());
+        }
+        LogicalPlan {
+            node: PlanNode::Join {
+                left: Box::new(left),
+                right: Box::new(right),
+                condition,
+                join_type,
+                algorithm: JoinAlgorithm::NestedLoops,
+            },
+            schema: derived_schema(column_names, column_types),
+            tables,
+        }
+    }
+
+    /// Output is the group_by columns followed by the aggregates, with no
+    /// aggregates being a distinct on the group_by columns.
+    pub fn aggregate(self, aggregates: Vec<AggregateExpr>, group_by: Vec<usize>) -> Result<Self> {
+        let mut column_names = Vec::new();
+        let mut column_types = Vec::new();
+        let mut tables = Vec::new();
+        for &col in &group_by {
+            column_names.push(self.schema.column_names[col].clone());
+            column_types.push(self.schema.column_types[col].clone());
+            tables.push(self.tables[col].clone());
+        }
+        for aggregate in &aggregates {
+            let typed = TypedAggregate::new(aggregate.clone(), &self.schema.column_types)?;
+            let (aggregation, data_type) = (typed.aggregation(), typed.arg_type());
+            column_names.push(format!("{:?}", aggregation).to_lowercase());
+            column_types.push(aggregation.result_type(data_type));
+            tables.push(None);
+        }
+        Ok(LogicalPlan {
+            node: PlanNode::Aggregate { input: Box::new(self), aggregates, group_by },
+            schema: derived_schema(column_names, column_types),
+            tables,
+        })
+    }
+
+    pub fn sort(self, sort_keys: Vec<SortKey>) -> Self {
+        let schema = derived_schema(
+            self.schema.column_names.clone(),
+            self.schema.column_types.clone(),
+        );
+        let tables = self.tables.clone();
+        LogicalPlan {
+            node: PlanNode::Sort { input: Box::new(self), sort_keys },
+            schema,
+            tables,
+        }
+    }
+
+    pub fn limit(self, limit: Option<usize>, offset: usize) -> Self {
+        let schema = derived_schema(
+            self.schema.column_names.clone(),
+            self.schema.column_types.clone(),
+        );
+        let tables = self.tables.clone();
+        LogicalPlan {
+            node: PlanNode::Limit { input: Box::new(self), limit, offset },
+            schema,
+            tables,
+        }
+    }
+
+    pub fn column_types(&self) -> &[DataType] {
+        &self.schema.column_types
+    }
+
+    /// Builds the executor pipeline for this plan, scanning relations
+    /// from the catalog.
+    pub fn build<C: Catalog + ?Sized>(&self, catalog: &C) -> Result<Box<dyn DbIterator>> {
+        self.build_with(catalog, false)
+    }
+
+    /// Like build, but each node is wrapped in Analyze, so that after
+    /// running the pipeline, its explain shows what each node did.
+    pub fn build_analyzed<C: Catalog + ?Sized>(&self, catalog: &C) -> Result<Box<dyn DbIterator>> {
+        self.build_with(catalog, true)
+    }
+
+    fn build_with<C: Catalog + ?Sized>(&self, catalog: &C, analyze: bool) -> Result<Box<dyn DbIterator>> {
+        let iter = match self.node {
+            PlanNode::Scan { ref relation, ref columns } => catalog.scan(relation, columns)?,
+            PlanNode::Filter { ref input, ref predicate } => {
+                input.build_with(catalog, analyze)?
+                    .filter(predicate.clone(), input.column_types())?
+                    .boxed()
+            },
+            PlanNode::Project { ref input, ref exprs } => {
+                input.build_with(catalog, analyze)?
+                    .computed_projection(exprs.clone(), input.column_types())?
+                    .boxed()
+            },
+            PlanNode::Join { ref left, ref right, ref condition, join_type, algorithm } => {
+                let other = right.build_with(catalog, analyze)?;
+                let input = left.build_with(catalog, analyze)?;
+                let (types_l, types_r) = (left.column_types(), right.column_types());
+                match *condition {
+                    JoinCondition::Predicate(ref predicate) => {
+                        input.nested_loops_join_on(other, predicate.clone(), join_type, types_l, types_r)?.boxed()
+                    },
+                    JoinCondition::Key { col_l, col_r } => match algorithm {
+                        JoinAlgorithm::NestedLoops => {
+                            input.nested_loops_join(other, col_l, col_r, join_type, types_l, types_r)?.boxed()
+                        },
+                        JoinAlgorithm::Hash => {
+                            input.hash_join(other, col_l, col_r, join_type, types_l, types_r)?.boxed()
+                        },
+                        JoinAlgorithm::SortMerge => {
+                            input.sort_merge_join(other, col_l, col_r, join_type, types_l, types_r)?.boxed()
+                        },
+                    },
+                }
+            },
+            PlanNode::Aggregate { ref input, ref aggregates, ref group_by } => {
+                input.build_with(catalog, analyze)?
+                    .hash_aggregate(aggregates.clone(), group_by.clone(), MAX_GROUPS_IN_MEMORY, input.column_types())?
+                    .boxed()
+            },
+            PlanNode::Sort { ref input, ref sort_keys } => {
+                input.build_with(catalog, analyze)?
+                    .sort_by_keys(sort_keys.clone(), true)
+                    .boxed()
+            },
+            PlanNode::Limit { ref input, limit, offset } => {
+                input.build_with(catalog, analyze)?
+                    .limit_offset(limit, offset)
+                    .boxed()
+            },
+        };
+        if analyze {
+            Ok(iter.analyze().boxed())
+        } else {
+            Ok(iter)
+        }
+    }
+}
+
+// Schema of a node which isn't a stored relation
+fn derived_schema(column_names: Vec<String>, column_types: Vec<DataType>) -> RelationSchema {
+    RelationSchema {
+        name: String::new(),
+        id: 0,
+        column_names,
+        column_types,
+    }
+}
+
+fn plan_error(msg: String) -> Error {
+    ErrorKind::Plan(msg).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use Schema;
+    use executor::TestSource;
+    use executor::tuple::{Tuple, ToTupleField};
+    use storage::stats::StatsCollector;
+
+    struct TestCatalog {
+        relations: Vec<(RelationSchema, Vec<Tuple>)>,
+    }
+
+    impl Catalog for TestCatalog {
+        fn relation(&self, name: &str) -> Option<RelationSchema> {
+            self.relations.iter()
+                .map(|(relation, _)| relation)
+                .find(|relation| relation.name.eq_ignore_ascii_case(name))
+                .cloned()
+        }
+
+        fn scan(&self, relation: &RelationSchema, columns: &[usize]) -> Result<Box<dyn DbIterator>> {
+            let (_, tuples) = self.relations.iter()
+                .find(|(r, _)| r.id == relation.id)
+                .unwrap();
+            Ok(TestSource::new(tuples.clone()).projection(columns.to_vec()).boxed())
+        }
+
+        fn stats(&self, relation: &RelationSchema) -> Option<TableStats> {
+            let (_, tuples) = self.relations.iter().find(|(r, _)| r.id == relation.id)?;
+            let mut collector = StatsCollector::new(&relation.column_types);
+            for tuple in tuples {
+                collector.add(tuple).ok()?;
+            }
+            Some(collector.finish())
+        }
+    }
+
+    fn test_catalog() -> TestCatalog {
+        use DataType::*;
+
+        let movies = RelationSchema {
+            name: "movies".to_owned(),
+            id: 1,
+            column_names: vec!["movieId".to_owned(), "title".to_owned(), "year".to_owned()],
+            column_types: vec![Integer, Text(16), SmallInt],
+        };
+        let movie_tuples = vec![
+            (1i32, "Alien", 1979i16),
+            (2, "Heat", 1995),
+            (3, "Memento", 2000),
+            (4, "Up", 2009),
+        ].into_iter().map(|(id, title, year)| {
+            Tuple::new(vec![id.to_tuple_field(), title.as_bytes().to_vec(), year.to_tuple_field()])
+        }).collect();
+
+        let ratings = RelationSchema {
+            name: "ratings".to_owned(),
+            id: 2,
+            column_names: vec!["userId".to_owned(), "movieId".to_owned(), "rating".to_owned()],
+            column_types: vec![Integer, Integer, Float],
+        };
+        let rating_tuples = vec![
+            (1i32, 1i32, 4.0f32),
+            (1, 2, 3.0),
+            (2, 1, 5.0),
+            (2, 3, 4.5),
+            (3, 1, 3.0),
+            (3, 3, 2.5),
+        ].into_iter().map(|(user, movie, rating)| {
+            Tuple::new(vec![user.to_tuple_field(), movie.to_tuple_field(), rating.to_tuple_field()])
+        }).collect();
+
+        let users = RelationSchema {
+            name: "users".to_owned(),
+            id: 3,
+            column_names: vec!["userId".to_owned(), "name".to_owned()],
+            column_types: vec![Integer, Text(8)],
+        };
+        let user_tuples = vec![(1i32, "alice"), (2, "bob"), (3, "carol")]
+            .into_iter()
+            .map(|(id, name)| Tuple::new(vec![id.to_tuple_field(), name.as_bytes().to_vec()]))
+            .collect();
+
+        TestCatalog {
+            relations: vec![(movies, movie_tuples), (ratings, rating_tuples), (users, user_tuples)],
+        }
+    }
+
+    fn run(sql: &str, catalog: &TestCatalog) -> Result<Vec<String>> {
+        let plan = plan(sql, catalog)?;
+        let schema = Schema {
+            column_names: plan.schema.column_names.clone(),
+            column_types: plan.schema.column_types.clone(),
+        };
+        let mut query = plan.build(catalog)?;
+        let mut rows = Vec::new();
+        while let Some(tuple) = query.next()? {
+            rows.push(tuple.to_string(&schema)?);
+        }
+        Ok(rows)
+    }
+
+    #[test]
+    fn test_plan_select() {
+        use DataType::*;
+        let catalog = test_catalog();
+
+        let sql = "SELECT title, year - 1900 AS age FROM movies WHERE year < 2005 ORDER BY year DESC";
+        let plan = plan(sql, &catalog).unwrap();
+        assert_eq!(plan.schema.column_names, vec!["title", "age"]);
+        assert_eq!(plan.schema.column_types, vec![Text(16), SmallInt]);
+        assert_eq!(
+            run(sql, &catalog).unwrap(),
+            vec!["Memento, 100", "Heat, 95", "Alien, 79"]
+        );
+
+        // order by columns which aren't output, and by position
+        assert_eq!(
+            run("SELECT title FROM movies m ORDER BY m.movieId DESC LIMIT 2 OFFSET 1", &catalog).unwrap(),
+            vec!["Memento", "Heat"]
+        );
+        assert_eq!(
+            run("SELECT movieId, title FROM movies WHERE title LIKE '%e%t%' ORDER BY 2", &catalog).unwrap(),
+            vec!["2, Heat", "3, Memento"]
+        );
+        assert_eq!(
+            run("SELECT * FROM movies WHERE movieId BETWEEN 2 AND 3 AND NOT title IS NULL", &catalog).unwrap(),
+            vec!["2, Heat, 1995", "3, Memento, 2000"]
+        );
+        assert_eq!(
+            run("SELECT DISTINCT userId FROM ratings ORDER BY userId LIMIT 2", &catalog).unwrap(),
+            vec!["1", "2"]
+        );
+
+        // negative results and literals, and BigInt
+        let sql = "SELECT 1980 - year, CAST(movieId AS BIGINT) * 10000000000 FROM movies WHERE year - 2000 < -1 ORDER BY 1";
+        assert_eq!(self::plan(sql, &catalog).unwrap().schema.column_types, vec![SmallInt, BigInt]);
+        assert_eq!(
+            run(sql, &catalog).unwrap(),
+            vec!["-15, 20000000000", "1, 10000000000"]
+        );
+    }
+
+    #[test]
+    fn test_plan_datetime() {
+        use DataType::*;
+        use executor::datetime::{parse_date, parse_timestamp};
+
+        let events = RelationSchema {
+            name: "events".to_owned(),
+            id: 1,
+            column_names: vec!["id".to_owned(), "at".to_owned(), "day".to_owned(), "ok".to_owned(), "amount".to_owned()],
+            column_types: vec![Integer, Timestamp, Date, Boolean, Double],
+        };
+        let event_tuples = vec![
+            (1i32, "1999-12-31 23:00:00", "1999-12-31", true, 1.5f64),
+            (2, "2000-01-01T08:30:00+01:00", "2000-01-01", false, 2.25),
+            (3, "2001-06-15 12:00:00.5", "2000-01-03", true, 4.0),
+        ].into_iter().map(|(id, at, day, ok, amount)| {
+            Tuple::new(vec![
+                id.to_tuple_field(),
+                parse_timestamp(at).unwrap().to_tuple_field(),
+                parse_date(day).unwrap().to_tuple_field(),
+                ok.to_tuple_field(),
+                amount.to_tuple_field(),
+            ])
+        }).collect();
+        let catalog = TestCatalog { relations: vec![(events, event_tuples)] };
+
+        assert_eq!(
+            run("SELECT id, at FROM events WHERE at >= DATE '2000-01-01' ORDER BY at DESC", &catalog).unwrap(),
+            vec!["3, 2001-06-15 12:00:00.5", "2, 2000-01-01 07:30:00"]
+        );
+        assert_eq!(
+            run("SELECT year(at), day - DATE '1999-12-31', CAST(at AS DATE) FROM events WHERE ok ORDER BY day", &catalog).unwrap(),
+            vec!["1999, 0, 1999-12-31", "2001, 3, 2001-06-15"]
+        );
+        assert_eq!(
+            run("SELECT id, day + 1 FROM events WHERE NOT ok OR amount > 3.5 ORDER BY 2 DESC", &catalog).unwrap(),
+            vec!["3, 2000-01-04", "2, 2000-01-02"]
+        );
+
+        let sql = "SELECT ok, sum(amount), count(*) FROM events GROUP BY ok ORDER BY ok";
+        assert_eq!(self::plan(sql, &catalog).unwrap().schema.column_types, vec![Boolean, Double, Integer]);
+        assert_eq!(run(sql, &catalog).unwrap(), vec!["false, 2.25, 1", "true, 5.5, 2"]);
+
+        assert!(run("SELECT id FROM events WHERE day < TIMESTAMP '2000-01-01 25:00'", &catalog).is_err());
+        assert!(plan("SELECT id FROM events WHERE day * 2 > 1", &catalog).is_err());
+    }
+
+    #[test]
+    fn test_plan_decimal() {
+        use DataType::*;
+        use executor::tuple::string_to_binary;
+
+        let relation = |name: &str, id, column_names: Vec<&str>, column_types: Vec<DataType>, rows: Vec<Vec<&str>>| {
+            let tuples = rows.into_iter().map(|row| {
+                Tuple::new(row.iter().zip(&column_types)
+                    .map(|(s, data_type)| string_to_binary(s, data_type).unwrap())
+                    .collect())
+            }).collect();
+            let schema = RelationSchema {
+                name: name.to_owned(),
+                id,
+                column_names: column_names.into_iter().map(str::to_owned).collect(),
+                column_types,
+            };
+            (schema, tuples)
+        };
+        let catalog = TestCatalog {
+            relations: vec![
+                relation(
+                    "items", 1,
+                    vec!["sku", "price"],
+                    vec![Integer, Decimal(8, 2)],
+                    vec![vec!["1", "19.99"], vec!["2", "0.10"], vec!["3", "0.20"], vec!["4", "-5.00"]],
+                ),
+                relation(
+                    "refunds", 2,
+                    vec!["amount"],
+                    vec![Decimal(12, 2)],
+                    vec![vec!["0.3"], vec!["-5"], vec!["0.10"]],
+                ),
+            ],
+        };
+
+        let sql = "SELECT sum(price), avg(price), min(price) FROM items WHERE sku > 1";
+        assert_eq!(
+            self::plan(sql, &catalog).unwrap().schema.column_types,
+            vec![Decimal(38, 2), Decimal(38, 6), Decimal(8, 2)]
+        );
+        assert_eq!(run(sql, &catalog).unwrap(), vec!["-4.70, -1.566667, -5.00"]);
+
+        assert_eq!(
+            run("SELECT sku, price * 3 FROM items WHERE price > 0.1 ORDER BY price DESC", &catalog).unwrap(),
+            vec!["1, 59.97", "3, 0.60"]
+        );
+        // literals with a point are exact too
+        assert_eq!(
+            run("SELECT sku FROM items WHERE price + 0.1 = 0.30 OR price < -4.995", &catalog).unwrap(),
+            vec!["3", "4"]
+        );
+        assert_eq!(
+            self::plan("SELECT 0.5 * price, -1.50 FROM items", &catalog).unwrap().schema.column_types,
+            vec![Decimal(10, 3), Decimal(3, 2)]
+        );
+
+        // join keys only need the same scale
+        assert_eq!(
+            run("SELECT sku FROM items JOIN refunds ON price = amount ORDER BY sku", &catalog).unwrap(),
+            vec!["2", "4"]
+        );
+        // and other keys are converted, or joined on as a predicate
+        assert_eq!(
+            run("SELECT sku FROM items JOIN refunds ON price = CAST(amount AS DECIMAL(12, 3)) ORDER BY sku", &catalog).unwrap(),
+            vec!["2", "4"]
+        );
+        assert!(run("SELECT CAST(price AS DECIMAL(3, 2)) FROM items", &catalog).is_err());
+    }
+
+    #[test]
+    fn test_plan_join_aggregate() {
+        let catalog = test_catalog();
+
+        let sql = "
+            SELECT m.title, count(*) AS n, avg(r.rating) AS avg_rating
+            FROM ratings r JOIN movies m ON r.movieId = m.movieId
+            GROUP BY m.title
+            HAVING count(*) > 1
+            ORDER BY avg(r.rating) DESC";
+        assert_eq!(
+            run(sql, &catalog).unwrap(),
+            vec!["Alien, 3, 4", "Memento, 2, 3.5"]
+        );
+
+        // join key taken from WHERE, instead of a cross join
+        let sql = "
+            SELECT title, rating FROM movies, ratings
+            WHERE movies.movieId = ratings.movieId AND userId = 3
+            ORDER BY rating";
+        let plan = plan(sql, &catalog).unwrap();
+        assert_eq!(run(sql, &catalog).unwrap(), vec!["Memento, 2.5", "Alien, 3"]);
+        fn has_join(plan: &LogicalPlan) -> bool {
+            match plan.node {
+                PlanNode::Scan { .. } => false,
+                PlanNode::Join { condition: JoinCondition::Key { col_l: 0, col_r: 1 }, .. } => true,
+                PlanNode::Join { .. } => false,
+                PlanNode::Filter { ref input, .. } |
+                PlanNode::Project { ref input, .. } |
+                PlanNode::Aggregate { ref input, .. } |
+                PlanNode::Sort { ref input, .. } |
+                PlanNode::Limit { ref input, .. } => has_join(input),
+            }
+        }
+        assert!(has_join(&plan));
+
+        // outer joins pad the missing side with nulls
+        let sql = "
+            SELECT m.title, r.rating FROM movies m
+            LEFT JOIN ratings r ON m.movieId = r.movieId
+            WHERE m.year > 2005";
+        assert_eq!(run(sql, &catalog).unwrap(), vec!["Up, NULL"]);
+
+        // even when the other side is empty
+        let mut no_ratings = test_catalog();
+        no_ratings.relations[1].1.clear();
+        assert_eq!(run(sql, &no_ratings).unwrap(), vec!["Up, NULL"]);
+        let sql = "SELECT r.rating, m.title FROM ratings r RIGHT JOIN movies m ON m.movieId = r.movieId";
+        assert_eq!(run(sql, &no_ratings).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn test_plan_join_conditions() {
+        use DataType::*;
+
+        let mut catalog = test_catalog();
+        let favorites = RelationSchema {
+            name: "favorites".to_owned(),
+            id: 4,
+            column_names: vec!["userId".to_owned(), "movieId".to_owned()],
+            column_types: vec![BigInt, SmallInt],
+        };
+        let favorite_tuples = vec![(1i64, 2i16), (3, 3), (3, 4)].into_iter()
+            .map(|(user, movie)| Tuple::new(vec![user.to_tuple_field(), movie.to_tuple_field()]))
+            .collect();
+        catalog.relations.push((favorites, favorite_tuples));
+
+        // keys of different types are converted to compare them
+        let sql = "
+            SELECT u.name, m.title FROM users u
+            JOIN favorites f ON u.userId = f.userId
+            JOIN movies m ON f.movieId = m.movieId
+            ORDER BY m.title";
+        assert_eq!(run(sql, &catalog).unwrap(), vec!["alice, Heat", "carol, Memento", "carol, Up"]);
+        let mut lines = Vec::new();
+        describe(&plan(sql, &catalog).unwrap(), &mut lines);
+        assert!(lines.iter().all(|line| !line.contains("NestedLoops")), "{:?}", lines);
+
+        // without an equality between the sides, the join is a nested
+        // loops join on the whole condition
+        assert_eq!(run("SELECT count(*) FROM movies, users", &catalog).unwrap(), vec!["12"]);
+        assert_eq!(run("SELECT count(*) FROM movies CROSS JOIN users", &catalog).unwrap(), vec!["12"]);
+        let sql = "
+            SELECT m.title, u.name FROM movies m
+            LEFT JOIN users u ON m.movieId < u.userId
+            ORDER BY m.title, u.name";
+        assert_eq!(
+            run(sql, &catalog).unwrap(),
+            vec!["Alien, bob", "Alien, carol", "Heat, carol", "Memento, NULL", "Up, NULL"]
+        );
+        // as are outer joins with other conditions than the key
+        let sql = "
+            SELECT m.title, r.rating FROM movies m
+            LEFT JOIN ratings r ON m.movieId = r.movieId AND r.rating > 4
+            ORDER BY m.title";
+        assert_eq!(run(sql, &catalog).unwrap(), vec!["Alien, 5", "Heat, NULL", "Memento, 4.5", "Up, NULL"]);
+
+        // filters on one side are pushed below the join, and the rest
+        // becomes its predicate
+        let sql = "
+            SELECT m.title, u.name FROM movies m, users u
+            WHERE m.movieId > u.userId AND u.name <> 'bob' AND m.year < 2001
+            ORDER BY m.title, u.name";
+        let plan = plan(sql, &catalog).unwrap();
+        let mut lines = Vec::new();
+        describe(&plan, &mut lines);
+        assert_eq!(lines.iter().filter(|line| line.starts_with("Filter")).count(), 2, "{:?}", lines);
+        assert!(lines.contains(&"Join NestedLoops".to_owned()));
+        assert_eq!(run(sql, &catalog).unwrap(), vec!["Heat, alice", "Memento, alice"]);
+    }
+
+    // Preorder, one line for each node
+    fn describe(plan: &LogicalPlan, lines: &mut Vec<String>) {
+        match plan.node {
+            PlanNode::Scan { ref relation, ref columns } => {
+                lines.push(format!("Scan {} {:?}", relation.name, columns));
+            },
+            PlanNode::Filter { ref input, ref predicate } => {
+                lines.push(format!("Filter {:?}", predicate));
+                describe(input, lines);
+            },
+            PlanNode::Join { ref left, ref right, algorithm, .. } => {
+                lines.push(format!("Join {:?}", algorithm));
+                describe(left, lines);
+                describe(right, lines);
+            },
+            PlanNode::Project { ref input, .. } => {
+                lines.push("Project".to_owned());
+                describe(input, lines);
+            },
+            PlanNode::Aggregate { ref input, .. } |
+            PlanNode::Sort { ref input, .. } |
+            PlanNode::Limit { ref input, .. } => describe(input, lines),
+        }
+    }
+
+    #[test]
+    fn test_optimize() {
+        use executor::expr::{BinaryOp, Value};
+        use sql::parse;
+
+        let catalog = test_catalog();
+        let sql = "
+            SELECT m.title, r.rating FROM ratings r JOIN movies m ON r.movieId = m.movieId
+            WHERE r.userId = 1 + 2 AND m.title LIKE '%e%' AND 1 = 1 AND r.rating < m.movieId + 1
+            ORDER BY r.rating";
+        let plan = plan(sql, &catalog).unwrap();
+        let mut lines = Vec::new();
+        describe(&plan, &mut lines);
+
+        // constants are folded, filters on one side are below the join,
+        // and scans only read the columns which are used
+        let residual = Expr::binary(
+            BinaryOp::Lt,
+            Expr::col(2),
+            Expr::binary(BinaryOp::Add, Expr::col(3), Expr::lit(1i16)),
+        );
+        let user = Expr::binary(BinaryOp::Eq, Expr::col(0), Expr::Literal(Value::SmallInt(3)));
+        assert_eq!(lines, vec![
+            "Project".to_owned(),
+            format!("Filter {:?}", residual),
+            "Join Hash".to_owned(),
+            format!("Filter {:?}", user),
+            "Scan ratings [0, 1, 2]".to_owned(),
+            format!("Filter {:?}", Expr::like(Expr::col(1), "%e%")),
+            "Scan movies [0, 1]".to_owned(),
+        ]);
+        assert_eq!(plan.schema.column_names, vec!["title", "rating"]);
+
+        // and give the same results as the plan from the binder
+        let queries = vec![
+            sql,
+            "SELECT title FROM movies WHERE 2 > 1 ORDER BY title LIMIT 2",
+            "SELECT count(*), max(rating) FROM ratings WHERE rating > 2.5 + 0.5",
+            "SELECT m.title, r.userId FROM movies m LEFT JOIN ratings r ON m.movieId = r.movieId
+             WHERE r.rating IS NULL OR m.year > 1999 ORDER BY m.title, r.userId",
+        ];
+        for sql in queries {
+            let unoptimized = Binder::new(&catalog).bind_select(&parse(sql).unwrap()).unwrap();
+            let mut expected = Vec::new();
+            let mut query = unoptimized.build(&catalog).unwrap();
+            while let Some(tuple) = query.next().unwrap() {
+                expected.push(tuple);
+            }
+            let mut query = optimize(unoptimized, &catalog).unwrap().build(&catalog).unwrap();
+            for tuple in expected {
+                assert_eq!(query.next().unwrap(), Some(tuple));
+            }
+            assert_eq!(query.next().unwrap(), None);
+        }
+    }
+
+    #[test]
+    fn test_join_order() {
+        use executor::expr::{BinaryOp, Value};
+        use sql::parse;
+
+        // ratings is the largest relation, and the filter on users keeps
+        // one of its tuples, so ratings is joined with that first, and
+        // the smaller result builds the hash table for movies
+        let catalog = test_catalog();
+        let sql = "
+            SELECT u.name, m.title FROM movies m
+            JOIN ratings r ON m.movieId = r.movieId
+            JOIN users u ON r.userId = u.userId
+            WHERE u.name = 'carol' ORDER BY m.title";
+        let plan = plan(sql, &catalog).unwrap();
+        let mut lines = Vec::new();
+        describe(&plan, &mut lines);
+        let carol = Expr::binary(BinaryOp::Eq, Expr::col(1), Expr::Literal(Value::Text("carol".to_owned())));
+        assert_eq!(lines, vec![
+            "Project".to_owned(),
+            "Join Hash".to_owned(),
+            "Scan movies [0, 1]".to_owned(),
+            "Join Hash".to_owned(),
+            "Scan ratings [0, 1]".to_owned(),
+            format!("Filter {:?}", carol),
+            "Scan users [0, 1]".to_owned(),
+        ]);
+
+        assert_eq!(run(sql, &catalog).unwrap(), vec!["carol, Alien", "carol, Memento"]);
+        let unoptimized = Binder::new(&catalog).bind_select(&parse(sql).unwrap()).unwrap();
+        let mut expected = Vec::new();
+        let mut query = unoptimized.build(&catalog).unwrap();
+        while let Some(tuple) = query.next().unwrap() {
+            expected.push(tuple);
+        }
+        let mut query = plan.build(&catalog).unwrap();
+        for tuple in expected {
+            assert_eq!(query.next().unwrap(), Some(tuple));
+        }
+        assert_eq!(query.next().unwrap(), None);
+    }
+
+    #[test]
+    fn test_build_analyzed() {
+        let catalog = test_catalog();
+        let plan = plan("SELECT title FROM movies WHERE year > 1990 ORDER BY title LIMIT 1", &catalog).unwrap();
+        let mut query = plan.build_analyzed(&catalog).unwrap();
+        while query.next().unwrap().is_some() {}
+
+        // each plan node is analyzed, and scans show the catalog's nodes
+        let explain = query.explain();
+        let mut nodes = Vec::new();
+        let mut node = Some(&explain);
+        while let Some(explain) = node {
+            nodes.push((explain.name.as_str(), explain.stats.as_ref().map(|stats| stats.tuples)));
+            node = explain.inputs.first();
+        }
+        assert_eq!(nodes, vec![
+            ("Limit", Some(1)),
+            ("SimpleSort", Some(1)),
+            ("ComputedProjection", Some(3)),
+            ("Selection", Some(3)),
+            ("Projection", Some(4)),
+            ("TestSource", None),
+        ]);
+    }
+
+    #[test]
+    fn test_plan_errors() {
+        let catalog = test_catalog();
+        let plan_error = |sql: &str| plan(sql, &catalog).unwrap_err().to_string();
+
+        assert_eq!(
+            plan_error("SELECT name FROM movies"),
+            "planning error: unknown column name at line 1, column 8"
+        );
+        assert_eq!(
+            plan_error("SELECT * FROM films"),
+            "planning error: unknown table films at line 1, column 15"
+        );
+        assert_eq!(
+            plan_error("SELECT movieId FROM movies m JOIN ratings r ON m.movieId = r.movieId"),
+            "planning error: column movieId is ambiguous at line 1, column 8"
+        );
+        assert_eq!(
+            plan_error("SELECT title, count(*) FROM movies"),
+            "planning error: column title must appear in GROUP BY or be used in an aggregate at line 1, column 8"
+        );
+        assert_eq!(
+            plan_error("SELECT title FROM movies WHERE count(*) > 1"),
+            "planning error: aggregate count isn't allowed in WHERE at line 1, column 32"
+        );
+        assert!(plan_error("SELECT sum(title) FROM movies").contains("can't apply Sum"));
+        assert!(plan_error("SELECT title FROM movies m JOIN users u ON m.title = u.userId").contains("type error"));
+        assert!(plan_error("SELECT title FROM movies m LEFT JOIN users u ON u.name").contains("type error"));
+        assert!(plan_error("SELECT title FROM movies WHERE title + 1 > 2").contains("type error"));
+    }
+}
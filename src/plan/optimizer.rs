@@ -0,0 +1,354 @@
+// Rule based rewrites of a LogicalPlan, each of which leaves the output
+// of the plan the same:
+//
+// - constant folding, evaluating expressions which don't read any
+//   columns once instead of for each tuple, and dropping filters which
+//   are always true
+// - predicate pushdown, moving filters (or the parts of them which are
+//   ANDed together) below joins, so that fewer tuples are joined. The
+//   rest of a filter on an inner join on a predicate becomes part of
+//   that predicate
+// - join ordering, choosing the order and algorithm of joins by their
+//   estimated cost (see join_order)
+// - projection pruning, so that scans only read the columns used above
+//   them, and less data is copied through the rest of the plan
+use error::*;
+use executor::expr::{BinaryOp, Expr, TypedExpr, Value};
+use executor::join::JoinType;
+use executor::tuple::Tuple;
+use super::{Catalog, JoinAlgorithm, JoinCondition, LogicalPlan, PlanNode};
+use super::join_order::reorder_joins;
+
+pub fn optimize<C: Catalog + ?Sized>(plan: LogicalPlan, catalog: &C) -> Result<LogicalPlan> {
+    let plan = fold_constants(plan)?;
+    let plan = push_down_predicates(plan)?;
+    let plan = reorder_joins(plan, catalog)?;
+    let width = plan.schema.column_types.len();
+    let (plan, _) = prune_columns(plan, &(0..width).collect::<Vec<_>>())?;
+    Ok(plan)
+}
+
+fn fold_constants(plan: LogicalPlan) -> Result<LogicalPlan> {
+    let LogicalPlan { node, schema, tables } = map_inputs(plan, fold_constants)?;
+    let node = match node {
+        PlanNode::Filter { input, predicate } => {
+            match fold_expr(predicate) {
+                Expr::Literal(Value::Boolean(true)) => return Ok(*input),
+                predicate => PlanNode::Filter { input, predicate },
+            }
+        },
+        PlanNode::Project { input, exprs } => {
+            let exprs = exprs.into_iter().map(fold_expr).collect();
+            PlanNode::Project { input, exprs }
+        },
+        PlanNode::Join { left, right, condition: JoinCondition::Predicate(predicate), join_type, algorithm } => {
+            let condition = JoinCondition::Predicate(fold_expr(predicate));
+            PlanNode::Join { left, right, condition, join_type, algorithm }
+        },
+        node => node,
+    };
+    Ok(LogicalPlan { node, schema, tables })
+}
+
+// Bottom up. An expression is only replaced by its value when the value
+// has the same type, and expressions which fail to evaluate (e.g. on
+// division by zero) are left to fail when the query runs.
+fn fold_expr(expr: Expr) -> Expr {
+    let expr = match expr.map_children(fold_expr) {
+        Expr::Binary(op, left, right) if op == BinaryOp::And || op == BinaryOp::Or => {
+            // true AND x is x, false AND x is false; the other way for OR
+            let identity = Value::Boolean(op == BinaryOp::And);
+            match (*left, *right) {
+                (Expr::Literal(ref value), other) |
+                (other, Expr::Literal(ref value)) if *value == identity => other,
+                (Expr::Literal(value), _) |
+                (_, Expr::Literal(value)) if value == Value::Boolean(op == BinaryOp::Or) => {
+                    Expr::Literal(value)
+                },
+                (left, right) => Expr::binary(op, left, right),
+            }
+        },
+        expr => expr,
+    };
+
+    if let Expr::Literal(_) = expr {
+        return expr;
+    }
+    if !expr.columns().is_empty() {
+        return expr;
+    }
+    let value = TypedExpr::new(expr.clone(), &[])
+        .and_then(|typed| {
+            let value = typed.eval(&Tuple::new(vec![]))?;
+            let same_type = Expr::Literal(value.clone()).check(&[])? == *typed.result_type();
+            Ok(if same_type { Some(value) } else { None })
+        });
+    match value {
+        Ok(Some(value)) => Expr::Literal(value),
+        _ => expr,
+    }
+}
+
+fn push_down_predicates(plan: LogicalPlan) -> Result<LogicalPlan> {
+    match map_inputs(plan, push_down_predicates)? {
+        LogicalPlan { node: PlanNode::Filter { input, predicate }, .. } => {
+            push_filter(*input, predicate)
+        },
+        // for the parts of its predicate which are only on one side
+        plan => match plan.node {
+            PlanNode::Join { condition: JoinCondition::Predicate(_), join_type: JoinType::Inner, .. } => {
+                push_filter(plan, Expr::lit(true))
+            },
+            _ => Ok(plan),
+        },
+    }
+}
+
+// Filters input on predicate, as far below its joins as possible.
+//
+// Conditions on only one side of a join can filter that side first,
+// unless the join pads that side's columns with nulls for unmatched
+// tuples of the other side: the padded tuples are only made by the
+// join, so a filter below it would never see them.
+//
+// The predicate of an inner join is the same as a filter above it, so is
+// pushed down along with the filter, and what's left of both becomes the
+// join's predicate.
+fn push_filter(input: LogicalPlan, predicate: Expr) -> Result<LogicalPlan> {
+    match input {
+        LogicalPlan { node: PlanNode::Filter { input, predicate: inner }, .. } => {
+            push_filter(*input, Expr::and(inner, predicate))
+        },
+        LogicalPlan { node: PlanNode::Join { left, right, condition, join_type, algorithm }, .. } => {
+            let mut conjuncts = split_conjuncts(predicate);
+            let merged = match condition {
+                JoinCondition::Predicate(ref on) if join_type == JoinType::Inner => {
+                    conjuncts.extend(split_conjuncts(on.clone()));
+                    true
+                },
+                _ => false,
+            };
+
+            let width_l = left.schema.column_types.len();
+            let mut predicates_l = Vec::new();
+            let mut predicates_r = Vec::new();
+            let mut rest = Vec::new();
+            for conjunct in conjuncts {
+                let columns = conjunct.columns();
+                if columns.iter().all(|&col| col < width_l) && !join_type.emits_unmatched_right() {
+                    predicates_l.push(conjunct);
+                } else if columns.iter().all(|&col| col >= width_l) && !join_type.emits_unmatched_left() {
+                    predicates_r.push(conjunct.map_columns(&|col| col - width_l));
+                } else {
+                    rest.push(conjunct);
+                }
+            }
+
+            let left = match and_all(predicates_l) {
+                Some(predicate) => push_filter(*left, predicate)?,
+                None => *left,
+            };
+            let right = match and_all(predicates_r) {
+                Some(predicate) => push_filter(*right, predicate)?,
+                None => *right,
+            };
+            if merged {
+                let predicate = and_all(rest).unwrap_or_else(|| Expr::lit(true));
+                return LogicalPlan::join_on(left, right, predicate, join_type);
+            }
+            let join = join_with(left, right, condition, join_type, algorithm)?;
+            match and_all(rest) {
+                Some(predicate) => join.filter(predicate),
+                None => Ok(join),
+            }
+        },
+        input => match and_all(split_conjuncts(predicate)) {
+            Some(predicate) => input.filter(predicate),
+            None => Ok(input),
+        },
+    }
+}
+
+// Rewrites plan to output only the required columns (sorted), or a few
+// more if a node needs them, e.g. the columns of a filter's predicate.
+// Returns the new plan, and where each old output column is in it.
+fn prune_columns(
+    plan: LogicalPlan,
+    required: &[usize],
+) -> Result<(LogicalPlan, Vec<Option<usize>>)>
+{
+    let LogicalPlan { node, schema, tables } = plan;
+    let width = schema.column_types.len();
+    match node {
+        PlanNode::Scan { relation, columns } => {
+            // at least one column, so that there's a tuple for each record
+            let keep = if required.is_empty() { vec![0] } else { required.to_vec() };
+            let columns = keep.iter().map(|&col| columns[col]).collect();
+            let table = tables[0].clone().unwrap_or_default();
+            let plan = LogicalPlan::scan_columns(relation, table, columns);
+            Ok((plan, positions(width, &keep)))
+        },
+        PlanNode::Filter { input, predicate } => {
+            let needed = union(required, &predicate.columns());
+            let (input, map) = prune_columns(*input, &needed)?;
+            let predicate = predicate.map_columns(&|col| map[col].unwrap());
+            Ok((input.filter(predicate)?, map))
+        },
+        PlanNode::Project { input, exprs } => {
+            let exprs: Vec<Expr> = required.iter().map(|&col| exprs[col].clone()).collect();
+            let column_names = required.iter().map(|&col| schema.column_names[col].clone()).collect();
+            let needed = exprs.iter().fold(vec![], |needed, expr| union(&needed, &expr.columns()));
+            let (input, map) = prune_columns(*input, &needed)?;
+            let exprs = exprs.into_iter()
+                .map(|expr| expr.map_columns(&|col| map[col].unwrap()))
+                .collect();
+            Ok((input.project(exprs, column_names)?, positions(width, required)))
+        },
+        PlanNode::Join { left, right, condition, join_type, algorithm } => {
+            let width_l = left.schema.column_types.len();
+            let width_r = right.schema.column_types.len();
+            let mut needed_l: Vec<usize> = required.iter().cloned().filter(|&col| col < width_l).collect();
+            let mut needed_r: Vec<usize> = required.iter().filter(|&&col| col >= width_l).map(|col| col - width_l).collect();
+            match condition {
+                JoinCondition::Key { col_l, col_r } => {
+                    needed_l = union(&needed_l, &[col_l]);
+                    needed_r = union(&needed_r, &[col_r]);
+                },
+                JoinCondition::Predicate(ref predicate) => {
+                    let (cols_l, cols_r): (Vec<usize>, Vec<usize>) = predicate.columns().into_iter()
+                        .partition(|&col| col < width_l);
+                    needed_l = union(&needed_l, &cols_l);
+                    needed_r = union(&needed_r, &cols_r.iter().map(|col| col - width_l).collect::<Vec<_>>());
+                },
+            }
+            let (left, map_l) = prune_columns(*left, &needed_l)?;
+            let (right, map_r) = prune_columns(*right, &needed_r)?;
+
+            // over the columns of both sides, even for semi and anti joins
+            let new_width_l = left.schema.column_types.len();
+            let map: Vec<Option<usize>> = (0..width_l + width_r).map(|col| {
+                if col < width_l {
+                    map_l[col]
+                } else {
+                    map_r[col - width_l].map(|col| col + new_width_l)
+                }
+            }).collect();
+            let condition = match condition {
+                JoinCondition::Key { col_l, col_r } => {
+                    JoinCondition::Key { col_l: map_l[col_l].unwrap(), col_r: map_r[col_r].unwrap() }
+                },
+                JoinCondition::Predicate(predicate) => {
+                    JoinCondition::Predicate(predicate.map_columns(&|col| map[col].unwrap()))
+                },
+            };
+            let join = join_with(left, right, condition, join_type, algorithm)?;
+            Ok((join, map[..width].to_vec()))
+        },
+        PlanNode::Aggregate { input, aggregates, group_by } => {
+            let aggregate_cols: Vec<usize> = aggregates.iter().flat_map(|(_, arg)| arg.columns()).collect();
+            let needed = union(&union(&[], &group_by), &aggregate_cols);
+            let (input, map) = prune_columns(*input, &needed)?;
+            let aggregates = aggregates.into_iter()
+                .map(|(aggregation, arg)| (aggregation, arg.map_columns(&|col| map[col].unwrap())))
+                .collect();
+            let group_by = group_by.into_iter().map(|col| map[col].unwrap()).collect();
+            Ok((input.aggregate(aggregates, group_by)?, (0..width).map(Some).collect()))
+        },
+        PlanNode::Sort { input, sort_keys } => {
+            let key_cols: Vec<usize> = sort_keys.iter().map(|&(col, _, _)| col).collect();
+            let (input, map) = prune_columns(*input, &union(required, &key_cols))?;
+            let sort_keys = sort_keys.into_iter()
+                .map(|(col, data_type, order)| (map[col].unwrap(), data_type, order))
+                .collect();
+            Ok((input.sort(sort_keys), map))
+        },
+        PlanNode::Limit { input, limit, offset } => {
+            let (input, map) = prune_columns(*input, required)?;
+            Ok((input.limit(limit, offset), map))
+        },
+    }
+}
+
+// Applies f to each input of the node. Only for rewrites which don't
+// change the output of the inputs.
+pub(super) fn map_inputs<F>(plan: LogicalPlan, mut f: F) -> Result<LogicalPlan>
+    where F: FnMut(LogicalPlan) -> Result<LogicalPlan>,
+{
+    let LogicalPlan { node, schema, tables } = plan;
+    let mut f = |input: Box<LogicalPlan>| f(*input).map(Box::new);
+    let node = match node {
+        PlanNode::Scan { .. } => node,
+        PlanNode::Filter { input, predicate } => PlanNode::Filter { input: f(input)?, predicate },
+        PlanNode::Project { input, exprs } => PlanNode::Project { input: f(input)?, exprs },
+        PlanNode::Join { left, right, condition, join_type, algorithm } => {
+            PlanNode::Join { left: f(left)?, right: f(right)?, condition, join_type, algorithm }
+        },
+        PlanNode::Aggregate { input, aggregates, group_by } => {
+            PlanNode::Aggregate { input: f(input)?, aggregates, group_by }
+        },
+        PlanNode::Sort { input, sort_keys } => PlanNode::Sort { input: f(input)?, sort_keys },
+        PlanNode::Limit { input, limit, offset } => PlanNode::Limit { input: f(input)?, limit, offset },
+    };
+    Ok(LogicalPlan { node, schema, tables })
+}
+
+// Joins on a predicate are always nested loops joins, so keep theirs.
+pub(super) fn with_algorithm(mut join: LogicalPlan, join_algorithm: JoinAlgorithm) -> LogicalPlan {
+    if let PlanNode::Join { condition: JoinCondition::Key { .. }, ref mut algorithm, .. } = join.node {
+        *algorithm = join_algorithm;
+    }
+    join
+}
+
+fn join_with(
+    left: LogicalPlan,
+    right: LogicalPlan,
+    condition: JoinCondition,
+    join_type: JoinType,
+    algorithm: JoinAlgorithm,
+) -> Result<LogicalPlan>
+{
+    match condition {
+        JoinCondition::Key { col_l, col_r } => {
+            let join = LogicalPlan::join(left, right, col_l, col_r, join_type)?;
+            Ok(with_algorithm(join, algorithm))
+        },
+        JoinCondition::Predicate(predicate) => LogicalPlan::join_on(left, right, predicate, join_type),
+    }
+}
+
+// A literal true, e.g. the predicate of a cross join, has no conjuncts.
+pub(super) fn split_conjuncts(expr: Expr) -> Vec<Expr> {
+    match expr {
+        Expr::Binary(BinaryOp::And, left, right) => {
+            let mut conjuncts = split_conjuncts(*left);
+            conjuncts.extend(split_conjuncts(*right));
+            conjuncts
+        },
+        Expr::Literal(Value::Boolean(true)) => vec![],
+        expr => vec![expr],
+    }
+}
+
+fn and_all(exprs: Vec<Expr>) -> Option<Expr> {
+    exprs.into_iter().fold(None, |acc, expr| match acc {
+        Some(acc) => Some(Expr::and(acc, expr)),
+        None => Some(expr),
+    })
+}
+
+// Where each of the kept columns is in the output
+fn positions(width: usize, kept: &[usize]) -> Vec<Option<usize>> {
+    let mut map = vec![None; width];
+    for (i, &col) in kept.iter().enumerate() {
+        map[col] = Some(i);
+    }
+    map
+}
+
+fn union(cols: &[usize], other: &[usize]) -> Vec<usize> {
+    let mut union: Vec<usize> = cols.iter().chain(other).cloned().collect();
+    union.sort();
+    union.dedup();
+    union
+}
@@ -0,0 +1,80 @@
+// Syntax tree of a SELECT query. Names aren't resolved, and expressions
+// aren't type checked, that's left to the planner.
+//
+// Operators, join types and sort orders are the executor's own, since
+// they mean the same thing here.
+use DataType;
+use executor::expr::BinaryOp;
+use executor::join::JoinType;
+use executor::simplesort::SortOrder;
+use super::Position;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Select {
+    pub distinct: bool,
+    pub projection: Vec<SelectItem>,
+    pub from: Option<TableExpr>,
+    pub selection: Option<Expr>, // WHERE
+    pub group_by: Vec<Expr>,
+    pub having: Option<Expr>,
+    pub order_by: Vec<OrderByItem>,
+    pub limit: Option<u64>,
+    pub offset: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SelectItem {
+    Wildcard, // *
+    QualifiedWildcard(String), // table.*
+    Expr { expr: Expr, alias: Option<String> },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TableExpr {
+    Table { name: String, alias: Option<String>, position: Position },
+    // a cross join (or tables separated by commas) has no constraint
+    Join {
+        left: Box<TableExpr>,
+        right: Box<TableExpr>,
+        join_type: JoinType,
+        constraint: Option<Expr>,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderByItem {
+    pub expr: Expr,
+    pub order: SortOrder,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Literal {
+    Integer(u64),
+    Float(f64), // with an exponent
+    Decimal(String), // with a point, as written so that no digits are lost
+    Str(String),
+    Boolean(bool),
+    Null,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Column { table: Option<String>, name: String, position: Position },
+    Literal(Literal),
+    Binary { op: BinaryOp, left: Box<Expr>, right: Box<Expr> },
+    Negative(Box<Expr>),
+    Not(Box<Expr>),
+    IsNull { expr: Box<Expr>, negated: bool },
+    Like { expr: Box<Expr>, pattern: String, negated: bool },
+    In { expr: Box<Expr>, list: Vec<Expr>, negated: bool },
+    Between { expr: Box<Expr>, low: Box<Expr>, high: Box<Expr>, negated: bool },
+    Case {
+        operand: Option<Box<Expr>>, // CASE x WHEN 1 ... compares x to each when
+        whens: Vec<(Expr, Expr)>,
+        otherwise: Option<Box<Expr>>,
+    },
+    Cast { expr: Box<Expr>, data_type: DataType },
+    // includes aggregates, e.g. count(DISTINCT x)
+    Function { name: String, args: Vec<Expr>, distinct: bool, position: Position },
+    Wildcard, // only as an arg, e.g. count(*)
+}
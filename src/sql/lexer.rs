@@ -0,0 +1,176 @@
+// Splits SQL text into tokens, each with the position it started at so
+// that parse errors can point at it.
+//
+// Keywords aren't distinguished from identifiers here, the parser checks
+// words against the keywords it expects (case insensitive). Quoted
+// identifiers ("...") never match a keyword.
+use error::*;
+use super::Position;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Token {
+    Word(String),
+    QuotedIdent(String),
+    Number(String),
+    Str(String),
+    Comma,
+    Period,
+    LeftParen,
+    RightParen,
+    Star,
+    Plus,
+    Minus,
+    Slash,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Semicolon,
+    Eof,
+}
+
+impl Token {
+    /// Whether the token is the keyword, case insensitive.
+    pub fn is_keyword(&self, keyword: &str) -> bool {
+        match *self {
+            Token::Word(ref word) => word.eq_ignore_ascii_case(keyword),
+            _ => false,
+        }
+    }
+}
+
+pub fn tokenize(sql: &str) -> Result<Vec<(Token, Position)>> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let mut pos = Position { line: 1, column: 1 };
+
+    // advances past n chars, keeping track of the position
+    let advance = |i: &mut usize, pos: &mut Position, n: usize| {
+        for _ in 0..n {
+            if chars[*i] == '\n' {
+                pos.line += 1;
+                pos.column = 1;
+            } else {
+                pos.column += 1;
+            }
+            *i += 1;
+        }
+    };
+
+    while i < chars.len() {
+        let c = chars[i];
+        let start = pos;
+
+        if c.is_whitespace() {
+            advance(&mut i, &mut pos, 1);
+            continue;
+        }
+        // comments run to the end of the line
+        if c == '-' && chars.get(i + 1) == Some(&'-') {
+            while i < chars.len() && chars[i] != '\n' {
+                advance(&mut i, &mut pos, 1);
+            }
+            continue;
+        }
+
+        let token = if c.is_alphabetic() || c == '_' {
+            let len = chars[i..].iter()
+                .take_while(|c| c.is_alphanumeric() || **c == '_')
+                .count();
+            let word = chars[i..i + len].iter().collect();
+            advance(&mut i, &mut pos, len);
+            Token::Word(word)
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|c| c.is_ascii_digit())) {
+            // digits with at most one point, e.g. 1, 1.5, .5 or 1.
+            let digits = |from: usize| chars[from..].iter().take_while(|c| c.is_ascii_digit()).count();
+            let mut len = digits(i);
+            if chars.get(i + len) == Some(&'.') {
+                len += 1 + digits(i + len + 1);
+            }
+            // exponent, e.g. 1e-3
+            if let Some(&e) = chars.get(i + len) {
+                if e == 'e' || e == 'E' {
+                    let mut exp_len = 1;
+                    if let Some(&sign) = chars.get(i + len + 1) {
+                        if sign == '+' || sign == '-' {
+                            exp_len += 1;
+                        }
+                    }
+                    let exp_digits = digits(i + len + exp_len);
+                    if exp_digits > 0 {
+                        len += exp_len + exp_digits;
+                    }
+                }
+            }
+            // a number has to end before another word or number,
+            // e.g. 1abc and 1.2.3 aren't numbers
+            if let Some(&next) = chars.get(i + len) {
+                if next.is_alphanumeric() || next == '_' || next == '.' {
+                    let word: String = chars[i..].iter()
+                        .take_while(|c| c.is_alphanumeric() || **c == '_' || **c == '.')
+                        .collect();
+                    bail!(ErrorKind::SqlParse(format!("invalid number {:?}", word), start.line, start.column));
+                }
+            }
+            let number = chars[i..i + len].iter().collect();
+            advance(&mut i, &mut pos, len);
+            Token::Number(number)
+        } else if c == '\'' || c == '"' {
+            // a doubled quote is an escaped quote
+            let mut s = String::new();
+            advance(&mut i, &mut pos, 1);
+            loop {
+                match chars.get(i) {
+                    None => bail!(ErrorKind::SqlParse("unterminated quote".to_owned(), start.line, start.column)),
+                    Some(&q) if q == c && chars.get(i + 1) == Some(&c) => {
+                        s.push(c);
+                        advance(&mut i, &mut pos, 2);
+                    },
+                    Some(&q) if q == c => {
+                        advance(&mut i, &mut pos, 1);
+                        break;
+                    },
+                    Some(&other) => {
+                        s.push(other);
+                        advance(&mut i, &mut pos, 1);
+                    },
+                }
+            }
+            if c == '\'' {
+                Token::Str(s)
+            } else {
+                Token::QuotedIdent(s)
+            }
+        } else {
+            let next = chars.get(i + 1).cloned();
+            let (token, len) = match (c, next) {
+                ('<', Some('=')) => (Token::LtEq, 2),
+                ('<', Some('>')) => (Token::NotEq, 2),
+                ('>', Some('=')) => (Token::GtEq, 2),
+                ('!', Some('=')) => (Token::NotEq, 2),
+                (',', _) => (Token::Comma, 1),
+                ('.', _) => (Token::Period, 1),
+                ('(', _) => (Token::LeftParen, 1),
+                (')', _) => (Token::RightParen, 1),
+                ('*', _) => (Token::Star, 1),
+                ('+', _) => (Token::Plus, 1),
+                ('-', _) => (Token::Minus, 1),
+                ('/', _) => (Token::Slash, 1),
+                ('=', _) => (Token::Eq, 1),
+                ('<', _) => (Token::Lt, 1),
+                ('>', _) => (Token::Gt, 1),
+                (';', _) => (Token::Semicolon, 1),
+                _ => bail!(ErrorKind::SqlParse(format!("unexpected character {:?}", c), start.line, start.column)),
+            };
+            advance(&mut i, &mut pos, len);
+            token
+        };
+        tokens.push((token, start));
+    }
+
+    tokens.push((Token::Eof, pos));
+    Ok(tokens)
+}
@@ -0,0 +1,281 @@
+//! SQL module
+//!
+//! - lexer, splitting SQL text into tokens
+//! - parser for a practical subset of SELECT: projections with aliases,
+//!   FROM with joins, WHERE, GROUP BY, HAVING, ORDER BY, LIMIT and OFFSET
+//! - ast, the tree the parser produces, with names left unresolved
+//!
+//! Parse errors carry the line and column where they happened.
+
+pub mod ast;
+pub mod lexer;
+pub mod parser;
+
+use error::*;
+use self::ast::Select;
+use self::parser::Parser;
+
+/// Line and column in the SQL text, both counting from 1.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+/// Parses one SELECT query, optionally ending in a semicolon.
+pub fn parse(sql: &str) -> Result<Select> {
+    Parser::new(sql)?.parse_query()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::ast::*;
+    use DataType;
+    use executor::expr::BinaryOp;
+    use executor::join::JoinType;
+    use executor::simplesort::SortOrder;
+
+    fn col(name: &str, line: usize, column: usize) -> Expr {
+        Expr::Column {
+            table: None,
+            name: name.to_owned(),
+            position: Position { line, column },
+        }
+    }
+
+    fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+        Expr::Binary { op, left: Box::new(left), right: Box::new(right) }
+    }
+
+    fn int(x: u64) -> Expr {
+        Expr::Literal(Literal::Integer(x))
+    }
+
+    fn parse_expr(sql: &str) -> Expr {
+        let select = parse(&format!("SELECT {}", sql)).unwrap();
+        match select.projection[0] {
+            SelectItem::Expr { ref expr, .. } => expr.clone(),
+            ref item => panic!("not an expr: {:?}", item),
+        }
+    }
+
+    fn parse_error(sql: &str) -> (String, usize, usize) {
+        match parse(sql) {
+            Err(Error(ErrorKind::SqlParse(msg, line, column), _)) => (msg, line, column),
+            res => panic!("expected a parse error, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_parse_select() {
+        let select = parse("
+            select m.title, count(*) AS ratings, avg(r.rating) avg_rating
+            from ratings r
+            join movies as m on r.movieId = m.movieId
+            where r.rating >= 3.5 and m.genres like '%Comedy%'
+            group by m.title
+            having count(*) > 10
+            order by ratings desc, m.title
+            limit 5 offset 10;
+        ").unwrap();
+
+        assert!(!select.distinct);
+        assert_eq!(select.projection.len(), 3);
+        match select.projection[1] {
+            SelectItem::Expr { expr: Expr::Function { ref name, ref args, distinct: false, .. }, ref alias } => {
+                assert_eq!(name, "count");
+                assert_eq!(args, &vec![Expr::Wildcard]);
+                assert_eq!(alias.as_ref().map(|s| s.as_str()), Some("ratings"));
+            },
+            ref item => panic!("unexpected {:?}", item),
+        }
+        match select.projection[2] {
+            SelectItem::Expr { ref alias, .. } => assert_eq!(alias.as_ref().unwrap(), "avg_rating"),
+            ref item => panic!("unexpected {:?}", item),
+        }
+
+        match select.from {
+            Some(TableExpr::Join { ref left, ref right, join_type, ref constraint }) => {
+                assert_eq!(join_type, JoinType::Inner);
+                assert!(constraint.is_some());
+                match (&**left, &**right) {
+                    (&TableExpr::Table { name: ref l, alias: ref l_alias, position },
+                     TableExpr::Table { name: r, alias: r_alias, .. }) => {
+                        assert_eq!((l.as_str(), l_alias.as_ref().unwrap().as_str()), ("ratings", "r"));
+                        assert_eq!((r.as_str(), r_alias.as_ref().unwrap().as_str()), ("movies", "m"));
+                        assert_eq!(position, Position { line: 3, column: 18 });
+                    },
+                    tables => panic!("unexpected {:?}", tables),
+                }
+            },
+            ref from => panic!("unexpected {:?}", from),
+        }
+
+        assert!(select.selection.is_some());
+        assert_eq!(select.group_by.len(), 1);
+        assert!(select.having.is_some());
+        assert_eq!(select.order_by.len(), 2);
+        assert_eq!(select.order_by[0].order, SortOrder::Descending);
+        assert_eq!(select.order_by[1].order, SortOrder::Ascending);
+        assert_eq!(select.limit, Some(5));
+        assert_eq!(select.offset, Some(10));
+    }
+
+    #[test]
+    fn test_parse_joins() {
+        let select = parse("SELECT * FROM a LEFT OUTER JOIN b ON a.x = b.x, c FULL JOIN d ON c.y = d.y").unwrap();
+        assert_eq!(select.projection, vec![SelectItem::Wildcard]);
+        // joins are left associative
+        match select.from.unwrap() {
+            TableExpr::Join { left, join_type: JoinType::FullOuter, .. } => {
+                match *left {
+                    TableExpr::Join { left, join_type: JoinType::Inner, constraint: None, .. } => {
+                        match *left {
+                            TableExpr::Join { join_type: JoinType::LeftOuter, constraint: Some(_), .. } => (),
+                            left => panic!("unexpected {:?}", left),
+                        }
+                    },
+                    left => panic!("unexpected {:?}", left),
+                }
+            },
+            from => panic!("unexpected {:?}", from),
+        }
+
+        let select = parse("SELECT t.*, x FROM t CROSS JOIN \"Select\"").unwrap();
+        assert_eq!(select.projection[0], SelectItem::QualifiedWildcard("t".to_owned()));
+        match select.from.unwrap() {
+            TableExpr::Join { right, constraint: None, .. } => {
+                match *right {
+                    TableExpr::Table { ref name, .. } => assert_eq!(name, "Select"),
+                    ref right => panic!("unexpected {:?}", right),
+                }
+            },
+            from => panic!("unexpected {:?}", from),
+        }
+    }
+
+    #[test]
+    fn test_parse_expr() {
+        // * binds tighter than +, AND tighter than OR
+        assert_eq!(
+            parse_expr("a + b * 2"),
+            binary(BinaryOp::Add, col("a", 1, 8), binary(BinaryOp::Multiply, col("b", 1, 12), int(2))),
+        );
+        assert_eq!(
+            parse_expr("a OR b AND NOT c"),
+            binary(
+                BinaryOp::Or,
+                col("a", 1, 8),
+                binary(BinaryOp::And, col("b", 1, 13), Expr::Not(Box::new(col("c", 1, 23)))),
+            ),
+        );
+        assert_eq!(
+            parse_expr("(1 - 2) - 3"),
+            binary(BinaryOp::Subtract, binary(BinaryOp::Subtract, int(1), int(2)), int(3)),
+        );
+        assert_eq!(parse_expr("-1.5e2"), Expr::Negative(Box::new(Expr::Literal(Literal::Float(150.)))));
+        assert_eq!(parse_expr("0.10"), Expr::Literal(Literal::Decimal("0.10".to_owned())));
+        assert_eq!(parse_expr(".5"), Expr::Literal(Literal::Decimal(".5".to_owned())));
+        assert_eq!(
+            parse_expr("1-2"),
+            binary(BinaryOp::Subtract, int(1), int(2)),
+        );
+
+        assert_eq!(
+            parse_expr("x NOT BETWEEN 1 AND 2"),
+            Expr::Between {
+                expr: Box::new(col("x", 1, 8)),
+                low: Box::new(int(1)),
+                high: Box::new(int(2)),
+                negated: true,
+            },
+        );
+        assert_eq!(
+            parse_expr("x IS NOT NULL"),
+            Expr::IsNull { expr: Box::new(col("x", 1, 8)), negated: true },
+        );
+        assert_eq!(
+            parse_expr("name NOT LIKE 'it''s%'"),
+            Expr::Like { expr: Box::new(col("name", 1, 8)), pattern: "it's%".to_owned(), negated: true },
+        );
+        assert_eq!(
+            parse_expr("x IN (1, NULL)"),
+            Expr::In { expr: Box::new(col("x", 1, 8)), list: vec![int(1), Expr::Literal(Literal::Null)], negated: false },
+        );
+        assert_eq!(
+            parse_expr("CAST(x AS varchar(10))"),
+            Expr::Cast { expr: Box::new(col("x", 1, 13)), data_type: DataType::Text(10) },
+        );
+        assert_eq!(
+            parse_expr("CAST(x AS numeric(10, 2))"),
+            Expr::Cast { expr: Box::new(col("x", 1, 13)), data_type: DataType::Decimal(10, 2) },
+        );
+        assert_eq!(
+            parse_expr("CASE x WHEN 1 THEN 'one' END"),
+            Expr::Case {
+                operand: Some(Box::new(col("x", 1, 13))),
+                whens: vec![(int(1), Expr::Literal(Literal::Str("one".to_owned())))],
+                otherwise: None,
+            },
+        );
+        match parse_expr("count(DISTINCT genre)") {
+            Expr::Function { name, args, distinct: true, .. } => {
+                assert_eq!(name, "count");
+                assert_eq!(args, vec![col("genre", 1, 23)]);
+            },
+            expr => panic!("unexpected {:?}", expr),
+        }
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert_eq!(
+            parse_error("SELECT a FROM"),
+            ("expected a table name, found end of query".to_owned(), 1, 14),
+        );
+        assert_eq!(
+            parse_error("SELECT a,\n  FROM t"),
+            ("expected an expression, found \"FROM\"".to_owned(), 2, 3),
+        );
+        assert_eq!(
+            parse_error("SELECT a FROM t WHERE a = 'x"),
+            ("unterminated quote".to_owned(), 1, 27),
+        );
+        assert_eq!(
+            parse_error("SELECT a FROM t LIMIT -1"),
+            ("expected a whole number, found \"-\"".to_owned(), 1, 23),
+        );
+        assert_eq!(
+            parse_error("SELECT a FROM t JOIN u"),
+            ("expected ON, found end of query".to_owned(), 1, 23),
+        );
+        assert_eq!(
+            parse_error("SELECT a b c FROM t"),
+            ("expected end of query, found \"c\"".to_owned(), 1, 12),
+        );
+        assert_eq!(
+            parse_error("SELECT CAST(a AS blob) FROM t"),
+            ("unknown type BLOB".to_owned(), 1, 18),
+        );
+        assert_eq!(
+            parse_error("SELECT CAST(a AS DECIMAL(5, 6)) FROM t"),
+            ("bad type DECIMAL(5, 6)".to_owned(), 1, 18),
+        );
+        // numbers have at most one point, and end before a word
+        assert_eq!(
+            parse_error("SELECT 1.2.3 FROM t"),
+            ("invalid number \"1.2.3\"".to_owned(), 1, 8),
+        );
+        assert_eq!(
+            parse_error("SELECT 1abc FROM t"),
+            ("invalid number \"1abc\"".to_owned(), 1, 8),
+        );
+        assert_eq!(
+            parse_error("SELECT a FROM t WHERE a = 2e5x"),
+            ("invalid number \"2e5x\"".to_owned(), 1, 27),
+        );
+        assert_eq!(parse_error("SELECT a # b").1, 1);
+        assert_eq!(parse_error("SELECT a # b").2, 10);
+    }
+}
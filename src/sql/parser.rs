@@ -0,0 +1,636 @@
+// Recursive descent parser for SELECT queries.
+//
+// Operator precedence, loosest first:
+//   OR
+//   AND
+//   NOT
+//   comparisons, IS NULL, LIKE, IN, BETWEEN (not chained, a < b < c is an error)
+//   + -
+//   * /
+//   unary -
+use DataType;
+use error::*;
+use executor::decimal;
+use executor::expr::BinaryOp;
+use executor::join::JoinType;
+use executor::simplesort::SortOrder;
+use super::Position;
+use super::ast::*;
+use super::lexer::{Token, tokenize};
+
+// Words which can't be used as a bare alias or name
+const RESERVED: &[&str] = &[
+    "SELECT", "DISTINCT", "ALL", "FROM", "WHERE", "GROUP", "BY", "HAVING",
+    "ORDER", "ASC", "DESC", "LIMIT", "OFFSET", "AS", "JOIN", "INNER", "LEFT",
+    "RIGHT", "FULL", "OUTER", "CROSS", "ON", "AND", "OR", "NOT", "IS", "NULL",
+    "TRUE", "FALSE", "LIKE", "IN", "BETWEEN", "CASE", "WHEN", "THEN", "ELSE",
+    "END", "CAST",
+];
+
+fn is_reserved(word: &str) -> bool {
+    RESERVED.iter().any(|keyword| word.eq_ignore_ascii_case(keyword))
+}
+
+pub struct Parser {
+    tokens: Vec<(Token, Position)>,
+    idx: usize, // index into tokens, the last token is always Eof
+}
+
+impl Parser {
+    pub fn new(sql: &str) -> Result<Self> {
+        Ok(Parser {
+            tokens: tokenize(sql)?,
+            idx: 0,
+        })
+    }
+
+    pub fn parse_query(&mut self) -> Result<Select> {
+        let select = self.parse_select()?;
+        self.consume(&Token::Semicolon);
+        if *self.peek() != Token::Eof {
+            return Err(self.expected("end of query"));
+        }
+        Ok(select)
+    }
+
+    // Helpers for moving through tokens
+
+    fn peek(&self) -> &Token {
+        &self.tokens[self.idx].0
+    }
+
+    fn peek_nth(&self, n: usize) -> &Token {
+        let idx = (self.idx + n).min(self.tokens.len() - 1);
+        &self.tokens[idx].0
+    }
+
+    fn position(&self) -> Position {
+        self.tokens[self.idx].1
+    }
+
+    fn next_token(&mut self) -> Token {
+        let token = self.tokens[self.idx].0.clone();
+        if self.idx < self.tokens.len() - 1 {
+            self.idx += 1;
+        }
+        token
+    }
+
+    fn consume(&mut self, token: &Token) -> bool {
+        if self.peek() == token {
+            self.next_token();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn consume_keyword(&mut self, keyword: &str) -> bool {
+        if self.peek().is_keyword(keyword) {
+            self.next_token();
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<()> {
+        if self.consume(token) {
+            Ok(())
+        } else {
+            Err(self.expected(&describe(token)))
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.consume_keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.expected(keyword))
+        }
+    }
+
+    fn error_at(&self, msg: String, position: Position) -> Error {
+        ErrorKind::SqlParse(msg, position.line, position.column).into()
+    }
+
+    fn expected(&self, expected: &str) -> Error {
+        self.error_at(
+            format!("expected {}, found {}", expected, describe(self.peek())),
+            self.position(),
+        )
+    }
+
+    // A name, either quoted or a word which isn't reserved
+    fn parse_identifier(&mut self) -> Result<String> {
+        match self.peek().clone() {
+            Token::Word(ref word) if !is_reserved(word) => {
+                self.next_token();
+                Ok(word.clone())
+            },
+            Token::QuotedIdent(ident) => {
+                self.next_token();
+                Ok(ident)
+            },
+            _ => Err(self.expected("an identifier")),
+        }
+    }
+
+    fn parse_alias(&mut self) -> Result<Option<String>> {
+        if self.consume_keyword("AS") {
+            return self.parse_identifier().map(Some);
+        }
+        match *self.peek() {
+            Token::Word(ref word) if !is_reserved(word) => (),
+            Token::QuotedIdent(_) => (),
+            _ => return Ok(None),
+        }
+        self.parse_identifier().map(Some)
+    }
+
+    fn parse_u64(&mut self) -> Result<u64> {
+        let position = self.position();
+        match self.next_token() {
+            Token::Number(number) => {
+                number.parse()
+                    .map_err(|_| self.error_at(format!("expected a whole number, found {}", number), position))
+            },
+            token => Err(self.error_at(format!("expected a whole number, found {}", describe(&token)), position)),
+        }
+    }
+
+    fn parse_comma_separated<T, F>(&mut self, mut parse: F) -> Result<Vec<T>>
+        where F: FnMut(&mut Self) -> Result<T>,
+    {
+        let mut items = vec![parse(self)?];
+        while self.consume(&Token::Comma) {
+            items.push(parse(self)?);
+        }
+        Ok(items)
+    }
+
+    // Clauses
+
+    fn parse_select(&mut self) -> Result<Select> {
+        self.expect_keyword("SELECT")?;
+        let distinct = self.consume_keyword("DISTINCT");
+        if !distinct {
+            self.consume_keyword("ALL");
+        }
+        let projection = self.parse_comma_separated(Self::parse_select_item)?;
+
+        let from = if self.consume_keyword("FROM") {
+            Some(self.parse_table_expr()?)
+        } else {
+            None
+        };
+
+        let selection = if self.consume_keyword("WHERE") {
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+
+        let group_by = if self.consume_keyword("GROUP") {
+            self.expect_keyword("BY")?;
+            self.parse_comma_separated(Self::parse_expr)?
+        } else {
+            vec![]
+        };
+
+        let having = if self.consume_keyword("HAVING") {
+            Some(self.parse_expr()?)
+        } else {
+            None
+        };
+
+        let order_by = if self.consume_keyword("ORDER") {
+            self.expect_keyword("BY")?;
+            self.parse_comma_separated(Self::parse_order_by_item)?
+        } else {
+            vec![]
+        };
+
+        let limit = if self.consume_keyword("LIMIT") {
+            Some(self.parse_u64()?)
+        } else {
+            None
+        };
+        let offset = if self.consume_keyword("OFFSET") {
+            Some(self.parse_u64()?)
+        } else {
+            None
+        };
+
+        Ok(Select {
+            distinct,
+            projection,
+            from,
+            selection,
+            group_by,
+            having,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    fn parse_select_item(&mut self) -> Result<SelectItem> {
+        if self.consume(&Token::Star) {
+            return Ok(SelectItem::Wildcard);
+        }
+        if *self.peek_nth(1) == Token::Period && *self.peek_nth(2) == Token::Star {
+            let table = self.parse_identifier()?;
+            self.next_token();
+            self.next_token();
+            return Ok(SelectItem::QualifiedWildcard(table));
+        }
+        let expr = self.parse_expr()?;
+        let alias = self.parse_alias()?;
+        Ok(SelectItem::Expr { expr, alias })
+    }
+
+    fn parse_table_expr(&mut self) -> Result<TableExpr> {
+        let mut left = self.parse_table_factor()?;
+        loop {
+            let join_type = if self.consume(&Token::Comma) {
+                None
+            } else if self.consume_keyword("CROSS") {
+                self.expect_keyword("JOIN")?;
+                None
+            } else if self.consume_keyword("JOIN") {
+                Some(JoinType::Inner)
+            } else if self.consume_keyword("INNER") {
+                self.expect_keyword("JOIN")?;
+                Some(JoinType::Inner)
+            } else if self.peek().is_keyword("LEFT")
+                || self.peek().is_keyword("RIGHT")
+                || self.peek().is_keyword("FULL")
+            {
+                let join_type = match self.next_token() {
+                    ref token if token.is_keyword("LEFT") => JoinType::LeftOuter,
+                    ref token if token.is_keyword("RIGHT") => JoinType::RightOuter,
+                    _ => JoinType::FullOuter,
+                };
+                self.consume_keyword("OUTER");
+                self.expect_keyword("JOIN")?;
+                Some(join_type)
+            } else {
+                return Ok(left);
+            };
+
+            let right = self.parse_table_factor()?;
+            let (join_type, constraint) = match join_type {
+                Some(join_type) => {
+                    self.expect_keyword("ON")?;
+                    (join_type, Some(self.parse_expr()?))
+                },
+                None => (JoinType::Inner, None),
+            };
+            left = TableExpr::Join {
+                left: Box::new(left),
+                right: Box::new(right),
+                join_type,
+                constraint,
+            };
+        }
+    }
+
+    fn parse_table_factor(&mut self) -> Result<TableExpr> {
+        let position = self.position();
+        let name = self.parse_identifier()
+            .map_err(|_| self.expected("a table name"))?;
+        let alias = self.parse_alias()?;
+        Ok(TableExpr::Table { name, alias, position })
+    }
+
+    fn parse_order_by_item(&mut self) -> Result<OrderByItem> {
+        let expr = self.parse_expr()?;
+        let order = if self.consume_keyword("DESC") {
+            SortOrder::Descending
+        } else {
+            self.consume_keyword("ASC");
+            SortOrder::Ascending
+        };
+        Ok(OrderByItem { expr, order })
+    }
+
+    // Expressions
+
+    pub fn parse_expr(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_and()?;
+        while self.consume_keyword("OR") {
+            let right = self.parse_and()?;
+            expr = binary(BinaryOp::Or, expr, right);
+        }
+        Ok(expr)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_not()?;
+        while self.consume_keyword("AND") {
+            let right = self.parse_not()?;
+            expr = binary(BinaryOp::And, expr, right);
+        }
+        Ok(expr)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr> {
+        if self.consume_keyword("NOT") {
+            Ok(Expr::Not(Box::new(self.parse_not()?)))
+        } else {
+            self.parse_comparison()
+        }
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr> {
+        let expr = self.parse_additive()?;
+
+        let op = match *self.peek() {
+            Token::Eq => Some(BinaryOp::Eq),
+            Token::NotEq => Some(BinaryOp::NotEq),
+            Token::Lt => Some(BinaryOp::Lt),
+            Token::LtEq => Some(BinaryOp::LtEq),
+            Token::Gt => Some(BinaryOp::Gt),
+            Token::GtEq => Some(BinaryOp::GtEq),
+            _ => None,
+        };
+        if let Some(op) = op {
+            self.next_token();
+            let right = self.parse_additive()?;
+            return Ok(binary(op, expr, right));
+        }
+
+        if self.consume_keyword("IS") {
+            let negated = self.consume_keyword("NOT");
+            self.expect_keyword("NULL")?;
+            return Ok(Expr::IsNull { expr: Box::new(expr), negated });
+        }
+
+        // NOT only belongs to this expr if followed by LIKE, IN or BETWEEN
+        let negated = self.peek().is_keyword("NOT") && (
+            self.peek_nth(1).is_keyword("LIKE") ||
+            self.peek_nth(1).is_keyword("IN") ||
+            self.peek_nth(1).is_keyword("BETWEEN")
+        );
+        if negated {
+            self.next_token();
+        }
+
+        if self.consume_keyword("LIKE") {
+            let position = self.position();
+            let pattern = match self.next_token() {
+                Token::Str(pattern) => pattern,
+                token => bail!(self.error_at(
+                    format!("expected a string pattern for LIKE, found {}", describe(&token)),
+                    position,
+                )),
+            };
+            Ok(Expr::Like { expr: Box::new(expr), pattern, negated })
+        } else if self.consume_keyword("IN") {
+            self.expect(&Token::LeftParen)?;
+            let list = self.parse_comma_separated(Self::parse_expr)?;
+            self.expect(&Token::RightParen)?;
+            Ok(Expr::In { expr: Box::new(expr), list, negated })
+        } else if self.consume_keyword("BETWEEN") {
+            let low = self.parse_additive()?;
+            self.expect_keyword("AND")?;
+            let high = self.parse_additive()?;
+            Ok(Expr::Between {
+                expr: Box::new(expr),
+                low: Box::new(low),
+                high: Box::new(high),
+                negated,
+            })
+        } else {
+            Ok(expr)
+        }
+    }
+
+    fn parse_additive(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_multiplicative()?;
+        loop {
+            let op = match *self.peek() {
+                Token::Plus => BinaryOp::Add,
+                Token::Minus => BinaryOp::Subtract,
+                _ => return Ok(expr),
+            };
+            self.next_token();
+            let right = self.parse_multiplicative()?;
+            expr = binary(op, expr, right);
+        }
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_unary()?;
+        loop {
+            let op = match *self.peek() {
+                Token::Star => BinaryOp::Multiply,
+                Token::Slash => BinaryOp::Divide,
+                _ => return Ok(expr),
+            };
+            self.next_token();
+            let right = self.parse_unary()?;
+            expr = binary(op, expr, right);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.consume(&Token::Minus) {
+            Ok(Expr::Negative(Box::new(self.parse_unary()?)))
+        } else if self.consume(&Token::Plus) {
+            self.parse_unary()
+        } else {
+            self.parse_primary()
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        let position = self.position();
+        match self.peek().clone() {
+            Token::Number(number) => {
+                self.next_token();
+                let literal = if number.contains(['e', 'E']) {
+                    number.parse().map(Literal::Float).ok()
+                } else if number.contains('.') {
+                    Some(Literal::Decimal(number.clone()))
+                } else {
+                    number.parse().map(Literal::Integer).ok()
+                };
+                literal.map(Expr::Literal)
+                    .ok_or_else(|| self.error_at(format!("bad number {}", number), position))
+            },
+            Token::Str(s) => {
+                self.next_token();
+                Ok(Expr::Literal(Literal::Str(s)))
+            },
+            Token::LeftParen => {
+                self.next_token();
+                let expr = self.parse_expr()?;
+                self.expect(&Token::RightParen)?;
+                Ok(expr)
+            },
+            ref token if token.is_keyword("NULL") => {
+                self.next_token();
+                Ok(Expr::Literal(Literal::Null))
+            },
+            ref token if token.is_keyword("TRUE") || token.is_keyword("FALSE") => {
+                self.next_token();
+                Ok(Expr::Literal(Literal::Boolean(token.is_keyword("TRUE"))))
+            },
+            ref token if token.is_keyword("CASE") => {
+                self.next_token();
+                self.parse_case()
+            },
+            ref token if token.is_keyword("CAST") => {
+                self.next_token();
+                self.expect(&Token::LeftParen)?;
+                let expr = self.parse_expr()?;
+                self.expect_keyword("AS")?;
+                let data_type = self.parse_data_type()?;
+                self.expect(&Token::RightParen)?;
+                Ok(Expr::Cast { expr: Box::new(expr), data_type })
+            },
+            // typed literals, e.g. DATE '2000-01-01', are casts of the string
+            Token::Word(ref word) if is_typed_literal(word) && matches!(*self.peek_nth(1), Token::Str(_)) => {
+                let data_type = self.parse_data_type()?;
+                let literal = self.parse_primary()?;
+                Ok(Expr::Cast { expr: Box::new(literal), data_type })
+            },
+            Token::Word(_) | Token::QuotedIdent(_) => {
+                let name = self.parse_identifier()
+                    .map_err(|_| self.expected("an expression"))?;
+                if self.consume(&Token::LeftParen) {
+                    self.parse_function_args(name, position)
+                } else if self.consume(&Token::Period) {
+                    let column = self.parse_identifier()?;
+                    Ok(Expr::Column { table: Some(name), name: column, position })
+                } else {
+                    Ok(Expr::Column { table: None, name, position })
+                }
+            },
+            _ => Err(self.expected("an expression")),
+        }
+    }
+
+    // After the opening paren
+    fn parse_function_args(&mut self, name: String, position: Position) -> Result<Expr> {
+        let distinct = self.consume_keyword("DISTINCT");
+        let args = if self.consume(&Token::Star) {
+            vec![Expr::Wildcard]
+        } else if *self.peek() == Token::RightParen {
+            vec![]
+        } else {
+            self.parse_comma_separated(Self::parse_expr)?
+        };
+        self.expect(&Token::RightParen)?;
+        Ok(Expr::Function { name, args, distinct, position })
+    }
+
+    // After CASE
+    fn parse_case(&mut self) -> Result<Expr> {
+        let operand = if self.peek().is_keyword("WHEN") {
+            None
+        } else {
+            Some(Box::new(self.parse_expr()?))
+        };
+
+        let mut whens = Vec::new();
+        while self.consume_keyword("WHEN") {
+            let when = self.parse_expr()?;
+            self.expect_keyword("THEN")?;
+            let then = self.parse_expr()?;
+            whens.push((when, then));
+        }
+        if whens.is_empty() {
+            return Err(self.expected("WHEN"));
+        }
+
+        let otherwise = if self.consume_keyword("ELSE") {
+            Some(Box::new(self.parse_expr()?))
+        } else {
+            None
+        };
+        self.expect_keyword("END")?;
+        Ok(Expr::Case { operand, whens, otherwise })
+    }
+
+    fn parse_data_type(&mut self) -> Result<DataType> {
+        let position = self.position();
+        let name = match self.next_token() {
+            Token::Word(word) => word.to_uppercase(),
+            token => bail!(self.error_at(format!("expected a type, found {}", describe(&token)), position)),
+        };
+        match name.as_str() {
+            "SMALLINT" => Ok(DataType::SmallInt),
+            "INT" | "INTEGER" => Ok(DataType::Integer),
+            "BIGINT" => Ok(DataType::BigInt),
+            "FLOAT" | "REAL" => Ok(DataType::Float),
+            "DOUBLE" => {
+                self.consume_keyword("PRECISION");
+                Ok(DataType::Double)
+            },
+            "BOOLEAN" | "BOOL" => Ok(DataType::Boolean),
+            "DATE" => Ok(DataType::Date),
+            "TIMESTAMP" => Ok(DataType::Timestamp),
+            // DECIMAL is DECIMAL(18, 0), and DECIMAL(p) is DECIMAL(p, 0)
+            "DECIMAL" | "NUMERIC" => {
+                let (mut precision, mut scale) = (18, 0);
+                if self.consume(&Token::LeftParen) {
+                    precision = self.parse_u64()?;
+                    if self.consume(&Token::Comma) {
+                        scale = self.parse_u64()?;
+                    }
+                    self.expect(&Token::RightParen)?;
+                }
+                if precision > u64::from(decimal::MAX_PRECISION)
+                    || scale > precision
+                    || !decimal::is_valid_type(precision as u8, scale as u8)
+                {
+                    bail!(self.error_at(format!("bad type DECIMAL({}, {})", precision, scale), position));
+                }
+                Ok(DataType::Decimal(precision as u8, scale as u8))
+            },
+            "TEXT" | "VARCHAR" | "CHAR" => {
+                self.expect(&Token::LeftParen)?;
+                let len = self.parse_u64()?;
+                self.expect(&Token::RightParen)?;
+                Ok(DataType::Text(len as usize))
+            },
+            _ => Err(self.error_at(format!("unknown type {}", name), position)),
+        }
+    }
+}
+
+fn is_typed_literal(word: &str) -> bool {
+    ["DATE", "TIMESTAMP"].iter().any(|type_name| word.eq_ignore_ascii_case(type_name))
+}
+
+fn binary(op: BinaryOp, left: Expr, right: Expr) -> Expr {
+    Expr::Binary { op, left: Box::new(left), right: Box::new(right) }
+}
+
+fn describe(token: &Token) -> String {
+    match *token {
+        Token::Word(ref word) => format!("\"{}\"", word),
+        Token::QuotedIdent(ref ident) => format!("identifier \"{}\"", ident),
+        Token::Number(ref number) => format!("number {}", number),
+        Token::Str(ref s) => format!("string '{}'", s),
+        Token::Comma => "\",\"".to_owned(),
+        Token::Period => "\".\"".to_owned(),
+        Token::LeftParen => "\"(\"".to_owned(),
+        Token::RightParen => "\")\"".to_owned(),
+        Token::Star => "\"*\"".to_owned(),
+        Token::Plus => "\"+\"".to_owned(),
+        Token::Minus => "\"-\"".to_owned(),
+        Token::Slash => "\"/\"".to_owned(),
+        Token::Eq => "\"=\"".to_owned(),
+        Token::NotEq => "\"<>\"".to_owned(),
+        Token::Lt => "\"<\"".to_owned(),
+        Token::LtEq => "\"<=\"".to_owned(),
+        Token::Gt => "\">\"".to_owned(),
+        Token::GtEq => "\">=\"".to_owned(),
+        Token::Semicolon => "\";\"".to_owned(),
+        Token::Eof => "end of query".to_owned(),
+    }
+}
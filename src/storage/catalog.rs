@@ -0,0 +1,369 @@
+// System catalog, which records the relations in a data directory: their
+// names, ids, columns and the files holding their tuples, so that a
+// relation can be scanned by name alone.
+//
+// It's saved as text in the "catalog" file of the data directory, with
+// tab separated fields so that names can have spaces:
+//
+//   relation	1	1	ratings
+//   column	userId	Integer
+//   column	rating	Float
+//
+// a relation line (id, file, name) followed by a line for each of its
+// columns (name, type). File names are relative to the data directory.
+// The catalog is rewritten to a temp file and renamed over the old one
+// on each change, so a crash leaves either the old or the new catalog.
+use std::fs::{self, File};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use {DataType, RelationSchema};
+use error::*;
+use executor::decimal;
+use super::disk::DiskScan;
+use super::import_csv;
+use super::stats::TableStats;
+
+const CATALOG_FILE: &str = "catalog";
+
+#[derive(Debug, Clone)]
+pub struct SystemCatalog {
+    dir: PathBuf,
+    entries: Vec<CatalogEntry>,
+}
+
+#[derive(Debug, Clone)]
+pub struct CatalogEntry {
+    pub schema: RelationSchema,
+    pub file: String, // in the data directory
+}
+
+impl SystemCatalog {
+    /// Opens the catalog of the data directory, creating both if needed.
+    pub fn open<P: Into<PathBuf>>(dir: P) -> Result<Self> {
+        let dir = dir.into();
+        fs::create_dir_all(&dir)
+            .chain_err(|| format!("could not create data directory {:?}", dir))?;
+        let path = dir.join(CATALOG_FILE);
+        let entries = if path.exists() {
+            load(&path).chain_err(|| format!("could not read catalog {:?}", path))?
+        } else {
+            Vec::new()
+        };
+        Ok(SystemCatalog { dir, entries })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn relations(&self) -> impl Iterator<Item = &RelationSchema> {
+        self.entries.iter().map(|entry| &entry.schema)
+    }
+
+    /// Relation names are case insensitive, like SQL identifiers.
+    pub fn relation(&self, name: &str) -> Option<&RelationSchema> {
+        self.entry(name).map(|entry| &entry.schema)
+    }
+
+    /// Path of the file holding the relation's tuples.
+    pub fn file_path(&self, name: &str) -> Result<PathBuf> {
+        let entry = self.entry(name).ok_or_else(|| unknown_relation(name))?;
+        Ok(self.dir.join(&entry.file))
+    }
+
+    /// Adds an empty relation, with the next free id, and saves the
+    /// catalog. Its file is named after its id, and created empty so
+    /// that the relation can be scanned right away.
+    pub fn create_relation(
+        &mut self,
+        name: &str,
+        column_names: Vec<String>,
+        column_types: Vec<DataType>,
+    ) -> Result<RelationSchema>
+    {
+        if self.entry(name).is_some() {
+            bail!(catalog_error(format!("relation {} already exists", name)));
+        }
+        if column_names.len() != column_types.len() {
+            bail!(catalog_error(format!(
+                "relation {} has {} column names but {} column types",
+                name,
+                column_names.len(),
+                column_types.len(),
+            )));
+        }
+        let has_bad_name = column_names.iter().chain(Some(&name.to_owned()))
+            .any(|name| name.is_empty() || name.contains(['\t', '\n', '\r']));
+        if has_bad_name {
+            bail!(catalog_error(format!("relation {} has an empty name, or one with tabs or newlines", name)));
+        }
+        // which the catalog couldn't load again
+        for data_type in &column_types {
+            if let DataType::Decimal(precision, scale) = *data_type {
+                if !decimal::is_valid_type(precision, scale) {
+                    bail!(catalog_error(format!("relation {} has bad type {:?}", name, data_type)));
+                }
+            }
+        }
+
+        let id = self.entries.iter().map(|entry| entry.schema.id).max().map_or(1, |id| id + 1);
+        let schema = RelationSchema {
+            name: name.to_owned(),
+            id,
+            column_names,
+            column_types,
+        };
+        let file = id.to_string();
+        let path = self.dir.join(&file);
+        File::create(&path)
+            .chain_err(|| format!("could not create {:?} for relation {}", path, name))?;
+        self.entries.push(CatalogEntry {
+            schema: schema.clone(),
+            file,
+        });
+        if let Err(err) = self.save() {
+            self.entries.pop();
+            let _ = fs::remove_file(path);
+            return Err(err);
+        }
+        Ok(schema)
+    }
+
+    /// Creates a relation and imports the records of a csv file (after its
+    /// header) into it, along with stats on them.
+    pub fn import_csv<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        csv_path: P,
+        column_names: Vec<String>,
+        column_types: Vec<DataType>,
+    ) -> Result<TableStats>
+    {
+        let schema = self.create_relation(name, column_names, column_types)?;
+        let path = self.file_path(name)?;
+        match import_csv(csv_path.as_ref(), &schema, &path) {
+            Ok(stats) => Ok(stats),
+            Err(err) => {
+                // a half imported relation shouldn't stay in the catalog
+                let _ = self.drop_relation(name);
+                Err(err)
+            },
+        }
+    }
+
+    /// Removes a relation from the catalog, and its files.
+    pub fn drop_relation(&mut self, name: &str) -> Result<()> {
+        let i = self.entries.iter()
+            .position(|entry| entry.schema.name.eq_ignore_ascii_case(name))
+            .ok_or_else(|| unknown_relation(name))?;
+        let entry = self.entries.remove(i);
+        self.save()?;
+
+        // files may never have been written
+        let path = self.dir.join(&entry.file);
+        let _ = fs::remove_file(stats_path(&path));
+        let _ = fs::remove_file(path);
+        Ok(())
+    }
+
+    /// Scan of all the columns of a relation, by name.
+    pub fn scan(&self, name: &str) -> Result<DiskScan<File>> {
+        let schema = self.relation(name).ok_or_else(|| unknown_relation(name))?;
+        let path = self.file_path(name)?;
+        DiskScan::from_path(&path, schema.column_types.clone())
+            .chain_err(|| format!("could not open {:?} for relation {}", path, schema.name))
+    }
+
+    /// Stats saved with the relation when it was imported.
+    pub fn stats(&self, name: &str) -> Result<TableStats> {
+        TableStats::load(stats_path(&self.file_path(name)?))
+    }
+
+    fn entry(&self, name: &str) -> Option<&CatalogEntry> {
+        self.entries.iter().find(|entry| entry.schema.name.eq_ignore_ascii_case(name))
+    }
+
+    fn save(&self) -> Result<()> {
+        let path = self.dir.join(CATALOG_FILE);
+        let tmp_path = self.dir.join(format!("{}.tmp", CATALOG_FILE));
+        {
+            let mut f = File::create(&tmp_path)?;
+            for entry in &self.entries {
+                let schema = &entry.schema;
+                writeln!(f, "relation\t{}\t{}\t{}", schema.id, entry.file, schema.name)?;
+                for (name, data_type) in schema.column_names.iter().zip(&schema.column_types) {
+                    writeln!(f, "column\t{}\t{}", name, type_name(data_type))?;
+                }
+            }
+            f.sync_all()?;
+        }
+        fs::rename(&tmp_path, &path)
+            .chain_err(|| format!("could not save catalog {:?}", path))
+    }
+}
+
+/// Stats are saved next to the relation's file.
+pub fn stats_path(path: &Path) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(".stats");
+    PathBuf::from(path)
+}
+
+fn load(path: &Path) -> Result<Vec<CatalogEntry>> {
+    let f = BufReader::new(File::open(path)?);
+    let mut entries: Vec<CatalogEntry> = Vec::new();
+    for line in f.lines() {
+        let line = line?;
+        match line.split('\t').collect::<Vec<_>>()[..] {
+            ["relation", id, file, name] => {
+                entries.push(CatalogEntry {
+                    schema: RelationSchema {
+                        name: name.to_owned(),
+                        id: id.parse()?,
+                        column_names: Vec::new(),
+                        column_types: Vec::new(),
+                    },
+                    file: file.to_owned(),
+                });
+            },
+            ["column", name, data_type] => {
+                let schema = match entries.last_mut() {
+                    Some(entry) => &mut entry.schema,
+                    None => bail!(catalog_error("column before any relation".to_owned())),
+                };
+                schema.column_names.push(name.to_owned());
+                schema.column_types.push(parse_type(data_type)?);
+            },
+            _ => bail!(catalog_error(format!("bad catalog line {:?}", line))),
+        }
+    }
+    Ok(entries)
+}
+
+fn type_name(data_type: &DataType) -> String {
+    match *data_type {
+        DataType::SmallInt => "SmallInt".to_owned(),
+        DataType::Integer => "Integer".to_owned(),
+        DataType::BigInt => "BigInt".to_owned(),
+        DataType::Float => "Float".to_owned(),
+        DataType::Double => "Double".to_owned(),
+        DataType::Boolean => "Boolean".to_owned(),
+        DataType::Date => "Date".to_owned(),
+        DataType::Timestamp => "Timestamp".to_owned(),
+        DataType::Decimal(precision, scale) => format!("Decimal({},{})", precision, scale),
+        DataType::Text(len) => format!("Text({})", len),
+    }
+}
+
+fn parse_type(s: &str) -> Result<DataType> {
+    match s {
+        "SmallInt" => Ok(DataType::SmallInt),
+        "Integer" => Ok(DataType::Integer),
+        "BigInt" => Ok(DataType::BigInt),
+        "Float" => Ok(DataType::Float),
+        "Double" => Ok(DataType::Double),
+        "Boolean" => Ok(DataType::Boolean),
+        "Date" => Ok(DataType::Date),
+        "Timestamp" => Ok(DataType::Timestamp),
+        _ => {
+            if let Some(len) = s.strip_prefix("Text(").and_then(|s| s.strip_suffix(')')) {
+                return Ok(DataType::Text(len.parse()?));
+            }
+            let parts = s.strip_prefix("Decimal(")
+                .and_then(|s| s.strip_suffix(')'))
+                .and_then(|s| s.split_once(','));
+            match parts {
+                Some((precision, scale)) => {
+                    let (precision, scale) = (precision.parse()?, scale.parse()?);
+                    if !decimal::is_valid_type(precision, scale) {
+                        bail!(catalog_error(format!("bad type {:?}", s)));
+                    }
+                    Ok(DataType::Decimal(precision, scale))
+                },
+                None => bail!(catalog_error(format!("unknown type {:?}", s))),
+            }
+        },
+    }
+}
+
+fn unknown_relation(name: &str) -> Error {
+    catalog_error(format!("unknown relation {}", name))
+}
+
+fn catalog_error(msg: String) -> Error {
+    ErrorKind::Catalog(msg).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use executor::DbIterator;
+    use executor::tuple::ToTupleField;
+    use storage::TempFile;
+
+    #[test]
+    fn test_system_catalog() {
+        use DataType::*;
+
+        let dir = TempFile::new("catalog");
+        let csv = TempFile::new("csv");
+        csv.create().unwrap().write_all(b"id,name\n3,ant\n1,bee\n").unwrap();
+
+        let mut catalog = SystemCatalog::open(dir.path()).unwrap();
+        let stats = catalog.import_csv(
+            "Insects",
+            csv.path(),
+            vec!["id".to_owned(), "common name".to_owned()],
+            vec![SmallInt, Text(4)],
+        ).unwrap();
+        assert_eq!(stats.row_count, 2);
+        catalog.create_relation("empty", vec!["x".to_owned()], vec![Decimal(12, 2)]).unwrap();
+        assert!(catalog.create_relation("INSECTS", vec![], vec![]).is_err());
+        for bad_type in [Decimal(5, 6), Decimal(0, 0), Decimal(39, 0)] {
+            assert!(catalog.create_relation("bad", vec!["x".to_owned()], vec![bad_type]).is_err());
+        }
+
+        // reopened from disk, and scanned by name
+        let catalog = SystemCatalog::open(dir.path()).unwrap();
+        let schema = catalog.relation("insects").unwrap();
+        assert_eq!(schema.id, 1);
+        assert_eq!(schema.name, "Insects");
+        assert_eq!(schema.column_names, vec!["id", "common name"]);
+        assert_eq!(schema.column_types, vec![SmallInt, Text(4)]);
+        assert_eq!(catalog.relation("empty").unwrap().id, 2);
+        assert_eq!(catalog.relation("empty").unwrap().column_types, vec![Decimal(12, 2)]);
+        assert_eq!(catalog.stats("insects").unwrap(), stats);
+        assert!(catalog.relation("bad").is_none());
+
+        // a created relation has no tuples, even after a reset
+        let mut scan = catalog.scan("empty").unwrap();
+        assert_eq!(scan.next().unwrap(), None);
+        scan.reset().unwrap();
+        assert_eq!(scan.next().unwrap(), None);
+
+        let mut scan = catalog.scan("insects").unwrap();
+        let tuple = scan.next().unwrap().unwrap();
+        assert_eq!(tuple[0].to_vec(), 3i16.to_tuple_field());
+        assert_eq!(tuple[1].to_vec(), b"ant".to_vec());
+        assert!(scan.next().unwrap().is_some());
+        assert_eq!(scan.next().unwrap(), None);
+
+        let err = catalog.scan("ants").err().unwrap();
+        assert_eq!(err.to_string(), "catalog error: unknown relation ants");
+
+        // a failed import leaves nothing behind
+        let mut catalog = catalog;
+        let missing = TempFile::new("missing");
+        assert!(catalog.import_csv("missing", missing.path(), vec![], vec![]).is_err());
+        assert!(catalog.relation("missing").is_none());
+
+        catalog.drop_relation("insects").unwrap();
+        let catalog = SystemCatalog::open(dir.path()).unwrap();
+        assert!(catalog.relation("insects").is_none());
+        assert!(catalog.relation("empty").is_some());
+
+        fs::remove_dir_all(dir.path()).unwrap();
+    }
+}
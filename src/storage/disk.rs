@@ -1,15 +1,40 @@
 // TODO use memmap for reader
 
 use byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
-use std::io::{Read, Write, Seek, SeekFrom};
+use std::io::{self, Read, Write, Seek, SeekFrom};
 use std::fs::File;
+use std::path::Path;
 
 // TODO: move all these to common module?
-use ColumnTypes;
+use {ColumnTypes, DataType};
+use executor::explain::Explain;
 use executor::tuple::Tuple;
 use executor::DbIterator; //TODO move dbiterator to top level mod?
 use error::*;
 
+const WRITE_BUFFER_BLOCKS: usize = 16;
+
+// a record and its pointer have to fit in a block after its 4 byte header
+const MAX_RECORD_LEN: usize = 8000 - 4 - 2;
+
+/// How the fields of a column are laid out in a record, when not null.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FieldLayout {
+    Fixed(usize), // always this many bytes
+    Variable, // a u16 length, then that many bytes
+}
+
+/// Layout of the columns of a relation with these types, where only
+/// Text fields vary in length.
+pub fn layout(col_types: &[DataType]) -> Vec<FieldLayout> {
+    col_types.iter()
+        .map(|col_type| match col_type.bytes_length() {
+            Some(len) => FieldLayout::Fixed(len),
+            None => FieldLayout::Variable,
+        })
+        .collect()
+}
+
 /// The DiskWriter (and block manager) holds:
 ///    - handle to the file
 ///    - buffer for write io (do this myself for now, to see how it
@@ -21,17 +46,23 @@ use error::*;
 ///
 /// Notes:
 /// - writes in 8k blocks
+/// - write buffer is written out every WRITE_BUFFER_BLOCKS blocks
 /// - currently only writes a completely new file. No updates or inserts
+/// - each record is a null bitmap, a bit for each field (set for null)
+///   starting from the low bit of the first byte, then the data of the
+///   fields which aren't null, with a u16 length before each Variable one
+/// - a record which doesn't fit in an empty block is an error
 pub struct DiskWriter<W> {
     write_handle: W,
     write_buffer: Vec<u8>, // holds bytes to append to file on disk
     block_buffer: [u8; 8000], // holds current block being written to
     block_upper: u16, // pointer to beginning of free space
     block_lower: u16, // pointer to end of free space
+    layout: Vec<FieldLayout>, // of each column
 }
 
 impl<W: Write> DiskWriter<W> {
-    pub fn new(writer: W) -> Result<Self> {
+    pub fn new(writer: W, layout: Vec<FieldLayout>) -> Result<Self> {
 
         Ok(DiskWriter {
             write_handle: writer,
@@ -39,6 +70,7 @@ impl<W: Write> DiskWriter<W> {
             block_buffer: [0; 8000],
             block_upper: 4, // leave space for header
             block_lower: 8000,
+            layout,
         })
     }
 
@@ -48,35 +80,38 @@ impl<W: Write> DiskWriter<W> {
         // - see if it will fit in block
         // - if yes, write to block
         // - if no, write block to file_buffer and 
-        let tuple_len = tuple.data.len() as u16; // assume len less than block
+        let record = self.encode_record(&tuple)?;
+        if record.len() > MAX_RECORD_LEN {
+            bail!(ErrorKind::RecordTooLarge(record.len(), MAX_RECORD_LEN));
+        }
+        let tuple_len = record.len() as u16;
 
         let free_space = self.block_lower - self.block_upper;
 
         // tuple len plus the one u16 pointer need to fit in block
         if tuple_len + 2 > free_space {
             self.write_buffer.extend_from_slice(&self.block_buffer);
+            if self.write_buffer.len() >= WRITE_BUFFER_BLOCKS * 8000 {
+                self.write_handle.write_all(&self.write_buffer)?;
+                self.write_buffer.clear();
+            }
 
             // block_upper leaves space for header
             self.block_upper = 4;
             self.block_lower = 8000;
 
-            // can I do this better?
-            // This seems like a weird hack
-            // TODO figure out how to zero out a fixed array.
-            // or just zero out the pointers?
-            (&mut self.block_buffer[..]).write(&[0;8000])?;
+            self.block_buffer.fill(0);
         }
 
         // now write to block
         let tuple_start = self.block_lower - tuple_len;
-
-        (&mut self.block_buffer[tuple_start as usize..self.block_lower as usize]).write(&tuple.data)?;
+        self.block_buffer[tuple_start as usize..self.block_lower as usize].copy_from_slice(&record);
 
         (&mut self.block_buffer[self.block_upper as usize..self.block_upper as usize+2])
             .write_u16::<BigEndian>(tuple_start)?;
 
         // increment pointers and free space pointers write to block
-        self.block_upper = self.block_upper + 2;
+        self.block_upper += 2;
         self.block_lower = tuple_start;
         (&mut self.block_buffer[0..2]).write_u16::<BigEndian>(self.block_upper)?;
         (&mut self.block_buffer[2..4]).write_u16::<BigEndian>(self.block_lower)?;
@@ -84,14 +119,40 @@ impl<W: Write> DiskWriter<W> {
         Ok(())
     }
 
+    // The null bitmap of tuple, then its fields as laid out in a record.
+    fn encode_record(&self, tuple: &Tuple) -> Result<Vec<u8>> {
+        if tuple.indexes.len() != self.layout.len() {
+            bail!("tuple has {} fields, but the layout has {}", tuple.indexes.len(), self.layout.len());
+        }
+
+        let mut record = null_bitmap(tuple);
+        for (col, field_layout) in self.layout.iter().enumerate() {
+            if tuple.is_null(col) {
+                continue;
+            }
+            let field = &tuple[col];
+            match *field_layout {
+                FieldLayout::Fixed(len) => if field.len() != len {
+                    bail!("field {} is {} bytes, but its layout is {}", col, field.len(), len);
+                },
+                FieldLayout::Variable => {
+                    // a field too long for a u16 makes the record too
+                    // long for a block, so it's never written
+                    record.write_u16::<BigEndian>(field.len() as u16)?;
+                },
+            }
+            record.extend_from_slice(field);
+        }
+        Ok(record)
+    }
+
     pub fn flush(&mut self) -> Result<()> {
         // for guaranteeing that all blocks will be written to disk
         // Writes current block to file_buffer, write file_buffer to disk
-        // for now, just writes the write_buffer at once to file.
-        // In future, would probably flush at intervals
+        // (full blocks may already have been written out by add_tuple)
 
         self.write_buffer.extend_from_slice(&self.block_buffer);
-        self.write_handle.write_all(&mut self.write_buffer)
+        self.write_handle.write_all(&self.write_buffer)
             .chain_err(|| "error flushing")
     }
 }
@@ -107,118 +168,199 @@ impl<W: Write> DiskWriter<W> {
 ///
 /// Notes:
 /// - reads in 8k blocks
+/// - can read only some columns of each record, see with_columns
+/// - records are checked against the layout when their block is read,
+///   so a corrupt block is an error instead of a panic
 pub struct DiskScan<R> {
     read_handle: R,
     block_buffer: [u8; 8000], // holds current block being written to
     record_pointers: Vec<u16>,
     current_record_pointer: usize, //index into record_pointers
-    tuple_indexes: Vec<usize>,
-    record_length: usize,
+    layout: Vec<FieldLayout>, // of each column
+    columns: Option<Vec<usize>>, // the columns read, if not all
+    offsets: Vec<Option<(usize, usize)>>, // start and length of the current record's fields, None for nulls
 }
 
 impl<R: Read + Seek> DiskScan<R> {
-    pub fn new(mut reader: R, col_types: ColumnTypes) -> Result<Self> {
-        // On initializationg, read first block
-        // for now, just read all to read_buffer (in future, this
-        //   could be variable length buffer, so as not to read
-        //   entire file at once
-
-        let mut block_buffer = [0u8; 8000];
-
-        reader.read_exact(&mut block_buffer)?;
-
-        // convert record pointers
-        // this can be done in one step using unsafe
-
-        // add 1 to this to get the number of bytes to take
-        let end_record_pointers = (&block_buffer[0..2]).read_u16::<BigEndian>()?;
-
-        let record_pointers_bytes: Vec<u8> = block_buffer
-            .iter()
-            .skip(4)
-            .cloned() // can I avoid?
-            .take(end_record_pointers as usize - 4) // take until
-            .collect();
-
-        let record_pointers = record_pointers_bytes.chunks(2)
-            .map(|mut bytes| {
-                bytes.read_u16::<BigEndian>()
-                    .chain_err(|| "error converting rec pointer to u16")
-            })
-            .collect::<Result<Vec<u16>>>()?;
-
-
-        // map schema to indexes of fields in tuple
-        // also calculate record length
-        let mut tuple_indexes = Vec::new();
-        let mut offset = 0;
-        for col_type in &col_types {
-            tuple_indexes.push(offset);
-            offset += col_type.bytes_length();
-        }
-        let record_length = offset;
+    pub fn new(reader: R, col_types: ColumnTypes) -> Result<Self> {
+        Self::with_layout(reader, layout(&col_types))
+    }
 
-        Ok(DiskScan {
+    /// For reading back tuples when only their layout is known.
+    pub fn with_layout(reader: R, layout: Vec<FieldLayout>) -> Result<Self> {
+        let mut scan = DiskScan {
             read_handle: reader,
-            block_buffer: block_buffer,
-            record_pointers: record_pointers,
+            block_buffer: [0u8; 8000],
+            record_pointers: Vec::new(),
             current_record_pointer: 0,
-            tuple_indexes: tuple_indexes,
-            record_length: record_length,
-        })
+            offsets: Vec::with_capacity(layout.len()),
+            layout,
+            columns: None,
+        };
+        // On initialization, read first block. An empty file has none.
+        scan.read_block()?;
+        Ok(scan)
+    }
+
+    /// Only reads the given columns of each record, in that order, so
+    /// that less data is copied into each tuple.
+    pub fn with_columns(mut self, columns: &[usize]) -> Self {
+        self.columns = Some(columns.to_vec());
+        self
     }
 
+    // Finds the fields of the record from start, from its null bitmap
+    // and the lengths of its Variable fields. Returns the length of the
+    // record, or None if it would run past end.
+    fn read_offsets(&mut self, start: usize, end: usize) -> Option<usize> {
+        let mut offset = start + bitmap_len(self.layout.len());
+        if offset > end {
+            return None;
+        }
+        self.offsets.clear();
+        for (col, field_layout) in self.layout.iter().enumerate() {
+            if self.block_buffer[start + col / 8] & (1 << (col % 8)) != 0 {
+                self.offsets.push(None);
+                continue;
+            }
+            let len = match *field_layout {
+                FieldLayout::Fixed(len) => len,
+                FieldLayout::Variable => {
+                    if offset + 2 > end {
+                        return None;
+                    }
+                    let len = (&self.block_buffer[offset..offset + 2]).read_u16::<BigEndian>().ok()?;
+                    offset += 2;
+                    len as usize
+                },
+            };
+            self.offsets.push(Some((offset, len)));
+            offset += len;
+        }
+        if offset > end {
+            return None;
+        }
+        Some(offset - start)
+    }
 }
 
 impl DiskScan<File> {
-    pub fn from_path(path: &str, col_types: ColumnTypes) -> Result<Self> {
+    pub fn from_path<P: AsRef<Path>>(path: P, col_types: ColumnTypes) -> Result<Self> {
         let f = File::open(path)?;
         Self::new(f, col_types)
     }
 }
 
 impl<R:Read + Seek> DbIterator for DiskScan<R> {
-    fn next(&mut self) -> Option<Tuple> {
+    fn next(&mut self) -> Result<Option<Tuple>> {
         // record_pointers needs to hold an iterator instead of a vec
         // iterator should be Windows?
-        // then map, using schema to determine where offsets should be
-        // ugh, text does have to be a fixed allocation
-        if self.current_record_pointer >= self.record_pointers.len() {
+        // then map, using the layout to determine where offsets should be
+        while self.current_record_pointer >= self.record_pointers.len() {
             // load next block
-            // todo refactor this with the initializing block load?
-            if self.read_handle.read_exact(&mut self.block_buffer).ok().is_none() {
-                return None;
+            if !self.read_block()? {
+                return Ok(None);
             }
-            self.init_record_pointers();
         }
 
+        // each record ends where the one before it starts, and was
+        // checked against the layout when its block was read
         let start = self.record_pointers[self.current_record_pointer] as usize;
-        let end = start + self.record_length;
-        let record = &self.block_buffer[start..end];
+        let end = match self.current_record_pointer {
+            0 => 8000,
+            i => self.record_pointers[i - 1] as usize,
+        };
+        self.read_offsets(start, end);
+
+        let mut data = Vec::new();
+        let mut indexes = Vec::new();
+        let mut nulls = Vec::new();
+        {
+            let offsets = &self.offsets;
+            let block_buffer = &self.block_buffer;
+            let mut add_field = |col: usize| {
+                indexes.push(data.len());
+                match offsets[col] {
+                    Some((offset, len)) => data.extend_from_slice(&block_buffer[offset..offset + len]),
+                    None => nulls.push(indexes.len() - 1),
+                }
+            };
+            match self.columns {
+                Some(ref columns) => columns.iter().for_each(|&col| add_field(col)),
+                None => (0..self.layout.len()).for_each(add_field),
+            }
+        }
 
         self.current_record_pointer += 1;
 
-        Some(Tuple {
-            data: record.to_vec(),
-            indexes: self.tuple_indexes.clone(),
-        })
+        Ok(Some(Tuple {
+            data,
+            indexes,
+            nulls,
+        }))
 
     }
 
-    fn reset(&mut self) {
-        self.read_handle.seek(SeekFrom::Start(0)).unwrap();
-        self.read_handle.read_exact(&mut self.block_buffer).unwrap();
-        self.init_record_pointers();
+    fn reset(&mut self) -> Result<()> {
+        self.read_handle.seek(SeekFrom::Start(0))?;
+        self.read_block()?;
+        Ok(())
+    }
+
+    fn explain(&self) -> Explain {
+        let columns = self.columns.as_ref().map_or(self.layout.len(), |columns| columns.len());
+        Explain::new("DiskScan")
+            .param("columns", format!("{} of {}", columns, self.layout.len()))
     }
 }
 
 impl<R:Read + Seek> DiskScan<R> {
-    fn init_record_pointers(&mut self) {
+    // Reads the next block and its record pointers. Returns false, with
+    // no records, at the end of the file. A file which ends partway
+    // through a block is corrupt.
+    fn read_block(&mut self) -> Result<bool> {
+        let mut filled = 0;
+        while filled < self.block_buffer.len() {
+            match self.read_handle.read(&mut self.block_buffer[filled..]) {
+                Ok(0) => break,
+                Ok(len) => filled += len,
+                Err(ref err) if err.kind() == io::ErrorKind::Interrupted => (),
+                Err(err) => return Err(err).chain_err(|| "error reading block"),
+            }
+        }
+        if filled == 0 {
+            self.record_pointers.clear();
+            self.current_record_pointer = 0;
+            return Ok(false);
+        }
+        if filled < self.block_buffer.len() {
+            bail!(ErrorKind::CorruptBlock(format!("file ends {} bytes into a block", filled)));
+        }
+        self.init_record_pointers()?;
+        Ok(true)
+    }
+
+    fn init_record_pointers(&mut self) -> Result<()> {
         // convert record pointers
         // this can be done in one step using unsafe
 
         // add 1 to this to get the number of bytes to take
-        let end_record_pointers = (&self.block_buffer[0..2]).read_u16::<BigEndian>().unwrap();
+        let end_record_pointers = (&self.block_buffer[0..2]).read_u16::<BigEndian>()?;
+        let start_records = (&self.block_buffer[2..4]).read_u16::<BigEndian>()?;
+
+        // a block which never had a tuple added has no header
+        if end_record_pointers == 0 && start_records == 0 {
+            self.record_pointers = Vec::new();
+            self.current_record_pointer = 0;
+            return Ok(());
+        }
+        if end_record_pointers < 4 || end_record_pointers > start_records || start_records > 8000 {
+            bail!(ErrorKind::CorruptBlock(format!(
+                "bad header, upper {} lower {}",
+                end_record_pointers,
+                start_records,
+            )));
+        }
 
         let record_pointers_bytes: Vec<u8> = self.block_buffer
             .iter()
@@ -232,17 +374,52 @@ impl<R:Read + Seek> DiskScan<R> {
                 bytes.read_u16::<BigEndian>()
                     .chain_err(|| "error converting rec pointer to u16")
             })
-            .collect::<Result<Vec<u16>>>().unwrap();
+            .collect::<Result<Vec<u16>>>()?;
+
+        // records are added from the end of the block down, each one
+        // ending where the one before it starts
+        let mut end = 8000;
+        for &pointer in &record_pointers {
+            let start = pointer as usize;
+            if start < start_records as usize || start > end {
+                bail!(ErrorKind::CorruptBlock(format!(
+                    "record pointer {} out of bounds",
+                    pointer,
+                )));
+            }
+            if self.read_offsets(start, end) != Some(end - start) {
+                bail!(ErrorKind::CorruptBlock(format!(
+                    "record at {} is {} bytes, which doesn't match its layout",
+                    pointer,
+                    end - start,
+                )));
+            }
+            end = start;
+        }
 
         self.record_pointers = record_pointers;
         self.current_record_pointer = 0;
+        Ok(())
+    }
+}
+
+fn bitmap_len(width: usize) -> usize {
+    width.div_ceil(8)
+}
+
+fn null_bitmap(tuple: &Tuple) -> Vec<u8> {
+    let mut bitmap = vec![0u8; bitmap_len(tuple.indexes.len())];
+    for &col in &tuple.nulls {
+        bitmap[col / 8] |= 1 << (col % 8);
     }
+    bitmap
 }
 
 #[cfg(test)]
 mod tests {
     use csv::StringRecord;
     use std::io::Cursor;
+    use executor::tuple::ToTupleField;
     use super::*;
 
     // TODO deprecate Schema
@@ -262,7 +439,7 @@ mod tests {
     fn test_block_buffer_one_write() {
         let schema = generate_relation_schema();
         let f = Cursor::new(Vec::new());
-        let mut disk_writer = DiskWriter::new(f).unwrap();
+        let mut disk_writer = DiskWriter::new(f, layout(&schema.column_types)).unwrap();
 
         // to_tuple
         let tuple_bytes = Tuple::from_stringrecord(
@@ -279,11 +456,12 @@ mod tests {
 
         let mut expected = [0;8000];
         // header of 4 bytes (two pointers to free space), plus one pointer to record
-        (&mut expected[0..6]).write(&[0x00, 0x06, 0x1F, 0x39, 0x1F, 0x39]).unwrap();
-        (&mut expected[7993..8000]).write(&[0, 17, 116, 101, 115, 116, 121]).unwrap();
+        expected[0..6].copy_from_slice(&[0x00, 0x06, 0x1F, 0x36, 0x1F, 0x36]);
+        // null bitmap, then the fields, text with its length first
+        expected[7990..8000].copy_from_slice(&[0, 0, 17, 0, 5, 116, 101, 115, 116, 121]);
 
         assert_eq!(disk_writer.block_buffer[0..6], expected[0..6]);
-        assert_eq!(disk_writer.block_buffer[7993..8000], expected[7993..8000]);
+        assert_eq!(disk_writer.block_buffer[7990..8000], expected[7990..8000]);
 
         // Then write one more tuple to diskwriter
         disk_writer.add_tuple(tuple_bytes).unwrap();
@@ -291,18 +469,30 @@ mod tests {
         let mut expected = [0;8000];
         // header of 4 bytes (two pointers to free space),
         // plus two pointers to records
-        (&mut expected[0..6]).write(&[0x00, 0x08, 0x1F, 0x32, 0x1F, 0x39, 0x1F, 0x32]).unwrap();
-        (&mut expected[7986..8000]).write(&[0, 17, 116, 101, 115, 116, 121, 0, 17, 116, 101, 115, 116, 121]).unwrap();
-
-        assert_eq!(disk_writer.block_buffer[0..6], expected[0..6]);
-        assert_eq!(disk_writer.block_buffer[7993..8000], expected[7993..8000]);
+        expected[0..8].copy_from_slice(&[0x00, 0x08, 0x1F, 0x2C, 0x1F, 0x36, 0x1F, 0x2C]);
+        expected[7980..8000].copy_from_slice(&[
+            0, 0, 17, 0, 5, 116, 101, 115, 116, 121,
+            0, 0, 17, 0, 5, 116, 101, 115, 116, 121,
+        ]);
+
+        assert_eq!(disk_writer.block_buffer[0..8], expected[0..8]);
+        assert_eq!(disk_writer.block_buffer[7980..8000], expected[7980..8000]);
+
+        // null fields are only in the bitmap
+        let mut tuple = Tuple::from_nullable(vec![None, Some(b"nul".to_vec())]);
+        disk_writer.add_tuple(tuple.clone()).unwrap();
+        assert_eq!(disk_writer.block_buffer[7974..7980], [1, 0, 3, 110, 117, 108]);
+        tuple.nulls = vec![0, 1];
+        tuple.data.clear();
+        disk_writer.add_tuple(tuple).unwrap();
+        assert_eq!(disk_writer.block_buffer[7973..7974], [3]);
     }
 
     #[test]
     fn test_block_buffer_overflow() {
         let schema = generate_relation_schema();
         let f = Cursor::new(Vec::new());
-        let mut disk_writer = DiskWriter::new(f).unwrap();
+        let mut disk_writer = DiskWriter::new(f, layout(&schema.column_types)).unwrap();
 
         // to_tuple
         let tuple_bytes = Tuple::from_stringrecord(
@@ -315,26 +505,27 @@ mod tests {
             }
         ).unwrap();
 
-        // Then write tuple to diskwriter 1000 times
-        // (each time adds 8 bytes: 2 byte pointer, 6 byte record
-        // since header is 4 bytes, the 1000 time should not fit in 8000bytes
-        // and there should be an overflow
-        for _ in 0..1000 {
+        // Then write tuple to diskwriter 727 times
+        // (each time adds 11 bytes: 2 byte pointer, 9 byte record with
+        // its null bitmap and text length) since header is 4 bytes, the
+        // 727th time should not fit in 8000bytes and there should be an
+        // overflow
+        for _ in 0..727 {
             disk_writer.add_tuple(tuple_bytes.clone()).unwrap();
         }
 
         // for the block, expect one record
         let mut expected = [0;8000];
         // header of 4 bytes (two pointers to free space), plus one pointer to record
-        (&mut expected[0..6]).write(&[0x00, 0x06, 0x1F, 0x3a, 0x1F, 0x3a]).unwrap();
-        (&mut expected[7994..8000]).write(&[0, 17, 116, 101, 115, 116]).unwrap();
+        expected[0..6].copy_from_slice(&[0x00, 0x06, 0x1F, 0x37, 0x1F, 0x37]);
+        expected[7991..8000].copy_from_slice(&[0, 0, 17, 0, 4, 116, 101, 115, 116]);
 
         assert_eq!(disk_writer.block_buffer[0..6], expected[0..6]);
-        assert_eq!(disk_writer.block_buffer[7993..8000], expected[7993..8000]);
+        assert_eq!(disk_writer.block_buffer[7991..8000], expected[7991..8000]);
 
         // for the filebuffer, expect that a block was written to the first 8k bytes
-         assert_eq!(&disk_writer.write_buffer[0..4], &[0x07u8, 0xD2, 0x07, 0xD6][..]);
-        assert_eq!(disk_writer.write_buffer[7994..8000], expected[7994..8000]);
+        assert_eq!(&disk_writer.write_buffer[0..4], &[0x05u8, 0xB0, 0x05, 0xBA][..]);
+        assert_eq!(disk_writer.write_buffer[7991..8000], expected[7991..8000]);
     }
 
     #[test]
@@ -343,7 +534,7 @@ mod tests {
 
         let schema = generate_relation_schema();
         let f = Cursor::new(output);
-        let mut disk_writer = DiskWriter::new(f).unwrap();
+        let mut disk_writer = DiskWriter::new(f, layout(&schema.column_types)).unwrap();
 
         // to_tuple
         let tuple_bytes_1 = Tuple::from_stringrecord(
@@ -374,26 +565,206 @@ mod tests {
 
         // get the underlying vec from the writer,
         // to use for read.
-        let disk_file = Cursor::new(disk_writer.write_handle.into_inner());
+        let bytes = disk_writer.write_handle.into_inner();
+        let disk_file = Cursor::new(bytes.clone());
 
         // Now read from the "disk file"
         let mut reader = DiskScan::new(disk_file, schema.column_types.clone()).unwrap();
         assert_eq!(
-            reader.next().unwrap(),
+            reader.next().unwrap().unwrap(),
             Tuple {
-                data: vec![0, 17, 116, 101, 115, 0, 0],
+                data: vec![0, 17, 116, 101, 115],
                 indexes: vec![0, 2],
+                nulls: vec![],
             }
         );
 
         assert_eq!(
-            reader.next().unwrap(),
+            reader.next().unwrap().unwrap(),
             Tuple {
-                data: vec![0, 23, 115, 101, 116, 0, 0],
+                data: vec![0, 23, 115, 101, 116],
                 indexes: vec![0, 2],
+                nulls: vec![],
+            }
+        );
+
+        assert_eq!(reader.next().unwrap(), None);
+
+        // only reading some columns
+        let mut reader = DiskScan::new(Cursor::new(bytes), schema.column_types.clone())
+            .unwrap()
+            .with_columns(&[1, 0]);
+        assert_eq!(
+            reader.next().unwrap().unwrap(),
+            Tuple {
+                data: vec![116, 101, 115, 0, 17],
+                indexes: vec![0, 3],
+                nulls: vec![],
             }
         );
+    }
+
+    #[test]
+    fn test_write_read_nulls() {
+        use DataType::*;
+
+        let tuples = vec![
+            Tuple::from_nullable(vec![None, Some(vec![1]), Some(vec![0, 2])]),
+            Tuple::from_nullable(vec![Some(vec![0, 0, 0, 3]), None, None]),
+            Tuple::null(3),
+        ];
+        let col_types = vec![Integer, Text(1), SmallInt];
+        let mut disk_writer = DiskWriter::new(Cursor::new(Vec::new()), layout(&col_types)).unwrap();
+        for tuple in &tuples {
+            disk_writer.add_tuple(tuple.clone()).unwrap();
+        }
+        disk_writer.flush().unwrap();
+        let bytes = disk_writer.write_handle.into_inner();
+
+        let expected_layout = vec![FieldLayout::Fixed(4), FieldLayout::Variable, FieldLayout::Fixed(2)];
+        assert_eq!(layout(&col_types), expected_layout);
+        let mut reader = DiskScan::with_layout(Cursor::new(bytes.clone()), expected_layout).unwrap();
+        for tuple in &tuples {
+            assert_eq!(reader.next().unwrap().as_ref(), Some(tuple));
+        }
+        assert_eq!(reader.next().unwrap(), None);
+
+        let mut reader = DiskScan::new(Cursor::new(bytes), col_types)
+            .unwrap()
+            .with_columns(&[2, 0]);
+        assert_eq!(reader.next().unwrap().unwrap(), Tuple::from_nullable(vec![Some(vec![0, 2]), None]));
+        assert_eq!(reader.next().unwrap().unwrap(), Tuple::from_nullable(vec![None, Some(vec![0, 0, 0, 3])]));
+        assert_eq!(reader.next().unwrap().unwrap(), Tuple::null(2));
+    }
+
+    #[test]
+    fn test_write_read_many_blocks() {
+        let fixed_layout = vec![FieldLayout::Fixed(4), FieldLayout::Fixed(2)];
+        let f = Cursor::new(Vec::new());
+        let mut disk_writer = DiskWriter::new(f, fixed_layout.clone()).unwrap();
+
+        // 7 byte records (with their null bitmap) plus 2 byte pointers,
+        // so 888 records per block. Enough blocks for the write buffer
+        // to be written out.
+        let record_count = 888 * (WRITE_BUFFER_BLOCKS + 2) + 1;
+        for i in 0..record_count {
+            disk_writer.add_tuple(Tuple::new(vec![
+                vec![0u8, 0, (i / 256) as u8, (i % 256) as u8],
+                vec![0u8, 1],
+            ])).unwrap();
+        }
+        disk_writer.flush().unwrap();
+
+        let disk_file = Cursor::new(disk_writer.write_handle.into_inner());
+        assert_eq!(disk_file.get_ref().len(), 8000 * (WRITE_BUFFER_BLOCKS + 3));
+
+        let mut reader = DiskScan::with_layout(disk_file, fixed_layout).unwrap();
+        for i in 0..record_count {
+            let tuple = reader.next().unwrap().unwrap();
+            assert_eq!(tuple[0], [0u8, 0, (i / 256) as u8, (i % 256) as u8][..]);
+            assert_eq!(tuple[1], [0u8, 1][..]);
+        }
+        assert_eq!(reader.next().unwrap(), None);
+    }
+
+    #[test]
+    fn test_read_corrupt_block() {
+        let f = Cursor::new(Vec::new());
+        let mut disk_writer = DiskWriter::new(f, vec![FieldLayout::Fixed(2)]).unwrap();
+        disk_writer.add_tuple(Tuple::new(vec![vec![0u8, 1]])).unwrap();
+        disk_writer.flush().unwrap();
+        let mut bytes = disk_writer.write_handle.into_inner();
+
+        // record pointer past the end of the block
+        let mut corrupt = bytes.clone();
+        (&mut corrupt[4..6]).write_u16::<BigEndian>(8001).unwrap();
+        assert!(DiskScan::with_layout(Cursor::new(corrupt), vec![FieldLayout::Fixed(2)]).is_err());
+
+        // record too short for its layout
+        let mut corrupt = bytes.clone();
+        (&mut corrupt[4..6]).write_u16::<BigEndian>(7998).unwrap();
+        assert!(DiskScan::with_layout(Cursor::new(corrupt), vec![FieldLayout::Fixed(2)]).is_err());
+
+        // read as text, the length runs past the end of the record
+        assert!(DiskScan::with_layout(Cursor::new(bytes.clone()), vec![FieldLayout::Variable]).is_err());
+
+        // the file ends partway through a block
+        let truncated = bytes[..bytes.len() - 1].to_vec();
+        match DiskScan::with_layout(Cursor::new(truncated), vec![FieldLayout::Fixed(2)]) {
+            Err(Error(ErrorKind::CorruptBlock(_), _)) => (),
+            res => panic!("expected a corrupt block, got {:?}", res.map(|_| ())),
+        }
+
+        // but an empty file just has no tuples
+        let mut reader = DiskScan::with_layout(Cursor::new(Vec::new()), vec![FieldLayout::Fixed(2)]).unwrap();
+        assert_eq!(reader.next().unwrap(), None);
+        reader.reset().unwrap();
+        assert_eq!(reader.next().unwrap(), None);
+
+        // header of a second block points past its records
+        bytes.extend_from_slice(&[0, 6, 0, 2]);
+        bytes.extend_from_slice(&[0; 7996]);
+        let mut reader = DiskScan::with_layout(Cursor::new(bytes), vec![FieldLayout::Fixed(2)]).unwrap();
+        assert_eq!(reader.next().unwrap().unwrap()[0], [0u8, 1][..]);
+        match reader.next() {
+            Err(Error(ErrorKind::CorruptBlock(_), _)) => (),
+            res => panic!("expected a corrupt block, got {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_write_read_variable() {
+        use DataType::*;
+
+        let col_types = vec![Text(255), SmallInt];
+        let names = ["", "a", "ab", "été", "a much longer name than the others"];
+        let tuples: Vec<_> = (0..500)
+            .map(|i| Tuple::new(vec![names[i % names.len()].as_bytes().to_vec(), (i as i16).to_tuple_field()]))
+            .collect();
+
+        // tuples read back the same, also with every field variable
+        for field_layout in [layout(&col_types), vec![FieldLayout::Variable; 2]] {
+            let mut disk_writer = DiskWriter::new(Cursor::new(Vec::new()), field_layout.clone()).unwrap();
+            for tuple in &tuples {
+                disk_writer.add_tuple(tuple.clone()).unwrap();
+            }
+            disk_writer.flush().unwrap();
+            let bytes = disk_writer.write_handle.into_inner();
+
+            let mut reader = DiskScan::with_layout(Cursor::new(bytes), field_layout).unwrap();
+            for tuple in &tuples {
+                assert_eq!(reader.next().unwrap().as_ref(), Some(tuple));
+            }
+            assert_eq!(reader.next().unwrap(), None);
+        }
+
+        // padded out to 255 bytes, these would take 17 blocks
+        let mut disk_writer = DiskWriter::new(Cursor::new(Vec::new()), layout(&col_types)).unwrap();
+        for tuple in tuples {
+            disk_writer.add_tuple(tuple).unwrap();
+        }
+        disk_writer.flush().unwrap();
+        assert_eq!(disk_writer.write_handle.into_inner().len(), 8000);
+    }
+
+    #[test]
+    fn test_write_errors() {
+        use DataType::*;
+
+        let mut disk_writer = DiskWriter::new(Cursor::new(Vec::new()), layout(&[Text(10000)])).unwrap();
+
+        // a record of the bitmap, text length and text has to fit in a block
+        disk_writer.add_tuple(Tuple::new(vec![vec![b'a'; MAX_RECORD_LEN - 3]])).unwrap();
+        match disk_writer.add_tuple(Tuple::new(vec![vec![b'a'; MAX_RECORD_LEN - 2]])) {
+            Err(Error(ErrorKind::RecordTooLarge(7995, 7994), _)) => (),
+            res => panic!("expected a record too large, got {:?}", res),
+        }
+        assert!(disk_writer.add_tuple(Tuple::new(vec![vec![b'a'; 70000]])).is_err());
 
-        assert_eq!(reader.next(), None);
+        // fields which don't match the layout
+        let mut disk_writer = DiskWriter::new(Cursor::new(Vec::new()), layout(&[Integer])).unwrap();
+        assert!(disk_writer.add_tuple(Tuple::new(vec![vec![0, 1]])).is_err());
+        assert!(disk_writer.add_tuple(Tuple::new(vec![vec![0, 0, 0, 1], vec![1]])).is_err());
+        assert!(disk_writer.add_tuple(Tuple::null(1)).is_ok());
     }
 }
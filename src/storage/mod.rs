@@ -1,25 +1,47 @@
 //! Storage module
 //!
 //! - module for handling binary disk storage
+//! - system catalog, recording the relations in a data directory
 //! - module for buffering a file scan
 //! - convenience functions for importing from csv
+//! - stats on imported relations, for the planner
+//! - temp files and a stream format for executor nodes which spill to disk
 
+pub mod catalog;
 pub mod disk;
+pub mod spill;
+pub mod stats;
 
 use csv;
-use std::fs::File;
+use std::env;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
 
 use ::executor::tuple::Tuple;
 use ::{RelationSchema, Schema};
 use self::disk::DiskWriter;
+use self::stats::{StatsCollector, TableStats};
 use error::*;
 
 /// import a csv file into db
+///
+/// Writes into the current directory, to a file named after the relation
+/// id. Stats on the relation are saved next to it, in "<id>.stats". See
+/// catalog::SystemCatalog::import_csv to import into a data directory
+/// instead, and record the relation there.
 pub fn from_csv(
     path: &str,
     schema: RelationSchema, // do i need col names for better
-    ) -> Result<()>
+    ) -> Result<TableStats>
 {
+    import_csv(Path::new(path), &schema, Path::new(&schema.id.to_string()))
+}
+
+// Writes the records of the csv file at path to a relation file at
+// relation_path, and its stats next to it.
+fn import_csv(path: &Path, schema: &RelationSchema, relation_path: &Path) -> Result<TableStats> {
     // schema contains the tableid
     // for each csv record
     //   read
@@ -27,9 +49,10 @@ pub fn from_csv(
     //   write to block_write_manger (DiskWriter)
     //
 
-    let f_write = File::create(schema.id.to_string())?;
-    let mut wtr = DiskWriter::new(f_write)?;
+    let f_write = File::create(relation_path)?;
+    let mut wtr = DiskWriter::new(f_write, disk::layout(&schema.column_types))?;
     let mut rdr = csv::Reader::from_path(path)?;
+    let mut stats = StatsCollector::new(&schema.column_types);
     for result in rdr.records() { // TODO in the future use byterecords
         let record = result?;
 
@@ -40,9 +63,52 @@ pub fn from_csv(
                 column_types: schema.column_types.clone(),
             }
         )?;
+        stats.add(&tuple)?;
         wtr.add_tuple(tuple)?;
     }
     wtr.flush()?;
-    Ok(())
+
+    let stats = stats.finish();
+    stats.save(catalog::stats_path(relation_path))?;
+    Ok(stats)
+}
+
+
+static TEMP_FILE_COUNT: AtomicUsize = AtomicUsize::new(0);
+
+/// A file in the system temp dir, removed when dropped.
+#[derive(Debug)]
+pub struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    pub fn new(prefix: &str) -> Self {
+        let count = TEMP_FILE_COUNT.fetch_add(1, Ordering::SeqCst);
+        let file_name = format!("lemurdb-{}-{}-{}", prefix, process::id(), count);
+        TempFile {
+            path: env::temp_dir().join(file_name),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn create(&self) -> Result<File> {
+        File::create(&self.path)
+            .chain_err(|| format!("error creating temp file {:?}", self.path))
+    }
+
+    pub fn open(&self) -> Result<File> {
+        File::open(&self.path)
+            .chain_err(|| format!("error opening temp file {:?}", self.path))
+    }
 }
 
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        // file may never have been created
+        let _ = fs::remove_file(&self.path);
+    }
+}
@@ -0,0 +1,164 @@
+// Stream format for tuples spilled to disk by an executor node.
+//
+// Spilled tuples are only read back in the order they were written, by
+// the node which wrote them, so unlike the DiskWriter block format there
+// is no layout and no block to fit in: each record is a u32 count of its
+// fields, a null bitmap (a bit for each field, set for null, starting
+// from the low bit of the first byte), then a u32 length before the data
+// of each field which isn't null.
+use byteorder::{ReadBytesExt, WriteBytesExt, BigEndian};
+use std::io::{BufRead, BufReader, BufWriter, Read, Seek, SeekFrom, Write};
+
+use error::*;
+use executor::DbIterator;
+use executor::explain::Explain;
+use executor::tuple::Tuple;
+
+pub struct SpillWriter<W: Write> {
+    write_handle: BufWriter<W>,
+}
+
+impl<W: Write> SpillWriter<W> {
+    pub fn new(writer: W) -> Self {
+        SpillWriter {
+            write_handle: BufWriter::new(writer),
+        }
+    }
+
+    pub fn add_tuple(&mut self, tuple: &Tuple) -> Result<()> {
+        let width = tuple.indexes.len();
+        self.write_handle.write_u32::<BigEndian>(to_u32(width)?)?;
+
+        let mut bitmap = vec![0u8; width.div_ceil(8)];
+        for &col in &tuple.nulls {
+            bitmap[col / 8] |= 1 << (col % 8);
+        }
+        self.write_handle.write_all(&bitmap)?;
+
+        for col in 0..width {
+            if tuple.is_null(col) {
+                continue;
+            }
+            let field = &tuple[col];
+            self.write_handle.write_u32::<BigEndian>(to_u32(field.len())?)?;
+            self.write_handle.write_all(field)?;
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        self.write_handle.flush()
+            .chain_err(|| "error writing spill file")
+    }
+}
+
+fn to_u32(len: usize) -> Result<u32> {
+    if len > u32::MAX as usize {
+        bail!("{} is too long to spill to disk", len);
+    }
+    Ok(len as u32)
+}
+
+/// Reads back the tuples written by a SpillWriter, in the same order.
+pub struct SpillScan<R: Read> {
+    read_handle: BufReader<R>,
+}
+
+impl<R: Read + Seek> SpillScan<R> {
+    pub fn new(reader: R) -> Self {
+        SpillScan {
+            read_handle: BufReader::new(reader),
+        }
+    }
+
+    fn read_tuple(&mut self) -> Result<Tuple> {
+        let width = self.read_handle.read_u32::<BigEndian>()? as usize;
+        let mut bitmap = vec![0u8; width.div_ceil(8)];
+        self.read_handle.read_exact(&mut bitmap)?;
+
+        let mut data = Vec::new();
+        let mut indexes = Vec::with_capacity(width);
+        let mut nulls = Vec::new();
+        for col in 0..width {
+            indexes.push(data.len());
+            if bitmap[col / 8] & (1 << (col % 8)) != 0 {
+                nulls.push(col);
+                continue;
+            }
+            let len = self.read_handle.read_u32::<BigEndian>()? as usize;
+            let start = data.len();
+            data.resize(start + len, 0);
+            self.read_handle.read_exact(&mut data[start..])?;
+        }
+        Ok(Tuple {
+            data,
+            indexes,
+            nulls,
+        })
+    }
+}
+
+impl<R: Read + Seek> DbIterator for SpillScan<R> {
+    fn next(&mut self) -> Result<Option<Tuple>> {
+        // the end of the file has to be at the end of a record
+        if self.read_handle.fill_buf()?.is_empty() {
+            return Ok(None);
+        }
+        self.read_tuple()
+            .map(Some)
+            .chain_err(|| "error reading spill file")
+    }
+
+    fn reset(&mut self) -> Result<()> {
+        self.read_handle.seek(SeekFrom::Start(0))?;
+        Ok(())
+    }
+
+    fn explain(&self) -> Explain {
+        Explain::new("SpillScan")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+    use super::*;
+
+    #[test]
+    fn test_write_read_spill() {
+        let tuples = vec![
+            Tuple::new(vec![b"a".to_vec(), vec![], vec![1, 2]]),
+            Tuple::from_nullable(vec![None, Some(b"b".to_vec()), None]),
+            Tuple::null(3),
+            // much larger than a block
+            Tuple::from_nullable(vec![Some(vec![b'c'; 20000]), None, Some(vec![b'd'; 70000])]),
+            Tuple::new(vec![]),
+        ];
+
+        let mut writer = SpillWriter::new(Vec::new());
+        for tuple in &tuples {
+            writer.add_tuple(tuple).unwrap();
+        }
+        writer.flush().unwrap();
+        let bytes = writer.write_handle.into_inner().unwrap();
+
+        let mut scan = SpillScan::new(Cursor::new(bytes.clone()));
+        for _ in 0..2 {
+            for tuple in &tuples {
+                assert_eq!(scan.next().unwrap().as_ref(), Some(tuple));
+            }
+            assert_eq!(scan.next().unwrap(), None);
+            scan.reset().unwrap();
+        }
+
+        // an empty spill has no tuples
+        assert_eq!(SpillScan::new(Cursor::new(Vec::new())).next().unwrap(), None);
+
+        // ending partway through a record is an error
+        let mut scan = SpillScan::new(Cursor::new(bytes[..bytes.len() - 1].to_vec()));
+        for tuple in &tuples[..4] {
+            assert_eq!(scan.next().unwrap().as_ref(), Some(tuple));
+        }
+        assert!(scan.next().is_err());
+    }
+}
@@ -0,0 +1,269 @@
+// Statistics on a relation, collected when it's imported, for the
+// planner's estimates of how many tuples each node gives.
+//
+// Distinct counts are estimated from the DISTINCT_SAMPLE smallest hashes
+// of each column's fields (a k minimum values sketch), so collecting
+// takes the same memory however large the relation is. A column with
+// fewer distinct fields than that has all of their hashes kept, so its
+// count is exact, but for hash collisions. Nulls aren't counted as
+// values, and a column of only nulls has no min or max.
+//
+// Saved as text, one line for the row count and then one for each
+// column, with min and max fields in hex:
+//
+//   rows 3
+//   column 3 0001 0007
+//   column 1 - -
+use std::cmp::Ordering;
+use std::collections::BTreeSet;
+use std::collections::hash_map::DefaultHasher;
+use std::fs::File;
+use std::hash::Hasher;
+use std::io::{BufRead, BufReader, Write};
+use std::path::Path;
+
+use DataType;
+use error::*;
+use executor::tuple::{Tuple, cmp_with_type};
+
+// Hashes kept for each column's distinct count. The estimate is off by
+// about 1 / sqrt(DISTINCT_SAMPLE), so 3% here.
+const DISTINCT_SAMPLE: usize = 1024;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TableStats {
+    pub row_count: u64,
+    pub columns: Vec<ColumnStats>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColumnStats {
+    pub distinct_count: u64,
+    pub min: Option<Vec<u8>>,
+    pub max: Option<Vec<u8>>,
+}
+
+impl TableStats {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let mut f = File::create(path)?;
+        writeln!(f, "rows {}", self.row_count)?;
+        for column in &self.columns {
+            writeln!(
+                f,
+                "column {} {} {}",
+                column.distinct_count,
+                to_hex(&column.min),
+                to_hex(&column.max),
+            )?;
+        }
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Self> {
+        let f = BufReader::new(File::open(path)?);
+        let mut lines = f.lines();
+
+        let row_count = match lines.next() {
+            Some(line) => {
+                let line = line?;
+                match line.split_whitespace().collect::<Vec<_>>()[..] {
+                    ["rows", count] => count.parse()?,
+                    _ => bail!(format!("bad stats line {:?}", line)),
+                }
+            },
+            None => bail!("empty stats file"),
+        };
+
+        let mut columns = Vec::new();
+        for line in lines {
+            let line = line?;
+            match line.split_whitespace().collect::<Vec<_>>()[..] {
+                ["column", distinct_count, min, max] => {
+                    columns.push(ColumnStats {
+                        distinct_count: distinct_count.parse()?,
+                        min: from_hex(min)?,
+                        max: from_hex(max)?,
+                    });
+                },
+                _ => bail!(format!("bad stats line {:?}", line)),
+            }
+        }
+        Ok(TableStats { row_count, columns })
+    }
+}
+
+/// Collects stats from each tuple of a relation.
+#[derive(Debug, Clone)]
+pub struct StatsCollector {
+    column_types: Vec<DataType>,
+    row_count: u64,
+    distinct: Vec<DistinctSketch>,
+    min: Vec<Option<Vec<u8>>>,
+    max: Vec<Option<Vec<u8>>>,
+}
+
+impl StatsCollector {
+    pub fn new(column_types: &[DataType]) -> Self {
+        let width = column_types.len();
+        StatsCollector {
+            column_types: column_types.to_vec(),
+            row_count: 0,
+            distinct: vec![DistinctSketch::default(); width],
+            min: vec![None; width],
+            max: vec![None; width],
+        }
+    }
+
+    pub fn add(&mut self, tuple: &Tuple) -> Result<()> {
+        self.row_count += 1;
+        for (col, data_type) in self.column_types.iter().enumerate() {
+            if tuple.is_null(col) {
+                continue;
+            }
+            let field = &tuple[col];
+            self.distinct[col].add(field);
+            let is_min = match self.min[col] {
+                Some(ref min) => cmp_with_type(field, min, data_type)? == Ordering::Less,
+                None => true,
+            };
+            if is_min {
+                self.min[col] = Some(field.to_vec());
+            }
+            let is_max = match self.max[col] {
+                Some(ref max) => cmp_with_type(field, max, data_type)? == Ordering::Greater,
+                None => true,
+            };
+            if is_max {
+                self.max[col] = Some(field.to_vec());
+            }
+        }
+        Ok(())
+    }
+
+    pub fn finish(self) -> TableStats {
+        let columns = self.distinct.into_iter()
+            .zip(self.min.into_iter().zip(self.max))
+            .map(|(distinct, (min, max))| ColumnStats {
+                distinct_count: distinct.estimate(),
+                min,
+                max,
+            })
+            .collect();
+        TableStats {
+            row_count: self.row_count,
+            columns,
+        }
+    }
+}
+
+// The smallest hashes of a column's fields. Hashes are spread evenly,
+// so if there are more distinct fields than are kept, the largest kept
+// hash is about DISTINCT_SAMPLE / distinct count of the way through
+// the range of hashes.
+#[derive(Debug, Clone, Default)]
+struct DistinctSketch {
+    smallest: BTreeSet<u64>,
+}
+
+impl DistinctSketch {
+    fn add(&mut self, field: &[u8]) {
+        let mut hasher = DefaultHasher::new();
+        hasher.write(field);
+        let hash = hasher.finish();
+
+        if self.smallest.len() < DISTINCT_SAMPLE {
+            self.smallest.insert(hash);
+        } else if self.smallest.last().is_some_and(|&largest| hash < largest) && self.smallest.insert(hash) {
+            self.smallest.pop_last();
+        }
+    }
+
+    fn estimate(&self) -> u64 {
+        match self.smallest.last() {
+            Some(&largest) if self.smallest.len() == DISTINCT_SAMPLE => {
+                let fraction = (largest as f64 + 1.) / (u64::MAX as f64 + 1.);
+                ((DISTINCT_SAMPLE - 1) as f64 / fraction) as u64
+            },
+            _ => self.smallest.len() as u64,
+        }
+    }
+}
+
+fn to_hex(field: &Option<Vec<u8>>) -> String {
+    match *field {
+        Some(ref field) if !field.is_empty() => {
+            field.iter().map(|byte| format!("{:02x}", byte)).collect()
+        },
+        // empty fields (e.g. Text(0)) are rare enough to not need a min
+        _ => "-".to_owned(),
+    }
+}
+
+fn from_hex(s: &str) -> Result<Option<Vec<u8>>> {
+    if s == "-" {
+        return Ok(None);
+    }
+    if !s.len().is_multiple_of(2) {
+        bail!(format!("bad hex field {:?}", s));
+    }
+    let bytes = (0..s.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&s[i..i + 2], 16))
+        .collect::<::std::result::Result<Vec<u8>, _>>()?;
+    Ok(Some(bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use executor::tuple::ToTupleField;
+    use storage::TempFile;
+
+    #[test]
+    fn test_collect_stats() {
+        use DataType::*;
+
+        let mut collector = StatsCollector::new(&[Integer, Text(4)]);
+        for &(id, name) in &[(7i32, Some("bee")), (2, None), (7, Some("ant")), (30, Some("bee"))] {
+            collector.add(&Tuple::from_nullable(vec![
+                Some(id.to_tuple_field()),
+                name.map(|name: &str| name.as_bytes().to_vec()),
+            ])).unwrap();
+        }
+        let stats = collector.finish();
+
+        assert_eq!(stats.row_count, 4);
+        assert_eq!(stats.columns[0], ColumnStats {
+            distinct_count: 3,
+            min: Some(2i32.to_tuple_field()),
+            max: Some(30i32.to_tuple_field()),
+        });
+        assert_eq!(stats.columns[1], ColumnStats {
+            distinct_count: 2,
+            min: Some(b"ant".to_vec()),
+            max: Some(b"bee".to_vec()),
+        });
+
+        let f = TempFile::new("stats");
+        stats.save(f.path()).unwrap();
+        assert_eq!(TableStats::load(f.path()).unwrap(), stats);
+    }
+
+    #[test]
+    fn test_estimate_distinct() {
+        // each value twice, so 50000 distinct
+        let mut collector = StatsCollector::new(&[DataType::Integer]);
+        for i in 0..100_000i32 {
+            collector.add(&Tuple::new(vec![(i / 2).to_tuple_field()])).unwrap();
+        }
+        assert_eq!(collector.distinct[0].smallest.len(), DISTINCT_SAMPLE);
+        let distinct_count = collector.finish().columns[0].distinct_count as f64;
+        assert!((distinct_count - 50_000.).abs() < 50_000. * 0.1, "{}", distinct_count);
+
+        // exact up to DISTINCT_SAMPLE
+        let mut collector = StatsCollector::new(&[DataType::Integer]);
+        for i in 0..3 * DISTINCT_SAMPLE as i32 {
+            collector.add(&Tuple::new(vec![(i % (DISTINCT_SAMPLE as i32 - 1)).to_tuple_field()])).unwrap();
+        }
+        assert_eq!(collector.finish().columns[0].distinct_count, DISTINCT_SAMPLE as u64 - 1);
+    }
+}